@@ -1,5 +1,65 @@
 use std::path::PathBuf;
 
+use ipnet::IpNet;
+
+use crate::utils::client_ip::{parse_trusted_proxies, ForwardedHeader};
+
+/// Backend di storage per input e risultati dei job
+#[derive(Debug, Clone, Default)]
+pub enum StorageBackend {
+    /// Filesystem locale sotto `temp_dir`
+    #[default]
+    Local,
+    /// Bucket S3-compatibile (AWS S3, MinIO, ...)
+    S3(S3Config),
+}
+
+/// Parametri di connessione a uno storage S3-compatibile
+#[derive(Clone)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// URL path-style (`endpoint/bucket/key`), necessario per MinIO
+    pub path_style: bool,
+}
+
+impl std::fmt::Debug for S3Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Config")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("path_style", &self.path_style)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Provider OpenID Connect generico (es. l'identity provider aziendale)
+#[derive(Clone)]
+pub struct OidcConfig {
+    /// Issuer: la configurazione è letta da `<issuer>/.well-known/openid-configuration`
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    /// Scope richiesti, separati da spazio
+    pub scopes: String,
+}
+
+impl std::fmt::Debug for OidcConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OidcConfig")
+            .field("issuer_url", &self.issuer_url)
+            .field("client_id", &self.client_id)
+            .field("redirect_uri", &self.redirect_uri)
+            .field("scopes", &self.scopes)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub host: String,
@@ -10,6 +70,27 @@ pub struct Config {
     pub google_client_id: Option<String>,
     pub google_client_secret: Option<String>,
     pub frontend_url: String,
+    /// Login tramite provider OIDC (assente = disabilitato)
+    pub oidc: Option<OidcConfig>,
+    pub storage: StorageBackend,
+    /// URL pubblico dell'API usato nei link generati (default: http://localhost:<port>)
+    pub public_url: Option<String>,
+    /// Segreto HMAC per i link di download firmati
+    pub signing_secret: Option<String>,
+    /// Validità di default dei link di download firmati (secondi)
+    pub download_link_ttl_secs: u64,
+    /// Reverse proxy di cui accettare l'header con l'IP del client
+    pub trusted_proxies: Vec<IpNet>,
+    /// Header impostato dai proxy fidati (l'unico letto)
+    pub client_ip_header: ForwardedHeader,
+    /// Richieste al minuto per IP prima dell'autenticazione (0 = nessun limite)
+    pub ip_rate_limit_per_minute: u32,
+    /// Token Bearer richiesto da `/metrics` (assente = endpoint pubblico)
+    pub metrics_token: Option<String>,
+    /// Spazio libero minimo nella directory temporanea per `/health/ready` (MB)
+    pub min_free_disk_mb: u64,
+    /// Tool esterni senza i quali il nodo non è pronto (es. `ffmpeg`, `pdftoppm`)
+    pub required_tools: Vec<String>,
 }
 
 impl Default for Config {
@@ -23,6 +104,17 @@ impl Default for Config {
             google_client_id: None,
             google_client_secret: None,
             frontend_url: "http://localhost:3000".to_string(),
+            oidc: None,
+            storage: StorageBackend::Local,
+            public_url: None,
+            signing_secret: None,
+            download_link_ttl_secs: 3600,
+            trusted_proxies: Vec::new(),
+            client_ip_header: ForwardedHeader::default(),
+            ip_rate_limit_per_minute: 600,
+            metrics_token: None,
+            min_free_disk_mb: 1024,
+            required_tools: Vec::new(),
         }
     }
 }
@@ -63,12 +155,108 @@ impl Config {
             config.frontend_url = frontend_url;
         }
 
+        if let (Ok(issuer_url), Ok(client_id)) = (
+            std::env::var("OIDC_ISSUER_URL"),
+            std::env::var("OIDC_CLIENT_ID"),
+        ) {
+            config.oidc = Some(OidcConfig {
+                issuer_url,
+                client_id,
+                client_secret: std::env::var("OIDC_CLIENT_SECRET").unwrap_or_default(),
+                redirect_uri: std::env::var("OIDC_REDIRECT_URI").unwrap_or_else(|_| {
+                    "http://localhost:4000/api/v1/auth/oidc/callback".to_string()
+                }),
+                scopes: std::env::var("OIDC_SCOPES")
+                    .unwrap_or_else(|_| "openid email profile".to_string()),
+            });
+        }
+
+        if let Ok(public_url) = std::env::var("CONVERTY_PUBLIC_URL") {
+            config.public_url = Some(public_url);
+        }
+
+        if let Ok(secret) = std::env::var("CONVERTY_SIGNING_SECRET") {
+            if !secret.is_empty() {
+                config.signing_secret = Some(secret);
+            }
+        }
+
+        if let Ok(ttl) = std::env::var("CONVERTY_DOWNLOAD_LINK_TTL_SECS") {
+            if let Ok(t) = ttl.parse() {
+                config.download_link_ttl_secs = t;
+            }
+        }
+
+        if let Ok(proxies) = std::env::var("CONVERTY_TRUSTED_PROXIES") {
+            config.trusted_proxies = parse_trusted_proxies(&proxies);
+        }
+
+        if let Ok(name) = std::env::var("CONVERTY_CLIENT_IP_HEADER") {
+            match ForwardedHeader::parse(&name) {
+                Some(header) => config.client_ip_header = header,
+                None => tracing::warn!(
+                    "CONVERTY_CLIENT_IP_HEADER non valido: '{}' (usato X-Forwarded-For)",
+                    name
+                ),
+            }
+        }
+
+        if let Ok(limit) = std::env::var("CONVERTY_IP_RATE_LIMIT_PER_MINUTE") {
+            if let Ok(l) = limit.parse() {
+                config.ip_rate_limit_per_minute = l;
+            }
+        }
+
+        if let Ok(token) = std::env::var("CONVERTY_METRICS_TOKEN") {
+            if !token.is_empty() {
+                config.metrics_token = Some(token);
+            }
+        }
+
+        if let Ok(mb) = std::env::var("CONVERTY_MIN_FREE_DISK_MB") {
+            if let Ok(m) = mb.parse() {
+                config.min_free_disk_mb = m;
+            }
+        }
+
+        if let Ok(tools) = std::env::var("CONVERTY_REQUIRED_TOOLS") {
+            config.required_tools = tools
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+        }
+
+        if std::env::var("CONVERTY_STORAGE").is_ok_and(|s| s.eq_ignore_ascii_case("s3")) {
+            let env = |name: &str| std::env::var(name).unwrap_or_default();
+            config.storage = StorageBackend::S3(S3Config {
+                endpoint: env("CONVERTY_S3_ENDPOINT"),
+                bucket: env("CONVERTY_S3_BUCKET"),
+                region: std::env::var("CONVERTY_S3_REGION")
+                    .unwrap_or_else(|_| "us-east-1".to_string()),
+                access_key: env("CONVERTY_S3_ACCESS_KEY"),
+                secret_key: env("CONVERTY_S3_SECRET_KEY"),
+                path_style: std::env::var("CONVERTY_S3_PATH_STYLE")
+                    .map(|v| v != "false" && v != "0")
+                    .unwrap_or(true),
+            });
+        }
+
         config
     }
 
     pub fn max_file_size_bytes(&self) -> u64 {
         self.max_file_size_mb * 1024 * 1024
     }
+
+    /// URL pubblico dell'API, senza slash finale
+    pub fn public_url(&self) -> String {
+        self.public_url
+            .clone()
+            .unwrap_or_else(|| format!("http://localhost:{}", self.port))
+            .trim_end_matches('/')
+            .to_string()
+    }
 }
 
 // Formati supportati
@@ -146,4 +334,24 @@ pub mod formats {
     pub fn is_supported_pdf_output(ext: &str) -> bool {
         PDF_OUTPUT.contains(&ext.to_lowercase().as_str())
     }
+
+    /// Formato presente in almeno una delle liste di input o output
+    pub fn is_known(ext: &str) -> bool {
+        let ext = ext.to_lowercase();
+        [
+            IMAGE_INPUT,
+            IMAGE_OUTPUT,
+            SVG_INPUT,
+            DOCUMENT_INPUT,
+            DOCUMENT_OUTPUT,
+            AUDIO_INPUT,
+            AUDIO_OUTPUT,
+            VIDEO_INPUT,
+            VIDEO_OUTPUT,
+            PDF_INPUT,
+            PDF_OUTPUT,
+        ]
+        .iter()
+        .any(|list| list.contains(&ext.as_str()))
+    }
 }
@@ -2,9 +2,14 @@ use chrono::{DateTime, Utc};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use sqlx::FromRow;
 use utoipa::ToSchema;
 
+use std::net::IpAddr;
+
 use super::DbPool;
+use crate::models::KeyScopes;
+use crate::utils::client_ip::parse_ip_net;
 
 /// Ruoli disponibili per API Key
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
@@ -52,6 +57,66 @@ pub struct ApiKey {
     pub last_used_at: Option<DateTime<Utc>>,
     pub created_by: Option<String>,
     pub notes: Option<String>,
+    /// Dimensione massima upload in MB (sostituisce quella globale)
+    pub max_file_size_mb: Option<i64>,
+    /// Conversioni mensili consentite
+    pub monthly_limit: Option<i64>,
+    /// Volume mensile di input in MB
+    pub monthly_volume_mb: Option<i64>,
+    /// Minuti di audio/video convertibili al mese
+    pub monthly_media_minutes: Option<i64>,
+    /// Permessi granulari (assenti = tutti i permessi del ruolo)
+    pub scopes: KeyScopes,
+    /// Scadenza della chiave (assente = nessuna scadenza)
+    #[schema(value_type = Option<String>, format = "date-time")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    pub previous_key_hash: Option<String>,
+    /// Fine del periodo di grazia del segreto precedente all'ultima rotazione
+    #[schema(value_type = Option<String>, format = "date-time")]
+    pub previous_key_expires_at: Option<DateTime<Utc>>,
+    /// IP o CIDR da cui la chiave è accettata (vuota = qualsiasi)
+    pub allowed_ips: Vec<String>,
+    /// Richieste rifiutate perché provenienti da un IP non consentito
+    pub rejected_ip_attempts: i64,
+    /// Piano assegnato (i suoi limiti prevalgono su quelli della chiave)
+    pub plan_id: Option<String>,
+}
+
+impl ApiKey {
+    /// Verifica che la chiave possa essere usata dall'IP del client
+    pub fn allows_ip(&self, ip: IpAddr) -> bool {
+        self.allowed_ips.is_empty()
+            || self
+                .allowed_ips
+                .iter()
+                .filter_map(|entry| parse_ip_net(entry))
+                .any(|net| net.contains(&ip))
+    }
+
+    /// Scadenza effettiva del segreto usato per autenticarsi
+    ///
+    /// Il segreto precedente a una rotazione vale fino alla fine del periodo
+    /// di grazia, e comunque non oltre la scadenza della chiave.
+    pub fn secret_expires_at(&self, provided_key: &str) -> Option<DateTime<Utc>> {
+        if self.key_hash == hash_api_key(provided_key) {
+            return self.expires_at;
+        }
+        match (self.previous_key_expires_at, self.expires_at) {
+            (Some(grace), Some(expiry)) => Some(grace.min(expiry)),
+            (grace, expiry) => grace.or(expiry),
+        }
+    }
+
+    /// Indica se l'hash è quello del segreto attuale o del precedente ancora
+    /// nel periodo di grazia
+    fn accepts_hash(&self, hash: &str, now: DateTime<Utc>) -> bool {
+        self.key_hash == hash
+            || (self.previous_key_hash.as_deref() == Some(hash)
+                && self
+                    .previous_key_expires_at
+                    .is_some_and(|grace| grace > now))
+    }
 }
 
 /// Risposta creazione API Key (include la chiave in chiaro una sola volta)
@@ -65,10 +130,38 @@ pub struct ApiKeyCreated {
     pub role: ApiKeyRole,
     pub rate_limit: i64,
     pub daily_limit: Option<i64>,
+    pub max_file_size_mb: Option<i64>,
+    pub monthly_limit: Option<i64>,
+    pub monthly_volume_mb: Option<i64>,
+    pub monthly_media_minutes: Option<i64>,
+    pub scopes: KeyScopes,
+    pub allowed_ips: Vec<String>,
+    pub plan_id: Option<String>,
+    #[schema(value_type = Option<String>, format = "date-time")]
+    pub expires_at: Option<DateTime<Utc>>,
     #[schema(value_type = String, format = "date-time")]
     pub created_at: DateTime<Utc>,
 }
 
+/// Risposta rotazione API Key (include il nuovo segreto una sola volta)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiKeyRotated {
+    pub id: String,
+    /// Il nuovo segreto in chiaro - mostrato solo una volta!
+    pub api_key: String,
+    pub key_prefix: String,
+    /// Fino a quando il segreto precedente resta valido (assente = già revocato)
+    #[schema(value_type = Option<String>, format = "date-time")]
+    pub previous_key_expires_at: Option<DateTime<Utc>>,
+}
+
+/// Request per ruotare il segreto di un'API Key
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct RotateApiKeyRequest {
+    /// Secondi in cui il segreto precedente resta valido (default: 24 ore, 0 = revoca subito)
+    pub grace_period_secs: Option<u64>,
+}
+
 /// Request per creare una nuova API Key
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateApiKeyRequest {
@@ -82,6 +175,30 @@ pub struct CreateApiKeyRequest {
     pub rate_limit: i64,
     /// Limite giornaliero (opzionale)
     pub daily_limit: Option<i64>,
+    /// Dimensione massima upload in MB (default: limite globale)
+    #[serde(default)]
+    pub max_file_size_mb: Option<i64>,
+    /// Conversioni mensili consentite (opzionale)
+    #[serde(default)]
+    pub monthly_limit: Option<i64>,
+    /// Volume mensile di input in MB (opzionale)
+    #[serde(default)]
+    pub monthly_volume_mb: Option<i64>,
+    /// Minuti di audio/video convertibili al mese (opzionale)
+    #[serde(default)]
+    pub monthly_media_minutes: Option<i64>,
+    /// Permessi granulari (default: nessuna restrizione)
+    #[serde(default)]
+    pub scopes: KeyScopes,
+    /// IP o CIDR da cui la chiave è accettata (default: qualsiasi)
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    /// Piano da assegnare (default: nessuno)
+    #[serde(default)]
+    pub plan_id: Option<String>,
+    /// Giorni di validità della chiave (default: nessuna scadenza)
+    #[serde(default)]
+    pub expires_in_days: Option<i64>,
     /// Note aggiuntive
     pub notes: Option<String>,
 }
@@ -100,7 +217,24 @@ pub struct UpdateApiKeyRequest {
     pub name: Option<String>,
     pub is_active: Option<bool>,
     pub rate_limit: Option<i64>,
+    /// Limite giornaliero (0 = nessun limite)
     pub daily_limit: Option<i64>,
+    /// Dimensione massima upload in MB (0 = limite globale)
+    pub max_file_size_mb: Option<i64>,
+    /// Conversioni mensili (0 = nessun limite)
+    pub monthly_limit: Option<i64>,
+    /// Volume mensile di input in MB (0 = nessun limite)
+    pub monthly_volume_mb: Option<i64>,
+    /// Minuti di audio/video al mese (0 = nessun limite)
+    pub monthly_media_minutes: Option<i64>,
+    /// Sostituisce gli scope correnti (`{}` = nessuna restrizione)
+    pub scopes: Option<KeyScopes>,
+    /// Sostituisce gli IP/CIDR consentiti (`[]` = qualsiasi IP)
+    pub allowed_ips: Option<Vec<String>>,
+    /// Piano assegnato (`""` = nessun piano)
+    pub plan_id: Option<String>,
+    /// Nuova scadenza in giorni da adesso (0 = nessuna scadenza)
+    pub expires_in_days: Option<i64>,
     pub notes: Option<String>,
 }
 
@@ -134,19 +268,12 @@ pub async fn create_api_key(
     let (key, prefix, hash) = generate_api_key();
     let now = Utc::now();
     let role = ApiKeyRole::from(request.role.as_str());
-
-    // Solo per utenti normali: salva anche la chiave in chiaro per poterla recuperare
-    // Admin keys restano solo hashate per sicurezza
-    let key_plaintext: Option<&str> = if role == ApiKeyRole::User {
-        Some(&key)
-    } else {
-        None
-    };
+    let expires_at = expiry_from_days(now, request.expires_in_days);
 
     sqlx::query(
         r#"
-        INSERT INTO api_keys (id, name, key_hash, key_prefix, role, is_active, rate_limit, daily_limit, created_at, updated_at, created_by, notes, key_plaintext)
-        VALUES (?, ?, ?, ?, ?, 1, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO api_keys (id, name, key_hash, key_prefix, role, is_active, rate_limit, daily_limit, created_at, updated_at, created_by, notes, max_file_size_mb, monthly_limit, monthly_volume_mb, monthly_media_minutes, scopes, expires_at, allowed_ips, plan_id)
+        VALUES (?, ?, ?, ?, ?, 1, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
     )
     .bind(&id)
@@ -160,7 +287,14 @@ pub async fn create_api_key(
     .bind(now.to_rfc3339())
     .bind(created_by)
     .bind(&request.notes)
-    .bind(key_plaintext)
+    .bind(request.max_file_size_mb)
+    .bind(request.monthly_limit)
+    .bind(request.monthly_volume_mb)
+    .bind(request.monthly_media_minutes)
+    .bind(request.scopes.to_json())
+    .bind(expires_at.map(|dt| dt.to_rfc3339()))
+    .bind(join_ip_list(&request.allowed_ips))
+    .bind(&request.plan_id)
     .execute(pool)
     .await?;
 
@@ -172,155 +306,170 @@ pub async fn create_api_key(
         role,
         rate_limit: request.rate_limit,
         daily_limit: request.daily_limit,
+        max_file_size_mb: request.max_file_size_mb,
+        monthly_limit: request.monthly_limit,
+        monthly_volume_mb: request.monthly_volume_mb,
+        monthly_media_minutes: request.monthly_media_minutes,
+        scopes: request.scopes.clone(),
+        allowed_ips: request.allowed_ips.clone(),
+        plan_id: request.plan_id.clone(),
+        expires_at,
         created_at: now,
     })
 }
 
+/// Colonne di `api_keys` lette nelle query
+const API_KEY_COLUMNS: &str =
+    "id, name, key_hash, key_prefix, role, is_active, rate_limit, daily_limit, \
+     created_at, updated_at, last_used_at, created_by, notes, max_file_size_mb, \
+     monthly_limit, monthly_volume_mb, monthly_media_minutes, scopes, \
+     expires_at, previous_key_hash, previous_key_expires_at, allowed_ips, \
+     rejected_ip_attempts, plan_id";
+
+/// Riga grezza della tabella `api_keys`
+#[derive(FromRow)]
+struct ApiKeyRow {
+    id: String,
+    name: String,
+    key_hash: String,
+    key_prefix: String,
+    role: String,
+    is_active: i64,
+    rate_limit: i64,
+    daily_limit: Option<i64>,
+    created_at: String,
+    updated_at: String,
+    last_used_at: Option<String>,
+    created_by: Option<String>,
+    notes: Option<String>,
+    max_file_size_mb: Option<i64>,
+    monthly_limit: Option<i64>,
+    monthly_volume_mb: Option<i64>,
+    monthly_media_minutes: Option<i64>,
+    scopes: Option<String>,
+    expires_at: Option<String>,
+    previous_key_hash: Option<String>,
+    previous_key_expires_at: Option<String>,
+    allowed_ips: Option<String>,
+    rejected_ip_attempts: i64,
+    plan_id: Option<String>,
+}
+
+fn parse_timestamp(value: Option<String>) -> Option<DateTime<Utc>> {
+    value.and_then(|s| {
+        DateTime::parse_from_rfc3339(&s)
+            .ok()
+            .map(|dt| dt.with_timezone(&Utc))
+    })
+}
+
+/// IP consentiti salvati come lista separata da virgole (NULL = qualsiasi)
+fn join_ip_list(ips: &[String]) -> Option<String> {
+    let joined = ips
+        .iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(",");
+    (!joined.is_empty()).then_some(joined)
+}
+
+fn split_ip_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Scadenza a `days` giorni da `now` (None o <= 0 = nessuna scadenza)
+fn expiry_from_days(now: DateTime<Utc>, days: Option<i64>) -> Option<DateTime<Utc>> {
+    days.filter(|d| *d > 0)
+        .map(|d| now + chrono::Duration::days(d))
+}
+
+impl From<ApiKeyRow> for ApiKey {
+    fn from(row: ApiKeyRow) -> Self {
+        ApiKey {
+            id: row.id,
+            name: row.name,
+            key_hash: row.key_hash,
+            key_prefix: row.key_prefix,
+            role: ApiKeyRole::from(row.role.as_str()),
+            is_active: row.is_active != 0,
+            rate_limit: row.rate_limit,
+            daily_limit: row.daily_limit,
+            created_at: DateTime::parse_from_rfc3339(&row.created_at)
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now()),
+            updated_at: DateTime::parse_from_rfc3339(&row.updated_at)
+                .map(|dt| dt.with_timezone(&Utc))
+                .unwrap_or_else(|_| Utc::now()),
+            last_used_at: parse_timestamp(row.last_used_at),
+            created_by: row.created_by,
+            notes: row.notes,
+            max_file_size_mb: row.max_file_size_mb,
+            monthly_limit: row.monthly_limit,
+            monthly_volume_mb: row.monthly_volume_mb,
+            monthly_media_minutes: row.monthly_media_minutes,
+            scopes: KeyScopes::from_json(row.scopes.as_deref()),
+            expires_at: parse_timestamp(row.expires_at),
+            previous_key_hash: row.previous_key_hash,
+            previous_key_expires_at: parse_timestamp(row.previous_key_expires_at),
+            allowed_ips: row
+                .allowed_ips
+                .as_deref()
+                .map(split_ip_list)
+                .unwrap_or_default(),
+            rejected_ip_attempts: row.rejected_ip_attempts,
+            plan_id: row.plan_id,
+        }
+    }
+}
+
 /// Trova API Key per hash
-#[allow(clippy::type_complexity)]
 pub async fn find_by_key(pool: &DbPool, api_key: &str) -> Result<Option<ApiKey>, sqlx::Error> {
     let hash = hash_api_key(api_key);
 
-    let row: Option<(
-        String,
-        String,
-        String,
-        String,
-        String,
-        i64,
-        i64,
-        Option<i64>,
-        String,
-        String,
-        Option<String>,
-        Option<String>,
-        Option<String>,
-    )> = sqlx::query_as(
-        r#"
-        SELECT id, name, key_hash, key_prefix, role, is_active, rate_limit, daily_limit,
-               created_at, updated_at, last_used_at, created_by, notes
-        FROM api_keys
-        WHERE key_hash = ?
-        "#,
-    )
+    // Accetta anche il segreto precedente a una rotazione
+    let row: Option<ApiKeyRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM api_keys WHERE key_hash = ? OR previous_key_hash = ?",
+        API_KEY_COLUMNS
+    ))
+    .bind(&hash)
     .bind(&hash)
     .fetch_optional(pool)
     .await?;
 
-    match row {
-        Some((
-            id,
-            name,
-            key_hash,
-            key_prefix,
-            role,
-            is_active,
-            rate_limit,
-            daily_limit,
-            created_at,
-            updated_at,
-            last_used_at,
-            created_by,
-            notes,
-        )) => Ok(Some(ApiKey {
-            id,
-            name,
-            key_hash,
-            key_prefix,
-            role: ApiKeyRole::from(role.as_str()),
-            is_active: is_active != 0,
-            rate_limit,
-            daily_limit,
-            created_at: DateTime::parse_from_rfc3339(&created_at)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now()),
-            updated_at: DateTime::parse_from_rfc3339(&updated_at)
-                .map(|dt| dt.with_timezone(&Utc))
-                .unwrap_or_else(|_| Utc::now()),
-            last_used_at: last_used_at.and_then(|s| {
-                DateTime::parse_from_rfc3339(&s)
-                    .ok()
-                    .map(|dt| dt.with_timezone(&Utc))
-            }),
-            created_by,
-            notes,
-        })),
-        None => Ok(None),
-    }
+    // Il segreto precedente vale solo durante il periodo di grazia
+    Ok(row
+        .map(ApiKey::from)
+        .filter(|key| key.accepts_hash(&hash, Utc::now())))
+}
+
+/// Trova API Key per ID
+pub async fn find_by_id(pool: &DbPool, id: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+    let row: Option<ApiKeyRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM api_keys WHERE id = ?",
+        API_KEY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(ApiKey::from))
 }
 
 /// Lista tutte le API Keys
-#[allow(clippy::type_complexity)]
 pub async fn list_all(pool: &DbPool) -> Result<Vec<ApiKey>, sqlx::Error> {
-    let rows: Vec<(
-        String,
-        String,
-        String,
-        String,
-        String,
-        i64,
-        i64,
-        Option<i64>,
-        String,
-        String,
-        Option<String>,
-        Option<String>,
-        Option<String>,
-    )> = sqlx::query_as(
-        r#"
-        SELECT id, name, key_hash, key_prefix, role, is_active, rate_limit, daily_limit,
-               created_at, updated_at, last_used_at, created_by, notes
-        FROM api_keys
-        ORDER BY created_at DESC
-        "#,
-    )
+    let rows: Vec<ApiKeyRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM api_keys ORDER BY created_at DESC",
+        API_KEY_COLUMNS
+    ))
     .fetch_all(pool)
     .await?;
 
-    Ok(rows
-        .into_iter()
-        .map(
-            |(
-                id,
-                name,
-                key_hash,
-                key_prefix,
-                role,
-                is_active,
-                rate_limit,
-                daily_limit,
-                created_at,
-                updated_at,
-                last_used_at,
-                created_by,
-                notes,
-            )| {
-                ApiKey {
-                    id,
-                    name,
-                    key_hash,
-                    key_prefix,
-                    role: ApiKeyRole::from(role.as_str()),
-                    is_active: is_active != 0,
-                    rate_limit,
-                    daily_limit,
-                    created_at: DateTime::parse_from_rfc3339(&created_at)
-                        .map(|dt| dt.with_timezone(&Utc))
-                        .unwrap_or_else(|_| Utc::now()),
-                    updated_at: DateTime::parse_from_rfc3339(&updated_at)
-                        .map(|dt| dt.with_timezone(&Utc))
-                        .unwrap_or_else(|_| Utc::now()),
-                    last_used_at: last_used_at.and_then(|s| {
-                        DateTime::parse_from_rfc3339(&s)
-                            .ok()
-                            .map(|dt| dt.with_timezone(&Utc))
-                    }),
-                    created_by,
-                    notes,
-                }
-            },
-        )
-        .collect())
+    Ok(rows.into_iter().map(ApiKey::from).collect())
 }
 
 /// Aggiorna timestamp ultimo uso
@@ -362,10 +511,68 @@ pub async fn update_api_key(
         updates.push("rate_limit = ?");
         values.push(rate_limit.to_string());
     }
+    if let Some(daily_limit) = request.daily_limit {
+        updates.push("daily_limit = ?");
+        values.push(daily_limit.to_string());
+    }
+    if let Some(max_file_size_mb) = request.max_file_size_mb {
+        updates.push("max_file_size_mb = ?");
+        values.push(max_file_size_mb.to_string());
+    }
+    if let Some(monthly_limit) = request.monthly_limit {
+        updates.push("monthly_limit = ?");
+        values.push(monthly_limit.to_string());
+    }
+    if let Some(monthly_volume_mb) = request.monthly_volume_mb {
+        updates.push("monthly_volume_mb = ?");
+        values.push(monthly_volume_mb.to_string());
+    }
+    if let Some(monthly_media_minutes) = request.monthly_media_minutes {
+        updates.push("monthly_media_minutes = ?");
+        values.push(monthly_media_minutes.to_string());
+    }
     if let Some(ref notes) = request.notes {
         updates.push("notes = ?");
         values.push(notes.clone());
     }
+    if let Some(days) = request.expires_in_days {
+        match expiry_from_days(Utc::now(), Some(days)) {
+            Some(expires_at) => {
+                updates.push("expires_at = ?");
+                values.push(expires_at.to_rfc3339());
+            }
+            None => updates.push("expires_at = NULL"),
+        }
+    }
+    if let Some(ref scopes) = request.scopes {
+        // Scope senza restrizioni salvati come NULL
+        match scopes.to_json() {
+            Some(json) => {
+                updates.push("scopes = ?");
+                values.push(json);
+            }
+            None => updates.push("scopes = NULL"),
+        }
+    }
+
+    if let Some(ref ips) = request.allowed_ips {
+        match join_ip_list(ips) {
+            Some(list) => {
+                updates.push("allowed_ips = ?");
+                values.push(list);
+            }
+            None => updates.push("allowed_ips = NULL"),
+        }
+    }
+
+    if let Some(ref plan_id) = request.plan_id {
+        if plan_id.is_empty() {
+            updates.push("plan_id = NULL");
+        } else {
+            updates.push("plan_id = ?");
+            values.push(plan_id.clone());
+        }
+    }
 
     if updates.is_empty() {
         return Ok(false);
@@ -386,8 +593,70 @@ pub async fn update_api_key(
     Ok(result.rows_affected() > 0)
 }
 
+/// Conta una richiesta rifiutata perché arrivata da un IP non consentito
+pub async fn record_ip_rejection(pool: &DbPool, api_key_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE api_keys SET rejected_ip_attempts = rejected_ip_attempts + 1 WHERE id = ?")
+        .bind(api_key_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Ruota il segreto di un'API Key
+///
+/// Il segreto attuale resta valido per `grace` (zero = revocato subito),
+/// così i client possono passare al nuovo senza interruzioni.
+pub async fn rotate_api_key(
+    pool: &DbPool,
+    id: &str,
+    grace: chrono::Duration,
+) -> Result<Option<ApiKeyRotated>, sqlx::Error> {
+    let (key, prefix, hash) = generate_api_key();
+    let now = Utc::now();
+    let previous_key_expires_at = (grace > chrono::Duration::zero()).then(|| now + grace);
+
+    let result = sqlx::query(
+        r#"
+        UPDATE api_keys SET
+            previous_key_hash = CASE WHEN ? IS NULL THEN NULL ELSE key_hash END,
+            previous_key_expires_at = ?,
+            key_hash = ?,
+            key_prefix = ?,
+            updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(previous_key_expires_at.map(|dt| dt.to_rfc3339()))
+    .bind(previous_key_expires_at.map(|dt| dt.to_rfc3339()))
+    .bind(&hash)
+    .bind(&prefix)
+    .bind(now.to_rfc3339())
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(ApiKeyRotated {
+        id: id.to_string(),
+        api_key: key,
+        key_prefix: prefix,
+        previous_key_expires_at,
+    }))
+}
+
 /// Elimina API Key
 pub async fn delete_api_key(pool: &DbPool, id: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query("DELETE FROM sessions WHERE api_key_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    sqlx::query("DELETE FROM organization_members WHERE api_key_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
     let result = sqlx::query("DELETE FROM api_keys WHERE id = ?")
         .bind(id)
         .execute(pool)
@@ -415,6 +684,14 @@ pub async fn ensure_initial_admin(pool: &DbPool) -> Result<Option<ApiKeyCreated>
         role: "admin".to_string(),
         rate_limit: 1000,
         daily_limit: None,
+        max_file_size_mb: None,
+        monthly_limit: None,
+        monthly_volume_mb: None,
+        monthly_media_minutes: None,
+        scopes: KeyScopes::default(),
+        allowed_ips: Vec::new(),
+        plan_id: None,
+        expires_in_days: None,
         notes: Some("Chiave admin iniziale creata automaticamente".to_string()),
     };
 
@@ -422,16 +699,123 @@ pub async fn ensure_initial_admin(pool: &DbPool) -> Result<Option<ApiKeyCreated>
     Ok(Some(key))
 }
 
-/// Recupera la chiave API in chiaro per un utente (solo per ruolo "user", non admin)
-pub async fn get_plaintext_key(
-    pool: &DbPool,
-    api_key_id: &str,
-) -> Result<Option<String>, sqlx::Error> {
-    let row: Option<(Option<String>,)> =
-        sqlx::query_as("SELECT key_plaintext FROM api_keys WHERE id = ? AND role = 'user'")
-            .bind(api_key_id)
-            .fetch_optional(pool)
-            .await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const CURRENT: &str = "cvt_current";
+    const PREVIOUS: &str = "cvt_previous";
+
+    fn key(expires_at: Option<DateTime<Utc>>, grace: Option<DateTime<Utc>>) -> ApiKey {
+        let now = Utc::now();
+        ApiKey {
+            id: "key".to_string(),
+            name: "test".to_string(),
+            key_hash: hash_api_key(CURRENT),
+            key_prefix: "cvt_curr".to_string(),
+            role: ApiKeyRole::User,
+            is_active: true,
+            rate_limit: 100,
+            daily_limit: None,
+            created_at: now,
+            updated_at: now,
+            last_used_at: None,
+            created_by: None,
+            notes: None,
+            max_file_size_mb: None,
+            monthly_limit: None,
+            monthly_volume_mb: None,
+            monthly_media_minutes: None,
+            scopes: KeyScopes::default(),
+            expires_at,
+            previous_key_hash: grace.map(|_| hash_api_key(PREVIOUS)),
+            previous_key_expires_at: grace,
+            allowed_ips: Vec::new(),
+            rejected_ip_attempts: 0,
+            plan_id: None,
+        }
+    }
+
+    #[test]
+    fn test_current_secret_uses_key_expiry() {
+        let now = Utc::now();
+        let expiry = now + Duration::days(30);
+        let grace = now + Duration::hours(1);
+
+        assert_eq!(
+            key(Some(expiry), Some(grace)).secret_expires_at(CURRENT),
+            Some(expiry)
+        );
+        assert_eq!(key(None, Some(grace)).secret_expires_at(CURRENT), None);
+    }
+
+    #[test]
+    fn test_previous_secret_expires_with_grace_period() {
+        let now = Utc::now();
+        let grace = now + Duration::hours(1);
+
+        assert_eq!(
+            key(None, Some(grace)).secret_expires_at(PREVIOUS),
+            Some(grace)
+        );
+        assert_eq!(
+            key(Some(now + Duration::days(30)), Some(grace)).secret_expires_at(PREVIOUS),
+            Some(grace)
+        );
+    }
+
+    #[test]
+    fn test_grace_period_capped_by_key_expiry() {
+        let now = Utc::now();
+        let expiry = now + Duration::minutes(10);
+        let grace = now + Duration::hours(1);
+
+        assert_eq!(
+            key(Some(expiry), Some(grace)).secret_expires_at(PREVIOUS),
+            Some(expiry)
+        );
+    }
 
-    Ok(row.and_then(|(plaintext,)| plaintext))
+    #[test]
+    fn test_previous_secret_accepted_only_during_grace() {
+        let now = Utc::now();
+        let current = hash_api_key(CURRENT);
+        let previous = hash_api_key(PREVIOUS);
+
+        let rotated = key(None, Some(now + Duration::hours(1)));
+        assert!(rotated.accepts_hash(&current, now));
+        assert!(rotated.accepts_hash(&previous, now));
+        assert!(!rotated.accepts_hash(&previous, now + Duration::hours(2)));
+        assert!(!rotated.accepts_hash(&hash_api_key("cvt_other"), now));
+
+        // Senza rotazione vale solo il segreto attuale
+        let never_rotated = key(None, None);
+        assert!(never_rotated.accepts_hash(&current, now));
+        assert!(!never_rotated.accepts_hash(&previous, now));
+    }
+
+    #[test]
+    fn test_allows_ip() {
+        let mut key = key(None, None);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        // Lista vuota: qualsiasi IP
+        assert!(key.allows_ip(ip("203.0.113.5")));
+
+        key.allowed_ips = vec!["10.0.0.0/8".to_string(), " 2001:db8::1 ".to_string()];
+        assert!(key.allows_ip(ip("10.1.2.3")));
+        assert!(key.allows_ip(ip("2001:db8::1")));
+        assert!(!key.allows_ip(ip("11.0.0.1")));
+        assert!(!key.allows_ip(ip("2001:db8::2")));
+    }
+
+    #[test]
+    fn test_allows_ip_fails_closed_on_invalid_entries() {
+        let mut key = key(None, None);
+        key.allowed_ips = vec!["not-an-ip".to_string(), "10.0.0.0/33".to_string()];
+
+        assert!(!key.allows_ip("10.0.0.1".parse().unwrap()));
+        assert!(!key.allows_ip("203.0.113.5".parse().unwrap()));
+    }
 }
@@ -0,0 +1,191 @@
+//! Registro delle operazioni amministrative e dei login
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::DbPool;
+
+/// Voce del registro di audit
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// API Key che ha eseguito l'azione (assente per azioni non autenticate)
+    pub actor_key_id: Option<String>,
+    /// Azione eseguita, es. `api_key.create`, `guest_config.update`, `auth.login`
+    pub action: String,
+    /// Oggetto dell'azione (ID della chiave, dell'organizzazione, ...)
+    pub target: Option<String>,
+    /// Valori precedenti dei campi modificati
+    #[schema(value_type = Option<Object>)]
+    pub before: Option<serde_json::Value>,
+    /// Valori nuovi dei campi modificati
+    #[schema(value_type = Option<Object>)]
+    pub after: Option<serde_json::Value>,
+    pub ip: Option<String>,
+    #[schema(value_type = String, format = "date-time")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Dati di una nuova voce di audit
+#[derive(Debug, Default)]
+pub struct NewAuditEntry<'a> {
+    pub actor_key_id: Option<&'a str>,
+    pub action: &'a str,
+    pub target: Option<&'a str>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub ip: Option<&'a str>,
+}
+
+/// Filtri per la lista del registro di audit
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AuditQuery {
+    pub actor_key_id: Option<String>,
+    /// Azione esatta, oppure prefisso con `*` finale (es. `api_key.*`)
+    pub action: Option<String>,
+    pub target: Option<String>,
+    /// Solo voci successive a questa data (RFC 3339 o YYYY-MM-DD)
+    pub since: Option<String>,
+    /// Solo voci precedenti a questa data (RFC 3339, o YYYY-MM-DD inclusa)
+    pub until: Option<String>,
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+    #[serde(default)]
+    pub offset: i64,
+}
+
+fn default_limit() -> i64 {
+    100
+}
+
+/// Response lista audit
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditListResponse {
+    pub entries: Vec<AuditEntry>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Registra una voce di audit
+pub async fn insert_entry(pool: &DbPool, entry: &NewAuditEntry<'_>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (actor_key_id, action, target, before, after, ip, timestamp)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(entry.actor_key_id)
+    .bind(entry.action)
+    .bind(entry.target)
+    .bind(entry.before.as_ref().map(|v| v.to_string()))
+    .bind(entry.after.as_ref().map(|v| v.to_string()))
+    .bind(entry.ip)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Lista le voci di audit, dalla più recente
+#[allow(clippy::type_complexity)]
+pub async fn list_entries(
+    pool: &DbPool,
+    query: &AuditQuery,
+) -> Result<AuditListResponse, sqlx::Error> {
+    let mut conditions = String::new();
+    let mut params: Vec<String> = Vec::new();
+
+    if let Some(actor) = &query.actor_key_id {
+        conditions.push_str(" AND actor_key_id = ?");
+        params.push(actor.clone());
+    }
+    if let Some(action) = &query.action {
+        match action.strip_suffix('*') {
+            Some(prefix) => {
+                conditions.push_str(" AND action LIKE ?");
+                params.push(format!("{}%", prefix));
+            }
+            None => {
+                conditions.push_str(" AND action = ?");
+                params.push(action.clone());
+            }
+        }
+    }
+    if let Some(target) = &query.target {
+        conditions.push_str(" AND target = ?");
+        params.push(target.clone());
+    }
+    if let Some(since) = &query.since {
+        conditions.push_str(" AND timestamp >= ?");
+        params.push(since.clone());
+    }
+    if let Some(until) = &query.until {
+        conditions.push_str(" AND timestamp <= ?");
+        params.push(until.clone());
+    }
+
+    let total: (i64,) = {
+        let sql = format!("SELECT COUNT(*) FROM audit_log WHERE 1=1{}", conditions);
+        let mut q = sqlx::query_as(&sql);
+        for p in &params {
+            q = q.bind(p);
+        }
+        q.fetch_one(pool).await?
+    };
+
+    let rows: Vec<(
+        i64,
+        Option<String>,
+        String,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        String,
+    )> = {
+        let sql = format!(
+            r#"
+            SELECT id, actor_key_id, action, target, before, after, ip, timestamp
+            FROM audit_log WHERE 1=1{}
+            ORDER BY id DESC LIMIT ? OFFSET ?
+            "#,
+            conditions
+        );
+        let mut q = sqlx::query_as(&sql);
+        for p in &params {
+            q = q.bind(p);
+        }
+        q.bind(query.limit)
+            .bind(query.offset)
+            .fetch_all(pool)
+            .await?
+    };
+
+    let parse_json = |v: Option<String>| v.and_then(|s| serde_json::from_str(&s).ok());
+    let entries = rows
+        .into_iter()
+        .map(
+            |(id, actor_key_id, action, target, before, after, ip, timestamp)| AuditEntry {
+                id,
+                actor_key_id,
+                action,
+                target,
+                before: parse_json(before),
+                after: parse_json(after),
+                ip,
+                timestamp: DateTime::parse_from_rfc3339(&timestamp)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now()),
+            },
+        )
+        .collect();
+
+    Ok(AuditListResponse {
+        entries,
+        total: total.0,
+        limit: query.limit,
+        offset: query.offset,
+    })
+}
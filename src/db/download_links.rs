@@ -0,0 +1,55 @@
+//! Registro dei link di download monouso
+
+use chrono::Utc;
+
+use super::DbPool;
+
+/// Registra un link monouso
+pub async fn create_link(
+    pool: &DbPool,
+    token: &str,
+    job_id: &str,
+    api_key_id: Option<&str>,
+    expires_at: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO download_links (token, job_id, api_key_id, expires_at, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(token)
+    .bind(job_id)
+    .bind(api_key_id)
+    .bind(expires_at)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Marca un link monouso come utilizzato.
+/// Ritorna `false` se il link non esiste o è già stato usato.
+pub async fn consume_link(pool: &DbPool, token: &str, job_id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE download_links SET used_at = ?
+        WHERE token = ? AND job_id = ? AND used_at IS NULL
+        "#,
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(token)
+    .bind(job_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Elimina i link scaduti
+pub async fn cleanup_expired_links(pool: &DbPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM download_links WHERE expires_at < ?")
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
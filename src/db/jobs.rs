@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use utoipa::ToSchema;
 
+use super::organizations::MEMBER_KEYS_SQL;
 use super::DbPool;
 
 /// Record job nel database
@@ -50,6 +51,9 @@ pub struct JobsQuery {
     pub status: Option<String>,
     pub conversion_type: Option<String>,
     pub api_key_id: Option<String>,
+    /// Limita ai job delle chiavi di un'organizzazione (impostato dal server)
+    #[serde(skip)]
+    pub organization_id: Option<String>,
     #[serde(default = "default_limit")]
     pub limit: i64,
     #[serde(default)]
@@ -147,6 +151,10 @@ pub async fn list_jobs(pool: &DbPool, query: &JobsQuery) -> Result<JobsListRespo
         count_sql.push_str(" AND api_key_id = ?");
         params.push(api_key.clone());
     }
+    if let Some(org_id) = &query.organization_id {
+        count_sql.push_str(&format!(" AND api_key_id IN ({})", MEMBER_KEYS_SQL));
+        params.push(org_id.clone());
+    }
 
     // Esegui count
     let total: (i64,) = {
@@ -178,6 +186,9 @@ pub async fn list_jobs(pool: &DbPool, query: &JobsQuery) -> Result<JobsListRespo
     if query.api_key_id.is_some() {
         data_sql.push_str(" AND api_key_id = ?");
     }
+    if query.organization_id.is_some() {
+        data_sql.push_str(&format!(" AND api_key_id IN ({})", MEMBER_KEYS_SQL));
+    }
 
     data_sql.push_str(" ORDER BY created_at DESC LIMIT ? OFFSET ?");
 
@@ -272,6 +283,13 @@ pub async fn count_active_jobs(pool: &DbPool) -> Result<i64, sqlx::Error> {
     Ok(row.0)
 }
 
+/// Numero di job per stato
+pub async fn count_jobs_by_status(pool: &DbPool) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    sqlx::query_as("SELECT status, COUNT(*) FROM jobs GROUP BY status ORDER BY status")
+        .fetch_all(pool)
+        .await
+}
+
 /// Conta i job attivi per un utente specifico
 pub async fn count_user_active_jobs(pool: &DbPool, api_key_id: &str) -> Result<i64, sqlx::Error> {
     let row: (i64,) = sqlx::query_as(
@@ -283,13 +301,21 @@ pub async fn count_user_active_jobs(pool: &DbPool, api_key_id: &str) -> Result<i
     Ok(row.0)
 }
 
-/// Ottieni il limite di job concorrenti per un'API key
+/// Ottieni il limite di job concorrenti per un'API key (quello del piano, se definito)
 pub async fn get_user_job_limit(pool: &DbPool, api_key_id: &str) -> Result<i64, sqlx::Error> {
-    let row: (Option<i64>,) =
-        sqlx::query_as("SELECT max_concurrent_jobs FROM api_keys WHERE id = ?")
-            .bind(api_key_id)
-            .fetch_one(pool)
-            .await?;
+    let row: (Option<i64>,) = sqlx::query_as(
+        r#"
+        SELECT COALESCE(
+            (SELECT p.max_concurrent_jobs FROM plans p
+             WHERE p.id = k.plan_id AND p.max_concurrent_jobs > 0),
+            k.max_concurrent_jobs
+        )
+        FROM api_keys k WHERE k.id = ?
+        "#,
+    )
+    .bind(api_key_id)
+    .fetch_one(pool)
+    .await?;
     Ok(row.0.unwrap_or(5))
 }
 
@@ -472,6 +498,108 @@ pub async fn get_job_webhook(pool: &DbPool, id: &str) -> Result<Option<String>,
     Ok(row.and_then(|r| r.0))
 }
 
+/// Salva il checksum SHA-256 del risultato di un job
+pub async fn set_job_result_checksum(
+    pool: &DbPool,
+    id: &str,
+    checksum: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE jobs SET result_checksum = ? WHERE id = ?")
+        .bind(checksum)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Associa un job guest all'hash del token di sessione
+pub async fn set_job_guest_token(
+    pool: &DbPool,
+    id: &str,
+    token_hash: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE jobs SET guest_token_hash = ? WHERE id = ?")
+        .bind(token_hash)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Ottieni l'hash del token di sessione guest di un job
+pub async fn get_job_guest_token(pool: &DbPool, id: &str) -> Result<Option<String>, sqlx::Error> {
+    let row: Option<(Option<String>,)> =
+        sqlx::query_as("SELECT guest_token_hash FROM jobs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(row.and_then(|r| r.0))
+}
+
+/// Salva l'ID della richiesta che ha creato un job
+pub async fn set_job_request_id(
+    pool: &DbPool,
+    id: &str,
+    request_id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE jobs SET request_id = ? WHERE id = ?")
+        .bind(request_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Ottieni l'ID della richiesta che ha creato un job
+pub async fn get_job_request_id(pool: &DbPool, id: &str) -> Result<Option<String>, sqlx::Error> {
+    let row: Option<(Option<String>,)> = sqlx::query_as("SELECT request_id FROM jobs WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.and_then(|r| r.0))
+}
+
+/// Salva la durata audio/video dell'input di un job
+pub async fn set_job_media_duration(
+    pool: &DbPool,
+    id: &str,
+    media_duration_ms: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE jobs SET media_duration_ms = ? WHERE id = ?")
+        .bind(media_duration_ms)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Ottieni la durata audio/video dell'input di un job (0 se non nota)
+pub async fn get_job_media_duration(pool: &DbPool, id: &str) -> Result<i64, sqlx::Error> {
+    let row: Option<(Option<i64>,)> =
+        sqlx::query_as("SELECT media_duration_ms FROM jobs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(row.and_then(|r| r.0).unwrap_or(0))
+}
+
+/// Ottieni il checksum SHA-256 del risultato di un job
+pub async fn get_job_result_checksum(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row: Option<(Option<String>,)> =
+        sqlx::query_as("SELECT result_checksum FROM jobs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(row.and_then(|r| r.0))
+}
+
 /// Conta i retry di un job
 pub async fn get_job_retry_count(pool: &DbPool, id: &str) -> Result<i64, sqlx::Error> {
     let row: Option<(Option<i64>,)> = sqlx::query_as("SELECT retry_count FROM jobs WHERE id = ?")
@@ -1,24 +1,37 @@
 pub mod api_keys;
+pub mod audit;
+pub mod download_links;
 pub mod jobs;
 #[cfg(feature = "google-auth")]
 pub mod oauth_users;
+pub mod organizations;
+pub mod outbound;
+pub mod plans;
+pub mod sessions;
 pub mod stats;
+pub mod timeseries;
+pub mod uploads;
 #[cfg(feature = "google-auth")]
 pub mod user_settings;
 
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use std::str::FromStr;
 use std::time::Duration;
 
 pub type DbPool = SqlitePool;
 
 /// Inizializza il database SQLite
 pub async fn init_db(database_url: &str) -> Result<DbPool, sqlx::Error> {
+    // WAL: le letture lunghe (es. export) non bloccano le scritture
+    let options =
+        SqliteConnectOptions::from_str(database_url)?.journal_mode(SqliteJournalMode::Wal);
+
     // Crea il pool di connessioni
     let pool = SqlitePoolOptions::new()
         .max_connections(20)
         .idle_timeout(Duration::from_secs(60))
         .acquire_timeout(Duration::from_secs(5))
-        .connect(database_url)
+        .connect_with(options)
         .await?;
 
     // Esegui le migrazioni
@@ -261,12 +274,75 @@ async fn run_migrations(pool: &DbPool) -> Result<(), sqlx::Error> {
         .execute(pool)
         .await?;
 
-    // Aggiungi colonna key_plaintext per utenti non-admin (ignora errore se esiste)
-    // Admin keys restano hashate, user keys salvate in chiaro per poterle recuperare
-    let _ = sqlx::query(r#"ALTER TABLE api_keys ADD COLUMN key_plaintext TEXT"#)
+    // Provider di identità degli utenti OAuth (Google o OIDC generico)
+    let _ = sqlx::query(
+        r#"ALTER TABLE oauth_users ADD COLUMN provider TEXT NOT NULL DEFAULT 'google'"#,
+    )
+    .execute(pool)
+    .await;
+
+    // Le API Key non sono più salvate in chiaro: svuota e rimuovi la vecchia
+    // colonna key_plaintext (l'UPDATE copre SQLite senza DROP COLUMN)
+    let _ = sqlx::query(r#"UPDATE api_keys SET key_plaintext = NULL"#)
+        .execute(pool)
+        .await;
+    let _ = sqlx::query(r#"ALTER TABLE api_keys DROP COLUMN key_plaintext"#)
+        .execute(pool)
+        .await;
+
+    // Limite upload per API Key (NULL = limite globale da Config)
+    let _ = sqlx::query(r#"ALTER TABLE api_keys ADD COLUMN max_file_size_mb INTEGER"#)
         .execute(pool)
         .await;
 
+    // Quote mensili per API Key (NULL = nessun limite)
+    for column in [
+        "monthly_limit",
+        "monthly_volume_mb",
+        "monthly_media_minutes",
+    ] {
+        let _ = sqlx::query(&format!(
+            "ALTER TABLE api_keys ADD COLUMN {} INTEGER",
+            column
+        ))
+        .execute(pool)
+        .await;
+    }
+
+    // Scope granulari per API Key (JSON, NULL = nessuna restrizione)
+    let _ = sqlx::query(r#"ALTER TABLE api_keys ADD COLUMN scopes TEXT"#)
+        .execute(pool)
+        .await;
+
+    // Scadenza delle API Key e segreto precedente valido durante la rotazione
+    for column in ["expires_at", "previous_key_hash", "previous_key_expires_at"] {
+        let _ = sqlx::query(&format!("ALTER TABLE api_keys ADD COLUMN {} TEXT", column))
+            .execute(pool)
+            .await;
+    }
+    sqlx::query(
+        r#"CREATE INDEX IF NOT EXISTS idx_api_keys_previous_hash ON api_keys(previous_key_hash)"#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Reti da cui l'API Key è accettata e tentativi rifiutati da altri IP
+    let _ = sqlx::query(r#"ALTER TABLE api_keys ADD COLUMN allowed_ips TEXT"#)
+        .execute(pool)
+        .await;
+    let _ = sqlx::query(
+        r#"ALTER TABLE api_keys ADD COLUMN rejected_ip_attempts INTEGER NOT NULL DEFAULT 0"#,
+    )
+    .execute(pool)
+    .await;
+
+    // Durata dell'input audio/video, per il budget di minuti media
+    let _ = sqlx::query(
+        r#"ALTER TABLE conversion_records ADD COLUMN media_duration_ms INTEGER NOT NULL DEFAULT 0"#,
+    )
+    .execute(pool)
+    .await;
+
     // Aggiungi original_filename alla tabella jobs
     let _ = sqlx::query(r#"ALTER TABLE jobs ADD COLUMN original_filename TEXT"#)
         .execute(pool)
@@ -308,6 +384,11 @@ async fn run_migrations(pool: &DbPool) -> Result<(), sqlx::Error> {
         .execute(pool)
         .await;
 
+    // Token di sessione dei job creati da guest (hash SHA-256)
+    let _ = sqlx::query(r#"ALTER TABLE jobs ADD COLUMN guest_token_hash TEXT"#)
+        .execute(pool)
+        .await;
+
     // Aggiungi drive_filter_types a user_settings per filtrare quali tipi salvare su Drive
     // Valori: "all" o lista separata da virgole es. "image,audio,video,document"
     let _ = sqlx::query(
@@ -316,5 +397,201 @@ async fn run_migrations(pool: &DbPool) -> Result<(), sqlx::Error> {
     .execute(pool)
     .await;
 
+    // Tabella configurazione destinazioni HTTP in uscita (protezione SSRF)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS outbound_config (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            allowed_hosts TEXT NOT NULL DEFAULT '',
+            denied_hosts TEXT NOT NULL DEFAULT '',
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO outbound_config (id, allowed_hosts, denied_hosts, updated_at)
+        VALUES (1, '', '', datetime('now'))
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Checksum SHA-256 del risultato (usato come ETag nei download)
+    let _ = sqlx::query(r#"ALTER TABLE jobs ADD COLUMN result_checksum TEXT"#)
+        .execute(pool)
+        .await;
+
+    // Link di download monouso (il link firmato contiene il token)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS download_links (
+            token TEXT PRIMARY KEY,
+            job_id TEXT NOT NULL,
+            api_key_id TEXT,
+            expires_at TEXT NOT NULL,
+            used_at TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (job_id) REFERENCES jobs(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_download_links_job ON download_links(job_id)"#)
+        .execute(pool)
+        .await?;
+
+    // Organizzazioni con quote condivise tra le API Key membri
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS organizations (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            daily_limit INTEGER,
+            monthly_limit INTEGER,
+            monthly_volume_mb INTEGER,
+            monthly_media_minutes INTEGER,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Ogni API Key appartiene al massimo a un'organizzazione
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS organization_members (
+            organization_id TEXT NOT NULL,
+            api_key_id TEXT NOT NULL UNIQUE,
+            role TEXT NOT NULL DEFAULT 'member',
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (organization_id) REFERENCES organizations(id) ON DELETE CASCADE,
+            FOREIGN KEY (api_key_id) REFERENCES api_keys(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"CREATE INDEX IF NOT EXISTS idx_organization_members_org ON organization_members(organization_id)"#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Token di sessione a breve scadenza (solo hash), legati a un'API Key
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sessions (
+            token_hash TEXT PRIMARY KEY,
+            api_key_id TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            FOREIGN KEY (api_key_id) REFERENCES api_keys(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Registro di audit delle operazioni admin e dei login (before/after in JSON)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            actor_key_id TEXT,
+            action TEXT NOT NULL,
+            target TEXT,
+            before TEXT,
+            after TEXT,
+            ip TEXT,
+            timestamp TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp ON audit_log(timestamp)"#)
+        .execute(pool)
+        .await?;
+
+    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log(actor_key_id)"#)
+        .execute(pool)
+        .await?;
+
+    // Upload riprendibili (tus): il contenuto è su disco, qui solo lo stato
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS uploads (
+            id TEXT PRIMARY KEY,
+            api_key_id TEXT,
+            upload_length INTEGER NOT NULL,
+            upload_offset INTEGER NOT NULL DEFAULT 0,
+            metadata TEXT,
+            filename TEXT,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Token di sessione degli upload creati da guest (hash SHA-256)
+    let _ = sqlx::query(r#"ALTER TABLE uploads ADD COLUMN guest_token_hash TEXT"#)
+        .execute(pool)
+        .await;
+
+    // Piani: limiti assegnabili ad API Key e utenti OAuth
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS plans (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            description TEXT,
+            rate_limit INTEGER,
+            daily_limit INTEGER,
+            max_file_size_mb INTEGER,
+            allowed_types TEXT,
+            max_concurrent_jobs INTEGER,
+            retention_hours INTEGER,
+            max_priority TEXT NOT NULL DEFAULT 'high',
+            is_default INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    let _ = sqlx::query(r#"ALTER TABLE api_keys ADD COLUMN plan_id TEXT"#)
+        .execute(pool)
+        .await;
+    let _ = sqlx::query(r#"ALTER TABLE oauth_users ADD COLUMN plan_id TEXT"#)
+        .execute(pool)
+        .await;
+
+    // Statistiche pre-aggregate per ora e per giorno
+    timeseries::migrate(pool).await?;
+
+    // X-Request-ID della richiesta che ha creato il job
+    let _ = sqlx::query(r#"ALTER TABLE jobs ADD COLUMN request_id TEXT"#)
+        .execute(pool)
+        .await;
+
+    // Durata audio/video dell'input, letta alla creazione del job (quote)
+    let _ = sqlx::query(r#"ALTER TABLE jobs ADD COLUMN media_duration_ms INTEGER"#)
+        .execute(pool)
+        .await;
+
     Ok(())
 }
@@ -3,13 +3,20 @@ use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use super::api_keys::{self, ApiKeyCreated, CreateApiKeyRequest};
+use super::plans;
+use super::sessions::{self, SESSION_TTL_HOURS};
 use super::DbPool;
+use crate::models::KeyScopes;
 
 /// OAuth User nel database
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct OAuthUser {
     pub id: String,
+    /// Identificativo presso il provider (per i provider diversi da Google
+    /// è prefissato con il nome del provider, es. `oidc:<sub>`)
     pub google_id: String,
+    /// Provider di identità: `google` oppure `oidc`
+    pub provider: String,
     pub email: String,
     pub name: Option<String>,
     pub picture_url: Option<String>,
@@ -22,36 +29,71 @@ pub struct OAuthUser {
     pub last_login_at: DateTime<Utc>,
 }
 
-/// Info utente da Google
+/// Provider Google OAuth
+pub const PROVIDER_GOOGLE: &str = "google";
+/// Provider OpenID Connect generico
+pub const PROVIDER_OIDC: &str = "oidc";
+
+/// Info utente dal provider di identità
 #[derive(Debug, Clone, Deserialize)]
-pub struct GoogleUserInfo {
-    pub google_id: String,
+pub struct ProviderUserInfo {
+    /// `PROVIDER_GOOGLE` o `PROVIDER_OIDC`
+    pub provider: String,
+    /// Subject (`sub`) presso il provider
+    pub subject: String,
     pub email: String,
     pub name: Option<String>,
     pub picture_url: Option<String>,
 }
 
+impl ProviderUserInfo {
+    /// Chiave salvata in `oauth_users.google_id`
+    ///
+    /// Gli utenti Google mantengono il solo `sub`, gli altri provider lo
+    /// prefissano per non collidere con gli ID Google.
+    pub fn external_id(&self) -> String {
+        if self.provider == PROVIDER_GOOGLE {
+            self.subject.clone()
+        } else {
+            format!("{}:{}", self.provider, self.subject)
+        }
+    }
+
+    /// Etichetta del provider nel nome della API Key generata
+    fn provider_label(&self) -> &str {
+        match self.provider.as_str() {
+            PROVIDER_GOOGLE => "Google",
+            PROVIDER_OIDC => "OIDC",
+            other => other,
+        }
+    }
+}
+
 /// Risultato login/registrazione
 #[derive(Debug, Serialize, ToSchema)]
 pub struct OAuthLoginResult {
     pub user: OAuthUser,
-    /// API key in chiaro - solo per nuovi utenti!
+    /// API key in chiaro - solo quando viene appena creata!
     pub api_key: Option<String>,
     pub api_key_prefix: String,
     pub is_new_user: bool,
+    /// Token di sessione a breve scadenza per il frontend
+    pub session_token: String,
+    #[schema(value_type = String, format = "date-time")]
+    pub session_expires_at: DateTime<Utc>,
 }
 
-/// Trova utente OAuth per Google ID
+/// Trova utente OAuth per ID esterno (vedi `ProviderUserInfo::external_id`)
 #[allow(clippy::type_complexity)]
 pub async fn find_by_google_id(
     pool: &DbPool,
     google_id: &str,
 ) -> Result<Option<OAuthUser>, sqlx::Error> {
     let row: Option<(
-        String, String, String, Option<String>, Option<String>, String, String, String, String
+        String, String, String, String, Option<String>, Option<String>, String, String, String, String
     )> = sqlx::query_as(
         r#"
-        SELECT id, google_id, email, name, picture_url, api_key_id, created_at, updated_at, last_login_at
+        SELECT id, google_id, provider, email, name, picture_url, api_key_id, created_at, updated_at, last_login_at
         FROM oauth_users
         WHERE google_id = ?
         "#,
@@ -64,6 +106,7 @@ pub async fn find_by_google_id(
         Some((
             id,
             google_id,
+            provider,
             email,
             name,
             picture_url,
@@ -74,6 +117,7 @@ pub async fn find_by_google_id(
         )) => Ok(Some(OAuthUser {
             id,
             google_id,
+            provider,
             email,
             name,
             picture_url,
@@ -99,10 +143,10 @@ pub async fn find_by_api_key_id(
     api_key_id: &str,
 ) -> Result<Option<OAuthUser>, sqlx::Error> {
     let row: Option<(
-        String, String, String, Option<String>, Option<String>, String, String, String, String
+        String, String, String, String, Option<String>, Option<String>, String, String, String, String
     )> = sqlx::query_as(
         r#"
-        SELECT id, google_id, email, name, picture_url, api_key_id, created_at, updated_at, last_login_at
+        SELECT id, google_id, provider, email, name, picture_url, api_key_id, created_at, updated_at, last_login_at
         FROM oauth_users
         WHERE api_key_id = ?
         "#,
@@ -115,6 +159,7 @@ pub async fn find_by_api_key_id(
         Some((
             id,
             google_id,
+            provider,
             email,
             name,
             picture_url,
@@ -125,6 +170,7 @@ pub async fn find_by_api_key_id(
         )) => Ok(Some(OAuthUser {
             id,
             google_id,
+            provider,
             email,
             name,
             picture_url,
@@ -146,17 +192,29 @@ pub async fn find_by_api_key_id(
 /// Crea nuovo utente OAuth con API Key associata
 pub async fn create_oauth_user(
     pool: &DbPool,
-    user_info: &GoogleUserInfo,
+    user_info: &ProviderUserInfo,
 ) -> Result<(OAuthUser, ApiKeyCreated), sqlx::Error> {
+    // I nuovi utenti ricevono il piano di default, se configurato
+    let plan_id = plans::default_plan(pool).await?.map(|plan| plan.id);
+
     // Crea API key per l'utente
     let api_key_request = CreateApiKeyRequest {
-        name: format!("Google: {}", user_info.email),
+        name: format!("{}: {}", user_info.provider_label(), user_info.email),
         role: "user".to_string(),
         rate_limit: 100,
         daily_limit: Some(500),
+        max_file_size_mb: None,
+        monthly_limit: None,
+        monthly_volume_mb: None,
+        monthly_media_minutes: None,
+        scopes: KeyScopes::default(),
+        allowed_ips: Vec::new(),
+        plan_id: plan_id.clone(),
+        expires_in_days: None,
         notes: Some(format!(
-            "Auto-generated for Google user: {}",
-            user_info.google_id
+            "Auto-generated for {} user: {}",
+            user_info.provider_label(),
+            user_info.subject
         )),
     };
 
@@ -164,20 +222,23 @@ pub async fn create_oauth_user(
 
     // Crea utente OAuth
     let id = uuid::Uuid::new_v4().to_string();
+    let external_id = user_info.external_id();
     let now = Utc::now();
 
     sqlx::query(
         r#"
-        INSERT INTO oauth_users (id, google_id, email, name, picture_url, api_key_id, created_at, updated_at, last_login_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO oauth_users (id, google_id, provider, email, name, picture_url, api_key_id, plan_id, created_at, updated_at, last_login_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
     )
     .bind(&id)
-    .bind(&user_info.google_id)
+    .bind(&external_id)
+    .bind(&user_info.provider)
     .bind(&user_info.email)
     .bind(&user_info.name)
     .bind(&user_info.picture_url)
     .bind(&api_key.id)
+    .bind(&plan_id)
     .bind(now.to_rfc3339())
     .bind(now.to_rfc3339())
     .bind(now.to_rfc3339())
@@ -186,7 +247,8 @@ pub async fn create_oauth_user(
 
     let oauth_user = OAuthUser {
         id,
-        google_id: user_info.google_id.clone(),
+        google_id: external_id,
+        provider: user_info.provider.clone(),
         email: user_info.email.clone(),
         name: user_info.name.clone(),
         picture_url: user_info.picture_url.clone(),
@@ -215,11 +277,11 @@ pub async fn update_last_login(pool: &DbPool, id: &str) -> Result<(), sqlx::Erro
     Ok(())
 }
 
-/// Aggiorna info utente da Google (email, nome, foto potrebbero cambiare)
+/// Aggiorna info utente dal provider (email, nome, foto potrebbero cambiare)
 pub async fn update_user_info(
     pool: &DbPool,
     id: &str,
-    user_info: &GoogleUserInfo,
+    user_info: &ProviderUserInfo,
 ) -> Result<(), sqlx::Error> {
     let now = Utc::now();
     sqlx::query(
@@ -253,20 +315,37 @@ pub async fn get_api_key_prefix(
     Ok(row.map(|(prefix,)| prefix))
 }
 
-/// Crea nuova API key per utente esistente (quando la vecchia non ha plaintext)
+/// Crea nuova API key per utente esistente (quando la sua è stata eliminata)
 async fn create_new_api_key_for_user(
     pool: &DbPool,
     user_id: &str,
-    user_info: &GoogleUserInfo,
+    user_info: &ProviderUserInfo,
 ) -> Result<ApiKeyCreated, sqlx::Error> {
+    // La nuova chiave eredita il piano dell'utente
+    let plan_id: Option<String> =
+        sqlx::query_scalar("SELECT plan_id FROM oauth_users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?
+            .flatten();
+
     let api_key_request = CreateApiKeyRequest {
-        name: format!("Google: {}", user_info.email),
+        name: format!("{}: {}", user_info.provider_label(), user_info.email),
         role: "user".to_string(),
         rate_limit: 100,
         daily_limit: Some(500),
+        max_file_size_mb: None,
+        monthly_limit: None,
+        monthly_volume_mb: None,
+        monthly_media_minutes: None,
+        scopes: KeyScopes::default(),
+        allowed_ips: Vec::new(),
+        plan_id: plan_id.clone(),
+        expires_in_days: None,
         notes: Some(format!(
-            "Auto-generated for Google user: {}",
-            user_info.google_id
+            "Auto-generated for {} user: {}",
+            user_info.provider_label(),
+            user_info.subject
         )),
     };
 
@@ -284,53 +363,54 @@ async fn create_new_api_key_for_user(
     Ok(api_key)
 }
 
-/// Login o registrazione con Google
+/// Login o registrazione tramite un provider di identità
+///
+/// La chiave in chiaro viene restituita solo quando è appena stata creata;
+/// agli utenti esistenti viene emesso soltanto un token di sessione.
 pub async fn login_or_register(
     pool: &DbPool,
-    user_info: GoogleUserInfo,
+    user_info: ProviderUserInfo,
 ) -> Result<OAuthLoginResult, sqlx::Error> {
-    // Cerca utente esistente
-    if let Some(mut existing_user) = find_by_google_id(pool, &user_info.google_id).await? {
-        // Aggiorna info e ultimo login
-        update_user_info(pool, &existing_user.id, &user_info).await?;
-
-        // Recupera la chiave in chiaro dal DB
-        let existing_key = api_keys::get_plaintext_key(pool, &existing_user.api_key_id).await?;
-
-        if let Some(api_key) = existing_key {
-            // Chiave trovata con plaintext, la usiamo
-            let prefix = get_api_key_prefix(pool, &existing_user.api_key_id)
-                .await?
-                .unwrap_or_else(|| "cv_...".to_string());
-
-            return Ok(OAuthLoginResult {
-                user: existing_user,
-                api_key: Some(api_key),
-                api_key_prefix: prefix,
-                is_new_user: false,
-            });
-        }
-
-        // Chiave vecchia senza plaintext: creiamo una nuova API key
-        let new_api_key = create_new_api_key_for_user(pool, &existing_user.id, &user_info).await?;
-        existing_user.api_key_id = new_api_key.id.clone();
-
-        return Ok(OAuthLoginResult {
-            user: existing_user,
-            api_key: Some(new_api_key.api_key),
-            api_key_prefix: new_api_key.key_prefix,
-            is_new_user: false,
-        });
-    }
-
-    // Nuovo utente: crea account e API key
-    let (new_user, api_key) = create_oauth_user(pool, &user_info).await?;
+    // Cerca utente esistente, altrimenti crea account e API key
+    let (user, api_key, api_key_prefix, is_new_user) =
+        if let Some(mut existing_user) = find_by_google_id(pool, &user_info.external_id()).await? {
+            // Aggiorna info e ultimo login
+            update_user_info(pool, &existing_user.id, &user_info).await?;
+
+            match api_keys::find_by_id(pool, &existing_user.api_key_id).await? {
+                Some(key) => (existing_user, None, key.key_prefix, false),
+                None => {
+                    // API key eliminata: ne creiamo una nuova
+                    let new_api_key =
+                        create_new_api_key_for_user(pool, &existing_user.id, &user_info).await?;
+                    existing_user.api_key_id = new_api_key.id.clone();
+                    (
+                        existing_user,
+                        Some(new_api_key.api_key),
+                        new_api_key.key_prefix,
+                        false,
+                    )
+                }
+            }
+        } else {
+            let (new_user, api_key) = create_oauth_user(pool, &user_info).await?;
+            (new_user, Some(api_key.api_key), api_key.key_prefix, true)
+        };
+
+    let session = sessions::create_session(
+        pool,
+        &user.api_key_id,
+        chrono::Duration::hours(SESSION_TTL_HOURS),
+    )
+    .await?;
 
     Ok(OAuthLoginResult {
-        user: new_user,
-        api_key: Some(api_key.api_key),
-        api_key_prefix: api_key.key_prefix,
-        is_new_user: true,
+        user,
+        api_key,
+        api_key_prefix,
+        is_new_user,
+        session_token: session.token,
+        session_expires_at: session.expires_at,
     })
 }
 
@@ -0,0 +1,406 @@
+//! Organizzazioni: gruppi di API Key con quote condivise e job visibili ai membri
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use super::DbPool;
+
+/// Sottoquery con le API Key membri di un'organizzazione (parametro: ID organizzazione)
+pub const MEMBER_KEYS_SQL: &str =
+    "SELECT api_key_id FROM organization_members WHERE organization_id = ?";
+
+/// Ruolo di un'API Key all'interno dell'organizzazione
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    /// Gestisce i membri dell'organizzazione
+    Owner,
+    /// Crea e gestisce i job dell'organizzazione
+    Member,
+    /// Solo lettura di job, cronologia e statistiche
+    Viewer,
+}
+
+impl std::fmt::Display for OrgRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrgRole::Owner => write!(f, "owner"),
+            OrgRole::Member => write!(f, "member"),
+            OrgRole::Viewer => write!(f, "viewer"),
+        }
+    }
+}
+
+impl From<&str> for OrgRole {
+    fn from(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "owner" => OrgRole::Owner,
+            "viewer" => OrgRole::Viewer,
+            _ => OrgRole::Member,
+        }
+    }
+}
+
+/// Organizzazione nel database
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    /// Conversioni giornaliere condivise tra i membri
+    pub daily_limit: Option<i64>,
+    /// Conversioni mensili condivise tra i membri
+    pub monthly_limit: Option<i64>,
+    /// Volume mensile di input in MB condiviso tra i membri
+    pub monthly_volume_mb: Option<i64>,
+    /// Minuti di audio/video al mese condivisi tra i membri
+    pub monthly_media_minutes: Option<i64>,
+    #[schema(value_type = String, format = "date-time")]
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = String, format = "date-time")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Membro di un'organizzazione
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OrganizationMember {
+    pub api_key_id: String,
+    pub key_name: String,
+    pub key_prefix: String,
+    pub role: OrgRole,
+    #[schema(value_type = String, format = "date-time")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request per creare un'organizzazione
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+    #[serde(default)]
+    pub daily_limit: Option<i64>,
+    #[serde(default)]
+    pub monthly_limit: Option<i64>,
+    #[serde(default)]
+    pub monthly_volume_mb: Option<i64>,
+    #[serde(default)]
+    pub monthly_media_minutes: Option<i64>,
+}
+
+/// Request per aggiornare un'organizzazione (limiti a 0 = nessun limite)
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateOrganizationRequest {
+    pub name: Option<String>,
+    pub daily_limit: Option<i64>,
+    pub monthly_limit: Option<i64>,
+    pub monthly_volume_mb: Option<i64>,
+    pub monthly_media_minutes: Option<i64>,
+}
+
+/// Request per aggiungere (o spostare) un'API Key in un'organizzazione
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddMemberRequest {
+    pub api_key_id: String,
+    /// Ruolo: owner, member (default) o viewer
+    #[serde(default = "default_member_role")]
+    pub role: OrgRole,
+}
+
+/// Request per cambiare il ruolo di un membro
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateMemberRequest {
+    pub role: OrgRole,
+}
+
+fn default_member_role() -> OrgRole {
+    OrgRole::Member
+}
+
+const ORGANIZATION_COLUMNS: &str = "id, name, daily_limit, monthly_limit, monthly_volume_mb, \
+     monthly_media_minutes, created_at, updated_at";
+
+#[derive(FromRow)]
+struct OrganizationRow {
+    id: String,
+    name: String,
+    daily_limit: Option<i64>,
+    monthly_limit: Option<i64>,
+    monthly_volume_mb: Option<i64>,
+    monthly_media_minutes: Option<i64>,
+    created_at: String,
+    updated_at: String,
+}
+
+fn parse_timestamp(value: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now())
+}
+
+impl From<OrganizationRow> for Organization {
+    fn from(row: OrganizationRow) -> Self {
+        Organization {
+            id: row.id,
+            name: row.name,
+            daily_limit: row.daily_limit,
+            monthly_limit: row.monthly_limit,
+            monthly_volume_mb: row.monthly_volume_mb,
+            monthly_media_minutes: row.monthly_media_minutes,
+            created_at: parse_timestamp(&row.created_at),
+            updated_at: parse_timestamp(&row.updated_at),
+        }
+    }
+}
+
+/// Crea una nuova organizzazione
+pub async fn create_organization(
+    pool: &DbPool,
+    request: &CreateOrganizationRequest,
+) -> Result<Organization, sqlx::Error> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"
+        INSERT INTO organizations (id, name, daily_limit, monthly_limit, monthly_volume_mb, monthly_media_minutes, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(&request.name)
+    .bind(request.daily_limit)
+    .bind(request.monthly_limit)
+    .bind(request.monthly_volume_mb)
+    .bind(request.monthly_media_minutes)
+    .bind(now.to_rfc3339())
+    .bind(now.to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(Organization {
+        id,
+        name: request.name.clone(),
+        daily_limit: request.daily_limit,
+        monthly_limit: request.monthly_limit,
+        monthly_volume_mb: request.monthly_volume_mb,
+        monthly_media_minutes: request.monthly_media_minutes,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+/// Trova un'organizzazione per ID
+pub async fn get_organization(
+    pool: &DbPool,
+    id: &str,
+) -> Result<Option<Organization>, sqlx::Error> {
+    let row: Option<OrganizationRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM organizations WHERE id = ?",
+        ORGANIZATION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(Organization::from))
+}
+
+/// Lista tutte le organizzazioni
+pub async fn list_organizations(pool: &DbPool) -> Result<Vec<Organization>, sqlx::Error> {
+    let rows: Vec<OrganizationRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM organizations ORDER BY name",
+        ORGANIZATION_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(Organization::from).collect())
+}
+
+/// Aggiorna un'organizzazione
+pub async fn update_organization(
+    pool: &DbPool,
+    id: &str,
+    request: &UpdateOrganizationRequest,
+) -> Result<bool, sqlx::Error> {
+    let mut updates = Vec::new();
+    let mut values: Vec<String> = Vec::new();
+
+    if let Some(ref name) = request.name {
+        updates.push("name = ?");
+        values.push(name.clone());
+    }
+    let limits = [
+        ("daily_limit = ?", request.daily_limit),
+        ("monthly_limit = ?", request.monthly_limit),
+        ("monthly_volume_mb = ?", request.monthly_volume_mb),
+        ("monthly_media_minutes = ?", request.monthly_media_minutes),
+    ];
+    for (column, value) in limits {
+        if let Some(value) = value {
+            updates.push(column);
+            values.push(value.to_string());
+        }
+    }
+
+    if updates.is_empty() {
+        return Ok(false);
+    }
+
+    updates.push("updated_at = ?");
+    values.push(Utc::now().to_rfc3339());
+
+    let query = format!(
+        "UPDATE organizations SET {} WHERE id = ?",
+        updates.join(", ")
+    );
+    let mut q = sqlx::query(&query);
+    for value in &values {
+        q = q.bind(value);
+    }
+    let result = q.bind(id).execute(pool).await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Elimina un'organizzazione (le API Key membri restano, senza organizzazione)
+pub async fn delete_organization(pool: &DbPool, id: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query("DELETE FROM organization_members WHERE organization_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    let result = sqlx::query("DELETE FROM organizations WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Aggiunge un'API Key all'organizzazione, o ne aggiorna il ruolo
+///
+/// Un'API Key appartiene al massimo a un'organizzazione: se era membro di
+/// un'altra viene spostata.
+pub async fn add_member(
+    pool: &DbPool,
+    organization_id: &str,
+    api_key_id: &str,
+    role: OrgRole,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO organization_members (organization_id, api_key_id, role, created_at)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT(api_key_id) DO UPDATE SET
+            organization_id = excluded.organization_id,
+            role = excluded.role
+        "#,
+    )
+    .bind(organization_id)
+    .bind(api_key_id)
+    .bind(role.to_string())
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Cambia il ruolo di un membro esistente
+pub async fn update_member_role(
+    pool: &DbPool,
+    organization_id: &str,
+    api_key_id: &str,
+    role: OrgRole,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE organization_members SET role = ? WHERE organization_id = ? AND api_key_id = ?",
+    )
+    .bind(role.to_string())
+    .bind(organization_id)
+    .bind(api_key_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Rimuove un'API Key dall'organizzazione
+pub async fn remove_member(
+    pool: &DbPool,
+    organization_id: &str,
+    api_key_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM organization_members WHERE organization_id = ? AND api_key_id = ?",
+    )
+    .bind(organization_id)
+    .bind(api_key_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Lista i membri di un'organizzazione
+pub async fn list_members(
+    pool: &DbPool,
+    organization_id: &str,
+) -> Result<Vec<OrganizationMember>, sqlx::Error> {
+    let rows: Vec<(String, String, String, String, String)> = sqlx::query_as(
+        r#"
+        SELECT m.api_key_id, k.name, k.key_prefix, m.role, m.created_at
+        FROM organization_members m
+        JOIN api_keys k ON k.id = m.api_key_id
+        WHERE m.organization_id = ?
+        ORDER BY m.created_at
+        "#,
+    )
+    .bind(organization_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(api_key_id, key_name, key_prefix, role, created_at)| OrganizationMember {
+                api_key_id,
+                key_name,
+                key_prefix,
+                role: OrgRole::from(role.as_str()),
+                created_at: parse_timestamp(&created_at),
+            },
+        )
+        .collect())
+}
+
+/// Organizzazione e ruolo di un'API Key, se è membro di un'organizzazione
+pub async fn find_membership(
+    pool: &DbPool,
+    api_key_id: &str,
+) -> Result<Option<(Organization, OrgRole)>, sqlx::Error> {
+    let row: Option<(String, String)> = sqlx::query_as(
+        "SELECT organization_id, role FROM organization_members WHERE api_key_id = ?",
+    )
+    .bind(api_key_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some((organization_id, role)) = row else {
+        return Ok(None);
+    };
+    Ok(get_organization(pool, &organization_id)
+        .await?
+        .map(|org| (org, OrgRole::from(role.as_str()))))
+}
+
+/// Verifica se un'API Key è membro di un'organizzazione
+pub async fn is_member(
+    pool: &DbPool,
+    organization_id: &str,
+    api_key_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let count: (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM organization_members WHERE organization_id = ? AND api_key_id = ?",
+    )
+    .bind(organization_id)
+    .bind(api_key_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(count.0 > 0)
+}
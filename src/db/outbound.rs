@@ -0,0 +1,68 @@
+//! Configurazione delle destinazioni HTTP in uscita (source_url, webhook_url)
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::DbPool;
+
+/// Liste allow/deny per le richieste HTTP in uscita
+///
+/// Ogni voce può essere un hostname (`example.com`), un wildcard di dominio
+/// (`*.example.com`) oppure un indirizzo/CIDR (`10.0.0.0/8`, `192.168.1.10`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct OutboundConfig {
+    /// Se non vuota, solo queste destinazioni sono raggiungibili.
+    /// Un CIDR privato in questa lista sblocca esplicitamente quella rete.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Destinazioni sempre bloccate
+    #[serde(default)]
+    pub denied_hosts: Vec<String>,
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Ottiene la configurazione outbound
+pub async fn get_outbound_config(pool: &DbPool) -> Result<OutboundConfig, sqlx::Error> {
+    let row: Option<(String, String)> =
+        sqlx::query_as("SELECT allowed_hosts, denied_hosts FROM outbound_config WHERE id = 1")
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(match row {
+        Some((allowed, denied)) => OutboundConfig {
+            allowed_hosts: split_list(&allowed),
+            denied_hosts: split_list(&denied),
+        },
+        None => OutboundConfig::default(),
+    })
+}
+
+/// Aggiorna la configurazione outbound
+pub async fn update_outbound_config(
+    pool: &DbPool,
+    config: &OutboundConfig,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE outbound_config SET
+            allowed_hosts = ?,
+            denied_hosts = ?,
+            updated_at = ?
+        WHERE id = 1
+        "#,
+    )
+    .bind(config.allowed_hosts.join(","))
+    .bind(config.denied_hosts.join(","))
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
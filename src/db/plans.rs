@@ -0,0 +1,387 @@
+//! Piani: insiemi di limiti (entitlement) assegnabili ad API Key e utenti OAuth
+//!
+//! I valori definiti dal piano prevalgono su quelli della singola chiave; i
+//! campi non impostati (o <= 0) ricadono sui valori della chiave o sui
+//! default globali.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use super::DbPool;
+use crate::models::JobPriority;
+
+/// Piano nel database
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Plan {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    /// Richieste al minuto
+    pub rate_limit: Option<i64>,
+    /// Conversioni giornaliere
+    pub daily_limit: Option<i64>,
+    /// Dimensione massima upload in MB
+    pub max_file_size_mb: Option<i64>,
+    /// Tipi di conversione consentiti (vuota = tutti)
+    pub allowed_types: Vec<String>,
+    /// Job in coda o in elaborazione contemporaneamente
+    pub max_concurrent_jobs: Option<i64>,
+    /// Ore massime di conservazione dei risultati dei job
+    pub retention_hours: Option<i64>,
+    /// Priorità massima richiedibile per i job
+    pub max_priority: JobPriority,
+    /// Piano assegnato agli utenti OAuth registrati automaticamente
+    pub is_default: bool,
+    #[schema(value_type = String, format = "date-time")]
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = String, format = "date-time")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Plan {
+    /// Verifica se il tipo di conversione è incluso nel piano
+    pub fn allows_type(&self, conversion_type: &str) -> bool {
+        self.allowed_types.is_empty()
+            || self
+                .allowed_types
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(conversion_type))
+    }
+}
+
+/// Request per creare un piano
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePlanRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub rate_limit: Option<i64>,
+    #[serde(default)]
+    pub daily_limit: Option<i64>,
+    #[serde(default)]
+    pub max_file_size_mb: Option<i64>,
+    #[serde(default)]
+    pub allowed_types: Vec<String>,
+    #[serde(default)]
+    pub max_concurrent_jobs: Option<i64>,
+    #[serde(default)]
+    pub retention_hours: Option<i64>,
+    /// Priorità massima (default: high, nessun tetto)
+    #[serde(default = "default_max_priority")]
+    pub max_priority: JobPriority,
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+fn default_max_priority() -> JobPriority {
+    JobPriority::High
+}
+
+/// Request per aggiornare un piano (limiti a 0 = nessun limite)
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdatePlanRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub rate_limit: Option<i64>,
+    pub daily_limit: Option<i64>,
+    pub max_file_size_mb: Option<i64>,
+    /// Sostituisce i tipi consentiti (`[]` = tutti)
+    pub allowed_types: Option<Vec<String>>,
+    pub max_concurrent_jobs: Option<i64>,
+    pub retention_hours: Option<i64>,
+    pub max_priority: Option<JobPriority>,
+    pub is_default: Option<bool>,
+}
+
+/// Request per assegnare un piano a un'API Key o a un utente OAuth
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AssignPlanRequest {
+    pub api_key_id: Option<String>,
+    /// L'utente OAuth riceve il piano anche sulle API Key generate in futuro
+    pub oauth_user_id: Option<String>,
+}
+
+const PLAN_COLUMNS: &str = "id, name, description, rate_limit, daily_limit, max_file_size_mb, \
+     allowed_types, max_concurrent_jobs, retention_hours, max_priority, is_default, \
+     created_at, updated_at";
+
+#[derive(FromRow)]
+struct PlanRow {
+    id: String,
+    name: String,
+    description: Option<String>,
+    rate_limit: Option<i64>,
+    daily_limit: Option<i64>,
+    max_file_size_mb: Option<i64>,
+    allowed_types: Option<String>,
+    max_concurrent_jobs: Option<i64>,
+    retention_hours: Option<i64>,
+    max_priority: String,
+    is_default: i64,
+    created_at: String,
+    updated_at: String,
+}
+
+fn parse_timestamp(value: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now())
+}
+
+/// Tipi consentiti salvati come lista separata da virgole (NULL = tutti)
+fn join_types(types: &[String]) -> Option<String> {
+    let joined = types
+        .iter()
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(",");
+    (!joined.is_empty()).then_some(joined)
+}
+
+/// Valori <= 0 equivalgono a nessun limite
+fn limit(value: Option<i64>) -> Option<i64> {
+    value.filter(|v| *v > 0)
+}
+
+impl From<PlanRow> for Plan {
+    fn from(row: PlanRow) -> Self {
+        Plan {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            rate_limit: limit(row.rate_limit),
+            daily_limit: limit(row.daily_limit),
+            max_file_size_mb: limit(row.max_file_size_mb),
+            allowed_types: row
+                .allowed_types
+                .map(|s| s.split(',').map(|t| t.trim().to_string()).collect())
+                .unwrap_or_default(),
+            max_concurrent_jobs: limit(row.max_concurrent_jobs),
+            retention_hours: limit(row.retention_hours),
+            max_priority: JobPriority::from_str(&row.max_priority),
+            is_default: row.is_default != 0,
+            created_at: parse_timestamp(&row.created_at),
+            updated_at: parse_timestamp(&row.updated_at),
+        }
+    }
+}
+
+/// Un solo piano può essere quello di default
+async fn clear_default(pool: &DbPool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE plans SET is_default = 0 WHERE is_default = 1")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Crea un nuovo piano
+pub async fn create_plan(pool: &DbPool, request: &CreatePlanRequest) -> Result<Plan, sqlx::Error> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+
+    if request.is_default {
+        clear_default(pool).await?;
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO plans (id, name, description, rate_limit, daily_limit, max_file_size_mb, allowed_types, max_concurrent_jobs, retention_hours, max_priority, is_default, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(&request.name)
+    .bind(&request.description)
+    .bind(request.rate_limit)
+    .bind(request.daily_limit)
+    .bind(request.max_file_size_mb)
+    .bind(join_types(&request.allowed_types))
+    .bind(request.max_concurrent_jobs)
+    .bind(request.retention_hours)
+    .bind(request.max_priority.to_string())
+    .bind(request.is_default)
+    .bind(&now)
+    .bind(&now)
+    .execute(pool)
+    .await?;
+
+    get_plan(pool, &id).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+/// Trova un piano per ID
+pub async fn get_plan(pool: &DbPool, id: &str) -> Result<Option<Plan>, sqlx::Error> {
+    let row: Option<PlanRow> =
+        sqlx::query_as(&format!("SELECT {} FROM plans WHERE id = ?", PLAN_COLUMNS))
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(row.map(Plan::from))
+}
+
+/// Piano assegnato ai nuovi utenti OAuth, se configurato
+pub async fn default_plan(pool: &DbPool) -> Result<Option<Plan>, sqlx::Error> {
+    let row: Option<PlanRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM plans WHERE is_default = 1 LIMIT 1",
+        PLAN_COLUMNS
+    ))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(Plan::from))
+}
+
+/// Lista tutti i piani
+pub async fn list_plans(pool: &DbPool) -> Result<Vec<Plan>, sqlx::Error> {
+    let rows: Vec<PlanRow> =
+        sqlx::query_as(&format!("SELECT {} FROM plans ORDER BY name", PLAN_COLUMNS))
+            .fetch_all(pool)
+            .await?;
+
+    Ok(rows.into_iter().map(Plan::from).collect())
+}
+
+/// Aggiorna un piano
+pub async fn update_plan(
+    pool: &DbPool,
+    id: &str,
+    request: &UpdatePlanRequest,
+) -> Result<bool, sqlx::Error> {
+    let mut updates = Vec::new();
+    let mut values: Vec<String> = Vec::new();
+
+    if let Some(ref name) = request.name {
+        updates.push("name = ?");
+        values.push(name.clone());
+    }
+    if let Some(ref description) = request.description {
+        updates.push("description = ?");
+        values.push(description.clone());
+    }
+    let limits = [
+        ("rate_limit = ?", request.rate_limit),
+        ("daily_limit = ?", request.daily_limit),
+        ("max_file_size_mb = ?", request.max_file_size_mb),
+        ("max_concurrent_jobs = ?", request.max_concurrent_jobs),
+        ("retention_hours = ?", request.retention_hours),
+    ];
+    for (column, value) in limits {
+        if let Some(value) = value {
+            updates.push(column);
+            values.push(value.to_string());
+        }
+    }
+    if let Some(ref types) = request.allowed_types {
+        match join_types(types) {
+            Some(list) => {
+                updates.push("allowed_types = ?");
+                values.push(list);
+            }
+            None => updates.push("allowed_types = NULL"),
+        }
+    }
+    if let Some(priority) = request.max_priority {
+        updates.push("max_priority = ?");
+        values.push(priority.to_string());
+    }
+    if let Some(is_default) = request.is_default {
+        if is_default {
+            clear_default(pool).await?;
+        }
+        updates.push("is_default = ?");
+        values.push(if is_default { "1" } else { "0" }.to_string());
+    }
+
+    if updates.is_empty() {
+        return Ok(false);
+    }
+
+    updates.push("updated_at = ?");
+    values.push(Utc::now().to_rfc3339());
+
+    let query = format!("UPDATE plans SET {} WHERE id = ?", updates.join(", "));
+    let mut q = sqlx::query(&query);
+    for value in &values {
+        q = q.bind(value);
+    }
+    let result = q.bind(id).execute(pool).await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Elimina un piano (chiavi e utenti assegnati restano senza piano)
+pub async fn delete_plan(pool: &DbPool, id: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query("UPDATE api_keys SET plan_id = NULL WHERE plan_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    sqlx::query("UPDATE oauth_users SET plan_id = NULL WHERE plan_id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    let result = sqlx::query("DELETE FROM plans WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Piano assegnato a un'API Key
+pub async fn find_for_key(pool: &DbPool, api_key_id: &str) -> Result<Option<Plan>, sqlx::Error> {
+    let row: Option<PlanRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM plans WHERE id = (SELECT plan_id FROM api_keys WHERE id = ?)",
+        PLAN_COLUMNS
+    ))
+    .bind(api_key_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(Plan::from))
+}
+
+/// Assegna un piano a un'API Key
+pub async fn assign_to_key(
+    pool: &DbPool,
+    plan_id: &str,
+    api_key_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE api_keys SET plan_id = ?, updated_at = ? WHERE id = ?")
+        .bind(plan_id)
+        .bind(Utc::now().to_rfc3339())
+        .bind(api_key_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Assegna un piano a un utente OAuth e alla sua API Key attuale
+pub async fn assign_to_oauth_user(
+    pool: &DbPool,
+    plan_id: &str,
+    oauth_user_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now().to_rfc3339();
+    let result = sqlx::query("UPDATE oauth_users SET plan_id = ?, updated_at = ? WHERE id = ?")
+        .bind(plan_id)
+        .bind(&now)
+        .bind(oauth_user_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query(
+        "UPDATE api_keys SET plan_id = ?, updated_at = ? \
+         WHERE id = (SELECT api_key_id FROM oauth_users WHERE id = ?)",
+    )
+    .bind(plan_id)
+    .bind(&now)
+    .bind(oauth_user_id)
+    .execute(pool)
+    .await?;
+    Ok(true)
+}
@@ -0,0 +1,108 @@
+//! Token di sessione a breve scadenza
+//!
+//! Sostituiscono la chiave in chiaro per il frontend: dopo il login viene
+//! emesso un token `cvs_...` legato all'API Key dell'utente. Nel database
+//! è salvato solo l'hash.
+
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+
+use super::api_keys::hash_api_key;
+use super::DbPool;
+
+/// Prefisso dei token di sessione, per distinguerli dalle API Key
+pub const SESSION_TOKEN_PREFIX: &str = "cvs_";
+
+/// Validità dei token di sessione emessi al login (ore)
+pub const SESSION_TTL_HOURS: i64 = 12;
+
+/// Token di sessione appena creato (il token in chiaro non viene salvato)
+#[derive(Debug, Clone)]
+pub struct SessionCreated {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Sessione valida trovata per un token
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub api_key_id: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Crea un token di sessione per un'API Key
+pub async fn create_session(
+    pool: &DbPool,
+    api_key_id: &str,
+    ttl: Duration,
+) -> Result<SessionCreated, sqlx::Error> {
+    let token_bytes: [u8; 32] = rand::thread_rng().gen();
+    let token = format!(
+        "{}{}",
+        SESSION_TOKEN_PREFIX,
+        base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            token_bytes
+        )
+    );
+    let now = Utc::now();
+    let expires_at = now + ttl;
+
+    sqlx::query(
+        r#"
+        INSERT INTO sessions (token_hash, api_key_id, created_at, expires_at)
+        VALUES (?, ?, ?, ?)
+        "#,
+    )
+    .bind(hash_api_key(&token))
+    .bind(api_key_id)
+    .bind(now.to_rfc3339())
+    .bind(expires_at.to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(SessionCreated { token, expires_at })
+}
+
+/// Trova la sessione di un token, se non è scaduta
+pub async fn find_session(pool: &DbPool, token: &str) -> Result<Option<Session>, sqlx::Error> {
+    let row: Option<(String, String, String)> = sqlx::query_as(
+        "SELECT api_key_id, created_at, expires_at FROM sessions WHERE token_hash = ?",
+    )
+    .bind(hash_api_key(token))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|(api_key_id, created_at, expires_at)| {
+        let parse = |value: &str| {
+            DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|dt| dt.with_timezone(&Utc))
+        };
+        let expires_at = parse(&expires_at)?;
+        (expires_at > Utc::now()).then_some(Session {
+            api_key_id,
+            created_at: parse(&created_at)?,
+            expires_at,
+        })
+    }))
+}
+
+/// Revoca un token di sessione
+pub async fn revoke_session(pool: &DbPool, token: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM sessions WHERE token_hash = ?")
+        .bind(hash_api_key(token))
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Elimina le sessioni scadute
+pub async fn cleanup_expired_sessions(pool: &DbPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM sessions WHERE expires_at < ?")
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
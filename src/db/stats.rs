@@ -1,12 +1,26 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 use utoipa::ToSchema;
 
+use super::organizations::MEMBER_KEYS_SQL;
+use super::timeseries;
 use super::DbPool;
 use crate::models::{
-    ApiKeyStats, ConversionSummary, FormatCount, FormatStats, GlobalStats, StatsQuery,
-    TimeWindowStats, TypeStats,
+    ApiKeyStats, ConversionSummary, ErrorCauseCount, FormatCount, FormatPairStats, FormatStats,
+    GlobalStats, LatencyPercentiles, StatsQuery, TimeWindowStats, TypeStats,
 };
+use crate::utils::error_cause::normalize_error;
+use crate::utils::validation::parse_date_param;
+
+/// Giorni considerati per percentili, coppie di formati e cause di errore
+const PERFORMANCE_WINDOW_DAYS: i64 = 30;
+
+/// Numero massimo di coppie di formati e di cause di errore riportate
+const PERFORMANCE_TOP_N: usize = 20;
 
 /// Record conversione per database
 #[derive(Debug, Clone)]
@@ -24,19 +38,23 @@ pub struct ConversionRecordDb {
     pub success: bool,
     pub error: Option<String>,
     pub client_ip: Option<String>,
+    /// Durata dell'input per audio/video (0 per gli altri tipi)
+    pub media_duration_ms: i64,
 }
 
-/// Inserisce un record di conversione
+/// Inserisce un record di conversione e aggiorna le statistiche aggregate
 pub async fn insert_conversion(
     pool: &DbPool,
     record: &ConversionRecordDb,
 ) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
     sqlx::query(
         r#"
         INSERT INTO conversion_records
         (id, timestamp, api_key_id, is_guest, conversion_type, input_format, output_format,
-         input_size_bytes, output_size_bytes, processing_time_ms, success, error, client_ip)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+         input_size_bytes, output_size_bytes, processing_time_ms, success, error, client_ip,
+         media_duration_ms)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
     )
     .bind(&record.id)
@@ -52,9 +70,100 @@ pub async fn insert_conversion(
     .bind(if record.success { 1 } else { 0 })
     .bind(&record.error)
     .bind(&record.client_ip)
-    .execute(pool)
+    .bind(record.media_duration_ms)
+    .execute(&mut *tx)
     .await?;
-    Ok(())
+    timeseries::add_to_rollups(&mut tx, record).await?;
+    tx.commit().await
+}
+
+/// Insieme di API Key su cui calcolare consumo, statistiche e cronologia
+#[derive(Debug, Clone, Copy)]
+pub enum UsageScope<'a> {
+    /// Una singola API Key
+    ApiKey(&'a str),
+    /// Tutte le API Key membri di un'organizzazione
+    Organization(&'a str),
+}
+
+impl<'a> UsageScope<'a> {
+    /// Condizione SQL su `api_key_id`, con l'ID come unico parametro
+    pub fn condition(&self) -> String {
+        match self {
+            UsageScope::ApiKey(_) => "api_key_id = ?".to_string(),
+            UsageScope::Organization(_) => format!("api_key_id IN ({})", MEMBER_KEYS_SQL),
+        }
+    }
+
+    pub fn id(&self) -> &'a str {
+        match self {
+            UsageScope::ApiKey(id) | UsageScope::Organization(id) => id,
+        }
+    }
+}
+
+/// Consumo di un'API Key in un periodo (solo conversioni riuscite)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyUsage {
+    pub conversions: i64,
+    pub input_bytes: i64,
+    pub media_duration_ms: i64,
+}
+
+/// Ottiene il consumo di un'API Key o di un'organizzazione a partire da `since`
+pub async fn get_usage(
+    pool: &DbPool,
+    scope: UsageScope<'_>,
+    since: DateTime<Utc>,
+) -> Result<KeyUsage, sqlx::Error> {
+    let row: (i64, i64, i64) = sqlx::query_as(&format!(
+        r#"
+        SELECT
+            COUNT(*),
+            COALESCE(SUM(input_size_bytes), 0),
+            COALESCE(SUM(media_duration_ms), 0)
+        FROM conversion_records
+        WHERE {} AND success = 1 AND timestamp >= ?
+        "#,
+        scope.condition()
+    ))
+    .bind(scope.id())
+    .bind(since.to_rfc3339())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(KeyUsage {
+        conversions: row.0,
+        input_bytes: row.1,
+        media_duration_ms: row.2,
+    })
+}
+
+/// Job in coda o in elaborazione di un'API Key o di un'organizzazione
+///
+/// Non ancora registrati in `conversion_records`, vanno comunque conteggiati
+/// nelle quote, con la durata audio/video letta alla creazione del job.
+pub async fn get_pending_usage(
+    pool: &DbPool,
+    scope: UsageScope<'_>,
+) -> Result<KeyUsage, sqlx::Error> {
+    let row: (i64, i64, i64) = sqlx::query_as(&format!(
+        r#"
+        SELECT COUNT(*), COALESCE(SUM(file_size_bytes), 0), COALESCE(SUM(media_duration_ms), 0)
+        FROM jobs
+        WHERE {} AND status IN ('pending', 'processing')
+        "#,
+        scope.condition()
+    ))
+    .bind(scope.id())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(KeyUsage {
+        conversions: row.0,
+        input_bytes: row.1,
+        media_duration_ms: row.2,
+    })
 }
 
 /// Ottiene statistiche globali
@@ -100,8 +209,12 @@ pub async fn get_global_stats(pool: &DbPool) -> Result<GlobalStats, sqlx::Error>
         total_input_bytes: total.3 as u64,
         total_output_bytes: total.4 as u64,
         avg_processing_time_ms: avg_time.0,
+        // Calcolati a parte da get_performance_stats e get_top_errors
+        processing_time_ms: LatencyPercentiles::default(),
         by_type,
         by_format,
+        by_format_pair: Vec::new(),
+        top_errors: Vec::new(),
         last_24h,
         last_hour,
     })
@@ -125,12 +238,196 @@ async fn get_type_stats(pool: &DbPool) -> Result<TypeStats, sqlx::Error> {
             "document" => stats.document = count as u64,
             "audio" => stats.audio = count as u64,
             "video" => stats.video = count as u64,
+            "pdf" => stats.pdf = count as u64,
             _ => {}
         }
     }
+
+    // Gli SVG sono convertiti come immagini: si contano dal formato di input
+    let (svg,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM conversion_records WHERE input_format = 'svg'")
+            .fetch_one(pool)
+            .await?;
+    stats.svg = svg as u64;
+
     Ok(stats)
 }
 
+/// Percentili complessivi e statistiche per coppia di formati degli ultimi
+/// 30 giorni
+///
+/// Aggregati in SQL: in memoria arrivano solo i risultati, uno per coppia.
+/// Va comunque usata solo dove servono i dettagli (`/api/v1/stats`), non nei
+/// riepiloghi. I percentili considerano solo le conversioni riuscite: i
+/// fallimenti sono spesso immediati e abbasserebbero i tempi.
+pub async fn get_performance_stats(
+    pool: &DbPool,
+) -> Result<(LatencyPercentiles, Vec<FormatPairStats>), sqlx::Error> {
+    let since = Utc::now() - Duration::days(PERFORMANCE_WINDOW_DAYS);
+
+    let pairs: Vec<(String, String, i64, i64)> = sqlx::query_as(&format!(
+        r#"
+        SELECT LOWER(input_format), LOWER(output_format), COUNT(*), SUM(success = 0)
+        FROM conversion_records
+        WHERE timestamp >= ?
+        GROUP BY 1, 2
+        ORDER BY 3 DESC, 1, 2
+        LIMIT {}
+        "#,
+        PERFORMANCE_TOP_N
+    ))
+    .bind(since.to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+
+    let pair_percentiles: Vec<(String, String, i64, i64, i64)> =
+        sqlx::query_as(&percentiles_sql(true))
+            .bind(since.to_rfc3339())
+            .fetch_all(pool)
+            .await?;
+    let mut pair_percentiles: HashMap<(String, String), LatencyPercentiles> = pair_percentiles
+        .into_iter()
+        .map(|(input_format, output_format, p50, p90, p99)| {
+            (
+                (input_format, output_format),
+                latency_percentiles(p50, p90, p99),
+            )
+        })
+        .collect();
+
+    let (p50, p90, p99): (i64, i64, i64) = sqlx::query_as(&percentiles_sql(false))
+        .bind(since.to_rfc3339())
+        .fetch_one(pool)
+        .await?;
+
+    let by_pair = pairs
+        .into_iter()
+        .map(
+            |(input_format, output_format, conversions, failed)| FormatPairStats {
+                processing_time_ms: pair_percentiles
+                    .remove(&(input_format.clone(), output_format.clone()))
+                    .unwrap_or_default(),
+                input_format,
+                output_format,
+                conversions: conversions as u64,
+                failed: failed as u64,
+                failure_rate: failed as f64 / conversions as f64 * 100.0,
+            },
+        )
+        .collect();
+
+    Ok((latency_percentiles(p50, p90, p99), by_pair))
+}
+
+/// Query dei percentili nearest-rank (come [`LatencyPercentiles::from_sorted`])
+/// delle conversioni riuscite, per coppia di formati o complessivi
+///
+/// Il rango `ceil(p / 100 * n)` è calcolato in aritmetica intera.
+fn percentiles_sql(by_pair: bool) -> String {
+    let partition = if by_pair {
+        "PARTITION BY LOWER(input_format), LOWER(output_format)"
+    } else {
+        ""
+    };
+    let rank = |p: u32| {
+        format!(
+            "COALESCE(MAX(CASE WHEN rn = MAX(1, (n * {} + 99) / 100) THEN processing_time_ms END), 0)",
+            p
+        )
+    };
+    format!(
+        r#"
+        WITH ranked AS (
+            SELECT LOWER(input_format) AS input_format, LOWER(output_format) AS output_format,
+                   processing_time_ms,
+                   ROW_NUMBER() OVER ({partition} ORDER BY processing_time_ms) AS rn,
+                   COUNT(*) OVER ({partition}) AS n
+            FROM conversion_records
+            WHERE timestamp >= ? AND success = 1
+        )
+        SELECT {columns}{p50}, {p90}, {p99}
+        FROM ranked
+        {group_by}
+        "#,
+        partition = partition,
+        columns = if by_pair {
+            "input_format, output_format, "
+        } else {
+            ""
+        },
+        p50 = rank(50),
+        p90 = rank(90),
+        p99 = rank(99),
+        group_by = if by_pair {
+            "GROUP BY input_format, output_format"
+        } else {
+            ""
+        },
+    )
+}
+
+fn latency_percentiles(p50: i64, p90: i64, p99: i64) -> LatencyPercentiles {
+    LatencyPercentiles {
+        p50: p50.max(0) as u64,
+        p90: p90.max(0) as u64,
+        p99: p99.max(0) as u64,
+    }
+}
+
+/// Cause di errore più frequenti degli ultimi 30 giorni, raggruppate dopo la
+/// normalizzazione
+///
+/// Con `scope` solo gli errori delle conversioni di quell'API Key o
+/// organizzazione, altrimenti quelli di tutti.
+pub async fn get_top_errors(
+    pool: &DbPool,
+    scope: Option<UsageScope<'_>>,
+) -> Result<Vec<ErrorCauseCount>, sqlx::Error> {
+    let since = Utc::now() - Duration::days(PERFORMANCE_WINDOW_DAYS);
+    let scope_condition = scope
+        .map(|s| format!("AND {}", s.condition()))
+        .unwrap_or_default();
+    let sql = format!(
+        r#"
+        SELECT error, COUNT(*), MAX(timestamp)
+        FROM conversion_records
+        WHERE success = 0 AND error IS NOT NULL AND timestamp >= ? {}
+        GROUP BY error
+        "#,
+        scope_condition
+    );
+    let mut query = sqlx::query_as::<_, (String, i64, String)>(&sql).bind(since.to_rfc3339());
+    if let Some(scope) = scope {
+        query = query.bind(scope.id());
+    }
+    let rows = query.fetch_all(pool).await?;
+
+    let mut causes: HashMap<String, (u64, DateTime<Utc>)> = HashMap::new();
+    for (error, count, last_seen) in rows {
+        let last_seen = DateTime::parse_from_rfc3339(&last_seen)
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or(since);
+        let entry = causes
+            .entry(normalize_error(&error))
+            .or_insert((0, last_seen));
+        entry.0 += count as u64;
+        entry.1 = entry.1.max(last_seen);
+    }
+
+    let mut top: Vec<ErrorCauseCount> = causes
+        .into_iter()
+        .map(|(cause, (count, last_seen))| ErrorCauseCount {
+            cause,
+            count,
+            last_seen,
+        })
+        .collect();
+    top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.cause.cmp(&b.cause)));
+    top.truncate(PERFORMANCE_TOP_N);
+
+    Ok(top)
+}
+
 async fn get_format_stats(pool: &DbPool) -> Result<FormatStats, sqlx::Error> {
     let input_rows: Vec<(String, i64)> = sqlx::query_as(
         r#"
@@ -209,19 +506,53 @@ pub async fn get_api_key_stats(
     api_key_id: &str,
 ) -> Result<Option<ApiKeyStats>, sqlx::Error> {
     // Verifica che l'API key esista
-    let key_info: Option<(String, String, String)> =
-        sqlx::query_as("SELECT id, name, created_at FROM api_keys WHERE id = ?")
+    let key_info: Option<(String, String)> =
+        sqlx::query_as("SELECT id, created_at FROM api_keys WHERE id = ?")
             .bind(api_key_id)
             .fetch_optional(pool)
             .await?;
 
-    let (key_id, _name, created_at_str) = match key_info {
-        Some(info) => info,
-        None => return Ok(None),
-    };
+    match key_info {
+        Some((key_id, created_at)) => scoped_stats(pool, UsageScope::ApiKey(&key_id), &created_at)
+            .await
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Ottiene statistiche aggregate delle API Key di un'organizzazione
+pub async fn get_organization_stats(
+    pool: &DbPool,
+    organization_id: &str,
+) -> Result<Option<ApiKeyStats>, sqlx::Error> {
+    let org_info: Option<(String, String)> =
+        sqlx::query_as("SELECT id, created_at FROM organizations WHERE id = ?")
+            .bind(organization_id)
+            .fetch_optional(pool)
+            .await?;
+
+    match org_info {
+        Some((org_id, created_at)) => {
+            scoped_stats(pool, UsageScope::Organization(&org_id), &created_at)
+                .await
+                .map(Some)
+        }
+        None => Ok(None),
+    }
+}
 
-    // Statistiche totali per questa API key
-    let stats: (i64, i64, i64, i64, i64) = sqlx::query_as(
+/// Statistiche delle conversioni di un'API Key o di un'organizzazione
+///
+/// `created_at` è usato come prima/ultima conversione se non ce ne sono.
+async fn scoped_stats(
+    pool: &DbPool,
+    scope: UsageScope<'_>,
+    created_at_str: &str,
+) -> Result<ApiKeyStats, sqlx::Error> {
+    let condition = scope.condition();
+
+    // Statistiche totali
+    let stats: (i64, i64, i64, i64, i64) = sqlx::query_as(&format!(
         r#"
         SELECT
             COUNT(*) as total,
@@ -230,22 +561,24 @@ pub async fn get_api_key_stats(
             COALESCE(SUM(input_size_bytes), 0) as input_bytes,
             COALESCE(SUM(output_size_bytes), 0) as output_bytes
         FROM conversion_records
-        WHERE api_key_id = ?
+        WHERE {}
         "#,
-    )
-    .bind(&key_id)
+        condition
+    ))
+    .bind(scope.id())
     .fetch_one(pool)
     .await?;
 
     // Prima e ultima conversione
-    let first_last: Option<(String, String)> = sqlx::query_as(
+    let first_last: Option<(String, String)> = sqlx::query_as(&format!(
         r#"
         SELECT MIN(timestamp), MAX(timestamp)
         FROM conversion_records
-        WHERE api_key_id = ?
+        WHERE {}
         "#,
-    )
-    .bind(&key_id)
+        condition
+    ))
+    .bind(scope.id())
     .fetch_optional(pool)
     .await?;
 
@@ -259,7 +592,7 @@ pub async fn get_api_key_stats(
                 .unwrap_or_else(|_| Utc::now()),
         ),
         None => {
-            let created = DateTime::parse_from_rfc3339(&created_at_str)
+            let created = DateTime::parse_from_rfc3339(created_at_str)
                 .map(|dt| dt.with_timezone(&Utc))
                 .unwrap_or_else(|_| Utc::now());
             (created, created)
@@ -268,32 +601,34 @@ pub async fn get_api_key_stats(
 
     // Conversioni oggi
     let today_start = Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap();
-    let today_count: (i64,) = sqlx::query_as(
+    let today_count: (i64,) = sqlx::query_as(&format!(
         r#"
         SELECT COUNT(*) FROM conversion_records
-        WHERE api_key_id = ? AND timestamp >= ?
+        WHERE {} AND timestamp >= ?
         "#,
-    )
-    .bind(&key_id)
+        condition
+    ))
+    .bind(scope.id())
     .bind(today_start.to_string())
     .fetch_one(pool)
     .await?;
 
     // Conversioni ultima ora
     let hour_ago = (Utc::now() - Duration::hours(1)).to_rfc3339();
-    let hour_count: (i64,) = sqlx::query_as(
+    let hour_count: (i64,) = sqlx::query_as(&format!(
         r#"
         SELECT COUNT(*) FROM conversion_records
-        WHERE api_key_id = ? AND timestamp >= ?
+        WHERE {} AND timestamp >= ?
         "#,
-    )
-    .bind(&key_id)
+        condition
+    ))
+    .bind(scope.id())
     .bind(&hour_ago)
     .fetch_one(pool)
     .await?;
 
-    Ok(Some(ApiKeyStats {
-        api_key: key_id,
+    Ok(ApiKeyStats {
+        api_key: scope.id().to_string(),
         total_conversions: stats.0 as u64,
         successful_conversions: stats.1 as u64,
         failed_conversions: stats.2 as u64,
@@ -303,7 +638,7 @@ pub async fn get_api_key_stats(
         last_used,
         conversions_today: today_count.0 as u64,
         conversions_this_hour: hour_count.0 as u64,
-    }))
+    })
 }
 
 /// Ottiene conversioni recenti con filtri
@@ -311,7 +646,7 @@ pub async fn get_api_key_stats(
 pub async fn get_recent_conversions(
     pool: &DbPool,
     query: &StatsQuery,
-    api_key_id: Option<&str>,
+    scope: Option<UsageScope<'_>>,
 ) -> Result<Vec<ConversionSummary>, sqlx::Error> {
     let mut sql = String::from(
         r#"
@@ -334,15 +669,19 @@ pub async fn get_recent_conversions(
     if query.only_failed {
         sql.push_str(" AND success = 0");
     }
-    if let Some(key_id) = api_key_id {
-        sql.push_str(&format!(" AND api_key_id = '{}'", key_id));
+    if let Some(scope) = scope {
+        sql.push_str(&format!(" AND {}", scope.condition()));
     }
 
     sql.push_str(" ORDER BY timestamp DESC");
     sql.push_str(&format!(" LIMIT {}", query.limit));
 
+    let mut q = sqlx::query_as(&sql);
+    if let Some(scope) = scope {
+        q = q.bind(scope.id());
+    }
     let rows: Vec<(String, String, String, String, String, i64, i64, i64, i64)> =
-        sqlx::query_as(&sql).fetch_all(pool).await?;
+        q.fetch_all(pool).await?;
 
     Ok(rows
         .into_iter()
@@ -497,7 +836,7 @@ pub struct ConversionHistoryItem {
 }
 
 /// Filtri per history conversioni
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct HistoryFilters {
     /// Filtro data: today, week, month, all
     #[serde(default)]
@@ -511,6 +850,86 @@ pub struct HistoryFilters {
     /// Filtro stato: completed, failed, all
     #[serde(default)]
     pub status: Option<String>,
+    /// Inizio periodo (RFC 3339 o YYYY-MM-DD)
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Fine periodo inclusa (RFC 3339 o YYYY-MM-DD)
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Filtro tipo conversione
+    #[serde(default)]
+    pub conversion_type: Option<String>,
+}
+
+/// Tabella a cui applicare i filtri della cronologia
+#[derive(Debug, Clone, Copy)]
+enum HistoryTable {
+    Jobs,
+    ConversionRecords,
+}
+
+impl HistoryFilters {
+    /// Condizioni SQL (ognuna preceduta da `AND`) con i relativi parametri
+    ///
+    /// Le date non valide vengono ignorate: vanno verificate prima con
+    /// `parse_date_param`.
+    fn conditions(&self, table: HistoryTable) -> (String, Vec<String>) {
+        let time_column = match table {
+            HistoryTable::Jobs => "created_at",
+            HistoryTable::ConversionRecords => "timestamp",
+        };
+        let mut sql = String::new();
+        let mut params = Vec::new();
+        let non_empty = |v: &Option<String>| v.clone().filter(|s| !s.is_empty());
+
+        if let Some(date_filter) = &self.date_filter {
+            let now = Utc::now();
+            let cutoff = match date_filter.as_str() {
+                "today" => Some(now - Duration::hours(24)),
+                "week" => Some(now - Duration::days(7)),
+                "month" => Some(now - Duration::days(30)),
+                _ => None,
+            };
+            if let Some(cutoff) = cutoff {
+                sql.push_str(&format!(" AND {} >= ?", time_column));
+                params.push(cutoff.to_rfc3339());
+            }
+        }
+        if let Some(from) = non_empty(&self.from).and_then(|v| parse_date_param(&v, false).ok()) {
+            sql.push_str(&format!(" AND {} >= ?", time_column));
+            params.push(from.to_rfc3339());
+        }
+        if let Some(to) = non_empty(&self.to).and_then(|v| parse_date_param(&v, true).ok()) {
+            sql.push_str(&format!(" AND {} <= ?", time_column));
+            params.push(to.to_rfc3339());
+        }
+        for (column, value) in [
+            ("input_format", &self.input_format),
+            ("output_format", &self.output_format),
+            ("conversion_type", &self.conversion_type),
+        ] {
+            if let Some(value) = non_empty(value) {
+                sql.push_str(&format!(" AND {} = ?", column));
+                params.push(value);
+            }
+        }
+        if let Some(status) = non_empty(&self.status).filter(|s| s != "all") {
+            match table {
+                HistoryTable::Jobs => {
+                    sql.push_str(" AND status = ?");
+                    params.push(status);
+                }
+                // I record esistono solo per conversioni terminate
+                HistoryTable::ConversionRecords => sql.push_str(match status.as_str() {
+                    "completed" => " AND success = 1",
+                    "failed" => " AND success = 0",
+                    _ => " AND 1 = 0",
+                }),
+            }
+        }
+
+        (sql, params)
+    }
 }
 
 /// Ottiene le conversioni di un utente (dalla tabella jobs)
@@ -519,63 +938,31 @@ pub async fn get_user_conversions(
     api_key_id: &str,
     limit: i64,
 ) -> Result<Vec<ConversionHistoryItem>, sqlx::Error> {
-    get_user_conversions_filtered(pool, api_key_id, limit, None).await
+    get_user_conversions_filtered(pool, UsageScope::ApiKey(api_key_id), limit, None).await
 }
 
-/// Ottiene le conversioni di un utente con filtri
+/// Ottiene le conversioni di un utente (o della sua organizzazione) con filtri
 #[allow(clippy::type_complexity)]
 pub async fn get_user_conversions_filtered(
     pool: &DbPool,
-    api_key_id: &str,
+    scope: UsageScope<'_>,
     limit: i64,
     filters: Option<&HistoryFilters>,
 ) -> Result<Vec<ConversionHistoryItem>, sqlx::Error> {
-    let mut sql = String::from(
+    let mut sql = format!(
         r#"
         SELECT id, input_format, output_format, status, created_at, completed_at, file_size_bytes, original_filename, drive_file_id
         FROM jobs
-        WHERE api_key_id = ?
+        WHERE {}
         "#,
+        scope.condition()
     );
 
     // Applica filtri
-    if let Some(f) = filters {
-        // Filtro data
-        if let Some(date_filter) = &f.date_filter {
-            let now = Utc::now();
-            let cutoff = match date_filter.as_str() {
-                "today" => (now - Duration::hours(24)).to_rfc3339(),
-                "week" => (now - Duration::days(7)).to_rfc3339(),
-                "month" => (now - Duration::days(30)).to_rfc3339(),
-                _ => String::new(),
-            };
-            if !cutoff.is_empty() {
-                sql.push_str(&format!(" AND created_at >= '{}'", cutoff));
-            }
-        }
-
-        // Filtro formato input
-        if let Some(input_fmt) = &f.input_format {
-            if !input_fmt.is_empty() {
-                sql.push_str(&format!(" AND input_format = '{}'", input_fmt));
-            }
-        }
-
-        // Filtro formato output
-        if let Some(output_fmt) = &f.output_format {
-            if !output_fmt.is_empty() {
-                sql.push_str(&format!(" AND output_format = '{}'", output_fmt));
-            }
-        }
-
-        // Filtro stato
-        if let Some(status) = &f.status {
-            if status != "all" && !status.is_empty() {
-                sql.push_str(&format!(" AND status = '{}'", status));
-            }
-        }
-    }
-
+    let (conditions, params) = filters
+        .map(|f| f.conditions(HistoryTable::Jobs))
+        .unwrap_or_default();
+    sql.push_str(&conditions);
     sql.push_str(" ORDER BY created_at DESC LIMIT ?");
 
     let rows: Vec<(
@@ -588,8 +975,9 @@ pub async fn get_user_conversions_filtered(
         Option<i64>,
         Option<String>,
         Option<String>,
-    )> = sqlx::query_as(&sql)
-        .bind(api_key_id)
+    )> = params
+        .iter()
+        .fold(sqlx::query_as(&sql).bind(scope.id()), |q, p| q.bind(p))
         .bind(limit)
         .fetch_all(pool)
         .await?;
@@ -623,3 +1011,184 @@ pub async fn get_user_conversions_filtered(
         )
         .collect())
 }
+
+/// Conversioni consegnate per volta durante un export
+const EXPORT_CHANNEL_CAPACITY: usize = 256;
+
+/// Conversioni lette per query durante un export
+const EXPORT_PAGE_SIZE: usize = 500;
+
+/// Riga di `conversion_records` per l'export della cronologia
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ConversionExportRow {
+    pub id: String,
+    pub timestamp: String,
+    pub api_key_id: Option<String>,
+    pub is_guest: bool,
+    pub conversion_type: String,
+    pub input_format: String,
+    pub output_format: String,
+    pub input_size_bytes: i64,
+    pub output_size_bytes: i64,
+    pub processing_time_ms: i64,
+    pub media_duration_ms: i64,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Presente solo negli export amministrativi
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+}
+
+/// Legge le conversioni filtrate in ordine cronologico, una pagina alla volta
+///
+/// Le righe vengono lette in pagine di `EXPORT_PAGE_SIZE` con query brevi
+/// (keyset su `(timestamp, id)`), così nessuna connessione resta occupata
+/// mentre il client scarica. Un task separato consegna le righe su un canale
+/// limitato: la memoria usata non dipende dal numero di conversioni e, se il
+/// client chiude la connessione, la lettura si interrompe. Dopo un errore lo
+/// stream termina.
+pub fn stream_conversions(
+    pool: DbPool,
+    scope: Option<UsageScope<'_>>,
+    filters: &HistoryFilters,
+    include_client_ip: bool,
+) -> ReceiverStream<Result<ConversionExportRow, sqlx::Error>> {
+    let (conditions, params) = filters.conditions(HistoryTable::ConversionRecords);
+    let scope = scope.map(|s| (s.condition(), s.id().to_string()));
+
+    let select = format!(
+        r#"
+        SELECT id, timestamp, api_key_id, is_guest, conversion_type, input_format,
+               output_format, input_size_bytes, output_size_bytes, processing_time_ms,
+               media_duration_ms, success, error, {} AS client_ip
+        FROM conversion_records
+        WHERE {}{}
+        "#,
+        if include_client_ip {
+            "client_ip"
+        } else {
+            "NULL"
+        },
+        scope
+            .as_ref()
+            .map_or("1 = 1", |(condition, _)| condition.as_str()),
+        conditions
+    );
+    let first_page = format!(
+        "{} ORDER BY timestamp ASC, id ASC LIMIT {}",
+        select, EXPORT_PAGE_SIZE
+    );
+    let next_page = format!(
+        "{} AND (timestamp > ? OR (timestamp = ? AND id > ?)) ORDER BY timestamp ASC, id ASC LIMIT {}",
+        select, EXPORT_PAGE_SIZE
+    );
+
+    let (tx, rx) = mpsc::channel(EXPORT_CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        // Ultima riga inviata: (timestamp, id)
+        let mut last: Option<(String, String)> = None;
+        loop {
+            let sql = if last.is_some() {
+                &next_page
+            } else {
+                &first_page
+            };
+            let mut q = sqlx::query_as::<_, ConversionExportRow>(sql);
+            if let Some((_, id)) = &scope {
+                q = q.bind(id);
+            }
+            for param in &params {
+                q = q.bind(param);
+            }
+            if let Some((timestamp, id)) = &last {
+                q = q.bind(timestamp).bind(timestamp).bind(id);
+            }
+
+            let rows = match q.fetch_all(&pool).await {
+                Ok(rows) => rows,
+                Err(e) => {
+                    let _ = tx.send(Err(e)).await;
+                    return;
+                }
+            };
+            let complete = rows.len() < EXPORT_PAGE_SIZE;
+            last = rows.last().map(|r| (r.timestamp.clone(), r.id.clone()));
+            for row in rows {
+                if tx.send(Ok(row)).await.is_err() {
+                    return;
+                }
+            }
+            if complete {
+                return;
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn insert(pool: &DbPool, input: &str, output: &str, time_ms: i64, success: bool) {
+        sqlx::query(
+            r#"
+            INSERT INTO conversion_records
+            (id, timestamp, is_guest, conversion_type, input_format, output_format,
+             input_size_bytes, output_size_bytes, processing_time_ms, success)
+            VALUES (?, ?, 0, 'image', ?, ?, 0, 0, ?, ?)
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(Utc::now().to_rfc3339())
+        .bind(input)
+        .bind(output)
+        .bind(time_ms)
+        .bind(success)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_performance_stats_match_nearest_rank() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display());
+        let pool = super::super::init_db(&url).await.unwrap();
+
+        let png_times: Vec<i64> = (1..=11).map(|i| i * 10).collect();
+        for &t in png_times.iter().rev() {
+            insert(&pool, "png", "jpg", t, true).await;
+        }
+        insert(&pool, "PNG", "JPG", 1, false).await;
+        insert(&pool, "pdf", "docx", 500, true).await;
+        insert(&pool, "pdf", "docx", 0, false).await;
+
+        let (overall, by_pair) = get_performance_stats(&pool).await.unwrap();
+
+        let mut all_times = png_times.clone();
+        all_times.push(500);
+        assert_eq!(overall, LatencyPercentiles::from_sorted(&all_times));
+
+        assert_eq!(by_pair.len(), 2);
+        let png = &by_pair[0];
+        assert_eq!(
+            (png.input_format.as_str(), png.output_format.as_str()),
+            ("png", "jpg")
+        );
+        assert_eq!((png.conversions, png.failed), (12, 1));
+        assert_eq!(
+            png.processing_time_ms,
+            LatencyPercentiles::from_sorted(&png_times)
+        );
+
+        let pdf = &by_pair[1];
+        assert_eq!((pdf.conversions, pdf.failed), (2, 1));
+        assert_eq!(pdf.failure_rate, 50.0);
+        assert_eq!(
+            pdf.processing_time_ms,
+            LatencyPercentiles::from_sorted(&[500])
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Statistiche nel tempo su tabelle pre-aggregate
+//!
+//! Ogni conversione viene sommata, nella stessa transazione dell'inserimento
+//! in `conversion_records`, a una riga oraria e a una giornaliera per API Key,
+//! tipo e coppia di formati. Le serie orarie leggono la tabella oraria, quelle
+//! giornaliere e settimanali la giornaliera: il costo della query dipende dal
+//! periodo richiesto e non dal numero di conversioni. Le tabelle non vengono
+//! svuotate dalla pulizia dei record, così i trend restano disponibili.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use sqlx::SqliteConnection;
+
+use super::stats::{ConversionRecordDb, UsageScope};
+use super::DbPool;
+use crate::models::{TimeBucket, TimeseriesGroupBy, TimeseriesPoint, TimeseriesSeries};
+
+/// Tabelle di rollup con l'ampiezza del relativo intervallo
+const ROLLUP_TABLES: [(&str, TimeBucket); 2] = [
+    ("conversion_rollup_hourly", TimeBucket::Hour),
+    ("conversion_rollup_daily", TimeBucket::Day),
+];
+
+/// Crea le tabelle di rollup e, al primo avvio, le popola dai record esistenti
+pub async fn migrate(pool: &DbPool) -> Result<(), sqlx::Error> {
+    for (table, bucket) in ROLLUP_TABLES {
+        sqlx::query(&format!(
+            r#"
+            CREATE TABLE IF NOT EXISTS {table} (
+                bucket TEXT NOT NULL,
+                api_key_id TEXT NOT NULL DEFAULT '',
+                conversion_type TEXT NOT NULL,
+                input_format TEXT NOT NULL,
+                output_format TEXT NOT NULL,
+                conversions INTEGER NOT NULL DEFAULT 0,
+                successful INTEGER NOT NULL DEFAULT 0,
+                input_bytes INTEGER NOT NULL DEFAULT 0,
+                output_bytes INTEGER NOT NULL DEFAULT 0,
+                processing_time_ms INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (bucket, api_key_id, conversion_type, input_format, output_format)
+            )
+            "#
+        ))
+        .execute(pool)
+        .await?;
+
+        let (rows,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {table}"))
+            .fetch_one(pool)
+            .await?;
+        if rows > 0 {
+            continue;
+        }
+
+        // I timestamp sono RFC 3339 in UTC: l'inizio dell'intervallo si ricava dal prefisso
+        let bucket_expr = match bucket {
+            TimeBucket::Hour => "substr(timestamp, 1, 13) || ':00:00+00:00'",
+            _ => "substr(timestamp, 1, 10) || 'T00:00:00+00:00'",
+        };
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO {table}
+            (bucket, api_key_id, conversion_type, input_format, output_format,
+             conversions, successful, input_bytes, output_bytes, processing_time_ms)
+            SELECT {bucket_expr}, COALESCE(api_key_id, ''), conversion_type,
+                   input_format, output_format, COUNT(*), SUM(success),
+                   SUM(input_size_bytes), SUM(output_size_bytes), SUM(processing_time_ms)
+            FROM conversion_records
+            GROUP BY 1, 2, 3, 4, 5
+            "#
+        ))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Somma una conversione alle tabelle di rollup
+pub async fn add_to_rollups(
+    conn: &mut SqliteConnection,
+    record: &ConversionRecordDb,
+) -> Result<(), sqlx::Error> {
+    for (table, bucket) in ROLLUP_TABLES {
+        sqlx::query(&format!(
+            r#"
+            INSERT INTO {table}
+            (bucket, api_key_id, conversion_type, input_format, output_format,
+             conversions, successful, input_bytes, output_bytes, processing_time_ms)
+            VALUES (?, ?, ?, ?, ?, 1, ?, ?, ?, ?)
+            ON CONFLICT (bucket, api_key_id, conversion_type, input_format, output_format)
+            DO UPDATE SET
+                conversions = conversions + 1,
+                successful = successful + excluded.successful,
+                input_bytes = input_bytes + excluded.input_bytes,
+                output_bytes = output_bytes + excluded.output_bytes,
+                processing_time_ms = processing_time_ms + excluded.processing_time_ms
+            "#
+        ))
+        .bind(bucket.truncate(record.timestamp).to_rfc3339())
+        .bind(record.api_key_id.as_deref().unwrap_or(""))
+        .bind(&record.conversion_type)
+        .bind(&record.input_format)
+        .bind(&record.output_format)
+        .bind(if record.success { 1 } else { 0 })
+        .bind(record.input_size_bytes)
+        .bind(record.output_size_bytes)
+        .bind(record.processing_time_ms)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Filtri di una serie temporale
+#[derive(Debug, Clone, Copy)]
+pub struct TimeseriesFilter<'a> {
+    /// Inizio del periodo, già allineato all'intervallo
+    pub from: DateTime<Utc>,
+    /// Fine del periodo, esclusa, già allineata all'intervallo
+    pub until: DateTime<Utc>,
+    pub bucket: TimeBucket,
+    pub group_by: Option<TimeseriesGroupBy>,
+    /// API Key o organizzazione a cui limitare i dati (None = tutte)
+    pub scope: Option<UsageScope<'a>>,
+}
+
+/// Calcola le serie, con un punto per ogni intervallo del periodo
+#[allow(clippy::type_complexity)]
+pub async fn get_timeseries(
+    pool: &DbPool,
+    filter: TimeseriesFilter<'_>,
+) -> Result<Vec<TimeseriesSeries>, sqlx::Error> {
+    let table = match filter.bucket {
+        TimeBucket::Hour => "conversion_rollup_hourly",
+        TimeBucket::Day | TimeBucket::Week => "conversion_rollup_daily",
+    };
+    let key_expr = match filter.group_by {
+        None => "''",
+        Some(TimeseriesGroupBy::Type) => "conversion_type",
+        Some(TimeseriesGroupBy::Format) => "input_format || '->' || output_format",
+        Some(TimeseriesGroupBy::ApiKey) => {
+            "CASE WHEN api_key_id = '' THEN 'guest' ELSE api_key_id END"
+        }
+    };
+
+    let mut sql = format!(
+        r#"
+        SELECT bucket, {key_expr}, SUM(conversions), SUM(successful),
+               SUM(input_bytes), SUM(output_bytes), SUM(processing_time_ms)
+        FROM {table}
+        WHERE bucket >= ? AND bucket < ?
+        "#
+    );
+    if let Some(scope) = filter.scope {
+        sql.push_str(&format!(" AND {}", scope.condition()));
+    }
+    sql.push_str(" GROUP BY 1, 2");
+
+    let mut q = sqlx::query_as(&sql)
+        .bind(filter.from.to_rfc3339())
+        .bind(filter.until.to_rfc3339());
+    if let Some(scope) = filter.scope {
+        q = q.bind(scope.id());
+    }
+    let rows: Vec<(String, String, i64, i64, i64, i64, i64)> = q.fetch_all(pool).await?;
+
+    // Somma per serie e intervallo (le settimane raccolgono più giorni)
+    let mut totals: BTreeMap<String, BTreeMap<DateTime<Utc>, Totals>> = BTreeMap::new();
+    for (bucket, key, conversions, successful, input_bytes, output_bytes, time_ms) in rows {
+        let Ok(start) = DateTime::parse_from_rfc3339(&bucket) else {
+            continue;
+        };
+        let start = filter.bucket.truncate(start.with_timezone(&Utc));
+        let entry = totals.entry(key).or_default().entry(start).or_default();
+        entry.conversions += conversions;
+        entry.successful += successful;
+        entry.input_bytes += input_bytes;
+        entry.output_bytes += output_bytes;
+        entry.processing_time_ms += time_ms;
+    }
+
+    if filter.group_by.is_none() && totals.is_empty() {
+        totals.insert(String::new(), BTreeMap::new());
+    }
+
+    Ok(totals
+        .into_iter()
+        .map(|(key, by_bucket)| TimeseriesSeries {
+            key: filter.group_by.map(|_| key),
+            points: bucket_starts(filter.bucket, filter.from, filter.until)
+                .map(|start| {
+                    by_bucket
+                        .get(&start)
+                        .map(|t| t.to_point(start))
+                        .unwrap_or(TimeseriesPoint {
+                            timestamp: start,
+                            ..Default::default()
+                        })
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Inizi degli intervalli in `[from, until)`
+pub fn bucket_starts(
+    bucket: TimeBucket,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> impl Iterator<Item = DateTime<Utc>> {
+    std::iter::successors(Some(bucket.truncate(from)), move |start| {
+        Some(bucket.next(*start))
+    })
+    .take_while(move |start| *start < until)
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    conversions: i64,
+    successful: i64,
+    input_bytes: i64,
+    output_bytes: i64,
+    processing_time_ms: i64,
+}
+
+impl Totals {
+    fn to_point(&self, timestamp: DateTime<Utc>) -> TimeseriesPoint {
+        TimeseriesPoint {
+            timestamp,
+            conversions: self.conversions as u64,
+            successful: self.successful as u64,
+            failed: (self.conversions - self.successful).max(0) as u64,
+            input_bytes: self.input_bytes as u64,
+            output_bytes: self.output_bytes as u64,
+            avg_processing_time_ms: if self.conversions > 0 {
+                self.processing_time_ms as f64 / self.conversions as f64
+            } else {
+                0.0
+            },
+        }
+    }
+}
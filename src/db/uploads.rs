@@ -0,0 +1,115 @@
+//! Upload riprendibili (protocollo tus)
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use super::DbPool;
+
+/// Upload in corso o completato, in attesa di essere usato da un job
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UploadRecord {
+    pub id: String,
+    pub api_key_id: Option<String>,
+    /// Dimensione totale dichiarata dal client (`Upload-Length`)
+    pub upload_length: i64,
+    /// Byte ricevuti finora (`Upload-Offset`)
+    pub upload_offset: i64,
+    /// Header `Upload-Metadata` originale
+    pub metadata: Option<String>,
+    pub filename: Option<String>,
+    pub created_at: String,
+    pub expires_at: String,
+    /// Hash del token di sessione guest (solo upload dei guest)
+    #[serde(skip_serializing)]
+    pub guest_token_hash: Option<String>,
+}
+
+impl UploadRecord {
+    pub fn is_complete(&self) -> bool {
+        self.upload_offset >= self.upload_length
+    }
+}
+
+/// Registra un nuovo upload
+pub async fn create_upload(pool: &DbPool, upload: &UploadRecord) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO uploads (id, api_key_id, upload_length, upload_offset, metadata, filename, created_at, expires_at, guest_token_hash)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&upload.id)
+    .bind(&upload.api_key_id)
+    .bind(upload.upload_length)
+    .bind(upload.upload_offset)
+    .bind(&upload.metadata)
+    .bind(&upload.filename)
+    .bind(&upload.created_at)
+    .bind(&upload.expires_at)
+    .bind(&upload.guest_token_hash)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Ottiene un upload non scaduto
+pub async fn get_upload(pool: &DbPool, id: &str) -> Result<Option<UploadRecord>, sqlx::Error> {
+    sqlx::query_as::<_, UploadRecord>(
+        r#"
+        SELECT id, api_key_id, upload_length, upload_offset, metadata, filename, created_at, expires_at,
+               guest_token_hash
+        FROM uploads WHERE id = ? AND expires_at > ?
+        "#,
+    )
+    .bind(id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await
+}
+
+/// Aggiorna l'offset di un upload
+pub async fn update_upload_offset(pool: &DbPool, id: &str, offset: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE uploads SET upload_offset = ? WHERE id = ?")
+        .bind(offset)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Elimina un upload completato, se non è già stato usato da un altro job
+///
+/// Ritorna `false` se il record non esiste più o non è completo.
+pub async fn claim_completed_upload(pool: &DbPool, id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM uploads WHERE id = ? AND upload_offset >= upload_length")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Elimina un upload
+pub async fn delete_upload(pool: &DbPool, id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM uploads WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Elimina gli upload scaduti e ritorna i loro ID (per rimuovere i file)
+pub async fn cleanup_expired_uploads(pool: &DbPool) -> Result<Vec<String>, sqlx::Error> {
+    let now = Utc::now().to_rfc3339();
+    let ids: Vec<(String,)> = sqlx::query_as("SELECT id FROM uploads WHERE expires_at < ?")
+        .bind(&now)
+        .fetch_all(pool)
+        .await?;
+
+    sqlx::query("DELETE FROM uploads WHERE expires_at < ?")
+        .bind(&now)
+        .execute(pool)
+        .await?;
+
+    Ok(ids.into_iter().map(|(id,)| id).collect())
+}
@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -20,6 +20,9 @@ pub enum AppError {
     #[error("File troppo grande: massimo {0} MB")]
     FileTooLarge(u64),
 
+    #[error("Dati oltre l'Upload-Length dichiarato di {0} byte")]
+    UploadLengthExceeded(u64),
+
     #[error("Errore di I/O: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -53,9 +56,19 @@ pub enum AppError {
     #[error("Limite giornaliero raggiunto: {0}")]
     DailyLimitExceeded(String),
 
+    #[error("Quota superata: {message}")]
+    QuotaExceeded {
+        message: String,
+        /// Momento in cui la quota si azzera
+        reset_at: chrono::DateTime<chrono::Utc>,
+    },
+
     #[error("Troppi job in coda: {0}")]
     TooManyJobs(String),
 
+    #[error("Conflitto: {0}")]
+    Conflict(String),
+
     #[error("Richiesta non valida: {0}")]
     BadRequest(String),
 
@@ -65,11 +78,28 @@ pub enum AppError {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let AppError::QuotaExceeded { reset_at, .. } = &self {
+            let status = StatusCode::TOO_MANY_REQUESTS;
+            let retry_after = (*reset_at - chrono::Utc::now()).num_seconds().max(1);
+            let body = Json(json!({
+                "error": self.to_string(),
+                "status": status.as_u16(),
+                "reset_at": reset_at.to_rfc3339()
+            }));
+            return (
+                status,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                body,
+            )
+                .into_response();
+        }
+
         let (status, error_message) = match &self {
             AppError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::UnsupportedFormat(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             AppError::ConversionError(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
             AppError::FileTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            AppError::UploadLengthExceeded(_) => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
             AppError::IoError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             AppError::ImageError(_) => (StatusCode::UNPROCESSABLE_ENTITY, self.to_string()),
             AppError::MissingField(_) => (StatusCode::BAD_REQUEST, self.to_string()),
@@ -81,7 +111,9 @@ impl IntoResponse for AppError {
             AppError::Forbidden(_) => (StatusCode::FORBIDDEN, self.to_string()),
             AppError::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
             AppError::DailyLimitExceeded(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            AppError::QuotaExceeded { .. } => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
             AppError::TooManyJobs(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            AppError::Conflict(_) => (StatusCode::CONFLICT, self.to_string()),
             AppError::BadRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             AppError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
         };
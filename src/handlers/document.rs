@@ -5,38 +5,6 @@ use std::path::Path;
 use crate::config::formats;
 use crate::error::{AppError, Result};
 
-pub fn convert_document(
-    input_data: &[u8],
-    input_format: &str,
-    output_format: &str,
-) -> Result<Vec<u8>> {
-    if !formats::is_supported_document_input(input_format) {
-        return Err(AppError::UnsupportedFormat(format!(
-            "Formato input non supportato: {}",
-            input_format
-        )));
-    }
-
-    if !formats::is_supported_document_output(output_format) {
-        return Err(AppError::UnsupportedFormat(format!(
-            "Formato output non supportato: {}",
-            output_format
-        )));
-    }
-
-    let content = String::from_utf8_lossy(input_data).to_string();
-
-    match output_format.to_lowercase().as_str() {
-        "pdf" => text_to_pdf(&content, input_format),
-        "txt" => Ok(extract_text(&content, input_format).into_bytes()),
-        "html" => Ok(to_html(&content, input_format).into_bytes()),
-        _ => Err(AppError::UnsupportedFormat(format!(
-            "Formato output non supportato: {}",
-            output_format
-        ))),
-    }
-}
-
 pub fn convert_document_file(
     input_path: &Path,
     output_path: &Path,
@@ -47,7 +15,14 @@ pub fn convert_document_file(
         .and_then(|e| e.to_str())
         .unwrap_or("txt");
 
-    let content = std::fs::read_to_string(input_path)?;
+    if !formats::is_supported_document_input(input_format) {
+        return Err(AppError::UnsupportedFormat(format!(
+            "Formato input non supportato: {}",
+            input_format
+        )));
+    }
+
+    let content = String::from_utf8_lossy(&std::fs::read(input_path)?).to_string();
 
     match output_format.to_lowercase().as_str() {
         "pdf" => {
@@ -6,12 +6,18 @@ use crate::error::{AppError, Result};
 use crate::models::ImageOptions;
 use crate::utils::encode_image;
 
-pub fn convert_image(
-    input_data: &[u8],
-    input_format: &str,
+/// Converte un file immagine applicando resize e qualità
+pub fn convert_image_file_with_options(
+    input_path: &Path,
+    output_path: &Path,
     output_format: &str,
     options: &ImageOptions,
-) -> Result<Vec<u8>> {
+) -> Result<()> {
+    let input_format = input_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+
     // Valida formati
     if !formats::is_supported_image_input(input_format) {
         return Err(AppError::UnsupportedFormat(format!(
@@ -27,29 +33,19 @@ pub fn convert_image(
         )));
     }
 
-    // Carica immagine
-    let mut img = image::load_from_memory(input_data)?;
+    // Carica immagine (formato dal contenuto, come per i dati in memoria)
+    let mut img = image::ImageReader::open(input_path)?
+        .with_guessed_format()?
+        .decode()?;
 
     // Applica resize se richiesto
     img = apply_resize(img, options);
 
     // Converti nel formato di output
     let output_data = encode_image(&img, output_format, options.quality)?;
+    std::fs::write(output_path, output_data)?;
 
-    Ok(output_data)
-}
-
-pub fn convert_image_with_quality(
-    input_data: &[u8],
-    input_format: &str,
-    output_format: &str,
-    quality: Option<u8>,
-) -> Result<Vec<u8>> {
-    let options = ImageOptions {
-        quality,
-        ..Default::default()
-    };
-    convert_image(input_data, input_format, output_format, &options)
+    Ok(())
 }
 
 pub fn convert_image_file(
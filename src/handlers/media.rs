@@ -3,20 +3,26 @@ use std::process::Command;
 
 use crate::config::formats;
 use crate::error::{AppError, Result};
+use crate::services::metrics;
 use crate::utils::check_ffmpeg_available;
 
-pub fn convert_audio(
-    input_data: &[u8],
-    input_format: &str,
+pub fn convert_audio_file(
+    input_path: &Path,
+    output_path: &Path,
     output_format: &str,
     quality: Option<u8>,
-) -> Result<Vec<u8>> {
+) -> Result<()> {
     if !check_ffmpeg_available() {
         return Err(AppError::FfmpegError(
             "FFmpeg non e' installato nel sistema".to_string(),
         ));
     }
 
+    let input_format = input_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+
     if !formats::is_supported_audio_input(input_format) {
         return Err(AppError::UnsupportedFormat(format!(
             "Formato audio input non supportato: {}",
@@ -31,34 +37,6 @@ pub fn convert_audio(
         )));
     }
 
-    // Crea file temporanei
-    let temp_dir = tempfile::tempdir()?;
-    let input_path = temp_dir.path().join(format!("input.{}", input_format));
-    let output_path = temp_dir.path().join(format!("output.{}", output_format));
-
-    std::fs::write(&input_path, input_data)?;
-
-    // Esegui conversione
-    convert_audio_file(&input_path, &output_path, output_format, quality)?;
-
-    // Leggi output
-    let output_data = std::fs::read(&output_path)?;
-
-    Ok(output_data)
-}
-
-pub fn convert_audio_file(
-    input_path: &Path,
-    output_path: &Path,
-    output_format: &str,
-    quality: Option<u8>,
-) -> Result<()> {
-    if !check_ffmpeg_available() {
-        return Err(AppError::FfmpegError(
-            "FFmpeg non e' installato nel sistema".to_string(),
-        ));
-    }
-
     let mut args = vec![
         "-y", // Sovrascrivi output
         "-i",
@@ -92,18 +70,23 @@ pub fn convert_audio_file(
     run_ffmpeg_command(&args)
 }
 
-pub fn convert_video(
-    input_data: &[u8],
-    input_format: &str,
+pub fn convert_video_file(
+    input_path: &Path,
+    output_path: &Path,
     output_format: &str,
     quality: Option<u8>,
-) -> Result<Vec<u8>> {
+) -> Result<()> {
     if !check_ffmpeg_available() {
         return Err(AppError::FfmpegError(
             "FFmpeg non e' installato nel sistema".to_string(),
         ));
     }
 
+    let input_format = input_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+
     if !formats::is_supported_video_input(input_format) {
         return Err(AppError::UnsupportedFormat(format!(
             "Formato video input non supportato: {}",
@@ -118,34 +101,6 @@ pub fn convert_video(
         )));
     }
 
-    // Crea file temporanei
-    let temp_dir = tempfile::tempdir()?;
-    let input_path = temp_dir.path().join(format!("input.{}", input_format));
-    let output_path = temp_dir.path().join(format!("output.{}", output_format));
-
-    std::fs::write(&input_path, input_data)?;
-
-    // Esegui conversione
-    convert_video_file(&input_path, &output_path, output_format, quality)?;
-
-    // Leggi output
-    let output_data = std::fs::read(&output_path)?;
-
-    Ok(output_data)
-}
-
-pub fn convert_video_file(
-    input_path: &Path,
-    output_path: &Path,
-    output_format: &str,
-    quality: Option<u8>,
-) -> Result<()> {
-    if !check_ffmpeg_available() {
-        return Err(AppError::FfmpegError(
-            "FFmpeg non e' installato nel sistema".to_string(),
-        ));
-    }
-
     let mut args = vec!["-y", "-i", input_path.to_str().unwrap_or("")];
 
     // Parametri specifici per formato
@@ -205,10 +160,64 @@ pub fn convert_video_file(
     run_ffmpeg_command(&args)
 }
 
+/// Durata in millisecondi di un file audio/video, letta con ffprobe
+///
+/// Ritorna `None` se ffprobe non è disponibile o il file non ha durata.
+pub fn probe_duration_ms(input_path: &Path) -> Option<i64> {
+    let output = metrics::run_tool(
+        Command::new("ffprobe")
+            .args([
+                "-v",
+                "error",
+                "-show_entries",
+                "format=duration",
+                "-of",
+                "default=noprint_wrappers=1:nokey=1",
+            ])
+            .arg(input_path),
+    )
+    .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .map(|secs| (secs * 1000.0).round() as i64)
+}
+
+/// Risoluzione (larghezza, altezza) del primo stream video, letta con ffprobe
+pub fn probe_resolution(input_path: &Path) -> Option<(u32, u32)> {
+    let output = metrics::run_tool(
+        Command::new("ffprobe")
+            .args([
+                "-v",
+                "error",
+                "-select_streams",
+                "v:0",
+                "-show_entries",
+                "stream=width,height",
+                "-of",
+                "csv=s=x:p=0",
+            ])
+            .arg(input_path),
+    )
+    .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (width, height) = stdout.trim().split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
 fn run_ffmpeg_command(args: &[&str]) -> Result<()> {
-    let output = Command::new("ffmpeg")
-        .args(args)
-        .output()
+    let output = metrics::run_tool(Command::new("ffmpeg").args(args))
         .map_err(|e| AppError::FfmpegError(format!("Impossibile eseguire ffmpeg: {}", e)))?;
 
     if !output.status.success() {
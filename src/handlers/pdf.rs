@@ -1,4 +1,3 @@
-use std::io::{Cursor, Write};
 use std::path::Path;
 use std::process::Command;
 
@@ -7,51 +6,10 @@ use zip::ZipWriter;
 
 use crate::config::formats;
 use crate::error::{AppError, Result};
+use crate::services::metrics;
 use crate::utils::check_pdftoppm_available;
 
-/// Converte un PDF in immagine usando pdftoppm (poppler-utils)
-pub fn convert_pdf_to_image(
-    input_data: &[u8],
-    output_format: &str,
-    page: Option<u32>,
-    dpi: Option<u32>,
-) -> Result<Vec<u8>> {
-    if !check_pdftoppm_available() {
-        return Err(AppError::PopplerError(
-            "pdftoppm (poppler-utils) non e' installato nel sistema".to_string(),
-        ));
-    }
-
-    if !formats::is_supported_pdf_output(output_format) {
-        return Err(AppError::UnsupportedFormat(format!(
-            "Formato output non supportato per PDF: {}. Formati supportati: png, jpg, tiff",
-            output_format
-        )));
-    }
-
-    // Crea file temporanei
-    let temp_dir = tempfile::tempdir()?;
-    let input_path = temp_dir.path().join("input.pdf");
-    let output_prefix = temp_dir.path().join("output");
-
-    std::fs::write(&input_path, input_data)?;
-
-    // Esegui conversione
-    let output_path = run_pdftoppm(
-        &input_path,
-        &output_prefix,
-        output_format,
-        page.unwrap_or(1),
-        dpi.unwrap_or(150),
-    )?;
-
-    // Leggi output
-    let output_data = std::fs::read(&output_path)?;
-
-    Ok(output_data)
-}
-
-/// Converte un PDF file in immagine
+/// Converte una pagina di un file PDF in immagine usando pdftoppm (poppler-utils)
 pub fn convert_pdf_file(
     input_path: &Path,
     output_path: &Path,
@@ -67,7 +25,7 @@ pub fn convert_pdf_file(
 
     if !formats::is_supported_pdf_output(output_format) {
         return Err(AppError::UnsupportedFormat(format!(
-            "Formato output non supportato per PDF: {}",
+            "Formato output non supportato per PDF: {}. Formati supportati: png, jpg, tiff",
             output_format
         )));
     }
@@ -126,9 +84,7 @@ fn run_pdftoppm(
         output_prefix.to_str().unwrap_or(""),
     ];
 
-    let output = Command::new("pdftoppm")
-        .args(&args)
-        .output()
+    let output = metrics::run_tool(Command::new("pdftoppm").args(&args))
         .map_err(|e| AppError::PopplerError(format!("Impossibile eseguire pdftoppm: {}", e)))?;
 
     if !output.status.success() {
@@ -158,16 +114,10 @@ fn run_pdftoppm(
     Ok(output_path)
 }
 
-/// Ottiene il numero di pagine di un PDF
-pub fn get_pdf_page_count(input_data: &[u8]) -> Result<u32> {
-    let temp_dir = tempfile::tempdir()?;
-    let input_path = temp_dir.path().join("input.pdf");
-    std::fs::write(&input_path, input_data)?;
-
+/// Ottiene il numero di pagine di un file PDF
+pub fn get_pdf_page_count(input_path: &Path) -> Result<u32> {
     // Usa pdfinfo per ottenere il numero di pagine
-    let output = Command::new("pdfinfo")
-        .arg(input_path.to_str().unwrap_or(""))
-        .output()
+    let output = metrics::run_tool(Command::new("pdfinfo").arg(input_path.to_str().unwrap_or("")))
         .map_err(|e| AppError::PopplerError(format!("Impossibile eseguire pdfinfo: {}", e)))?;
 
     if !output.status.success() {
@@ -191,60 +141,58 @@ pub fn get_pdf_page_count(input_data: &[u8]) -> Result<u32> {
     Ok(1)
 }
 
-/// Converte tutte le pagine di un PDF in immagini (restituisce lista di file)
-pub fn convert_pdf_all_pages(
-    input_data: &[u8],
+/// Converte tutte le pagine di un file PDF in un archivio ZIP contenente le immagini.
+///
+/// Le pagine vengono convertite una alla volta e copiate nell'archivio su disco.
+pub fn convert_pdf_file_to_zip(
+    input_path: &Path,
+    output_path: &Path,
     output_format: &str,
     dpi: Option<u32>,
-) -> Result<Vec<(String, Vec<u8>)>> {
+    base_name: &str,
+) -> Result<()> {
     if !check_pdftoppm_available() {
         return Err(AppError::PopplerError(
             "pdftoppm (poppler-utils) non e' installato nel sistema".to_string(),
         ));
     }
 
-    let page_count = get_pdf_page_count(input_data)?;
-    let mut pages = Vec::new();
-
-    for page in 1..=page_count {
-        let data = convert_pdf_to_image(input_data, output_format, Some(page), dpi)?;
-        let filename = format!("page_{:03}.{}", page, output_format);
-        pages.push((filename, data));
+    if !formats::is_supported_pdf_output(output_format) {
+        return Err(AppError::UnsupportedFormat(format!(
+            "Formato output non supportato per PDF: {}. Formati supportati: png, jpg, tiff",
+            output_format
+        )));
     }
 
-    Ok(pages)
-}
+    let page_count = get_pdf_page_count(input_path)?;
+    let temp_dir = tempfile::tempdir()?;
 
-/// Converte tutte le pagine di un PDF in un archivio ZIP contenente le immagini
-pub fn convert_pdf_to_zip(
-    input_data: &[u8],
-    output_format: &str,
-    dpi: Option<u32>,
-    base_name: &str,
-) -> Result<Vec<u8>> {
-    // Converti tutte le pagine
-    let pages = convert_pdf_all_pages(input_data, output_format, dpi)?;
-
-    // Crea ZIP in memoria
-    let mut buffer = Cursor::new(Vec::new());
-    {
-        let mut zip = ZipWriter::new(&mut buffer);
-        let options = SimpleFileOptions::default()
-            .compression_method(zip::CompressionMethod::Deflated)
-            .compression_level(Some(6));
-
-        for (filename, data) in pages {
-            // Usa il nome base del PDF come prefisso cartella
-            let path_in_zip = format!("{}/{}", base_name, filename);
-            zip.start_file(&path_in_zip, options)
-                .map_err(|e| AppError::Internal(format!("Errore creazione ZIP: {}", e)))?;
-            zip.write_all(&data)
-                .map_err(|e| AppError::Internal(format!("Errore scrittura ZIP: {}", e)))?;
-        }
+    let mut zip = ZipWriter::new(std::fs::File::create(output_path)?);
+    let options = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .compression_level(Some(6));
 
-        zip.finish()
-            .map_err(|e| AppError::Internal(format!("Errore finalizzazione ZIP: {}", e)))?;
+    for page in 1..=page_count {
+        let page_path = run_pdftoppm(
+            input_path,
+            &temp_dir.path().join(format!("page_{:03}", page)),
+            output_format,
+            page,
+            dpi.unwrap_or(150),
+        )?;
+
+        // Usa il nome base del PDF come prefisso cartella
+        let path_in_zip = format!("{}/page_{:03}.{}", base_name, page, output_format);
+        zip.start_file(&path_in_zip, options)
+            .map_err(|e| AppError::Internal(format!("Errore creazione ZIP: {}", e)))?;
+        let mut page_file = std::fs::File::open(&page_path)?;
+        std::io::copy(&mut page_file, &mut zip)
+            .map_err(|e| AppError::Internal(format!("Errore scrittura ZIP: {}", e)))?;
+        std::fs::remove_file(&page_path).ok();
     }
 
-    Ok(buffer.into_inner())
+    zip.finish()
+        .map_err(|e| AppError::Internal(format!("Errore finalizzazione ZIP: {}", e)))?;
+
+    Ok(())
 }
@@ -1,31 +1,46 @@
 use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::sync::Arc;
 
-use axum::{middleware, Router};
+use axum::{extract::DefaultBodyLimit, middleware, Router};
 use tower_http::{
     cors::{Any, CorsLayer},
     trace::TraceLayer,
 };
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use utoipa::OpenApi;
 use utoipa_scalar::{Scalar, Servable};
 
 use converty::config::Config;
 use converty::db;
 use converty::db::api_keys::{
-    self, ApiKey, ApiKeyCreated, ApiKeyRole, CreateApiKeyRequest, UpdateApiKeyRequest,
+    self, ApiKey, ApiKeyCreated, ApiKeyRole, ApiKeyRotated, CreateApiKeyRequest,
+    RotateApiKeyRequest, UpdateApiKeyRequest,
 };
+use converty::db::audit::{AuditEntry, AuditListResponse, AuditQuery};
 use converty::db::jobs::{JobRecord, JobsListResponse, JobsQuery};
+use converty::db::organizations::{
+    AddMemberRequest, CreateOrganizationRequest, OrgRole, Organization, OrganizationMember,
+    UpdateMemberRequest, UpdateOrganizationRequest,
+};
+use converty::db::outbound::OutboundConfig;
+use converty::db::plans::{AssignPlanRequest, CreatePlanRequest, Plan, UpdatePlanRequest};
 use converty::db::stats::GuestConfig;
 use converty::middleware::auth::{self, AuthState};
-use converty::middleware::rate_limit;
+use converty::middleware::body_limit::{self, BodyLimitState};
+use converty::middleware::metrics;
+use converty::middleware::rate_limit::{self, IpRateLimitState, RateLimitState, RateLimiters};
+use converty::middleware::request_id;
 use converty::models::{JobPriority, *};
 use converty::routes;
 use converty::routes::admin::{ApiKeyWithStats, CleanupRequest, CleanupResponse, MessageResponse};
 #[cfg(feature = "google-auth")]
 use converty::routes::auth::{
-    CurrentUserResponse, GoogleAuthUrlResponse, UserInfo, UserStats as AuthUserStats,
+    CurrentUserResponse, GoogleAuthUrlResponse, LogoutResponse, RegeneratedApiKeyResponse,
+    UserInfo, UserStats as AuthUserStats,
 };
-use converty::services::queue;
+use converty::routes::organizations::OrganizationDetails;
+use converty::services::download_links::LinkSigner;
+use converty::services::{queue, storage, telemetry};
 use converty::utils::check_ffmpeg_available;
 
 #[cfg(feature = "google-auth")]
@@ -44,31 +59,70 @@ use converty::utils::check_ffmpeg_available;
         crate::routes::convert::convert_video,
         crate::routes::convert::convert_batch,
         crate::routes::health::health_check,
+        crate::routes::health::liveness,
+        crate::routes::health::readiness_check,
+        crate::routes::metrics::get_metrics,
         crate::routes::health::get_formats,
         crate::routes::stats::get_stats,
         crate::routes::stats::get_summary,
+        crate::routes::stats::get_timeseries,
         crate::routes::jobs::list_jobs,
         crate::routes::jobs::create_job,
         crate::routes::jobs::get_job_status,
         crate::routes::jobs::delete_job,
         crate::routes::jobs::download_job_result,
+        crate::routes::jobs::create_download_link,
+        crate::routes::uploads::create_upload,
+        crate::routes::uploads::get_upload_offset,
+        crate::routes::uploads::append_upload,
+        crate::routes::uploads::delete_upload,
         crate::routes::jobs::job_progress_stream,
         crate::routes::jobs::retry_job,
         crate::routes::jobs::cancel_job,
+        crate::routes::jobs::export_history,
+        crate::routes::jobs::admin_export_conversions,
         crate::routes::admin::list_api_keys,
         crate::routes::admin::create_api_key,
         crate::routes::admin::get_api_key,
         crate::routes::admin::update_api_key,
         crate::routes::admin::delete_api_key,
+        crate::routes::admin::rotate_api_key,
         crate::routes::admin::get_guest_config,
         crate::routes::admin::update_guest_config,
+        crate::routes::admin::get_outbound_config,
+        crate::routes::admin::update_outbound_config,
         crate::routes::admin::cleanup_old_data,
+        crate::routes::admin::list_audit_log,
+        crate::routes::organizations::list_organizations,
+        crate::routes::organizations::create_organization,
+        crate::routes::organizations::get_organization,
+        crate::routes::organizations::update_organization,
+        crate::routes::organizations::delete_organization,
+        crate::routes::organizations::add_member,
+        crate::routes::organizations::remove_member,
+        crate::routes::organizations::get_own_organization,
+        crate::routes::organizations::update_own_member,
+        crate::routes::organizations::remove_own_member,
+        crate::routes::plans::list_plans,
+        crate::routes::plans::create_plan,
+        crate::routes::plans::get_plan,
+        crate::routes::plans::update_plan,
+        crate::routes::plans::delete_plan,
+        crate::routes::plans::assign_plan,
         crate::routes::auth::get_google_auth_url,
         crate::routes::auth::google_callback,
+        crate::routes::auth::get_oidc_auth_url,
+        crate::routes::auth::oidc_callback,
         crate::routes::auth::get_current_user,
+        crate::routes::auth::regenerate_api_key,
+        crate::routes::auth::logout,
     ),
     components(schemas(
         HealthResponse,
+        LivenessResponse,
+        ReadinessResponse,
+        ReadinessCheck,
+        CheckStatus,
         FormatsResponse,
         FormatSupport,
         BatchConvertResponse,
@@ -76,43 +130,80 @@ use converty::utils::check_ffmpeg_available;
         FailedFile,
         JobResponse,
         JobCreatedResponse,
+        CreateDownloadLinkRequest,
+        DownloadLinkResponse,
         JobStatus,
         ConversionType,
         ErrorResponse,
         StatsResponse,
         GlobalStats,
         ApiKeyStats,
+        QuotaStatus,
+        QuotaCounter,
+        KeyScopes,
+        ScopeRoute,
         TypeStats,
+        LatencyPercentiles,
+        FormatPairStats,
+        ErrorCauseCount,
         FormatStats,
         TimeWindowStats,
         StatsSummary,
+        TimeseriesResponse,
+        TimeseriesSeries,
+        TimeseriesPoint,
+        TimeBucket,
+        TimeseriesGroupBy,
         ProgressUpdate,
         ApiKey,
         ApiKeyCreated,
+        ApiKeyRotated,
+        RotateApiKeyRequest,
         ApiKeyRole,
         CreateApiKeyRequest,
         UpdateApiKeyRequest,
         ApiKeyWithStats,
         GuestConfig,
+        OutboundConfig,
         CleanupRequest,
         CleanupResponse,
+        AuditEntry,
+        AuditListResponse,
+        AuditQuery,
         MessageResponse,
+        Organization,
+        OrganizationMember,
+        OrganizationDetails,
+        Plan,
+        CreatePlanRequest,
+        UpdatePlanRequest,
+        AssignPlanRequest,
+        OrgRole,
+        CreateOrganizationRequest,
+        UpdateOrganizationRequest,
+        AddMemberRequest,
+        UpdateMemberRequest,
         JobRecord,
         JobsListResponse,
         JobsQuery,
         JobPriority,
         GoogleAuthUrlResponse,
         CurrentUserResponse,
+        RegeneratedApiKeyResponse,
+        LogoutResponse,
         UserInfo,
         AuthUserStats,
     )),
     tags(
         (name = "Conversione", description = "Endpoints per convertire file"),
-        (name = "Sistema", description = "Health check e info"),
+        (name = "Sistema", description = "Health check, metriche e info"),
         (name = "Jobs", description = "Gestione job asincroni"),
+        (name = "Uploads", description = "Upload riprendibili (protocollo tus)"),
         (name = "Statistiche", description = "Statistiche conversioni"),
         (name = "Admin", description = "Gestione API Keys e configurazione"),
-        (name = "Auth", description = "Autenticazione Google OAuth"),
+        (name = "Organizzazioni", description = "Organizzazioni con quote condivise"),
+        (name = "Piani", description = "Piani e limiti assegnati alle API Key"),
+        (name = "Auth", description = "Autenticazione Google OAuth e OIDC"),
     ),
     servers(
         (url = "https://convapi.gavatech.org", description = "Server produzione"),
@@ -141,28 +232,63 @@ struct ApiDoc;
         crate::routes::convert::convert_video,
         crate::routes::convert::convert_batch,
         crate::routes::health::health_check,
+        crate::routes::health::liveness,
+        crate::routes::health::readiness_check,
+        crate::routes::metrics::get_metrics,
         crate::routes::health::get_formats,
         crate::routes::stats::get_stats,
         crate::routes::stats::get_summary,
+        crate::routes::stats::get_timeseries,
         crate::routes::jobs::list_jobs,
         crate::routes::jobs::create_job,
         crate::routes::jobs::get_job_status,
         crate::routes::jobs::delete_job,
         crate::routes::jobs::download_job_result,
+        crate::routes::jobs::create_download_link,
+        crate::routes::uploads::create_upload,
+        crate::routes::uploads::get_upload_offset,
+        crate::routes::uploads::append_upload,
+        crate::routes::uploads::delete_upload,
         crate::routes::jobs::job_progress_stream,
         crate::routes::jobs::retry_job,
         crate::routes::jobs::cancel_job,
+        crate::routes::jobs::export_history,
+        crate::routes::jobs::admin_export_conversions,
         crate::routes::admin::list_api_keys,
         crate::routes::admin::create_api_key,
         crate::routes::admin::get_api_key,
         crate::routes::admin::update_api_key,
         crate::routes::admin::delete_api_key,
+        crate::routes::admin::rotate_api_key,
         crate::routes::admin::get_guest_config,
         crate::routes::admin::update_guest_config,
+        crate::routes::admin::get_outbound_config,
+        crate::routes::admin::update_outbound_config,
         crate::routes::admin::cleanup_old_data,
+        crate::routes::admin::list_audit_log,
+        crate::routes::organizations::list_organizations,
+        crate::routes::organizations::create_organization,
+        crate::routes::organizations::get_organization,
+        crate::routes::organizations::update_organization,
+        crate::routes::organizations::delete_organization,
+        crate::routes::organizations::add_member,
+        crate::routes::organizations::remove_member,
+        crate::routes::organizations::get_own_organization,
+        crate::routes::organizations::update_own_member,
+        crate::routes::organizations::remove_own_member,
+        crate::routes::plans::list_plans,
+        crate::routes::plans::create_plan,
+        crate::routes::plans::get_plan,
+        crate::routes::plans::update_plan,
+        crate::routes::plans::delete_plan,
+        crate::routes::plans::assign_plan,
     ),
     components(schemas(
         HealthResponse,
+        LivenessResponse,
+        ReadinessResponse,
+        ReadinessCheck,
+        CheckStatus,
         FormatsResponse,
         FormatSupport,
         BatchConvertResponse,
@@ -170,27 +296,59 @@ struct ApiDoc;
         FailedFile,
         JobResponse,
         JobCreatedResponse,
+        CreateDownloadLinkRequest,
+        DownloadLinkResponse,
         JobStatus,
         ConversionType,
         ErrorResponse,
         StatsResponse,
         GlobalStats,
         ApiKeyStats,
+        QuotaStatus,
+        QuotaCounter,
+        KeyScopes,
+        ScopeRoute,
         TypeStats,
+        LatencyPercentiles,
+        FormatPairStats,
+        ErrorCauseCount,
         FormatStats,
         TimeWindowStats,
         StatsSummary,
+        TimeseriesResponse,
+        TimeseriesSeries,
+        TimeseriesPoint,
+        TimeBucket,
+        TimeseriesGroupBy,
         ProgressUpdate,
         ApiKey,
         ApiKeyCreated,
+        ApiKeyRotated,
+        RotateApiKeyRequest,
         ApiKeyRole,
         CreateApiKeyRequest,
         UpdateApiKeyRequest,
         ApiKeyWithStats,
         GuestConfig,
+        OutboundConfig,
         CleanupRequest,
         CleanupResponse,
+        AuditEntry,
+        AuditListResponse,
+        AuditQuery,
         MessageResponse,
+        Organization,
+        OrganizationMember,
+        OrganizationDetails,
+        Plan,
+        CreatePlanRequest,
+        UpdatePlanRequest,
+        AssignPlanRequest,
+        OrgRole,
+        CreateOrganizationRequest,
+        UpdateOrganizationRequest,
+        AddMemberRequest,
+        UpdateMemberRequest,
         JobRecord,
         JobsListResponse,
         JobsQuery,
@@ -198,10 +356,13 @@ struct ApiDoc;
     )),
     tags(
         (name = "Conversione", description = "Endpoints per convertire file"),
-        (name = "Sistema", description = "Health check e info"),
+        (name = "Sistema", description = "Health check, metriche e info"),
         (name = "Jobs", description = "Gestione job asincroni"),
+        (name = "Uploads", description = "Upload riprendibili (protocollo tus)"),
         (name = "Statistiche", description = "Statistiche conversioni"),
         (name = "Admin", description = "Gestione API Keys e configurazione"),
+        (name = "Organizzazioni", description = "Organizzazioni con quote condivise"),
+        (name = "Piani", description = "Piani e limiti assegnati alle API Key"),
     ),
     servers(
         (url = "https://convapi.gavatech.org", description = "Server produzione"),
@@ -236,14 +397,8 @@ async fn main() {
     // Carica variabili da .env
     dotenvy::dotenv().ok();
 
-    // Inizializza logging
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "converty=info,tower_http=info".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    // Inizializza logging ed eventuale export delle tracce
+    let telemetry = telemetry::init();
 
     // Carica configurazione
     let config = Config::from_env();
@@ -290,16 +445,34 @@ async fn main() {
         tracing::warn!("FFmpeg non trovato - conversione audio/video disabilitata");
     }
 
-    // Crea rate limiter (100 richieste/minuto per default)
-    let rate_limiter = rate_limit::create_rate_limiter(100);
-
-    // Crea job queue con broadcast channel per progress
-    let (job_queue, progress_tx) = queue::create_job_queue(db_pool.clone());
+    // Rate limiter per IP (prima dell'autenticazione) e per API Key / IP guest
+    // (quote lette dal database)
+    let rate_limiters = RateLimiters::new();
 
     // Crea directory temporanea
     std::fs::create_dir_all(&config.temp_dir).ok();
 
-    // CORS layer - espone Content-Disposition per il download
+    // Storage per input e risultati dei job (locale o S3)
+    let storage = storage::from_config(&config).expect("Configurazione storage non valida");
+    tracing::info!("Storage job: {:?}", storage);
+
+    // Signer per i link di download firmati
+    if config.signing_secret.is_none() {
+        tracing::warn!(
+            "CONVERTY_SIGNING_SECRET non impostato: i link di download firmati non sopravvivono al riavvio"
+        );
+    }
+    let links = LinkSigner::from_config(&config);
+
+    // Crea job queue con broadcast channel per progress
+    let (job_queue, progress_tx) = queue::create_job_queue(
+        db_pool.clone(),
+        storage.clone(),
+        config.temp_dir.join("work"),
+        links,
+    );
+
+    // CORS layer - espone Content-Disposition e header di range/cache per il download
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
@@ -307,14 +480,69 @@ async fn main() {
         .expose_headers([
             axum::http::header::CONTENT_DISPOSITION,
             axum::http::header::CONTENT_TYPE,
+            axum::http::header::CONTENT_LENGTH,
+            axum::http::header::CONTENT_RANGE,
+            axum::http::header::ACCEPT_RANGES,
+            axum::http::header::ETAG,
+            axum::http::header::LAST_MODIFIED,
+            axum::http::header::LOCATION,
+            routes::uploads::TUS_RESUMABLE,
+            routes::uploads::UPLOAD_OFFSET,
+            routes::uploads::UPLOAD_LENGTH,
+            routes::uploads::UPLOAD_METADATA,
+            routes::uploads::UPLOAD_EXPIRES,
+            rate_limit::RATELIMIT_LIMIT,
+            rate_limit::RATELIMIT_REMAINING,
+            rate_limit::RATELIMIT_RESET,
+            axum::http::header::RETRY_AFTER,
+            auth::API_KEY_EXPIRES_AT,
+            auth::GUEST_TOKEN,
+            request_id::REQUEST_ID,
         ]);
 
+    if !config.trusted_proxies.is_empty() {
+        tracing::info!(
+            "Proxy fidati: {:?} (IP del client da {:?})",
+            config.trusted_proxies,
+            config.client_ip_header
+        );
+    }
+
     // Auth state per middleware
     let auth_state = AuthState {
         db: db_pool.clone(),
+        trusted_proxies: Arc::new(config.trusted_proxies.clone()),
+        client_ip_header: config.client_ip_header,
+    };
+
+    let rate_limit_state = RateLimitState {
+        db: db_pool.clone(),
+        limiters: rate_limiters.clone(),
+    };
+
+    let ip_rate_limit_state = IpRateLimitState {
+        limiters: rate_limiters.clone(),
+        per_minute: NonZeroU32::new(config.ip_rate_limit_per_minute),
+        trusted_proxies: Arc::new(config.trusted_proxies.clone()),
+        client_ip_header: config.client_ip_header,
+    };
+
+    // Limite upload: sostituisce quello di default di axum (2 MB)
+    let body_limit_state = BodyLimitState {
+        db: db_pool.clone(),
+        default_max_mb: config.max_file_size_mb,
     };
 
     // API routes con middleware
+    let metrics_routes = routes::metrics::router(
+        db_pool.clone(),
+        job_queue.clone(),
+        config.metrics_token.clone(),
+    );
+
+    let probe_routes =
+        routes::health::probes_router(db_pool.clone(), job_queue.clone(), config.clone());
+
     let api_routes = routes::create_router(
         job_queue,
         progress_tx,
@@ -324,21 +552,38 @@ async fn main() {
         config.google_client_secret.clone(),
         config.frontend_url.clone(),
     )
+    .layer(DefaultBodyLimit::disable())
+    .layer(middleware::from_fn_with_state(
+        body_limit_state,
+        body_limit::enforce_body_limit,
+    ))
+    .layer(middleware::from_fn_with_state(
+        rate_limit_state,
+        rate_limit::rate_limit_middleware,
+    ))
     .layer(middleware::from_fn_with_state(
         auth_state,
         auth::api_key_auth,
     ))
-    .layer(middleware::from_fn(move |req, next| {
-        let limiter = rate_limiter.clone();
-        async move { rate_limit::rate_limit_middleware(limiter, req, next).await }
-    }));
+    .layer(middleware::from_fn_with_state(
+        ip_rate_limit_state,
+        rate_limit::ip_rate_limit_middleware,
+    ));
 
     // Costruisci router completo con Scalar API docs
+    let max_upload_size = config.max_file_size_bytes();
     let app = Router::new()
         .merge(Scalar::with_url("/scalar", ApiDoc::openapi()))
         .merge(api_routes)
-        .layer(TraceLayer::new_for_http())
+        .merge(metrics_routes)
+        .merge(probe_routes)
+        .layer(middleware::from_fn(metrics::track_requests))
+        .layer(TraceLayer::new_for_http().make_span_with(request_id::make_span))
+        .layer(middleware::from_fn(request_id::propagate_request_id))
         .layer(cors)
+        .layer(middleware::from_fn(move |req, next| {
+            routes::uploads::tus_options(max_upload_size, req, next)
+        }))
         .into_make_service_with_connect_info::<SocketAddr>();
 
     // Avvia server
@@ -359,8 +604,12 @@ async fn main() {
     tracing::info!("----------------------------------------");
     tracing::info!("Endpoints Pubblici:");
     tracing::info!("  GET  /api/v1/health           - Health check");
+    tracing::info!("  GET  /health/live             - Liveness probe");
+    tracing::info!("  GET  /health/ready            - Readiness probe");
+    tracing::info!("  GET  /metrics                 - Metriche Prometheus");
     tracing::info!("  GET  /api/v1/formats          - Formati supportati");
     tracing::info!("  GET  /api/v1/stats/summary    - Statistiche pubbliche");
+    tracing::info!("  GET  /api/v1/stats/timeseries - Statistiche nel tempo");
     tracing::info!("----------------------------------------");
     tracing::info!("Endpoints Conversione:");
     tracing::info!("  POST /api/v1/convert/image    - Converti immagine");
@@ -375,29 +624,46 @@ async fn main() {
     tracing::info!("  GET  /api/v1/jobs/:id         - Stato job");
     tracing::info!("  GET  /api/v1/jobs/:id/progress- SSE progress stream");
     tracing::info!("  GET  /api/v1/jobs/:id/download- Scarica risultato");
+    tracing::info!("  POST /api/v1/jobs/:id/link    - Link download firmato");
     tracing::info!("  DEL  /api/v1/jobs/:id         - Elimina job");
+    tracing::info!("  GET  /api/v1/jobs/history/export - Esporta cronologia (CSV/NDJSON)");
+    tracing::info!("  POST /api/v1/uploads          - Upload riprendibile (tus)");
     tracing::info!("----------------------------------------");
     tracing::info!("Endpoints Admin:");
     tracing::info!("  GET  /api/v1/admin/keys       - Lista API Keys");
     tracing::info!("  POST /api/v1/admin/keys       - Crea API Key");
     tracing::info!("  PUT  /api/v1/admin/keys/:id   - Modifica API Key");
     tracing::info!("  DEL  /api/v1/admin/keys/:id   - Elimina API Key");
+    tracing::info!("  POST /api/v1/admin/keys/:id/rotate - Ruota API Key");
+    tracing::info!("  GET  /api/v1/admin/audit      - Registro di audit");
+    tracing::info!("  GET  /api/v1/admin/conversions/export - Esporta tutte le conversioni");
+    tracing::info!("  *    /api/v1/admin/organizations - Gestione organizzazioni");
+    tracing::info!("  GET  /api/v1/organization  - Organizzazione corrente");
+    tracing::info!("  *    /api/v1/admin/plans      - Gestione piani");
     tracing::info!("  GET  /api/v1/admin/guest      - Config guest");
     tracing::info!("  PUT  /api/v1/admin/guest      - Modifica guest");
     tracing::info!("  POST /api/v1/admin/cleanup    - Pulisci vecchi dati");
     tracing::info!("----------------------------------------");
     tracing::info!("Endpoints Auth:");
     tracing::info!("  POST /api/v1/auth/google      - Login con Google");
+    tracing::info!("  GET  /api/v1/auth/oidc/url    - Login con provider OIDC");
     tracing::info!("  GET  /api/v1/auth/me          - Info utente corrente");
+    tracing::info!("  POST /api/v1/auth/me/api-key  - Rigenera API Key");
+    tracing::info!("  POST /api/v1/auth/logout      - Revoca sessione");
     tracing::info!("----------------------------------------");
     if config.google_client_id.is_some() {
         tracing::info!("Google OAuth: Configurato");
     } else {
         tracing::warn!("Google OAuth: NON configurato (imposta GOOGLE_CLIENT_ID)");
     }
+    if let Some(oidc) = &config.oidc {
+        tracing::info!("OIDC: Configurato ({})", oidc.issuer_url);
+    }
 
     // Task background per cleanup job vecchi (ogni ora)
     let cleanup_pool = db_pool.clone();
+    let cleanup_storage = storage.clone();
+    let cleanup_config = config.clone();
     tokio::spawn(async move {
         loop {
             tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
@@ -410,13 +676,30 @@ async fn main() {
                         files.len()
                     );
                     for file in files {
-                        if let Err(e) = std::fs::remove_file(&file) {
+                        if let Err(e) = cleanup_storage.delete(&file).await {
                             tracing::warn!("Errore rimozione file {}: {}", file, e);
                         }
                     }
                 }
                 Err(e) => tracing::error!("Errore cleanup: {}", e),
             }
+            if let Err(e) = converty::db::download_links::cleanup_expired_links(&cleanup_pool).await
+            {
+                tracing::error!("Errore cleanup link di download: {}", e);
+            }
+            match converty::db::uploads::cleanup_expired_uploads(&cleanup_pool).await {
+                Ok(ids) => {
+                    for id in ids {
+                        let path = routes::uploads::upload_path(&cleanup_config, &id);
+                        tokio::fs::remove_file(path).await.ok();
+                    }
+                }
+                Err(e) => tracing::error!("Errore cleanup upload scaduti: {}", e),
+            }
+            if let Err(e) = converty::db::sessions::cleanup_expired_sessions(&cleanup_pool).await {
+                tracing::error!("Errore cleanup sessioni scadute: {}", e);
+            }
+            rate_limiters.retain_recent(std::time::Duration::from_secs(600));
         }
     });
 
@@ -463,4 +746,5 @@ async fn main() {
         .unwrap();
 
     tracing::info!("Server arrestato correttamente.");
+    telemetry.shutdown();
 }
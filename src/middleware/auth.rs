@@ -1,27 +1,55 @@
 use axum::{
     extract::{ConnectInfo, Request, State},
-    http::{header, StatusCode},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
     middleware::Next,
     response::Response,
     Extension, Json,
 };
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
 use serde_json::json;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 
 use crate::db::api_keys::{self, ApiKeyRole};
+use crate::db::organizations::{self, OrgRole};
+use crate::db::plans;
+use crate::db::sessions::{self, SESSION_TOKEN_PREFIX};
 use crate::db::DbPool;
-use crate::models::AuthInfo;
+use crate::models::{AuthInfo, KeyScopes, OrgMembership, QuotaLimits, ScopeRoute};
+use crate::utils::client_ip::{resolve_client_ip, ForwardedHeader};
+
+/// Header con il token di sessione guest, restituito alla creazione di un job
+pub const GUEST_TOKEN: HeaderName = HeaderName::from_static("x-guest-token");
+
+/// Header con la scadenza del segreto usato, inviato quando è vicina
+pub const API_KEY_EXPIRES_AT: HeaderName = HeaderName::from_static("x-api-key-expires-at");
+
+/// Giorni prima della scadenza da cui inviare [`API_KEY_EXPIRES_AT`]
+const EXPIRY_WARNING_DAYS: i64 = 7;
+
+/// Route di logout: revoca la sessione anche per le chiavi in sola lettura
+const LOGOUT_PATH: &str = "/api/v1/auth/logout";
+
+/// Risposta d'errore del middleware di autenticazione
+type AuthRejection = (StatusCode, Json<serde_json::Value>);
 
 /// Stato per il middleware di autenticazione
 #[derive(Clone)]
 pub struct AuthState {
     pub db: DbPool,
+    /// Proxy fidati per la risoluzione dell'IP del client
+    pub trusted_proxies: Arc<Vec<IpNet>>,
+    /// Header con l'IP del client impostato dai proxy fidati
+    pub client_ip_header: ForwardedHeader,
 }
 
 /// Middleware per autenticazione API Key con supporto guest
 ///
 /// L'API Key deve essere passata nell'header `X-API-Key`
-/// oppure come Authorization Bearer token
+/// oppure come Authorization Bearer token. Al posto della chiave è
+/// accettato anche un token di sessione (`cvs_...`) emesso al login, ma non
+/// nel parametro `api_key` della query string.
 ///
 /// Se nessuna API Key è fornita, l'utente è trattato come guest
 /// con limitazioni configurabili
@@ -31,24 +59,109 @@ pub async fn api_key_auth(
     mut request: Request,
     next: Next,
 ) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
-    let client_ip = Some(addr.ip().to_string());
+    let resolved_ip = resolve_client_ip(
+        addr.ip(),
+        request.headers(),
+        &state.trusted_proxies,
+        state.client_ip_header,
+    );
+    let (auth_info, expiry_warning) = authenticate(
+        &state,
+        request.headers(),
+        request.uri().query(),
+        resolved_ip,
+    )
+    .await?;
+
+    // Scope dell'API Key: gruppi di route consentiti
+    let scopes = &auth_info.scopes;
+    if let Some(route) = ScopeRoute::from_path(request.uri().path()) {
+        if !scopes.allows_route(route) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(json!({
+                    "error": format!("API Key non abilitata per le route '{}'", route),
+                    "status": 403
+                })),
+            ));
+        }
+    }
+
+    // Sola lettura su tutte le route, incluse impostazioni, organizzazioni e auth,
+    // tranne il logout: revocare la propria sessione deve restare possibile.
+    // Il ruolo viewer di un'organizzazione equivale a una chiave in sola lettura
+    let org_viewer = auth_info
+        .organization
+        .as_ref()
+        .is_some_and(|org| org.role == OrgRole::Viewer);
+    let is_logout = request.uri().path() == LOGOUT_PATH;
+    if !is_logout
+        && (!scopes.allows_method(request.method())
+            || (org_viewer && !KeyScopes::is_read_method(request.method())))
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "error": "API Key in sola lettura",
+                "status": 403
+            })),
+        ));
+    }
+
+    // Aggiungi informazioni autenticazione come extension
+    request.extensions_mut().insert(auth_info.clone());
+
+    // Per le route admin, inserisci anche il ruolo e l'id separatamente
+    request.extensions_mut().insert(auth_info.role.clone());
+    request
+        .extensions_mut()
+        .insert(auth_info.api_key_id.clone());
+
+    let mut response = next.run(request).await;
+    if let Some(expires_at) = expiry_warning {
+        if let Ok(value) = HeaderValue::from_str(&expires_at.to_rfc3339()) {
+            response.headers_mut().insert(API_KEY_EXPIRES_AT, value);
+        }
+    }
+
+    Ok(response)
+}
+
+/// Risolve le credenziali della richiesta (API Key, token di sessione o guest)
+///
+/// Ritorna anche la scadenza della chiave se è vicina.
+#[tracing::instrument(name = "auth", skip_all, fields(api_key_id = tracing::field::Empty, guest))]
+async fn authenticate(
+    state: &AuthState,
+    headers: &HeaderMap,
+    query: Option<&str>,
+    resolved_ip: IpAddr,
+) -> Result<(AuthInfo, Option<DateTime<Utc>>), AuthRejection> {
+    let client_ip = Some(resolved_ip.to_string());
 
     // Controlla header X-API-Key
-    let api_key_header = request
-        .headers()
-        .get("X-API-Key")
-        .and_then(|v| v.to_str().ok());
+    let api_key_header = headers.get("X-API-Key").and_then(|v| v.to_str().ok());
 
     // Controlla query parameter api_key
-    let api_key_query = request.uri().query().and_then(|q| {
+    let api_key_query = query.and_then(|q| {
         q.split('&')
             .find(|p| p.starts_with("api_key="))
             .map(|p| p.trim_start_matches("api_key="))
     });
 
+    // I token di sessione nella query finirebbero nei log e nel Referer
+    if api_key_query.is_some_and(|k| k.starts_with(SESSION_TOKEN_PREFIX)) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({
+                "error": "Token di sessione non accettato nella query string, usa l'header Authorization",
+                "status": 401
+            })),
+        ));
+    }
+
     // Controlla Authorization Bearer
-    let api_key_bearer = request
-        .headers()
+    let api_key_bearer = headers
         .get(header::AUTHORIZATION)
         .and_then(|v| v.to_str().ok())
         .and_then(|v| v.strip_prefix("Bearer "));
@@ -56,10 +169,29 @@ pub async fn api_key_auth(
     // Ottieni la chiave fornita
     let provided_key = api_key_header.or(api_key_query).or(api_key_bearer);
 
+    // Scadenza vicina della chiave (o del segreto precedente a una rotazione)
+    let mut expiry_warning = None;
+
     let auth_info = match provided_key {
         Some(key) => {
+            // Token di sessione (frontend) o API key
+            let is_session = key.starts_with(SESSION_TOKEN_PREFIX);
+            let mut session_started_at = None;
+            let api_key = if is_session {
+                match sessions::find_session(&state.db, key).await {
+                    Ok(Some(session)) => {
+                        session_started_at = Some(session.created_at);
+                        api_keys::find_by_id(&state.db, &session.api_key_id).await
+                    }
+                    Ok(None) => Ok(None),
+                    Err(e) => Err(e),
+                }
+            } else {
+                api_keys::find_by_key(&state.db, key).await
+            };
+
             // Verifica API key nel database
-            match api_keys::find_by_key(&state.db, key).await {
+            match api_key {
                 Ok(Some(api_key)) => {
                     if !api_key.is_active {
                         return Err((
@@ -71,15 +203,106 @@ pub async fn api_key_auth(
                         ));
                     }
 
+                    let expires_at = if is_session {
+                        api_key.expires_at
+                    } else {
+                        api_key.secret_expires_at(key)
+                    };
+                    if let Some(expires_at) = expires_at {
+                        let now = chrono::Utc::now();
+                        if expires_at <= now {
+                            return Err((
+                                StatusCode::UNAUTHORIZED,
+                                Json(json!({
+                                    "error": "API Key scaduta",
+                                    "status": 401
+                                })),
+                            ));
+                        }
+                        if expires_at - now <= chrono::Duration::days(EXPIRY_WARNING_DAYS) {
+                            expiry_warning = Some(expires_at);
+                        }
+                    }
+
+                    // Chiavi limitate a reti specifiche (es. server con IP fissi)
+                    if !api_key.allows_ip(resolved_ip) {
+                        tracing::warn!(
+                            "API Key {} usata da IP non consentito: {}",
+                            api_key.key_prefix,
+                            resolved_ip
+                        );
+                        let _ = api_keys::record_ip_rejection(&state.db, &api_key.id).await;
+                        return Err((
+                            StatusCode::FORBIDDEN,
+                            Json(json!({
+                                "error": "Indirizzo IP non autorizzato per questa API Key",
+                                "status": 403
+                            })),
+                        ));
+                    }
+
                     // Aggiorna ultimo utilizzo
                     let _ = api_keys::update_last_used(&state.db, &api_key.id).await;
 
-                    AuthInfo {
+                    let organization =
+                        match organizations::find_membership(&state.db, &api_key.id).await {
+                            Ok(membership) => membership.map(|(org, role)| OrgMembership {
+                                quota: QuotaLimits::from(&org),
+                                id: org.id,
+                                role,
+                            }),
+                            Err(e) => {
+                                tracing::error!("Errore lettura organizzazione: {}", e);
+                                return Err((
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                    Json(json!({
+                                        "error": "Errore interno autenticazione",
+                                        "status": 500
+                                    })),
+                                ));
+                            }
+                        };
+
+                    let plan = match api_key.plan_id.as_deref() {
+                        Some(plan_id) => match plans::get_plan(&state.db, plan_id).await {
+                            Ok(plan) => plan,
+                            Err(e) => {
+                                tracing::error!("Errore lettura piano: {}", e);
+                                return Err((
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                    Json(json!({
+                                        "error": "Errore interno autenticazione",
+                                        "status": 500
+                                    })),
+                                ));
+                            }
+                        },
+                        None => None,
+                    };
+
+                    let quota = QuotaLimits::from(&api_key);
+                    let mut info = AuthInfo {
                         api_key_id: Some(api_key.id),
                         is_guest: false,
                         role: api_key.role,
                         client_ip,
+                        rate_limit: api_key.rate_limit.clamp(0, u32::MAX as i64) as u32,
+                        max_file_size_mb: api_key
+                            .max_file_size_mb
+                            .filter(|mb| *mb > 0)
+                            .map(|mb| mb as u64),
+                        quota,
+                        guest_token: None,
+                        scopes: api_key.scopes,
+                        organization,
+                        plan: None,
+                        session_started_at,
+                    };
+                    // I limiti del piano prevalgono su quelli della chiave
+                    if let Some(plan) = plan {
+                        info.apply_plan(plan);
                     }
+                    info
                 }
                 Ok(None) => {
                     return Err((
@@ -109,20 +332,28 @@ pub async fn api_key_auth(
                 is_guest: true,
                 role: ApiKeyRole::User,
                 client_ip,
+                rate_limit: 0,
+                max_file_size_mb: None,
+                quota: QuotaLimits::default(),
+                guest_token: headers
+                    .get(GUEST_TOKEN)
+                    .and_then(|v| v.to_str().ok())
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.to_string()),
+                scopes: KeyScopes::default(),
+                organization: None,
+                plan: None,
+                session_started_at: None,
             }
         }
     };
 
-    // Aggiungi informazioni autenticazione come extension
-    request.extensions_mut().insert(auth_info.clone());
-
-    // Per le route admin, inserisci anche il ruolo e l'id separatamente
-    request.extensions_mut().insert(auth_info.role.clone());
-    request
-        .extensions_mut()
-        .insert(auth_info.api_key_id.clone());
-
-    Ok(next.run(request).await)
+    let span = tracing::Span::current();
+    span.record("guest", auth_info.is_guest);
+    if let Some(id) = &auth_info.api_key_id {
+        span.record("api_key_id", id.as_str());
+    }
+    Ok((auth_info, expiry_warning))
 }
 
 /// Middleware per richiedere autenticazione (no guest)
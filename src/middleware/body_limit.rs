@@ -0,0 +1,92 @@
+//! Limite dimensione upload applicato a livello HTTP
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use http_body_util::Limited;
+
+use crate::db::{stats, DbPool};
+use crate::error::{AppError, Result};
+use crate::models::AuthInfo;
+
+/// Margine concesso oltre il limite per l'overhead multipart (boundary, header dei campi)
+const MULTIPART_OVERHEAD_BYTES: u64 = 64 * 1024;
+
+/// Stato per il middleware del limite upload
+#[derive(Clone)]
+pub struct BodyLimitState {
+    pub db: DbPool,
+    /// Limite globale da `Config.max_file_size_mb`
+    pub default_max_mb: u64,
+}
+
+/// Limite upload effettivo per la richiesta corrente
+///
+/// Inserito come extension dal middleware, usato dagli handler per
+/// verificare la dimensione dei singoli file ricevuti.
+#[derive(Clone, Copy, Debug)]
+pub struct UploadLimit {
+    pub max_mb: u64,
+}
+
+impl UploadLimit {
+    pub fn max_bytes(&self) -> u64 {
+        self.max_mb * 1024 * 1024
+    }
+}
+
+/// Middleware che applica il limite upload prima che il body venga letto
+///
+/// Il limite dipende dal chiamante: per i guest quello configurato dall'admin,
+/// per le API Key l'eventuale override della chiave, altrimenti quello globale.
+/// Le richieste con `Content-Length` oltre il limite vengono rifiutate subito;
+/// negli altri casi il body viene limitato durante la lettura.
+/// Si applica solo ai metodi con body (POST, PUT, PATCH).
+/// Va installato dopo (più interno di) `api_key_auth`.
+pub async fn enforce_body_limit(
+    State(state): State<BodyLimitState>,
+    Extension(auth): Extension<AuthInfo>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response> {
+    if !matches!(
+        *request.method(),
+        Method::POST | Method::PUT | Method::PATCH
+    ) {
+        return Ok(next.run(request).await);
+    }
+
+    let limit = if auth.is_guest {
+        let config = stats::get_guest_config(&state.db)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        UploadLimit {
+            max_mb: config.max_file_size_mb.max(0) as u64,
+        }
+    } else {
+        UploadLimit {
+            max_mb: auth.max_file_size_mb.unwrap_or(state.default_max_mb),
+        }
+    };
+    request.extensions_mut().insert(limit);
+
+    let max_body = limit.max_bytes() + MULTIPART_OVERHEAD_BYTES;
+
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len > max_body) {
+        return Err(AppError::FileTooLarge(limit.max_mb));
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = Body::new(Limited::new(body, max_body as usize));
+    Ok(next.run(Request::from_parts(parts, body)).await)
+}
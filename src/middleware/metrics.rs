@@ -0,0 +1,45 @@
+//! Metriche delle richieste HTTP
+
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::services::metrics;
+
+/// Conta le richieste e ne misura la latenza per route e status
+///
+/// Le richieste che non corrispondono a nessuna route finiscono sotto
+/// `unmatched`, così path arbitrari non creano nuove serie.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = method_label(request.method());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    metrics::record_request(method, &route, response.status().as_u16(), start.elapsed());
+
+    response
+}
+
+/// Metodi non standard raggruppati per limitare le serie
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        _ => "OTHER",
+    }
+}
@@ -1,2 +1,5 @@
 pub mod auth;
+pub mod body_limit;
+pub mod metrics;
 pub mod rate_limit;
+pub mod request_id;
@@ -1,35 +1,283 @@
-use axum::{extract::Request, http::StatusCode, middleware::Next, response::Response, Json};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
 use governor::{
-    clock::DefaultClock,
+    clock::{Clock, DefaultClock},
+    middleware::StateInformationMiddleware,
     state::{InMemoryState, NotKeyed},
     Quota, RateLimiter,
 };
-use serde_json::json;
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::num::NonZeroU32;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::db::{stats, DbPool};
+use crate::error::AppError;
+use crate::models::AuthInfo;
+use crate::utils::client_ip::{guest_bucket, resolve_client_ip, ForwardedHeader};
+
+pub const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+type KeyLimiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock, StateInformationMiddleware>;
+
+struct LimiterEntry {
+    per_minute: NonZeroU32,
+    limiter: Arc<KeyLimiter>,
+    last_seen: Instant,
+}
+
+/// Rate limiter per chiamante (API Key o IP guest)
+///
+/// Ogni chiave ha il proprio limiter con la quota configurata; se la quota
+/// cambia (modifica da admin) il limiter viene ricreato alla richiesta successiva.
+#[derive(Clone, Default)]
+pub struct RateLimiters {
+    entries: Arc<Mutex<HashMap<String, LimiterEntry>>>,
+}
+
+/// Esito della verifica del rate limit, con i valori per gli header `RateLimit-*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Secondi al ripristino completo della quota
+    pub reset_secs: u64,
+    /// Secondi di attesa se la richiesta è stata rifiutata
+    pub retry_after: Option<u64>,
+}
+
+impl RateLimiters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consuma una richiesta dalla quota di `key` (`per_minute` richieste al minuto)
+    pub fn check(&self, key: &str, per_minute: NonZeroU32) -> RateLimitStatus {
+        let limiter = {
+            let mut entries = self.entries.lock().unwrap();
+            let entry = entries
+                .entry(key.to_string())
+                .or_insert_with(|| LimiterEntry {
+                    per_minute,
+                    limiter: new_limiter(per_minute),
+                    last_seen: Instant::now(),
+                });
+            if entry.per_minute != per_minute {
+                entry.per_minute = per_minute;
+                entry.limiter = new_limiter(per_minute);
+            }
+            entry.last_seen = Instant::now();
+            entry.limiter.clone()
+        };
+
+        let limit = per_minute.get();
+        match limiter.check() {
+            Ok(snapshot) => {
+                let remaining = snapshot.remaining_burst_capacity();
+                // Una cella si rigenera ogni 60/limit secondi
+                let used = (limit - remaining) as u64;
+                RateLimitStatus {
+                    limit,
+                    remaining,
+                    reset_secs: (used * 60).div_ceil(limit as u64),
+                    retry_after: None,
+                }
+            }
+            Err(not_until) => {
+                let wait = not_until.wait_time_from(DefaultClock::default().now());
+                let wait_secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                RateLimitStatus {
+                    limit,
+                    remaining: 0,
+                    reset_secs: wait_secs.max(1),
+                    retry_after: Some(wait_secs.max(1)),
+                }
+            }
+        }
+    }
+
+    /// Rimuove i limiter non usati da almeno `max_idle`
+    pub fn retain_recent(&self, max_idle: Duration) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.last_seen.elapsed() < max_idle);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn new_limiter(per_minute: NonZeroU32) -> Arc<KeyLimiter> {
+    Arc::new(
+        RateLimiter::direct(Quota::per_minute(per_minute))
+            .with_middleware::<StateInformationMiddleware>(),
+    )
+}
+
+/// Stato per il middleware di rate limiting
+#[derive(Clone)]
+pub struct RateLimitState {
+    pub db: DbPool,
+    pub limiters: RateLimiters,
+}
 
-pub type SharedRateLimiter = Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>;
+/// Stato per il rate limiting per IP prima dell'autenticazione
+#[derive(Clone)]
+pub struct IpRateLimitState {
+    pub limiters: RateLimiters,
+    /// Richieste al minuto per IP (`None` = nessun limite)
+    pub per_minute: Option<NonZeroU32>,
+    /// Proxy fidati per la risoluzione dell'IP del client
+    pub trusted_proxies: Arc<Vec<IpNet>>,
+    pub client_ip_header: ForwardedHeader,
+}
 
-/// Crea un rate limiter con il limite specificato di richieste al minuto
-pub fn create_rate_limiter(requests_per_minute: u32) -> SharedRateLimiter {
-    let quota = Quota::per_minute(NonZeroU32::new(requests_per_minute).unwrap());
-    Arc::new(RateLimiter::direct(quota))
+/// Middleware per rate limiting per IP, prima dell'autenticazione
+///
+/// Limite economico (nessuna query) che vale per tutte le richieste, incluse
+/// quelle con API Key o token non validi: rallenta i tentativi di indovinare
+/// le credenziali. Va installato prima di (più esterno di) `api_key_auth`;
+/// la quota per API Key o piano resta a `rate_limit_middleware`.
+pub async fn ip_rate_limit_middleware(
+    State(state): State<IpRateLimitState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(per_minute) = state.per_minute else {
+        return next.run(request).await;
+    };
+    let ip = resolve_client_ip(
+        addr.ip(),
+        request.headers(),
+        &state.trusted_proxies,
+        state.client_ip_header,
+    );
+
+    let status = state
+        .limiters
+        .check(&format!("preauth:{}", guest_bucket(ip)), per_minute);
+    match status.retry_after {
+        // Gli header RateLimit-* delle richieste accettate sono quelli della quota per chiave
+        None => next.run(request).await,
+        Some(_) => rejected(status),
+    }
 }
 
-/// Middleware per rate limiting
+/// Middleware per rate limiting per API Key o IP guest
+///
+/// Usa `api_keys.rate_limit` per le chiavi e `guest_config.rate_limit_per_minute`
+/// per i guest (0 = nessun limite). Aggiunge gli header `RateLimit-Limit`,
+/// `RateLimit-Remaining` e `RateLimit-Reset`; in caso di rifiuto anche `Retry-After`.
+/// Va installato dopo (più interno di) `api_key_auth`.
 pub async fn rate_limit_middleware(
-    limiter: SharedRateLimiter,
+    State(state): State<RateLimitState>,
+    Extension(auth): Extension<AuthInfo>,
     request: Request,
     next: Next,
-) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
-    match limiter.check() {
-        Ok(_) => Ok(next.run(request).await),
-        Err(_) => Err((
-            StatusCode::TOO_MANY_REQUESTS,
-            Json(json!({
-                "error": "Troppe richieste. Riprova tra poco.",
-                "status": 429
-            })),
-        )),
+) -> Response {
+    let (key, per_minute) = if let Some(ref id) = auth.api_key_id {
+        (format!("key:{}", id), auth.rate_limit)
+    } else {
+        match stats::get_guest_config(&state.db).await {
+            Ok(config) => (
+                format!("ip:{}", auth.guest_bucket().as_deref().unwrap_or("unknown")),
+                config.rate_limit_per_minute.clamp(0, u32::MAX as i64) as u32,
+            ),
+            Err(e) => return AppError::Internal(e.to_string()).into_response(),
+        }
+    };
+
+    let Some(per_minute) = NonZeroU32::new(per_minute) else {
+        return next.run(request).await;
+    };
+
+    let status = state.limiters.check(&key, per_minute);
+    if status.retry_after.is_some() {
+        return rejected(status);
+    }
+
+    let mut response = next.run(request).await;
+    insert_headers(&mut response, status);
+    response
+}
+
+/// Risposta 429 con `Retry-After` e header `RateLimit-*`
+fn rejected(status: RateLimitStatus) -> Response {
+    let retry_after = status.retry_after.unwrap_or(status.reset_secs);
+    let mut response = AppError::RateLimited(format!(
+        "limite di {} richieste al minuto superato, riprova tra {} secondi",
+        status.limit, retry_after
+    ))
+    .into_response();
+    response
+        .headers_mut()
+        .insert(axum::http::header::RETRY_AFTER, retry_after.into());
+    insert_headers(&mut response, status);
+    response
+}
+
+fn insert_headers(response: &mut Response, status: RateLimitStatus) {
+    let headers = response.headers_mut();
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(status.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(status.remaining));
+    headers.insert(RATELIMIT_RESET, HeaderValue::from(status.reset_secs));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_after_quota() {
+        let limiters = RateLimiters::new();
+        let quota = NonZeroU32::new(3).unwrap();
+
+        let first = limiters.check("key:a", quota);
+        assert_eq!(first.remaining, 2);
+        assert_eq!(first.retry_after, None);
+        limiters.check("key:a", quota);
+        assert_eq!(limiters.check("key:a", quota).remaining, 0);
+
+        let rejected = limiters.check("key:a", quota);
+        assert!(rejected.retry_after.is_some_and(|s| (1..=20).contains(&s)));
+
+        // Le altre chiavi hanno una quota separata
+        assert_eq!(limiters.check("ip:127.0.0.1", quota).retry_after, None);
+    }
+
+    #[test]
+    fn test_quota_change_resets_limiter() {
+        let limiters = RateLimiters::new();
+        let one = NonZeroU32::new(1).unwrap();
+        limiters.check("key:a", one);
+        assert!(limiters.check("key:a", one).retry_after.is_some());
+
+        let status = limiters.check("key:a", NonZeroU32::new(10).unwrap());
+        assert_eq!(status.retry_after, None);
+        assert_eq!(status.limit, 10);
+    }
+
+    #[test]
+    fn test_retain_recent() {
+        let limiters = RateLimiters::new();
+        limiters.check("key:a", NonZeroU32::new(5).unwrap());
+        limiters.retain_recent(Duration::from_secs(60));
+        assert_eq!(limiters.len(), 1);
+        limiters.retain_recent(Duration::ZERO);
+        assert!(limiters.is_empty());
     }
 }
@@ -0,0 +1,71 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Span;
+use uuid::Uuid;
+
+use crate::services::telemetry;
+
+/// Header con l'ID di correlazione della richiesta
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Lunghezza massima di un ID ricevuto dal client
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// ID di correlazione della richiesta, disponibile come extension
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Middleware che assegna a ogni richiesta un `X-Request-ID`
+///
+/// Un ID ricevuto dal client (o da un proxy) viene riusato se valido,
+/// altrimenti ne viene generato uno. L'ID è restituito nella risposta.
+pub async fn propagate_request_id(mut request: Request, next: Next) -> Response {
+    let id = incoming_request_id(request.headers()).unwrap_or_else(|| Uuid::new_v4().to_string());
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+    response
+}
+
+/// Span HTTP per `TraceLayer`, con route, ID della richiesta e contesto
+/// di trace ricevuto (`traceparent`)
+pub fn make_span(request: &Request) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |p| p.as_str());
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map_or("", |id| id.0.as_str());
+
+    let span = tracing::info_span!(
+        "http_request",
+        otel.name = %format!("{} {}", request.method(), route),
+        method = %request.method(),
+        route,
+        uri = %request.uri().path(),
+        request_id,
+    );
+    telemetry::set_parent_from_headers(&span, request.headers());
+    span
+}
+
+fn incoming_request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| {
+            !v.is_empty()
+                && v.len() <= MAX_REQUEST_ID_LEN
+                && v.chars().all(|c| c.is_ascii_graphic())
+        })
+        .map(|v| v.to_string())
+}
@@ -1,6 +1,22 @@
 //! Authentication-related models
 
+use chrono::{DateTime, Utc};
+
 use crate::db::api_keys::ApiKeyRole;
+use crate::db::organizations::OrgRole;
+use crate::db::plans::Plan;
+use crate::db::stats::UsageScope;
+use crate::models::{KeyScopes, QuotaLimits};
+use crate::utils::client_ip;
+
+/// Organization the API key belongs to
+#[derive(Clone, Debug)]
+pub struct OrgMembership {
+    pub id: String,
+    pub role: OrgRole,
+    /// Quotas shared by all the organization's keys
+    pub quota: QuotaLimits,
+}
 
 /// Authenticated user information extracted from request
 #[derive(Clone, Debug)]
@@ -11,8 +27,24 @@ pub struct AuthInfo {
     pub is_guest: bool,
     /// Role of the authenticated user
     pub role: ApiKeyRole,
-    /// Client IP address
+    /// Client IP address (resolved through trusted proxies)
     pub client_ip: Option<String>,
+    /// Requests per minute allowed for the API key (0 = unlimited, unused for guests)
+    pub rate_limit: u32,
+    /// Per-key upload size override in MB (None = global limit)
+    pub max_file_size_mb: Option<u64>,
+    /// Daily/monthly quotas of the API key
+    pub quota: QuotaLimits,
+    /// Guest session token (`X-Guest-Token`), ties guest jobs to their creator
+    pub guest_token: Option<String>,
+    /// Fine-grained permissions of the API key (unrestricted for guests)
+    pub scopes: KeyScopes,
+    /// Organization membership of the API key, if any
+    pub organization: Option<OrgMembership>,
+    /// Plan assigned to the API key, already applied to the limits above
+    pub plan: Option<Plan>,
+    /// Login time, when authenticated with a session token (`cvs_...`)
+    pub session_started_at: Option<DateTime<Utc>>,
 }
 
 impl Default for AuthInfo {
@@ -22,6 +54,46 @@ impl Default for AuthInfo {
             is_guest: true,
             role: ApiKeyRole::User,
             client_ip: None,
+            rate_limit: 0,
+            max_file_size_mb: None,
+            quota: QuotaLimits::default(),
+            guest_token: None,
+            scopes: KeyScopes::default(),
+            organization: None,
+            plan: None,
+            session_started_at: None,
+        }
+    }
+}
+
+impl AuthInfo {
+    /// Key used to count guest usage: the client IP, or its /64 for IPv6
+    pub fn guest_bucket(&self) -> Option<String> {
+        let ip = self.client_ip.as_deref()?.parse().ok()?;
+        Some(client_ip::guest_bucket(ip))
+    }
+
+    /// Apply a plan: the limits it defines replace the key's own ones
+    pub fn apply_plan(&mut self, plan: Plan) {
+        if let Some(rate_limit) = plan.rate_limit {
+            self.rate_limit = rate_limit.clamp(0, u32::MAX as i64) as u32;
+        }
+        if let Some(max_mb) = plan.max_file_size_mb {
+            self.max_file_size_mb = Some(max_mb as u64);
+        }
+        if let Some(daily) = plan.daily_limit {
+            self.quota.daily_conversions = Some(daily as u64);
+        }
+        self.plan = Some(plan);
+    }
+
+    /// Keys whose jobs and conversions are visible to this user: the whole
+    /// organization for members, otherwise only the API key itself
+    pub fn usage_scope(&self) -> Option<UsageScope<'_>> {
+        match (&self.organization, &self.api_key_id) {
+            (Some(org), Some(_)) => Some(UsageScope::Organization(&org.id)),
+            (None, Some(key_id)) => Some(UsageScope::ApiKey(key_id)),
+            _ => None,
         }
     }
 }
@@ -58,12 +58,14 @@ pub struct Job {
     pub id: Uuid,
     pub status: JobStatus,
     pub conversion_type: ConversionType,
+    /// Chiave dell'input nello storage
     pub input_path: PathBuf,
     pub input_format: String,
     pub output_format: String,
     pub quality: Option<u8>,
     pub created_at: DateTime<Utc>,
     pub completed_at: Option<DateTime<Utc>>,
+    /// Chiave del risultato nello storage
     pub result_path: Option<PathBuf>,
     pub error: Option<String>,
     pub progress: u8,
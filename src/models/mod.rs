@@ -1,11 +1,15 @@
 pub mod auth;
 pub mod job;
+pub mod quota;
 pub mod request;
 pub mod response;
+pub mod scope;
 pub mod stats;
 
-pub use auth::AuthInfo;
+pub use auth::{AuthInfo, OrgMembership};
 pub use job::*;
+pub use quota::{QuotaCounter, QuotaLimits, QuotaStatus};
 pub use request::*;
 pub use response::*;
+pub use scope::{KeyScopes, ScopeRoute};
 pub use stats::*;
@@ -0,0 +1,81 @@
+//! Modelli delle quote delle API Key
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::db::api_keys::ApiKey;
+use crate::db::organizations::Organization;
+
+/// Quote configurate su un'API Key (None = illimitata)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuotaLimits {
+    pub daily_conversions: Option<u64>,
+    pub monthly_conversions: Option<u64>,
+    pub monthly_volume_mb: Option<u64>,
+    pub monthly_media_minutes: Option<u64>,
+}
+
+impl QuotaLimits {
+    /// Indica se non è configurata nessuna quota
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Valori <= 0 equivalgono a nessun limite
+fn limit(value: Option<i64>) -> Option<u64> {
+    value.filter(|v| *v > 0).map(|v| v as u64)
+}
+
+impl From<&ApiKey> for QuotaLimits {
+    fn from(key: &ApiKey) -> Self {
+        Self {
+            daily_conversions: limit(key.daily_limit),
+            monthly_conversions: limit(key.monthly_limit),
+            monthly_volume_mb: limit(key.monthly_volume_mb),
+            monthly_media_minutes: limit(key.monthly_media_minutes),
+        }
+    }
+}
+
+impl From<&Organization> for QuotaLimits {
+    fn from(org: &Organization) -> Self {
+        Self {
+            daily_conversions: limit(org.daily_limit),
+            monthly_conversions: limit(org.monthly_limit),
+            monthly_volume_mb: limit(org.monthly_volume_mb),
+            monthly_media_minutes: limit(org.monthly_media_minutes),
+        }
+    }
+}
+
+/// Consumo di una singola quota
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QuotaCounter {
+    pub used: u64,
+    /// Limite configurato (assente = illimitato)
+    pub limit: Option<u64>,
+    /// Momento in cui il contatore si azzera
+    #[schema(value_type = String, format = "date-time")]
+    pub reset_at: DateTime<Utc>,
+}
+
+impl QuotaCounter {
+    pub fn is_exceeded(&self) -> bool {
+        self.limit.is_some_and(|limit| self.used >= limit)
+    }
+}
+
+/// Stato delle quote di un'API Key
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QuotaStatus {
+    /// Conversioni riuscite oggi (UTC)
+    pub daily_conversions: QuotaCounter,
+    /// Conversioni riuscite nel mese corrente (UTC)
+    pub monthly_conversions: QuotaCounter,
+    /// MB di input convertiti nel mese corrente
+    pub monthly_volume_mb: QuotaCounter,
+    /// Minuti di audio/video convertiti nel mese corrente
+    pub monthly_media_minutes: QuotaCounter,
+}
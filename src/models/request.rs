@@ -90,6 +90,9 @@ pub struct CreateJobRequest {
     /// URL sorgente per scaricare il file (alternativa a upload)
     #[serde(default)]
     pub source_url: Option<String>,
+    /// ID di un upload tus completato (alternativa a upload)
+    #[serde(default)]
+    pub upload_id: Option<String>,
     /// Priorità del job (low, normal, high)
     #[serde(default)]
     pub priority: JobPriority,
@@ -101,6 +104,17 @@ pub struct CreateJobRequest {
     pub expires_in_hours: Option<i64>,
 }
 
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct CreateDownloadLinkRequest {
+    /// Validità del link in secondi (default da configurazione, massimo 7 giorni)
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>,
+    /// Il link può essere usato una sola volta: scarica il file intero, senza
+    /// richieste `Range` (niente seek nei player)
+    #[serde(default)]
+    pub single_use: bool,
+}
+
 #[derive(Debug, Clone, Deserialize, serde::Serialize, PartialEq, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ConversionType {
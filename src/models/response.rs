@@ -13,6 +13,49 @@ pub struct HealthResponse {
     pub ffmpeg_available: bool,
 }
 
+/// Risposta della liveness probe
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LivenessResponse {
+    /// Sempre "ok" finché il processo risponde
+    pub status: String,
+    pub version: String,
+}
+
+/// Esito di una verifica di readiness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    /// Funziona ma con limitazioni (es. tool opzionale mancante)
+    Warn,
+    /// Il nodo non deve ricevere traffico
+    Fail,
+}
+
+/// Singola verifica di readiness
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReadinessCheck {
+    /// Nome della verifica: database, temp_dir, ffmpeg, pdftoppm, pdfinfo, queue, drive
+    pub name: String,
+    pub status: CheckStatus,
+    /// Descrizione del problema o dello stato
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Dettagli specifici (versioni, spazio libero, permessi in uso...)
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    #[schema(value_type = Object)]
+    pub details: serde_json::Value,
+}
+
+/// Risposta della readiness probe
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    /// "ok" se nessuna verifica è fallita, altrimenti "degraded"
+    pub status: String,
+    pub version: String,
+    pub checks: Vec<ReadinessCheck>,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct FormatsResponse {
     pub image: FormatSupport,
@@ -78,6 +121,17 @@ pub struct JobResponse {
 pub struct JobCreatedResponse {
     pub id: String,
     pub message: String,
+    /// Token di sessione guest da inviare in `X-Guest-Token` per accedere al job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guest_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DownloadLinkResponse {
+    /// URL firmato, utilizzabile senza API Key
+    pub url: String,
+    pub expires_at: String,
+    pub single_use: bool,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -0,0 +1,202 @@
+//! Permission scopes for API keys
+
+use axum::http::Method;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Gruppi di route limitabili con gli scope
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ScopeRoute {
+    /// `/api/v1/convert/*` (escluso batch)
+    Convert,
+    /// `/api/v1/jobs*` e `/api/v1/uploads*`
+    Jobs,
+    /// `/api/v1/stats*`
+    Stats,
+    /// `/api/v1/convert/batch`
+    Batch,
+}
+
+impl ScopeRoute {
+    /// Gruppo di appartenenza di un path (None = non soggetto agli scope)
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.strip_prefix("/api/v1/")?;
+        let segment = path.split('/').next().unwrap_or_default();
+        match segment {
+            "convert" if path.starts_with("convert/batch") => Some(ScopeRoute::Batch),
+            "convert" => Some(ScopeRoute::Convert),
+            "jobs" | "uploads" => Some(ScopeRoute::Jobs),
+            "stats" => Some(ScopeRoute::Stats),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ScopeRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScopeRoute::Convert => write!(f, "convert"),
+            ScopeRoute::Jobs => write!(f, "jobs"),
+            ScopeRoute::Stats => write!(f, "stats"),
+            ScopeRoute::Batch => write!(f, "batch"),
+        }
+    }
+}
+
+/// Permessi granulari di un'API Key
+///
+/// Liste vuote e valori assenti non pongono restrizioni: una chiave senza
+/// scope configurati mantiene tutti i permessi del suo ruolo.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct KeyScopes {
+    /// Tipi di conversione consentiti (image, document, audio, video, pdf)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_types: Vec<String>,
+    /// Gruppi di route consentiti
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_routes: Vec<ScopeRoute>,
+    /// Solo richieste in lettura (GET/HEAD) su tutte le route
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+    /// Lato massimo in pixel di immagini e video (input e output)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_resolution: Option<u32>,
+    /// Durata massima in secondi di audio e video
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_duration_secs: Option<u64>,
+    /// Domini consentiti per `source_url` (`example.com`, `*.example.com`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_source_domains: Vec<String>,
+}
+
+impl KeyScopes {
+    /// Legge gli scope salvati come JSON (valore assente o non valido = nessuno scope)
+    pub fn from_json(value: Option<&str>) -> Self {
+        value
+            .and_then(|v| serde_json::from_str(v).ok())
+            .unwrap_or_default()
+    }
+
+    /// Serializza gli scope per il database (None se non ci sono restrizioni)
+    pub fn to_json(&self) -> Option<String> {
+        if self.is_unrestricted() {
+            None
+        } else {
+            serde_json::to_string(self).ok()
+        }
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn allows_type(&self, conversion_type: &str) -> bool {
+        self.allowed_types.is_empty()
+            || self
+                .allowed_types
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(conversion_type))
+    }
+
+    pub fn allows_route(&self, route: ScopeRoute) -> bool {
+        self.allowed_routes.is_empty() || self.allowed_routes.contains(&route)
+    }
+
+    pub fn allows_method(&self, method: &Method) -> bool {
+        !self.read_only || Self::is_read_method(method)
+    }
+
+    /// Metodi consentiti alle chiavi in sola lettura
+    pub fn is_read_method(method: &Method) -> bool {
+        matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+    }
+
+    pub fn allows_resolution(&self, width: u32, height: u32) -> bool {
+        self.max_resolution
+            .is_none_or(|max| width.max(height) <= max)
+    }
+
+    pub fn allows_duration_ms(&self, duration_ms: i64) -> bool {
+        self.max_duration_secs
+            .is_none_or(|max| duration_ms <= (max as i64).saturating_mul(1000))
+    }
+
+    pub fn allows_source_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+        self.allowed_source_domains.is_empty()
+            || self.allowed_source_domains.iter().any(|domain| {
+                let domain = domain.trim().to_lowercase();
+                match domain.strip_prefix("*.") {
+                    Some(suffix) => host == suffix || host.ends_with(&format!(".{}", suffix)),
+                    None => host == domain,
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_from_path() {
+        assert_eq!(
+            ScopeRoute::from_path("/api/v1/convert/image"),
+            Some(ScopeRoute::Convert)
+        );
+        assert_eq!(
+            ScopeRoute::from_path("/api/v1/convert/batch"),
+            Some(ScopeRoute::Batch)
+        );
+        assert_eq!(
+            ScopeRoute::from_path("/api/v1/jobs/abc/download"),
+            Some(ScopeRoute::Jobs)
+        );
+        assert_eq!(
+            ScopeRoute::from_path("/api/v1/stats"),
+            Some(ScopeRoute::Stats)
+        );
+        assert_eq!(ScopeRoute::from_path("/api/v1/auth/me"), None);
+        assert_eq!(ScopeRoute::from_path("/health"), None);
+    }
+
+    #[test]
+    fn test_default_scopes_allow_everything() {
+        let scopes = KeyScopes::default();
+        assert!(scopes.allows_type("video"));
+        assert!(scopes.allows_route(ScopeRoute::Batch));
+        assert!(scopes.allows_method(&Method::DELETE));
+        assert!(scopes.allows_resolution(10_000, 10_000));
+        assert!(scopes.allows_source_host("example.com"));
+        assert_eq!(scopes.to_json(), None);
+    }
+
+    #[test]
+    fn test_restricted_scopes() {
+        let scopes = KeyScopes {
+            allowed_types: vec!["image".to_string()],
+            allowed_routes: vec![ScopeRoute::Convert],
+            read_only: true,
+            max_resolution: Some(1920),
+            max_duration_secs: Some(60),
+            allowed_source_domains: vec!["*.example.com".to_string(), "cdn.test".to_string()],
+        };
+        assert!(scopes.allows_type("IMAGE"));
+        assert!(!scopes.allows_type("video"));
+        assert!(!scopes.allows_route(ScopeRoute::Jobs));
+        assert!(scopes.allows_method(&Method::GET));
+        assert!(!scopes.allows_method(&Method::POST));
+        assert!(scopes.allows_resolution(1920, 1080));
+        assert!(!scopes.allows_resolution(1080, 2000));
+        assert!(scopes.allows_duration_ms(60_000));
+        assert!(!scopes.allows_duration_ms(60_001));
+        assert!(scopes.allows_source_host("img.example.com"));
+        assert!(scopes.allows_source_host("example.com"));
+        assert!(scopes.allows_source_host("cdn.test"));
+        assert!(!scopes.allows_source_host("evil-example.com"));
+
+        let json = scopes.to_json();
+        assert_eq!(KeyScopes::from_json(json.as_deref()), scopes);
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Timelike, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -34,10 +34,21 @@ pub struct GlobalStats {
     pub total_output_bytes: u64,
     /// Tempo medio di elaborazione (ms)
     pub avg_processing_time_ms: f64,
+    /// Percentili del tempo di elaborazione delle conversioni riuscite
+    /// (ultimi 30 giorni, solo con API Key)
+    #[serde(default)]
+    pub processing_time_ms: LatencyPercentiles,
     /// Conversioni per tipo
     pub by_type: TypeStats,
     /// Conversioni per formato
     pub by_format: FormatStats,
+    /// Tempi e fallimenti per coppia di formati (ultimi 30 giorni, solo con API Key)
+    #[serde(default)]
+    pub by_format_pair: Vec<FormatPairStats>,
+    /// Cause di errore più frequenti (ultimi 30 giorni): di tutte le
+    /// conversioni per gli admin, altrimenti della propria API Key o organizzazione
+    #[serde(default)]
+    pub top_errors: Vec<ErrorCauseCount>,
     /// Statistiche ultime 24 ore
     pub last_24h: TimeWindowStats,
     /// Statistiche ultima ora
@@ -51,6 +62,61 @@ pub struct TypeStats {
     pub document: u64,
     pub audio: u64,
     pub video: u64,
+    /// Pagine PDF convertite in immagine
+    #[serde(default)]
+    pub pdf: u64,
+    /// Input SVG (contati anche in `image`)
+    #[serde(default)]
+    pub svg: u64,
+}
+
+/// Percentili di un tempo di elaborazione (ms)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema, Default)]
+pub struct LatencyPercentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+impl LatencyPercentiles {
+    /// Percentili nearest-rank di una lista di tempi già ordinata
+    pub fn from_sorted(sorted: &[i64]) -> Self {
+        let rank = |p: f64| {
+            if sorted.is_empty() {
+                return 0;
+            }
+            let index = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+            sorted[index.clamp(1, sorted.len()) - 1].max(0) as u64
+        };
+        Self {
+            p50: rank(50.0),
+            p90: rank(90.0),
+            p99: rank(99.0),
+        }
+    }
+}
+
+/// Tempi e affidabilità di una coppia di formati
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FormatPairStats {
+    pub input_format: String,
+    pub output_format: String,
+    pub conversions: u64,
+    pub failed: u64,
+    /// Percentuale di fallimenti (0-100)
+    pub failure_rate: f64,
+    /// Percentili del tempo di elaborazione delle conversioni riuscite
+    pub processing_time_ms: LatencyPercentiles,
+}
+
+/// Causa di errore normalizzata con il numero di occorrenze
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorCauseCount {
+    /// Messaggio senza percorsi, ID e numeri variabili
+    pub cause: String,
+    pub count: u64,
+    #[schema(value_type = String, format = "date-time")]
+    pub last_seen: DateTime<Utc>,
 }
 
 /// Statistiche per formato
@@ -97,6 +163,9 @@ pub struct ApiKeyStats {
 pub struct StatsResponse {
     pub global: GlobalStats,
     pub api_key_stats: Option<ApiKeyStats>,
+    /// Statistiche aggregate dell'organizzazione dell'API Key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization_stats: Option<ApiKeyStats>,
     pub recent_conversions: Vec<ConversionSummary>,
     pub server_uptime_seconds: u64,
     #[schema(value_type = String, format = "date-time")]
@@ -184,3 +253,165 @@ pub struct StatsSummary {
     /// Tempo di uptime server (secondi)
     pub uptime_seconds: u64,
 }
+
+/// Ampiezza degli intervalli di una serie temporale (UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeBucket {
+    Hour,
+    #[default]
+    Day,
+    /// Settimane da lunedì a domenica
+    Week,
+}
+
+impl TimeBucket {
+    /// Inizio dell'intervallo che contiene `at`
+    pub fn truncate(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let day = at.date_naive();
+        let start = match self {
+            TimeBucket::Hour => day.and_hms_opt(at.hour(), 0, 0),
+            TimeBucket::Day => day.and_hms_opt(0, 0, 0),
+            TimeBucket::Week => (day
+                - chrono::Duration::days(day.weekday().num_days_from_monday() as i64))
+            .and_hms_opt(0, 0, 0),
+        };
+        start.map(|dt| dt.and_utc()).unwrap_or(at)
+    }
+
+    /// Inizio dell'intervallo successivo a quello che inizia in `start`
+    pub fn next(&self, start: DateTime<Utc>) -> DateTime<Utc> {
+        start + self.duration()
+    }
+
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            TimeBucket::Hour => chrono::Duration::hours(1),
+            TimeBucket::Day => chrono::Duration::days(1),
+            TimeBucket::Week => chrono::Duration::weeks(1),
+        }
+    }
+
+    /// Periodo mostrato se `from` non è indicato
+    pub fn default_range(&self) -> chrono::Duration {
+        match self {
+            TimeBucket::Hour => chrono::Duration::hours(48),
+            TimeBucket::Day => chrono::Duration::days(30),
+            TimeBucket::Week => chrono::Duration::weeks(12),
+        }
+    }
+}
+
+/// Dimensione per cui suddividere una serie temporale
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeseriesGroupBy {
+    /// Tipo di conversione (`image`, `video`, ...)
+    Type,
+    /// Coppia di formati (`png->jpg`)
+    Format,
+    /// API Key (`guest` per le conversioni senza chiave)
+    ApiKey,
+}
+
+/// Query per le statistiche nel tempo
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TimeseriesQuery {
+    /// Inizio del periodo (RFC 3339 o `YYYY-MM-DD`)
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Fine del periodo, inclusa (RFC 3339 o `YYYY-MM-DD`, default: adesso)
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Ampiezza degli intervalli
+    #[serde(default)]
+    pub bucket: TimeBucket,
+    /// Suddivisione in più serie
+    #[serde(default)]
+    pub group_by: Option<TimeseriesGroupBy>,
+    /// Solo le conversioni di questa API Key (solo admin)
+    #[serde(default)]
+    pub api_key_id: Option<String>,
+}
+
+/// Valori aggregati di un intervallo
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct TimeseriesPoint {
+    /// Inizio dell'intervallo
+    #[schema(value_type = String, format = "date-time")]
+    pub timestamp: DateTime<Utc>,
+    pub conversions: u64,
+    pub successful: u64,
+    pub failed: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    /// Tempo medio di elaborazione (ms)
+    pub avg_processing_time_ms: f64,
+}
+
+/// Serie di punti per un valore della dimensione di raggruppamento
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TimeseriesSeries {
+    /// Valore del raggruppamento (assente senza `group_by`)
+    pub key: Option<String>,
+    /// Un punto per intervallo, anche se vuoto
+    pub points: Vec<TimeseriesPoint>,
+}
+
+/// Statistiche nel tempo
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TimeseriesResponse {
+    /// Inizio del primo intervallo
+    #[schema(value_type = String, format = "date-time")]
+    pub from: DateTime<Utc>,
+    /// Fine dell'ultimo intervallo (esclusa)
+    #[schema(value_type = String, format = "date-time")]
+    pub to: DateTime<Utc>,
+    pub bucket: TimeBucket,
+    pub group_by: Option<TimeseriesGroupBy>,
+    pub series: Vec<TimeseriesSeries>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_time_bucket_truncate() {
+        // Giovedì 15 ottobre 2026
+        let t = at("2026-10-15T17:42:10.5+00:00");
+        assert_eq!(TimeBucket::Hour.truncate(t), at("2026-10-15T17:00:00Z"));
+        assert_eq!(TimeBucket::Day.truncate(t), at("2026-10-15T00:00:00Z"));
+        assert_eq!(TimeBucket::Week.truncate(t), at("2026-10-12T00:00:00Z"));
+        // Il lunedì è già l'inizio della settimana, la domenica ne è la fine
+        assert_eq!(
+            TimeBucket::Week.truncate(at("2026-10-12T00:00:00Z")),
+            at("2026-10-12T00:00:00Z")
+        );
+        assert_eq!(
+            TimeBucket::Week.truncate(at("2026-10-18T23:59:59Z")),
+            at("2026-10-12T00:00:00Z")
+        );
+        assert_eq!(
+            TimeBucket::Week.next(at("2026-10-12T00:00:00Z")),
+            at("2026-10-19T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        assert_eq!(
+            LatencyPercentiles::from_sorted(&[]),
+            LatencyPercentiles::default()
+        );
+        let times: Vec<i64> = (1..=100).collect();
+        let p = LatencyPercentiles::from_sorted(&times);
+        assert_eq!((p.p50, p.p90, p.p99), (50, 90, 99));
+        let p = LatencyPercentiles::from_sorted(&[7]);
+        assert_eq!((p.p50, p.p90, p.p99), (7, 7, 7));
+    }
+}
@@ -1,5 +1,6 @@
 use axum::{
-    extract::{Path, State},
+    body::Bytes,
+    extract::{Path, Query, State},
     http::StatusCode,
     routing::{delete, get, post, put},
     Extension, Json, Router,
@@ -8,11 +9,28 @@ use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use crate::db::api_keys::{
-    self, ApiKey, ApiKeyCreated, ApiKeyRole, CreateApiKeyRequest, UpdateApiKeyRequest,
+    self, ApiKey, ApiKeyCreated, ApiKeyRole, ApiKeyRotated, CreateApiKeyRequest,
+    RotateApiKeyRequest, UpdateApiKeyRequest,
 };
-use crate::db::stats::{self, GuestConfig};
+use crate::db::audit::{self as audit_db, AuditListResponse, AuditQuery};
+use crate::db::outbound::{self, OutboundConfig};
+use crate::db::plans;
+use crate::db::stats::{self, GuestConfig, UsageScope};
 use crate::db::DbPool;
 use crate::error::{AppError, Result};
+use crate::models::{AuthInfo, QuotaLimits, QuotaStatus};
+use crate::services::audit;
+use crate::services::outbound::validate_rule;
+use crate::services::quota;
+use crate::utils::client_ip::parse_ip_net;
+use crate::utils::json_body::optional_json;
+use crate::utils::validation::parse_date_param;
+
+/// Periodo di grazia di default del segreto precedente a una rotazione
+const DEFAULT_ROTATION_GRACE_SECS: u64 = 24 * 3600;
+
+/// Periodo di grazia massimo (30 giorni)
+const MAX_ROTATION_GRACE_SECS: u64 = 30 * 24 * 3600;
 
 #[derive(Clone)]
 pub struct AdminState {
@@ -25,14 +43,20 @@ pub fn router(db: DbPool) -> Router {
         // API Keys management
         .route("/api/v1/admin/keys", get(list_api_keys))
         .route("/api/v1/admin/keys", post(create_api_key))
-        .route("/api/v1/admin/keys/{id}", get(get_api_key))
-        .route("/api/v1/admin/keys/{id}", put(update_api_key))
-        .route("/api/v1/admin/keys/{id}", delete(delete_api_key))
+        .route("/api/v1/admin/keys/:id", get(get_api_key))
+        .route("/api/v1/admin/keys/:id", put(update_api_key))
+        .route("/api/v1/admin/keys/:id", delete(delete_api_key))
+        .route("/api/v1/admin/keys/:id/rotate", post(rotate_api_key))
         // Guest configuration
         .route("/api/v1/admin/guest", get(get_guest_config))
         .route("/api/v1/admin/guest", put(update_guest_config))
+        // Outbound (source_url / webhook)
+        .route("/api/v1/admin/outbound", get(get_outbound_config))
+        .route("/api/v1/admin/outbound", put(update_outbound_config))
         // Maintenance
         .route("/api/v1/admin/cleanup", post(cleanup_old_data))
+        // Audit
+        .route("/api/v1/admin/audit", get(list_audit_log))
         .with_state(state)
 }
 
@@ -68,6 +92,7 @@ pub async fn list_api_keys(
     request_body = CreateApiKeyRequest,
     responses(
         (status = 201, description = "API Key creata", body = ApiKeyCreated),
+        (status = 400, description = "IP consentito o piano non valido"),
         (status = 401, description = "Non autorizzato"),
         (status = 403, description = "Solo admin"),
     ),
@@ -76,16 +101,29 @@ pub async fn list_api_keys(
 )]
 pub async fn create_api_key(
     State(state): State<AdminState>,
-    Extension(role): Extension<ApiKeyRole>,
-    Extension(creator_id): Extension<Option<String>>,
+    Extension(auth): Extension<AuthInfo>,
     Json(request): Json<CreateApiKeyRequest>,
 ) -> Result<(StatusCode, Json<ApiKeyCreated>)> {
-    require_admin(&role)?;
+    require_admin(&auth.role)?;
+    validate_allowed_ips(&request.allowed_ips)?;
+    validate_plan(&state.db, request.plan_id.as_deref()).await?;
 
-    let key = api_keys::create_api_key(&state.db, &request, creator_id.as_deref())
+    let key = api_keys::create_api_key(&state.db, &request, auth.api_key_id.as_deref())
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
+    // Nel registro lo stato salvato, senza la chiave in chiaro
+    let created = find_key(&state.db, &key.id).await?;
+    audit::record(
+        &state.db,
+        &auth,
+        "api_key.create",
+        Some(&key.id),
+        None,
+        audit::snapshot(&created),
+    )
+    .await;
+
     Ok((StatusCode::CREATED, Json(key)))
 }
 
@@ -113,21 +151,19 @@ pub async fn get_api_key(
     require_admin(&role)?;
 
     // Trova la chiave
-    let keys = api_keys::list_all(&state.db)
+    let key = api_keys::find_by_id(&state.db, &id)
         .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let key = keys
-        .into_iter()
-        .find(|k| k.id == id)
+        .map_err(|e| AppError::Internal(e.to_string()))?
         .ok_or_else(|| AppError::NotFound("API Key non trovata".to_string()))?;
 
-    // Ottieni statistiche
+    // Ottieni statistiche e consumo delle quote
     let stats = stats::get_api_key_stats(&state.db, &id)
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
+    let quota =
+        quota::quota_status(&state.db, UsageScope::ApiKey(&id), &QuotaLimits::from(&key)).await?;
 
-    Ok(Json(ApiKeyWithStats { key, stats }))
+    Ok(Json(ApiKeyWithStats { key, stats, quota }))
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -135,6 +171,7 @@ pub struct ApiKeyWithStats {
     #[serde(flatten)]
     pub key: ApiKey,
     pub stats: Option<crate::models::ApiKeyStats>,
+    pub quota: QuotaStatus,
 }
 
 /// Aggiorna API Key
@@ -147,6 +184,7 @@ pub struct ApiKeyWithStats {
     request_body = UpdateApiKeyRequest,
     responses(
         (status = 200, description = "API Key aggiornata"),
+        (status = 400, description = "IP consentito o piano non valido"),
         (status = 404, description = "Non trovata"),
         (status = 401, description = "Non autorizzato"),
         (status = 403, description = "Solo admin"),
@@ -156,17 +194,29 @@ pub struct ApiKeyWithStats {
 )]
 pub async fn update_api_key(
     State(state): State<AdminState>,
-    Extension(role): Extension<ApiKeyRole>,
+    Extension(auth): Extension<AuthInfo>,
     Path(id): Path<String>,
     Json(request): Json<UpdateApiKeyRequest>,
 ) -> Result<Json<MessageResponse>> {
-    require_admin(&role)?;
+    require_admin(&auth.role)?;
+    if let Some(ref ips) = request.allowed_ips {
+        validate_allowed_ips(ips)?;
+    }
+    validate_plan(
+        &state.db,
+        request.plan_id.as_deref().filter(|id| !id.is_empty()),
+    )
+    .await?;
 
+    let before = find_key(&state.db, &id).await?;
     let updated = api_keys::update_api_key(&state.db, &id, &request)
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
     if updated {
+        let after = find_key(&state.db, &id).await?;
+        let (old, new) = audit::diff(&before, &after);
+        audit::record(&state.db, &auth, "api_key.update", Some(&id), old, new).await;
         Ok(Json(MessageResponse {
             message: "API Key aggiornata".to_string(),
         }))
@@ -193,16 +243,26 @@ pub async fn update_api_key(
 )]
 pub async fn delete_api_key(
     State(state): State<AdminState>,
-    Extension(role): Extension<ApiKeyRole>,
+    Extension(auth): Extension<AuthInfo>,
     Path(id): Path<String>,
 ) -> Result<Json<MessageResponse>> {
-    require_admin(&role)?;
+    require_admin(&auth.role)?;
 
+    let before = find_key(&state.db, &id).await?;
     let deleted = api_keys::delete_api_key(&state.db, &id)
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
     if deleted {
+        audit::record(
+            &state.db,
+            &auth,
+            "api_key.delete",
+            Some(&id),
+            audit::snapshot(&before),
+            None,
+        )
+        .await;
         Ok(Json(MessageResponse {
             message: "API Key eliminata".to_string(),
         }))
@@ -211,6 +271,63 @@ pub async fn delete_api_key(
     }
 }
 
+/// Ruota il segreto di un'API Key
+///
+/// Restituisce un nuovo segreto; quello precedente continua a funzionare
+/// per il periodo di grazia richiesto, poi viene rifiutato.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/keys/{id}/rotate",
+    params(
+        ("id" = String, Path, description = "ID API Key")
+    ),
+    request_body = RotateApiKeyRequest,
+    responses(
+        (status = 200, description = "Nuovo segreto", body = ApiKeyRotated),
+        (status = 400, description = "Body JSON non valido"),
+        (status = 404, description = "Non trovata"),
+        (status = 401, description = "Non autorizzato"),
+        (status = 403, description = "Solo admin"),
+    ),
+    security(("api_key" = [])),
+    tag = "Admin"
+)]
+pub async fn rotate_api_key(
+    State(state): State<AdminState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<Json<ApiKeyRotated>> {
+    require_admin(&auth.role)?;
+
+    let request: RotateApiKeyRequest = optional_json(&body)?;
+    let grace_secs = request
+        .grace_period_secs
+        .unwrap_or(DEFAULT_ROTATION_GRACE_SECS)
+        .min(MAX_ROTATION_GRACE_SECS);
+
+    let rotated =
+        api_keys::rotate_api_key(&state.db, &id, chrono::Duration::seconds(grace_secs as i64))
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+            .ok_or_else(|| AppError::NotFound("API Key non trovata".to_string()))?;
+
+    audit::record(
+        &state.db,
+        &auth,
+        "api_key.rotate",
+        Some(&id),
+        None,
+        Some(serde_json::json!({
+            "key_prefix": rotated.key_prefix,
+            "previous_key_expires_at": rotated.previous_key_expires_at,
+        })),
+    )
+    .await;
+
+    Ok(Json(rotated))
+}
+
 /// Ottieni configurazione guest
 #[utoipa::path(
     get,
@@ -251,20 +368,99 @@ pub async fn get_guest_config(
 )]
 pub async fn update_guest_config(
     State(state): State<AdminState>,
-    Extension(role): Extension<ApiKeyRole>,
+    Extension(auth): Extension<AuthInfo>,
     Json(config): Json<GuestConfig>,
 ) -> Result<Json<MessageResponse>> {
-    require_admin(&role)?;
+    require_admin(&auth.role)?;
 
+    let before = stats::get_guest_config(&state.db)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
     stats::update_guest_config(&state.db, &config)
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
+    let (old, new) = audit::diff(&before, &config);
+    audit::record(&state.db, &auth, "guest_config.update", None, old, new).await;
+
     Ok(Json(MessageResponse {
         message: "Configurazione guest aggiornata".to_string(),
     }))
 }
 
+/// Ottieni allow/deny list per le richieste in uscita
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/outbound",
+    responses(
+        (status = 200, description = "Configurazione outbound", body = OutboundConfig),
+        (status = 401, description = "Non autorizzato"),
+        (status = 403, description = "Solo admin"),
+    ),
+    security(("api_key" = [])),
+    tag = "Admin"
+)]
+pub async fn get_outbound_config(
+    State(state): State<AdminState>,
+    Extension(role): Extension<ApiKeyRole>,
+) -> Result<Json<OutboundConfig>> {
+    require_admin(&role)?;
+
+    let config = outbound::get_outbound_config(&state.db)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(Json(config))
+}
+
+/// Aggiorna allow/deny list per le richieste in uscita
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/outbound",
+    request_body = OutboundConfig,
+    responses(
+        (status = 200, description = "Configurazione aggiornata"),
+        (status = 400, description = "Voce non valida"),
+        (status = 401, description = "Non autorizzato"),
+        (status = 403, description = "Solo admin"),
+    ),
+    security(("api_key" = [])),
+    tag = "Admin"
+)]
+pub async fn update_outbound_config(
+    State(state): State<AdminState>,
+    Extension(auth): Extension<AuthInfo>,
+    Json(config): Json<OutboundConfig>,
+) -> Result<Json<MessageResponse>> {
+    require_admin(&auth.role)?;
+
+    if let Some(invalid) = config
+        .allowed_hosts
+        .iter()
+        .chain(config.denied_hosts.iter())
+        .find(|entry| !validate_rule(entry))
+    {
+        return Err(AppError::BadRequest(format!(
+            "Voce non valida: '{}' (usa hostname, *.dominio, IP o CIDR)",
+            invalid
+        )));
+    }
+
+    let before = outbound::get_outbound_config(&state.db)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    outbound::update_outbound_config(&state.db, &config)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let (old, new) = audit::diff(&before, &config);
+    audit::record(&state.db, &auth, "outbound_config.update", None, old, new).await;
+
+    Ok(Json(MessageResponse {
+        message: "Configurazione outbound aggiornata".to_string(),
+    }))
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct CleanupRequest {
     /// Giorni di dati da mantenere (default: 30)
@@ -297,15 +493,25 @@ pub struct CleanupResponse {
 )]
 pub async fn cleanup_old_data(
     State(state): State<AdminState>,
-    Extension(role): Extension<ApiKeyRole>,
+    Extension(auth): Extension<AuthInfo>,
     Json(request): Json<CleanupRequest>,
 ) -> Result<Json<CleanupResponse>> {
-    require_admin(&role)?;
+    require_admin(&auth.role)?;
 
     let deleted = stats::cleanup_old_records(&state.db, request.days)
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
+    audit::record(
+        &state.db,
+        &auth,
+        "cleanup",
+        None,
+        None,
+        Some(serde_json::json!({ "days": request.days, "records_deleted": deleted })),
+    )
+    .await;
+
     Ok(Json(CleanupResponse {
         records_deleted: deleted,
         message: format!(
@@ -315,11 +521,95 @@ pub async fn cleanup_old_data(
     }))
 }
 
+/// Voci di audit restituite al massimo per pagina
+const MAX_AUDIT_LIMIT: i64 = 1000;
+
+/// Registro di audit delle operazioni admin e dei login
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/audit",
+    params(
+        ("actor_key_id" = Option<String>, Query, description = "Filtra per API Key che ha eseguito l'azione"),
+        ("action" = Option<String>, Query, description = "Azione esatta o prefisso con * (es. api_key.*)"),
+        ("target" = Option<String>, Query, description = "Filtra per oggetto dell'azione"),
+        ("since" = Option<String>, Query, description = "Da questa data (RFC 3339 o YYYY-MM-DD)"),
+        ("until" = Option<String>, Query, description = "Fino a questa data inclusa (RFC 3339 o YYYY-MM-DD)"),
+        ("limit" = Option<i64>, Query, description = "Limite risultati (default 100, massimo 1000)"),
+        ("offset" = Option<i64>, Query, description = "Offset per paginazione"),
+    ),
+    responses(
+        (status = 200, description = "Voci di audit", body = AuditListResponse),
+        (status = 400, description = "Data non valida"),
+        (status = 401, description = "Non autorizzato"),
+        (status = 403, description = "Solo admin"),
+    ),
+    security(("api_key" = [])),
+    tag = "Admin"
+)]
+pub async fn list_audit_log(
+    State(state): State<AdminState>,
+    Extension(role): Extension<ApiKeyRole>,
+    Query(mut query): Query<AuditQuery>,
+) -> Result<Json<AuditListResponse>> {
+    require_admin(&role)?;
+
+    // Date normalizzate come i timestamp salvati, così il confronto testuale
+    // funziona; una data come `until` include l'intera giornata
+    let normalize = |value: Option<String>, end_of_day: bool| -> Result<Option<String>> {
+        value
+            .filter(|v| !v.is_empty())
+            .map(|v| parse_date_param(&v, end_of_day).map(|dt| dt.to_rfc3339()))
+            .transpose()
+    };
+    query.since = normalize(query.since.take(), false)?;
+    query.until = normalize(query.until.take(), true)?;
+    query.limit = query.limit.clamp(1, MAX_AUDIT_LIMIT);
+    query.offset = query.offset.max(0);
+
+    let response = audit_db::list_entries(&state.db, &query)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(Json(response))
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct MessageResponse {
     pub message: String,
 }
 
+/// Verifica che gli IP consentiti siano indirizzi o CIDR validi
+fn validate_allowed_ips(ips: &[String]) -> Result<()> {
+    match ips.iter().find(|entry| parse_ip_net(entry).is_none()) {
+        Some(invalid) => Err(AppError::BadRequest(format!(
+            "IP non valido: '{}' (usa un indirizzo o un CIDR)",
+            invalid
+        ))),
+        None => Ok(()),
+    }
+}
+
+async fn validate_plan(db: &DbPool, plan_id: Option<&str>) -> Result<()> {
+    let Some(plan_id) = plan_id else {
+        return Ok(());
+    };
+    match plans::get_plan(db, plan_id).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(AppError::BadRequest(format!(
+            "Piano non trovato: '{}'",
+            plan_id
+        ))),
+        Err(e) => Err(AppError::Internal(e.to_string())),
+    }
+}
+
+async fn find_key(db: &DbPool, id: &str) -> Result<ApiKey> {
+    api_keys::find_by_id(db, id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("API Key non trovata".to_string()))
+}
+
 fn require_admin(role: &ApiKeyRole) -> Result<()> {
     if *role != ApiKeyRole::Admin {
         return Err(AppError::Forbidden(
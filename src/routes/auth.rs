@@ -1,19 +1,32 @@
 use axum::{
     extract::{Query, State},
+    http::{header, HeaderMap},
     response::Redirect,
-    routing::get,
+    routing::{get, post},
     Extension, Json, Router,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
 use utoipa::ToSchema;
 
-use crate::db::oauth_users::{self, GoogleUserInfo, OAuthUser};
-use crate::db::stats as db_stats;
+use crate::config::OidcConfig;
+use crate::db::api_keys;
+use crate::db::audit::NewAuditEntry;
+use crate::db::oauth_users::{
+    self, OAuthLoginResult, OAuthUser, ProviderUserInfo, PROVIDER_GOOGLE, PROVIDER_OIDC,
+};
+use crate::db::sessions::{self, SESSION_TOKEN_PREFIX};
+use crate::db::stats::{self as db_stats, UsageScope};
 use crate::db::DbPool;
 use crate::error::{AppError, Result};
-use crate::models::AuthInfo;
+use crate::models::{AuthInfo, QuotaStatus};
+use crate::services::oidc::OidcProvider;
+use crate::services::{audit, quota};
+
+/// Minuti dopo il login in cui un token di sessione può rigenerare l'API Key
+const FRESH_LOGIN_MINUTES: i64 = 5;
 
 /// State per le route di autenticazione
 #[derive(Clone)]
@@ -24,6 +37,10 @@ pub struct AuthRouteState {
     pub frontend_url: String,
     /// Cache per i state OAuth (CSRF protection)
     pub oauth_states: std::sync::Arc<RwLock<HashMap<String, std::time::Instant>>>,
+    /// Provider OIDC generico (assente se non configurato)
+    pub oidc: Option<Arc<OidcProvider>>,
+    /// State OIDC in attesa di callback, con il nonce atteso nell'ID token
+    pub oidc_states: Arc<RwLock<HashMap<String, (String, Instant)>>>,
 }
 
 pub fn router(
@@ -31,6 +48,7 @@ pub fn router(
     google_client_id: Option<String>,
     google_client_secret: Option<String>,
     frontend_url: String,
+    oidc: Option<OidcConfig>,
 ) -> Router {
     let state = AuthRouteState {
         db,
@@ -38,11 +56,17 @@ pub fn router(
         google_client_secret,
         frontend_url,
         oauth_states: std::sync::Arc::new(RwLock::new(HashMap::new())),
+        oidc: oidc.map(|config| Arc::new(OidcProvider::new(config))),
+        oidc_states: Arc::new(RwLock::new(HashMap::new())),
     };
     Router::new()
         .route("/api/v1/auth/google/url", get(get_google_auth_url))
         .route("/api/v1/auth/google/callback", get(google_callback))
+        .route("/api/v1/auth/oidc/url", get(get_oidc_auth_url))
+        .route("/api/v1/auth/oidc/callback", get(oidc_callback))
         .route("/api/v1/auth/me", get(get_current_user))
+        .route("/api/v1/auth/me/api-key", post(regenerate_api_key))
+        .route("/api/v1/auth/logout", post(logout))
         .with_state(state)
 }
 
@@ -78,6 +102,22 @@ pub struct CurrentUserResponse {
     pub user: UserInfo,
     pub api_key_prefix: String,
     pub stats: UserStats,
+    /// Consumo delle quote dell'API Key
+    pub quota: QuotaStatus,
+}
+
+/// Risposta rigenerazione API Key (il segreto è mostrato una sola volta)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RegeneratedApiKeyResponse {
+    pub api_key: String,
+    pub api_key_prefix: String,
+}
+
+/// Risposta logout
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LogoutResponse {
+    /// Se è stato revocato un token di sessione
+    pub revoked: bool,
 }
 
 /// Statistiche utente
@@ -89,7 +129,7 @@ pub struct UserStats {
     pub bytes_processed: u64,
 }
 
-/// Query params per callback Google
+/// Query params per callback Google e OIDC
 #[derive(Debug, Deserialize)]
 pub struct GoogleCallbackQuery {
     pub code: Option<String>,
@@ -179,12 +219,13 @@ pub async fn get_google_auth_url(
         ("error" = Option<String>, Query, description = "Errore da Google"),
     ),
     responses(
-        (status = 302, description = "Redirect al frontend con token"),
+        (status = 302, description = "Redirect al frontend con il token di sessione nel fragment"),
     ),
     tag = "Auth"
 )]
 pub async fn google_callback(
     State(state): State<AuthRouteState>,
+    Extension(auth): Extension<AuthInfo>,
     Query(query): Query<GoogleCallbackQuery>,
 ) -> std::result::Result<Redirect, Redirect> {
     let frontend_url = &state.frontend_url;
@@ -242,8 +283,9 @@ pub async fn google_callback(
         .map_err(|e| error_redirect(&format!("Failed to get user info: {}", e)))?;
 
     // Crea o trova utente nel database
-    let google_user_info = GoogleUserInfo {
-        google_id: user_info.sub,
+    let google_user_info = ProviderUserInfo {
+        provider: PROVIDER_GOOGLE.to_string(),
+        subject: user_info.sub,
         email: user_info.email,
         name: user_info.name,
         picture_url: user_info.picture,
@@ -263,6 +305,34 @@ pub async fn google_callback(
     )
     .await;
 
+    Ok(login_redirect(&state, &auth, &result).await)
+}
+
+/// Registra il login e reindirizza al frontend con i dati della sessione
+async fn login_redirect(
+    state: &AuthRouteState,
+    auth: &AuthInfo,
+    result: &OAuthLoginResult,
+) -> Redirect {
+    let frontend_url = &state.frontend_url;
+
+    audit::record_entry(
+        &state.db,
+        NewAuditEntry {
+            actor_key_id: Some(&result.user.api_key_id),
+            action: "auth.login",
+            target: Some(&result.user.id),
+            after: Some(serde_json::json!({
+                "email": result.user.email,
+                "provider": result.user.provider,
+                "is_new_user": result.is_new_user,
+            })),
+            ip: auth.client_ip.as_deref(),
+            ..Default::default()
+        },
+    )
+    .await;
+
     // Costruisci URL di redirect con i dati
     let mut redirect_url = format!(
         "{}?auth_success=true&user_id={}&email={}&api_key_prefix={}",
@@ -281,12 +351,132 @@ pub async fn google_callback(
     if result.is_new_user {
         redirect_url.push_str("&is_new_user=true");
     }
-    // Invia sempre la API key (sia per nuovi che per utenti esistenti)
+    // Token di sessione per il frontend nel fragment: il browser non lo invia
+    // ai server, quindi non finisce nei log di accesso né nel Referer
+    redirect_url.push_str(&format!(
+        "#session_token={}&session_expires_at={}",
+        urlencoding::encode(&result.session_token),
+        urlencoding::encode(&result.session_expires_at.to_rfc3339()),
+    ));
+    // La API key in chiaro solo quando è appena stata creata
     if let Some(api_key) = &result.api_key {
         redirect_url.push_str(&format!("&api_key={}", urlencoding::encode(api_key)));
     }
 
-    Ok(Redirect::temporary(&redirect_url))
+    Redirect::temporary(&redirect_url)
+}
+
+/// Genera URL per autenticazione con il provider OIDC
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/oidc/url",
+    responses(
+        (status = 200, description = "URL di autenticazione OIDC", body = GoogleAuthUrlResponse),
+        (status = 500, description = "OIDC non configurato o provider non raggiungibile"),
+    ),
+    tag = "Auth"
+)]
+pub async fn get_oidc_auth_url(
+    State(state): State<AuthRouteState>,
+) -> Result<Json<GoogleAuthUrlResponse>> {
+    let provider = state
+        .oidc
+        .as_ref()
+        .ok_or_else(|| AppError::Internal("OIDC non configurato".to_string()))?;
+
+    // State per CSRF protection, nonce contro il replay dell'ID token
+    let oauth_state = uuid::Uuid::new_v4().simple().to_string();
+    let nonce = uuid::Uuid::new_v4().simple().to_string();
+
+    let url = provider
+        .authorization_url(&oauth_state, &nonce)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    {
+        let mut states = state.oidc_states.write().unwrap();
+        // Pulisci stati vecchi (> 10 minuti)
+        let now = Instant::now();
+        states.retain(|_, (_, timestamp)| now.duration_since(*timestamp).as_secs() < 600);
+        states.insert(oauth_state, (nonce, now));
+    }
+
+    Ok(Json(GoogleAuthUrlResponse { url }))
+}
+
+/// Callback dal provider OIDC
+///
+/// Al primo accesso l'utente viene registrato con una nuova API Key, come
+/// per il login Google.
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/oidc/callback",
+    params(
+        ("code" = Option<String>, Query, description = "Authorization code dal provider"),
+        ("state" = Option<String>, Query, description = "State per CSRF protection"),
+        ("error" = Option<String>, Query, description = "Errore dal provider"),
+    ),
+    responses(
+        (status = 302, description = "Redirect al frontend con il token di sessione nel fragment"),
+    ),
+    tag = "Auth"
+)]
+pub async fn oidc_callback(
+    State(state): State<AuthRouteState>,
+    Extension(auth): Extension<AuthInfo>,
+    Query(query): Query<GoogleCallbackQuery>,
+) -> std::result::Result<Redirect, Redirect> {
+    let error_redirect = |msg: &str| {
+        Redirect::temporary(&format!(
+            "{}?auth_error={}",
+            state.frontend_url,
+            urlencoding::encode(msg)
+        ))
+    };
+
+    if let Some(error) = query.error {
+        return Err(error_redirect(&error));
+    }
+
+    let code = query
+        .code
+        .ok_or_else(|| error_redirect("Missing authorization code"))?;
+    let oauth_state = query
+        .state
+        .ok_or_else(|| error_redirect("Missing state parameter"))?;
+
+    // Verifica CSRF state e recupera il nonce atteso
+    let nonce = state
+        .oidc_states
+        .write()
+        .unwrap()
+        .remove(&oauth_state)
+        .map(|(nonce, _)| nonce)
+        .ok_or_else(|| error_redirect("Invalid state - possible CSRF attack"))?;
+
+    let provider = state
+        .oidc
+        .as_ref()
+        .ok_or_else(|| error_redirect("OIDC not configured"))?;
+
+    let user = provider
+        .login(&code, &nonce)
+        .await
+        .map_err(|e| error_redirect(&format!("OIDC login failed: {}", e)))?;
+
+    let user_info = ProviderUserInfo {
+        provider: PROVIDER_OIDC.to_string(),
+        subject: user.subject,
+        email: user.email,
+        name: user.name,
+        picture_url: user.picture_url,
+    };
+
+    let result = oauth_users::login_or_register(&state.db, user_info)
+        .await
+        .map_err(|e| error_redirect(&format!("Database error: {}", e)))?;
+
+    Ok(login_redirect(&state, &auth, &result).await)
 }
 
 /// Scambia authorization code per access token
@@ -418,9 +608,110 @@ pub async fn get_current_user(
         .map_err(|e| AppError::Internal(format!("Errore: {}", e)))?
         .unwrap_or_else(|| "cv_...".to_string());
 
+    let quota = quota::quota_status(&state.db, UsageScope::ApiKey(api_key_id), &auth.quota).await?;
+
     Ok(Json(CurrentUserResponse {
         user: oauth_user.into(),
         api_key_prefix,
         stats,
+        quota,
     }))
 }
+
+/// Rigenera l'API Key dell'utente corrente
+///
+/// La chiave precedente smette subito di funzionare; i token di sessione
+/// restano validi. È l'unico modo per rivedere una chiave in chiaro. Con un
+/// token di sessione è consentita solo nei primi minuti dopo il login, così un
+/// token sottratto non basta a ottenere una chiave permanente.
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/me/api-key",
+    responses(
+        (status = 200, description = "Nuova API Key", body = RegeneratedApiKeyResponse),
+        (status = 401, description = "Non autenticato"),
+        (status = 403, description = "Sessione non recente: serve un nuovo login"),
+        (status = 404, description = "Utente non trovato"),
+    ),
+    security(("api_key" = [])),
+    tag = "Auth"
+)]
+pub async fn regenerate_api_key(
+    State(state): State<AuthRouteState>,
+    Extension(auth): Extension<AuthInfo>,
+) -> Result<Json<RegeneratedApiKeyResponse>> {
+    let api_key_id = auth
+        .api_key_id
+        .as_ref()
+        .filter(|_| !auth.is_guest)
+        .ok_or_else(|| AppError::Unauthorized("Autenticazione richiesta".to_string()))?;
+
+    if let Some(started_at) = auth.session_started_at {
+        if chrono::Utc::now() - started_at > chrono::Duration::minutes(FRESH_LOGIN_MINUTES) {
+            return Err(AppError::Forbidden(
+                "Per rigenerare l'API Key serve un login recente: accedi di nuovo".to_string(),
+            ));
+        }
+    }
+
+    // Solo gli utenti OAuth: le altre chiavi si ruotano dall'admin
+    oauth_users::find_by_api_key_id(&state.db, api_key_id)
+        .await
+        .map_err(|e| AppError::Internal(format!("Errore database: {}", e)))?
+        .ok_or_else(|| AppError::NotFound("Utente non trovato".to_string()))?;
+
+    let rotated = api_keys::rotate_api_key(&state.db, api_key_id, chrono::Duration::zero())
+        .await
+        .map_err(|e| AppError::Internal(format!("Errore database: {}", e)))?
+        .ok_or_else(|| AppError::NotFound("API Key non trovata".to_string()))?;
+
+    audit::record(
+        &state.db,
+        &auth,
+        "auth.api_key_regenerate",
+        Some(api_key_id),
+        None,
+        Some(serde_json::json!({ "key_prefix": rotated.key_prefix })),
+    )
+    .await;
+
+    Ok(Json(RegeneratedApiKeyResponse {
+        api_key: rotated.api_key,
+        api_key_prefix: rotated.key_prefix,
+    }))
+}
+
+/// Revoca il token di sessione usato nella richiesta
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/logout",
+    responses(
+        (status = 200, description = "Sessione revocata", body = LogoutResponse),
+    ),
+    security(("api_key" = [])),
+    tag = "Auth"
+)]
+pub async fn logout(
+    State(state): State<AuthRouteState>,
+    headers: HeaderMap,
+) -> Result<Json<LogoutResponse>> {
+    let token = headers
+        .get("X-API-Key")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+        })
+        .filter(|t| t.starts_with(SESSION_TOKEN_PREFIX));
+
+    let revoked = match token {
+        Some(token) => sessions::revoke_session(&state.db, token)
+            .await
+            .map_err(|e| AppError::Internal(format!("Errore database: {}", e)))?,
+        None => false,
+    };
+
+    Ok(Json(LogoutResponse { revoked }))
+}
@@ -4,11 +4,15 @@ use axum::{extract::Multipart, extract::Query, extract::State, Extension, Json};
 use std::time::Instant;
 
 use crate::error::{AppError, Result};
+use crate::middleware::body_limit::UploadLimit;
 use crate::models::{AuthInfo, BatchConvertResponse, ConvertQuery, ConvertedFile, FailedFile};
 use crate::services::converter;
-use crate::utils::get_extension;
+use crate::services::entitlements::check_conversion;
+use crate::services::quota::{check_input_quota, check_quota, incoming_conversion};
+use crate::services::scopes::check_input_scope;
+use crate::utils::multipart::{multipart_error, save_field};
 
-use super::helpers::record_conversion;
+use super::helpers::{output_size, record_conversion};
 use super::ConvertState;
 
 /// Converti multipli file in batch
@@ -28,6 +32,7 @@ use super::ConvertState;
 pub async fn convert_batch(
     State(state): State<ConvertState>,
     Extension(auth): Extension<AuthInfo>,
+    Extension(limit): Extension<UploadLimit>,
     Query(query): Query<ConvertQuery>,
     mut multipart: Multipart,
 ) -> Result<Json<BatchConvertResponse>> {
@@ -38,20 +43,25 @@ pub async fn convert_batch(
         ));
     }
 
+    check_quota(&state.db, &auth, incoming_conversion(0, 0)).await?;
+
     let mut converted = Vec::new();
     let mut failed = Vec::new();
 
+    std::fs::create_dir_all(&state.config.temp_dir)?;
+
     while let Some(field) = multipart
         .next_field()
         .await
-        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(|e| multipart_error(e, limit.max_mb))?
     {
         let start = Instant::now();
         let filename = field.file_name().unwrap_or("file").to_string();
-        let input_format = get_extension(&filename).unwrap_or_default();
 
-        let data = match field.bytes().await {
-            Ok(d) => d,
+        // Ogni file ha la sua directory di lavoro, rimossa a fine iterazione
+        let work_dir = tempfile::tempdir_in(&state.config.temp_dir)?;
+        let file = match save_field(field, work_dir.path(), limit.max_mb).await {
+            Ok(f) => f,
             Err(e) => {
                 failed.push(FailedFile {
                     original_name: filename,
@@ -61,7 +71,8 @@ pub async fn convert_batch(
             }
         };
 
-        let input_size = data.len() as i64;
+        let input_format = file.extension.clone();
+        let input_size = file.size as i64;
 
         // Determina tipo conversione automaticamente
         let conversion_type = converter::detect_conversion_type(&input_format);
@@ -69,15 +80,34 @@ pub async fn convert_batch(
         if let Some(conv_type) = conversion_type {
             let type_str = conv_type.to_string();
 
-            match converter::convert(
-                &data,
-                &input_format,
+            // Scope, piano e quote (che possono esaurirsi durante il batch):
+            // il file viene scartato, il batch prosegue
+            let allowed = async {
+                check_conversion(&state.db, &auth, &type_str).await?;
+                let duration_ms = check_input_scope(&auth, &conv_type, file.path()).await?;
+                check_input_quota(&state.db, &auth, file.path(), duration_ms).await?;
+                Ok::<_, AppError>(duration_ms)
+            };
+            let duration_ms = match allowed.await {
+                Ok(duration_ms) => duration_ms,
+                Err(e) => {
+                    failed.push(FailedFile {
+                        original_name: filename,
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            match converter::convert_file_in_dir(
+                file.path(),
+                work_dir.path(),
                 &query.output_format,
                 &conv_type,
                 query.quality,
             ) {
-                Ok(output) => {
-                    let output_size = output.len() as i64;
+                Ok(output_path) => {
+                    let output_size = output_size(&output_path);
 
                     // Registra successo
                     record_conversion(
@@ -91,6 +121,7 @@ pub async fn convert_batch(
                         start.elapsed().as_millis() as i64,
                         true,
                         None,
+                        duration_ms,
                     )
                     .await;
 
@@ -113,6 +144,7 @@ pub async fn convert_batch(
                         start.elapsed().as_millis() as i64,
                         false,
                         Some(e.to_string()),
+                        duration_ms,
                     )
                     .await;
 
@@ -2,22 +2,24 @@
 
 use axum::{
     extract::{Multipart, Query, State},
-    http::header,
     response::IntoResponse,
     Extension,
 };
 use std::time::Instant;
 
 use crate::db::stats;
-use crate::error::{AppError, Result};
+use crate::error::Result;
 use crate::handlers::image as image_handler;
 use crate::handlers::pdf as pdf_handler;
+use crate::middleware::body_limit::UploadLimit;
 use crate::models::{AuthInfo, ConversionType, ConvertQuery, ImageOptions, PdfConvertQuery};
 use crate::services::converter;
-use crate::utils::{get_content_type, get_extension};
+use crate::services::entitlements::check_conversion;
+use crate::services::quota::check_input_quota;
+use crate::services::scopes::{check_input_scope, check_output_resolution};
+use crate::utils::get_content_type;
 
-use super::guest::{check_guest_file_size, check_guest_limits};
-use super::helpers::record_conversion;
+use super::helpers::{file_response, output_size, receive_file, record_conversion};
 use super::ConvertState;
 
 /// Converti un'immagine
@@ -42,46 +44,40 @@ use super::ConvertState;
 pub async fn convert_image(
     State(state): State<ConvertState>,
     Extension(auth): Extension<AuthInfo>,
+    Extension(limit): Extension<UploadLimit>,
     Query(query): Query<ConvertQuery>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse> {
     let start = Instant::now();
 
-    // Verifica limiti guest
-    if auth.is_guest {
-        check_guest_limits(&state.db, &auth, "image").await?;
-    }
+    // Verifica limiti guest, piano e quote API Key
+    check_conversion(&state.db, &auth, "image").await?;
+    check_output_resolution(&auth, query.width, query.height)?;
 
-    // Estrai file dal multipart
-    let field = multipart
-        .next_field()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?
-        .ok_or_else(|| AppError::MissingField("file".to_string()))?;
-
-    let filename = field.file_name().unwrap_or("file").to_string();
-    let input_format = get_extension(&filename).unwrap_or_default();
-    let data = field
-        .bytes()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let input_size = data.len() as i64;
-
-    // Verifica dimensione file per guest
-    if auth.is_guest {
-        check_guest_file_size(&state.db, input_size).await?;
-    }
+    // Salva il file del multipart su disco
+    let (work_dir, file) = receive_file(&state, &mut multipart, limit).await?;
+    let duration_ms = check_input_scope(&auth, &ConversionType::Image, file.path()).await?;
+    check_input_quota(&state.db, &auth, file.path(), duration_ms).await?;
+    let input_format = file.extension.clone();
+    let input_size = file.size as i64;
 
     // Crea opzioni immagine con resize
     let options = ImageOptions::from_query(&query);
 
     // Esegui conversione con resize
-    let result = image_handler::convert_image(&data, &input_format, &query.output_format, &options);
+    let output_path = work_dir
+        .path()
+        .join(format!("output.{}", query.output_format));
+    let result = image_handler::convert_image_file_with_options(
+        file.path(),
+        &output_path,
+        &query.output_format,
+        &options,
+    );
 
     match result {
-        Ok(output) => {
-            let output_size = output.len() as i64;
+        Ok(()) => {
+            let output_size = output_size(&output_path);
 
             // Registra conversione nel database
             record_conversion(
@@ -95,36 +91,21 @@ pub async fn convert_image(
                 start.elapsed().as_millis() as i64,
                 true,
                 None,
+                0,
             )
             .await;
 
             // Incrementa uso guest
             if auth.is_guest {
-                if let Some(ip) = &auth.client_ip {
-                    let _ = stats::increment_guest_usage(&state.db, ip).await;
+                if let Some(bucket) = auth.guest_bucket() {
+                    let _ = stats::increment_guest_usage(&state.db, &bucket).await;
                 }
             }
 
             let content_type = get_content_type(&query.output_format).to_string();
-            let output_filename = format!(
-                "{}.{}",
-                filename
-                    .rsplit_once('.')
-                    .map(|(n, _)| n)
-                    .unwrap_or(&filename),
-                query.output_format
-            );
-
-            Ok((
-                [
-                    (header::CONTENT_TYPE, content_type),
-                    (
-                        header::CONTENT_DISPOSITION,
-                        format!("attachment; filename=\"{}\"", output_filename),
-                    ),
-                ],
-                output,
-            ))
+            let output_filename = format!("{}.{}", file.base_name(), query.output_format);
+
+            file_response(work_dir, &output_path, content_type, output_filename).await
         }
         Err(e) => {
             // Registra errore
@@ -139,6 +120,7 @@ pub async fn convert_image(
                 start.elapsed().as_millis() as i64,
                 false,
                 Some(e.to_string()),
+                0,
             )
             .await;
 
@@ -164,12 +146,14 @@ pub async fn convert_image(
 pub async fn convert_document(
     State(state): State<ConvertState>,
     Extension(auth): Extension<AuthInfo>,
+    Extension(limit): Extension<UploadLimit>,
     Query(query): Query<ConvertQuery>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse> {
     convert_single_tracked(
         &state,
         &auth,
+        limit,
         &mut multipart,
         &query,
         ConversionType::Document,
@@ -196,10 +180,19 @@ pub async fn convert_document(
 pub async fn convert_audio(
     State(state): State<ConvertState>,
     Extension(auth): Extension<AuthInfo>,
+    Extension(limit): Extension<UploadLimit>,
     Query(query): Query<ConvertQuery>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse> {
-    convert_single_tracked(&state, &auth, &mut multipart, &query, ConversionType::Audio).await
+    convert_single_tracked(
+        &state,
+        &auth,
+        limit,
+        &mut multipart,
+        &query,
+        ConversionType::Audio,
+    )
+    .await
 }
 
 /// Converti un file video (richiede FFmpeg)
@@ -221,10 +214,19 @@ pub async fn convert_audio(
 pub async fn convert_video(
     State(state): State<ConvertState>,
     Extension(auth): Extension<AuthInfo>,
+    Extension(limit): Extension<UploadLimit>,
     Query(query): Query<ConvertQuery>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse> {
-    convert_single_tracked(&state, &auth, &mut multipart, &query, ConversionType::Video).await
+    convert_single_tracked(
+        &state,
+        &auth,
+        limit,
+        &mut multipart,
+        &query,
+        ConversionType::Video,
+    )
+    .await
 }
 
 /// Converti un PDF in immagine (richiede pdftoppm/poppler)
@@ -248,57 +250,52 @@ pub async fn convert_video(
 pub async fn convert_pdf(
     State(state): State<ConvertState>,
     Extension(auth): Extension<AuthInfo>,
+    Extension(limit): Extension<UploadLimit>,
     Query(query): Query<PdfConvertQuery>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse> {
     let start = Instant::now();
 
-    // Verifica limiti guest
-    if auth.is_guest {
-        check_guest_limits(&state.db, &auth, "pdf").await?;
-    }
+    // Verifica limiti guest, piano e quote API Key
+    check_conversion(&state.db, &auth, "pdf").await?;
 
-    // Estrai file dal multipart
-    let field = multipart
-        .next_field()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?
-        .ok_or_else(|| AppError::MissingField("file".to_string()))?;
-
-    let filename = field.file_name().unwrap_or("file.pdf").to_string();
-    let base_name = filename
-        .rsplit_once('.')
-        .map(|(n, _)| n)
-        .unwrap_or(&filename);
-    let data = field
-        .bytes()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let input_size = data.len() as i64;
-
-    // Verifica dimensione file per guest
-    if auth.is_guest {
-        check_guest_file_size(&state.db, input_size).await?;
-    }
+    // Salva il file del multipart su disco
+    let (work_dir, file) = receive_file(&state, &mut multipart, limit).await?;
+    let duration_ms = check_input_scope(&auth, &ConversionType::Pdf, file.path()).await?;
+    check_input_quota(&state.db, &auth, file.path(), duration_ms).await?;
+    let base_name = file.base_name();
+    let input_size = file.size as i64;
 
     // Esegui conversione PDF -> Immagine (singola o tutte le pagine)
-    let result = if query.all_pages {
+    let (output_path, result) = if query.all_pages {
         // Converti tutte le pagine e crea ZIP
-        pdf_handler::convert_pdf_to_zip(&data, &query.output_format, Some(query.dpi), base_name)
+        let output_path = work_dir.path().join("output.zip");
+        let result = pdf_handler::convert_pdf_file_to_zip(
+            file.path(),
+            &output_path,
+            &query.output_format,
+            Some(query.dpi),
+            base_name,
+        );
+        (output_path, result)
     } else {
         // Converti singola pagina
-        pdf_handler::convert_pdf_to_image(
-            &data,
+        let output_path = work_dir
+            .path()
+            .join(format!("output.{}", query.output_format));
+        let result = pdf_handler::convert_pdf_file(
+            file.path(),
+            &output_path,
             &query.output_format,
             Some(query.page),
             Some(query.dpi),
-        )
+        );
+        (output_path, result)
     };
 
     match result {
-        Ok(output) => {
-            let output_size = output.len() as i64;
+        Ok(()) => {
+            let output_size = output_size(&output_path);
             let output_format_for_stats = if query.all_pages {
                 "zip"
             } else {
@@ -317,13 +314,14 @@ pub async fn convert_pdf(
                 start.elapsed().as_millis() as i64,
                 true,
                 None,
+                0,
             )
             .await;
 
             // Incrementa uso guest
             if auth.is_guest {
-                if let Some(ip) = &auth.client_ip {
-                    let _ = stats::increment_guest_usage(&state.db, ip).await;
+                if let Some(bucket) = auth.guest_bucket() {
+                    let _ = stats::increment_guest_usage(&state.db, &bucket).await;
                 }
             }
 
@@ -340,16 +338,7 @@ pub async fn convert_pdf(
                 )
             };
 
-            Ok((
-                [
-                    (header::CONTENT_TYPE, content_type),
-                    (
-                        header::CONTENT_DISPOSITION,
-                        format!("attachment; filename=\"{}\"", output_filename),
-                    ),
-                ],
-                output,
-            ))
+            file_response(work_dir, &output_path, content_type, output_filename).await
         }
         Err(e) => {
             // Registra errore
@@ -364,6 +353,7 @@ pub async fn convert_pdf(
                 start.elapsed().as_millis() as i64,
                 false,
                 Some(e.to_string()),
+                0,
             )
             .await;
 
@@ -376,6 +366,7 @@ pub async fn convert_pdf(
 async fn convert_single_tracked(
     state: &ConvertState,
     auth: &AuthInfo,
+    limit: UploadLimit,
     multipart: &mut Multipart,
     query: &ConvertQuery,
     conversion_type: ConversionType,
@@ -383,44 +374,28 @@ async fn convert_single_tracked(
     let start = Instant::now();
     let type_str = conversion_type.to_string();
 
-    // Verifica limiti guest
-    if auth.is_guest {
-        check_guest_limits(&state.db, auth, &type_str).await?;
-    }
+    // Verifica limiti guest, piano e quote API Key
+    check_conversion(&state.db, auth, &type_str).await?;
 
-    // Estrai file dal multipart
-    let field = multipart
-        .next_field()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?
-        .ok_or_else(|| AppError::MissingField("file".to_string()))?;
-
-    let filename = field.file_name().unwrap_or("file").to_string();
-    let input_format = get_extension(&filename).unwrap_or_default();
-    let data = field
-        .bytes()
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let input_size = data.len() as i64;
-
-    // Verifica dimensione file per guest
-    if auth.is_guest {
-        check_guest_file_size(&state.db, input_size).await?;
-    }
+    // Salva il file del multipart su disco
+    let (work_dir, file) = receive_file(state, multipart, limit).await?;
+    let duration_ms = check_input_scope(auth, &conversion_type, file.path()).await?;
+    check_input_quota(&state.db, auth, file.path(), duration_ms).await?;
+    let input_format = file.extension.clone();
+    let input_size = file.size as i64;
 
     // Esegui conversione
-    let result = converter::convert(
-        &data,
-        &input_format,
+    let result = converter::convert_file_in_dir(
+        file.path(),
+        work_dir.path(),
         &query.output_format,
         &conversion_type,
         query.quality,
     );
 
     match result {
-        Ok(output) => {
-            let output_size = output.len() as i64;
+        Ok(output_path) => {
+            let output_size = output_size(&output_path);
 
             // Registra conversione
             record_conversion(
@@ -434,36 +409,21 @@ async fn convert_single_tracked(
                 start.elapsed().as_millis() as i64,
                 true,
                 None,
+                duration_ms,
             )
             .await;
 
             // Incrementa uso guest
             if auth.is_guest {
-                if let Some(ip) = &auth.client_ip {
-                    let _ = stats::increment_guest_usage(&state.db, ip).await;
+                if let Some(bucket) = auth.guest_bucket() {
+                    let _ = stats::increment_guest_usage(&state.db, &bucket).await;
                 }
             }
 
             let content_type = get_content_type(&query.output_format).to_string();
-            let output_filename = format!(
-                "{}.{}",
-                filename
-                    .rsplit_once('.')
-                    .map(|(n, _)| n)
-                    .unwrap_or(&filename),
-                query.output_format
-            );
-
-            Ok((
-                [
-                    (header::CONTENT_TYPE, content_type),
-                    (
-                        header::CONTENT_DISPOSITION,
-                        format!("attachment; filename=\"{}\"", output_filename),
-                    ),
-                ],
-                output,
-            ))
+            let output_filename = format!("{}.{}", file.base_name(), query.output_format);
+
+            file_response(work_dir, &output_path, content_type, output_filename).await
         }
         Err(e) => {
             // Registra errore
@@ -478,6 +438,7 @@ async fn convert_single_tracked(
                 start.elapsed().as_millis() as i64,
                 false,
                 Some(e.to_string()),
+                duration_ms,
             )
             .await;
 
@@ -1,8 +1,85 @@
 //! Helper functions for conversion routes
 
+use std::path::Path;
+
+use axum::{
+    body::Body,
+    extract::Multipart,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use tempfile::TempDir;
+use tokio_util::io::ReaderStream;
+
 use crate::db::stats::{self, ConversionRecordDb};
 use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::middleware::body_limit::UploadLimit;
 use crate::models::AuthInfo;
+use crate::services::metrics;
+use crate::utils::multipart::{multipart_error, save_field, UploadedFile};
+
+use super::ConvertState;
+
+/// Salva il file del multipart in una directory di lavoro temporanea.
+///
+/// La directory contiene anche l'output della conversione e viene rimossa
+/// al drop (per le risposte, a fine stream).
+pub async fn receive_file(
+    state: &ConvertState,
+    multipart: &mut Multipart,
+    limit: UploadLimit,
+) -> Result<(TempDir, UploadedFile)> {
+    let field = multipart
+        .next_field()
+        .await
+        .map_err(|e| multipart_error(e, limit.max_mb))?
+        .ok_or_else(|| AppError::MissingField("file".to_string()))?;
+
+    std::fs::create_dir_all(&state.config.temp_dir)?;
+    let work_dir = tempfile::tempdir_in(&state.config.temp_dir)?;
+    let file = save_field(field, work_dir.path(), limit.max_mb).await?;
+
+    Ok((work_dir, file))
+}
+
+/// Risposta che invia il file convertito in streaming
+pub async fn file_response(
+    work_dir: TempDir,
+    output_path: &Path,
+    content_type: String,
+    filename: String,
+) -> Result<Response> {
+    let file = tokio::fs::File::open(output_path).await?;
+    let length = file.metadata().await?.len();
+
+    // La directory di lavoro resta in vita finché lo stream non termina
+    let stream = ReaderStream::new(file).map(move |chunk| {
+        let _ = &work_dir;
+        chunk
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+            (header::CONTENT_LENGTH, length.to_string()),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Dimensione del file convertito
+pub fn output_size(output_path: &Path) -> i64 {
+    std::fs::metadata(output_path)
+        .map(|m| m.len() as i64)
+        .unwrap_or(0)
+}
 
 /// Record a conversion in the database for statistics
 #[allow(clippy::too_many_arguments)]
@@ -17,7 +94,18 @@ pub async fn record_conversion(
     processing_time_ms: i64,
     success: bool,
     error: Option<String>,
+    media_duration_ms: i64,
 ) {
+    metrics::record_conversion(
+        conversion_type,
+        input_format,
+        output_format,
+        success,
+        std::time::Duration::from_millis(processing_time_ms.max(0) as u64),
+        input_size.max(0) as u64,
+        output_size.max(0) as u64,
+    );
+
     let record = ConversionRecordDb {
         id: uuid::Uuid::new_v4().to_string(),
         timestamp: chrono::Utc::now(),
@@ -32,6 +120,7 @@ pub async fn record_conversion(
         success,
         error,
         client_ip: auth.client_ip.clone(),
+        media_duration_ms,
     };
 
     if let Err(e) = stats::insert_conversion(db, &record).await {
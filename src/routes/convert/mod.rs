@@ -4,11 +4,11 @@
 
 mod batch;
 mod endpoints;
-mod guest;
 mod helpers;
 
 use axum::{routing::post, Router};
 
+use crate::config::Config;
 use crate::db::DbPool;
 use crate::services::queue::JobQueue;
 
@@ -16,20 +16,25 @@ use crate::services::queue::JobQueue;
 pub use crate::models::AuthInfo;
 
 // Re-export public items (including utoipa path types)
+pub use crate::services::entitlements::check_guest_limits;
 pub use batch::*;
 pub use endpoints::*;
-pub use guest::{check_guest_file_size, check_guest_limits};
 
 /// Shared state for conversion routes
 #[derive(Clone)]
 pub struct ConvertState {
     pub job_queue: JobQueue,
     pub db: DbPool,
+    pub config: Config,
 }
 
 /// Create the router for conversion endpoints
-pub fn router(job_queue: JobQueue, db: DbPool) -> Router {
-    let state = ConvertState { job_queue, db };
+pub fn router(job_queue: JobQueue, db: DbPool, config: Config) -> Router {
+    let state = ConvertState {
+        job_queue,
+        db,
+        config,
+    };
     Router::new()
         .route("/api/v1/convert/image", post(convert_image))
         .route("/api/v1/convert/document", post(convert_document))
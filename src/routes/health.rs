@@ -1,7 +1,19 @@
-use axum::{extract::State, routing::get, Json, Router};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::get,
+    Json, Router,
+};
 
-use crate::config::formats;
-use crate::models::{FormatSupport, FormatsResponse, HealthResponse};
+use crate::config::{formats, Config};
+use crate::db::DbPool;
+use crate::models::{
+    CheckStatus, FormatSupport, FormatsResponse, HealthResponse, LivenessResponse,
+    ReadinessResponse,
+};
+use crate::routes::metrics::has_metrics_token;
+use crate::services::queue::JobQueue;
+use crate::services::readiness::{self, ReadinessCache};
 use crate::utils::{check_ffmpeg_available, check_pdftoppm_available};
 
 #[derive(Clone)]
@@ -17,6 +29,28 @@ pub fn router(max_file_size_mb: u64) -> Router {
         .with_state(state)
 }
 
+#[derive(Clone)]
+pub struct ProbesState {
+    pub db: DbPool,
+    pub job_queue: JobQueue,
+    pub config: Config,
+    pub cache: ReadinessCache,
+}
+
+/// Probe per l'orchestratore, montate fuori da autenticazione e rate limit
+pub fn probes_router(db: DbPool, job_queue: JobQueue, config: Config) -> Router {
+    let state = ProbesState {
+        db,
+        job_queue,
+        config,
+        cache: ReadinessCache::new(),
+    };
+    Router::new()
+        .route("/health/live", get(liveness))
+        .route("/health/ready", get(readiness_check))
+        .with_state(state)
+}
+
 /// Health check dell'API
 #[utoipa::path(
     get,
@@ -34,6 +68,80 @@ pub async fn health_check() -> Json<HealthResponse> {
     })
 }
 
+/// Liveness probe: il processo risponde
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    responses(
+        (status = 200, description = "Processo attivo", body = LivenessResponse),
+    ),
+    tag = "Sistema"
+)]
+pub async fn liveness() -> Json<LivenessResponse> {
+    Json(LivenessResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
+
+/// Readiness probe: verifica database, directory temporanea, tool esterni,
+/// coda dei job e credenziali Drive
+///
+/// Se una verifica fallisce lo stato è `degraded` e la risposta è 503, così
+/// il nodo viene escluso dal bilanciamento; gli avvisi non cambiano lo status.
+/// Il risultato viene riusato per alcuni secondi. Messaggi e dettagli delle
+/// verifiche sono inclusi solo con `Authorization: Bearer <CONVERTY_METRICS_TOKEN>`.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "Nodo pronto (eventualmente con avvisi)", body = ReadinessResponse),
+        (status = 503, description = "Nodo non pronto, dettagli nelle verifiche", body = ReadinessResponse),
+    ),
+    tag = "Sistema"
+)]
+pub async fn readiness_check(
+    State(state): State<ProbesState>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let checks = state
+        .cache
+        .checks(&state.db, &state.job_queue, &state.config)
+        .await;
+    let (status, code) = match readiness::overall_status(&checks) {
+        CheckStatus::Fail => ("degraded", StatusCode::SERVICE_UNAVAILABLE),
+        CheckStatus::Ok | CheckStatus::Warn => ("ok", StatusCode::OK),
+    };
+    if code != StatusCode::OK {
+        let failed: Vec<&str> = checks
+            .iter()
+            .filter(|c| c.status == CheckStatus::Fail)
+            .map(|c| c.name.as_str())
+            .collect();
+        tracing::warn!("Readiness fallita: {}", failed.join(", "));
+    }
+
+    let with_details = state
+        .config
+        .metrics_token
+        .as_deref()
+        .is_some_and(|token| has_metrics_token(&headers, token));
+    let checks = if with_details {
+        checks
+    } else {
+        readiness::redact(checks)
+    };
+
+    (
+        code,
+        Json(ReadinessResponse {
+            status: status.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            checks,
+        }),
+    )
+}
+
 /// Ottieni i formati supportati (filtra in base alle librerie disponibili)
 #[utoipa::path(
     get,
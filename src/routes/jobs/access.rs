@@ -0,0 +1,213 @@
+//! Controlli di accesso ai job
+
+use sha2::{Digest, Sha256};
+
+use crate::db::api_keys::ApiKeyRole;
+use crate::db::jobs::{self as db_jobs, JobRecord};
+use crate::db::organizations;
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::models::AuthInfo;
+use crate::services::download_links::generate_token;
+
+/// Hash del token di sessione guest salvato nel job
+pub fn hash_guest_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Token di sessione per un guest: riusa quello inviato, altrimenti ne crea uno
+pub fn guest_session_token(auth: &AuthInfo) -> String {
+    auth.guest_token.clone().unwrap_or_else(generate_token)
+}
+
+/// Carica un job verificando che il chiamante possa accedervi
+///
+/// Gli admin accedono a tutti i job; le API Key ai propri e a quelli delle
+/// chiavi della stessa organizzazione (i viewer restano in sola lettura,
+/// vedi middleware); i guest solo ai job creati con lo stesso `X-Guest-Token`. Per gli altri il job
+/// risulta inesistente, per non rivelare quali ID sono validi.
+pub async fn authorize_job(db: &DbPool, auth: &AuthInfo, id: &str) -> Result<JobRecord> {
+    let job = db_jobs::get_job(db, id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .ok_or_else(|| AppError::JobNotFound(id.to_string()))?;
+
+    if auth.role == ApiKeyRole::Admin && !auth.is_guest {
+        return Ok(job);
+    }
+
+    let allowed = match (&job.api_key_id, &auth.api_key_id) {
+        (Some(owner), Some(caller)) if owner == caller => true,
+        (Some(owner), Some(_)) => match &auth.organization {
+            Some(org) => organizations::is_member(db, &org.id, owner)
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            None => false,
+        },
+        (Some(_), None) | (None, Some(_)) => false,
+        (None, None) => match auth.guest_token.as_deref() {
+            Some(token) => db_jobs::get_job_guest_token(db, id)
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?
+                .is_some_and(|hash| hash == hash_guest_token(token)),
+            None => false,
+        },
+    };
+
+    if allowed {
+        Ok(job)
+    } else {
+        Err(AppError::JobNotFound(id.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::organizations::OrgRole;
+    use crate::models::{OrgMembership, QuotaLimits};
+
+    const GUEST_TOKEN: &str = "guest-token";
+
+    /// Database con tre chiavi ("owner" e "teammate" nella stessa
+    /// organizzazione, "stranger" in un'altra), un job di "owner" e uno guest
+    async fn setup() -> (tempfile::TempDir, DbPool) {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display());
+        let db = crate::db::init_db(&url).await.unwrap();
+        let now = chrono::Utc::now().to_rfc3339();
+
+        for id in ["owner", "teammate", "stranger"] {
+            sqlx::query(
+                "INSERT INTO api_keys (id, name, key_hash, key_prefix, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(id)
+            .bind(id)
+            .bind(format!("hash-{}", id))
+            .bind("cvt_test")
+            .bind(&now)
+            .bind(&now)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+        for (org, key) in [
+            ("team", "owner"),
+            ("team", "teammate"),
+            ("other", "stranger"),
+        ] {
+            sqlx::query(
+                "INSERT OR IGNORE INTO organizations (id, name, created_at, updated_at) VALUES (?, ?, ?, ?)",
+            )
+            .bind(org)
+            .bind(org)
+            .bind(&now)
+            .bind(&now)
+            .execute(&db)
+            .await
+            .unwrap();
+            organizations::add_member(&db, org, key, OrgRole::Member)
+                .await
+                .unwrap();
+        }
+        for (id, owner) in [("job-owner", Some("owner")), ("job-guest", None)] {
+            sqlx::query(
+                "INSERT INTO jobs (id, api_key_id, conversion_type, input_format, output_format, input_path, created_at, updated_at) VALUES (?, ?, 'image', 'png', 'jpg', 'input.png', ?, ?)",
+            )
+            .bind(id)
+            .bind(owner)
+            .bind(&now)
+            .bind(&now)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+        db_jobs::set_job_guest_token(&db, "job-guest", &hash_guest_token(GUEST_TOKEN))
+            .await
+            .unwrap();
+
+        (dir, db)
+    }
+
+    fn key(id: &str, org: Option<&str>) -> AuthInfo {
+        AuthInfo {
+            api_key_id: Some(id.to_string()),
+            is_guest: false,
+            organization: org.map(|org| OrgMembership {
+                id: org.to_string(),
+                role: OrgRole::Member,
+                quota: QuotaLimits::default(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn guest(token: Option<&str>) -> AuthInfo {
+        AuthInfo {
+            guest_token: token.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    async fn allowed(db: &DbPool, auth: &AuthInfo, id: &str) -> bool {
+        match authorize_job(db, auth, id).await {
+            Ok(job) => {
+                assert_eq!(job.id, id);
+                true
+            }
+            Err(AppError::JobNotFound(_)) => false,
+            Err(e) => panic!("errore inatteso: {}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_owner_and_other_keys() {
+        let (_dir, db) = setup().await;
+
+        assert!(allowed(&db, &key("owner", None), "job-owner").await);
+        assert!(!allowed(&db, &key("stranger", None), "job-owner").await);
+        // Le API Key non vedono i job guest
+        assert!(!allowed(&db, &key("owner", None), "job-guest").await);
+        // Job inesistente
+        assert!(!allowed(&db, &key("owner", None), "missing").await);
+    }
+
+    #[tokio::test]
+    async fn test_organization_members_share_jobs() {
+        let (_dir, db) = setup().await;
+
+        assert!(allowed(&db, &key("teammate", Some("team")), "job-owner").await);
+        assert!(!allowed(&db, &key("stranger", Some("other")), "job-owner").await);
+        // Senza organizzazione il compagno di team è un estraneo
+        assert!(!allowed(&db, &key("teammate", None), "job-owner").await);
+    }
+
+    #[tokio::test]
+    async fn test_guest_requires_same_token() {
+        let (_dir, db) = setup().await;
+
+        assert!(allowed(&db, &guest(Some(GUEST_TOKEN)), "job-guest").await);
+        assert!(!allowed(&db, &guest(Some("other-token")), "job-guest").await);
+        assert!(!allowed(&db, &guest(None), "job-guest").await);
+        assert!(!allowed(&db, &guest(Some(GUEST_TOKEN)), "job-owner").await);
+    }
+
+    #[tokio::test]
+    async fn test_admin_accesses_every_job() {
+        let (_dir, db) = setup().await;
+        let admin = AuthInfo {
+            role: ApiKeyRole::Admin,
+            ..key("stranger", None)
+        };
+
+        assert!(allowed(&db, &admin, "job-owner").await);
+        assert!(allowed(&db, &admin, "job-guest").await);
+
+        // Il ruolo admin non vale per le richieste guest
+        let guest_admin = AuthInfo {
+            role: ApiKeyRole::Admin,
+            ..guest(None)
+        };
+        assert!(!allowed(&db, &guest_admin, "job-owner").await);
+    }
+}
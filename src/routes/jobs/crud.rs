@@ -1,23 +1,40 @@
 //! CRUD operations for jobs
 
+use std::time::SystemTime;
+
 use axum::{
+    body::Body,
     extract::{Multipart, Path, Query, State},
-    http::header,
-    response::IntoResponse,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Extension, Json,
 };
+use tracing::Instrument;
 use uuid::Uuid;
 
 use crate::db::api_keys::ApiKeyRole;
+use crate::db::download_links;
 use crate::db::jobs::{self as db_jobs, JobsListResponse, JobsQuery};
 use crate::db::stats;
 use crate::error::{AppError, Result};
+use crate::middleware::body_limit::UploadLimit;
+use crate::middleware::request_id::RequestId;
 use crate::models::{
-    AuthInfo, CreateJobRequest, JobCreatedResponse, JobResponse, JobStatus, ProgressUpdate,
+    AuthInfo, CreateJobRequest, JobCreatedResponse, JobPriority, JobResponse, JobStatus,
+    ProgressUpdate,
 };
+use crate::routes::uploads;
+use crate::services::download_links::SignedLinkParams;
+use crate::services::entitlements::{check_conversion, check_priority, job_retention_hours};
 use crate::services::queue::{self, download_from_url};
+use crate::services::quota::{check_input_quota, check_quota, incoming_conversion};
+use crate::services::scopes::{check_input_scope, check_output_resolution, check_source_url_scope};
+use crate::utils::multipart::{multipart_error, save_field};
+use crate::utils::range::{ByteRange, Validators};
+use crate::utils::validation::parse_date_param;
 use crate::utils::{get_content_type, get_extension};
 
+use super::access::{authorize_job, guest_session_token, hash_guest_token};
 use super::JobsState;
 
 /// Response per history
@@ -43,6 +60,15 @@ pub struct HistoryQuery {
     /// Filtro stato: completed, failed, all
     #[serde(default)]
     pub status: Option<String>,
+    /// Inizio periodo (RFC 3339 o YYYY-MM-DD)
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Fine periodo inclusa (RFC 3339 o YYYY-MM-DD)
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Filtro tipo conversione
+    #[serde(default)]
+    pub conversion_type: Option<String>,
 }
 
 fn default_history_limit() -> i64 {
@@ -67,13 +93,16 @@ fn default_history_limit() -> i64 {
 pub async fn list_jobs(
     State(state): State<JobsState>,
     Extension(auth): Extension<AuthInfo>,
-    Query(query): Query<JobsQuery>,
+    Query(mut query): Query<JobsQuery>,
 ) -> Result<Json<JobsListResponse>> {
-    // Solo admin può vedere tutti i job
-    if auth.role != ApiKeyRole::Admin {
-        return Err(AppError::Forbidden(
-            "Solo gli admin possono vedere la lista completa dei job".to_string(),
-        ));
+    // Gli admin vedono tutti i job, i membri di un'organizzazione quelli del team
+    if auth.role != ApiKeyRole::Admin || auth.is_guest {
+        let org = auth.organization.as_ref().ok_or_else(|| {
+            AppError::Forbidden(
+                "Solo gli admin possono vedere la lista completa dei job".to_string(),
+            )
+        })?;
+        query.organization_id = Some(org.id.clone());
     }
 
     let response = db_jobs::list_jobs(&state.db, &query)
@@ -94,9 +123,13 @@ pub async fn list_jobs(
         ("input_format" = Option<String>, Query, description = "Filtro formato input"),
         ("output_format" = Option<String>, Query, description = "Filtro formato output"),
         ("status" = Option<String>, Query, description = "Filtro stato: completed, failed, all"),
+        ("from" = Option<String>, Query, description = "Inizio periodo (RFC 3339 o YYYY-MM-DD)"),
+        ("to" = Option<String>, Query, description = "Fine periodo inclusa (RFC 3339 o YYYY-MM-DD)"),
+        ("conversion_type" = Option<String>, Query, description = "Filtro tipo conversione"),
     ),
     responses(
         (status = 200, description = "Cronologia conversioni", body = HistoryResponse),
+        (status = 400, description = "Data non valida"),
         (status = 401, description = "API Key richiesta"),
     )
 )]
@@ -106,7 +139,8 @@ pub async fn get_history(
     Query(query): Query<HistoryQuery>,
 ) -> Result<Json<HistoryResponse>> {
     // Richiede autenticazione
-    let api_key_id = auth.api_key_id.ok_or_else(|| {
+    // I membri di un'organizzazione vedono la cronologia di tutto il team
+    let scope = auth.usage_scope().ok_or_else(|| {
         AppError::Unauthorized("API Key richiesta per vedere la cronologia".to_string())
     })?;
 
@@ -116,16 +150,35 @@ pub async fn get_history(
         input_format: query.input_format,
         output_format: query.output_format,
         status: query.status,
+        from: query.from,
+        to: query.to,
+        conversion_type: query.conversion_type,
     };
+    validate_history_dates(&filters)?;
 
-    let jobs =
-        stats::get_user_conversions_filtered(&state.db, &api_key_id, query.limit, Some(&filters))
-            .await
-            .map_err(|e| AppError::Internal(e.to_string()))?;
+    let jobs = stats::get_user_conversions_filtered(&state.db, scope, query.limit, Some(&filters))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
 
     Ok(Json(HistoryResponse { jobs }))
 }
 
+/// Verifica le date `from`/`to` dei filtri della cronologia
+pub(super) fn validate_history_dates(filters: &stats::HistoryFilters) -> Result<()> {
+    let from = filters.from.as_deref().filter(|v| !v.is_empty());
+    let to = filters.to.as_deref().filter(|v| !v.is_empty());
+    let from = from.map(|v| parse_date_param(v, false)).transpose()?;
+    let to = to.map(|v| parse_date_param(v, true)).transpose()?;
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err(AppError::BadRequest(
+                "'from' deve precedere 'to'".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
 /// Crea un nuovo job di conversione asincrono
 #[utoipa::path(
     post,
@@ -137,6 +190,7 @@ pub async fn get_history(
         ("conversion_type" = String, Query, description = "Tipo conversione: image, document, audio, video"),
         ("quality" = Option<u8>, Query, description = "Qualità (1-100)"),
         ("source_url" = Option<String>, Query, description = "URL sorgente (alternativa a upload file)"),
+        ("upload_id" = Option<String>, Query, description = "ID di un upload tus completato (alternativa a upload file)"),
         ("priority" = Option<String>, Query, description = "Priorità: low, normal, high"),
         ("webhook_url" = Option<String>, Query, description = "URL webhook per notifica completamento"),
         ("expires_in_hours" = Option<i64>, Query, description = "Ore prima della scadenza risultato")
@@ -144,54 +198,110 @@ pub async fn get_history(
     responses(
         (status = 200, description = "Job creato", body = JobCreatedResponse),
         (status = 400, description = "Richiesta non valida"),
-        (status = 429, description = "Troppi job in coda"),
+        (status = 429, description = "Troppi job in coda o quota esaurita"),
     )
 )]
 pub async fn create_job(
     State(state): State<JobsState>,
     Extension(auth): Extension<AuthInfo>,
+    Extension(limit): Extension<UploadLimit>,
+    request_id: Option<Extension<RequestId>>,
     Query(query): Query<CreateJobRequest>,
-    mut multipart: Multipart,
+    multipart: Option<Multipart>,
 ) -> Result<Json<JobCreatedResponse>> {
-    // Determina sorgente dati: URL o upload
-    let (data, input_format, original_filename) = if let Some(ref source_url) = query.source_url {
+    check_conversion(&state.db, &auth, &query.conversion_type.to_string()).await?;
+    check_priority(&auth, query.priority)?;
+    check_output_resolution(&auth, query.width, query.height)?;
+    if let Some(ref source_url) = query.source_url {
+        check_source_url_scope(&auth, source_url)?;
+    }
+
+    // File temporaneo (download o multipart): viene spostato nello storage
+    // da create_job, altrimenti rimosso quando esce dallo scope
+    let temp_input: tempfile::TempPath;
+    // Upload tus da rivendicare prima della creazione del job
+    let mut completed_upload = None;
+
+    // Determina sorgente dati: URL, upload tus o multipart
+    let (input_path, input_format, original_filename) = if let Some(ref upload_id) = query.upload_id
+    {
+        let upload = uploads::completed_upload(&state.db, &state.config, &auth, upload_id).await?;
+        let input_format = upload
+            .filename
+            .as_deref()
+            .and_then(get_extension)
+            .filter(|ext| !ext.is_empty())
+            .ok_or_else(|| {
+                AppError::BadRequest(
+                    "Formato di input sconosciuto: indicare in Upload-Metadata un filename con estensione"
+                        .to_string(),
+                )
+            })?;
+        let input_path = upload.path.clone();
+        let original_filename = upload.filename.clone();
+        completed_upload = Some(upload);
+        (input_path, input_format, original_filename)
+    } else if let Some(ref source_url) = query.source_url {
         // Scarica da URL - estrai filename dall'URL
-        let (bytes, ext) = download_from_url(source_url).await?;
+        std::fs::create_dir_all(&state.config.temp_dir)?;
+        temp_input = tempfile::NamedTempFile::new_in(&state.config.temp_dir)?.into_temp_path();
+        let ext = download_from_url(
+            &state.db,
+            source_url,
+            &temp_input,
+            limit.max_mb,
+            &auth.scopes,
+        )
+        .await?;
         let url_filename = source_url
             .rsplit('/')
             .next()
             .and_then(|s| s.split('?').next())
             .filter(|s| !s.is_empty())
             .map(|s| s.to_string());
-        (bytes, ext, url_filename)
+        (temp_input.to_path_buf(), ext, url_filename)
     } else {
-        // Estrai file da multipart
+        // Salva il file del multipart su disco
+        let mut multipart =
+            multipart.ok_or_else(|| AppError::MissingField("file o source_url".to_string()))?;
         let field = multipart
             .next_field()
             .await
-            .map_err(|e| AppError::Internal(e.to_string()))?
+            .map_err(|e| multipart_error(e, limit.max_mb))?
             .ok_or_else(|| AppError::MissingField("file o source_url".to_string()))?;
 
-        let filename = field.file_name().unwrap_or("file").to_string();
-        let input_format = get_extension(&filename).unwrap_or_default();
-        let original_filename = if filename != "file" {
-            Some(filename.clone())
+        let file = save_field(field, &state.config.temp_dir, limit.max_mb).await?;
+        let input_format = file.extension.clone();
+        let original_filename = if file.filename != "file" {
+            Some(file.filename.clone())
         } else {
             None
         };
-        let bytes = field
-            .bytes()
-            .await
-            .map_err(|e| AppError::Internal(e.to_string()))?;
-        (bytes.to_vec(), input_format, original_filename)
+        temp_input = file.into_temp_path();
+        (temp_input.to_path_buf(), input_format, original_filename)
+    };
+
+    let duration_ms = check_input_scope(&auth, &query.conversion_type, &input_path).await?;
+    check_input_quota(&state.db, &auth, &input_path, duration_ms).await?;
+
+    // Rivendica l'upload: da qui il file è del job e viene rimosso se la
+    // creazione fallisce
+    let _claimed_upload = match &completed_upload {
+        Some(upload) => Some(uploads::claim_upload(&state.db, upload).await?),
+        None => None,
     };
 
+    // I job dei guest sono legati al token di sessione
+    let guest_token = auth.is_guest.then(|| guest_session_token(&auth));
+    // Conservazione del risultato entro il massimo del piano
+    let expires_in_hours = job_retention_hours(&auth, query.expires_in_hours);
+
     // Crea job con nuovi parametri
     let job_id = {
         let q = state.queue.read().await;
         q.create_job(
             query.conversion_type.clone(),
-            data,
+            &input_path,
             input_format,
             query.output_format.clone(),
             query.quality,
@@ -199,21 +309,45 @@ pub async fn create_job(
             Some(query.priority.to_string()),
             query.webhook_url.clone(),
             query.source_url.clone(),
-            query.expires_in_hours,
+            expires_in_hours,
             original_filename,
         )
         .await?
     };
+    // La durata resta sul job per le quote dei job in coda e dei retry
+    if duration_ms > 0 {
+        db_jobs::set_job_media_duration(&state.db, &job_id.to_string(), duration_ms)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+    }
+    if let Some(ref token) = guest_token {
+        db_jobs::set_job_guest_token(&state.db, &job_id.to_string(), &hash_guest_token(token))
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+    }
+    // Collega il job alla richiesta per ritrovarlo nei log e nelle tracce
+    if let Some(Extension(RequestId(request_id))) = request_id {
+        if let Err(e) =
+            db_jobs::set_job_request_id(&state.db, &job_id.to_string(), &request_id).await
+        {
+            tracing::warn!("Errore salvataggio request ID job {}: {}", job_id, e);
+        }
+    }
 
     // Avvia elaborazione in background
     let queue_clone = state.queue.clone();
-    tokio::spawn(async move {
-        queue::process_job(queue_clone, job_id).await;
-    });
+    // Lo span del job resta figlio di quello della richiesta
+    tokio::spawn(
+        async move {
+            queue::process_job(queue_clone, job_id).await;
+        }
+        .in_current_span(),
+    );
 
     Ok(Json(JobCreatedResponse {
         id: job_id.to_string(),
         message: "Job creato e in elaborazione".to_string(),
+        guest_token,
     }))
 }
 
@@ -232,9 +366,11 @@ pub async fn create_job(
 )]
 pub async fn get_job_status(
     State(state): State<JobsState>,
+    Extension(auth): Extension<AuthInfo>,
     Path(id): Path<String>,
 ) -> Result<Json<JobResponse>> {
     let job_id = Uuid::parse_str(&id).map_err(|_| AppError::JobNotFound(id.clone()))?;
+    authorize_job(&state.db, &auth, &id).await?;
 
     let q = state.queue.read().await;
     let job = q
@@ -269,9 +405,11 @@ pub async fn get_job_status(
 )]
 pub async fn delete_job(
     State(state): State<JobsState>,
+    Extension(auth): Extension<AuthInfo>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>> {
     let job_id = Uuid::parse_str(&id).map_err(|_| AppError::JobNotFound(id.clone()))?;
+    authorize_job(&state.db, &auth, &id).await?;
 
     let q = state.queue.read().await;
     q.delete_job(&job_id).await?;
@@ -283,25 +421,55 @@ pub async fn delete_job(
 }
 
 /// Scarica il risultato di un job completato
+///
+/// Con i parametri `expires` e `sig` di un link firmato
+/// (`POST /api/v1/jobs/{id}/link`) non serve l'API Key.
+/// Supporta `Range`/`If-Range` e GET condizionali (`ETag`, `Last-Modified`).
+/// I link monouso sono consumati dalla prima richiesta, quindi non accettano
+/// `Range`: per la riproduzione con seek serve un link riutilizzabile.
 #[utoipa::path(
     get,
     path = "/api/v1/jobs/{id}/download",
     tag = "Jobs",
     params(
-        ("id" = String, Path, description = "ID del job")
+        ("id" = String, Path, description = "ID del job"),
+        ("expires" = Option<i64>, Query, description = "Scadenza del link firmato (unix timestamp)"),
+        ("sig" = Option<String>, Query, description = "Firma del link"),
+        ("token" = Option<String>, Query, description = "Token dei link monouso"),
     ),
     responses(
-        (status = 200, description = "File convertito"),
+        (status = 200, description = "File convertito (stream)"),
+        (status = 206, description = "Porzione del file richiesta con Range"),
+        (status = 400, description = "Range con un link monouso"),
+        (status = 304, description = "Non modificato (If-None-Match / If-Modified-Since)"),
+        (status = 403, description = "Link firmato non valido, scaduto o già usato"),
+        (status = 416, description = "Range non soddisfacibile"),
         (status = 404, description = "Job non trovato"),
         (status = 202, description = "Job non ancora completato"),
     )
 )]
 pub async fn download_job_result(
     State(state): State<JobsState>,
+    Extension(auth): Extension<AuthInfo>,
     Path(id): Path<String>,
-) -> Result<impl IntoResponse> {
+    Query(link): Query<SignedLinkParams>,
+    headers: HeaderMap,
+) -> Result<Response> {
     let job_id = Uuid::parse_str(&id).map_err(|_| AppError::JobNotFound(id.clone()))?;
 
+    // Il link firmato sostituisce il controllo sul proprietario
+    if link.is_signed() {
+        state.queue.read().await.links().verify(&id, &link)?;
+        if link.token.is_some() && headers.contains_key(header::RANGE) {
+            return Err(AppError::BadRequest(
+                "I link monouso non supportano richieste Range: usare un link riutilizzabile"
+                    .to_string(),
+            ));
+        }
+    } else {
+        authorize_job(&state.db, &auth, &id).await?;
+    }
+
     // Ottieni job info incluso result_path
     let (output_format, result_path) = {
         let q = state.queue.read().await;
@@ -312,8 +480,65 @@ pub async fn download_job_result(
         (job.output_format.clone(), job.result_path.clone())
     };
 
-    // Ottieni risultato
-    let data = queue::get_job_result(&state.queue, &job_id).await?;
+    // Metadati del risultato (il contenuto viene letto in streaming)
+    let result = queue::get_job_result(&state.queue, &job_id).await?;
+
+    // ETag dal checksum; per i job senza checksum un ETag debole da dimensione e data
+    let last_modified = result.completed_at.map(SystemTime::from);
+    let validators = Validators {
+        etag: match &result.checksum {
+            Some(checksum) => Some(format!("\"{}\"", checksum)),
+            None => result
+                .completed_at
+                .map(|dt| format!("W/\"{}-{}\"", result.size, dt.timestamp())),
+        },
+        last_modified,
+    };
+
+    let header_value = |value: String| {
+        HeaderValue::from_str(&value)
+            .map_err(|_| AppError::Internal("Header non valido".to_string()))
+    };
+    let mut response_headers = HeaderMap::new();
+    if let Some(etag) = validators.etag.as_deref().and_then(|e| e.parse().ok()) {
+        response_headers.insert(header::ETAG, etag);
+    }
+    if let Some(lm) = last_modified.and_then(|t| httpdate::fmt_http_date(t).parse().ok()) {
+        response_headers.insert(header::LAST_MODIFIED, lm);
+    }
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    // GET condizionale
+    if validators.is_not_modified(&headers) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    let range = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(value) if validators.if_range_matches(&headers) => {
+            ByteRange::parse(value, result.size)
+        }
+        _ => ByteRange::Full,
+    };
+
+    if range == ByteRange::Unsatisfiable {
+        response_headers.insert(
+            header::CONTENT_RANGE,
+            header_value(format!("bytes */{}", result.size))?,
+        );
+        return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response());
+    }
+
+    // I link monouso vengono consumati solo quando il risultato è disponibile
+    if let (true, Some(token)) = (link.is_signed(), link.token.as_deref()) {
+        let consumed = download_links::consume_link(&state.db, token, &id)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        if !consumed {
+            return Err(AppError::Forbidden(
+                "Link di download già utilizzato".to_string(),
+            ));
+        }
+    }
 
     // Determina il tipo effettivo del file dal path del risultato
     let actual_extension = result_path
@@ -326,16 +551,35 @@ pub async fn download_job_result(
     let content_type = get_content_type(actual_extension).to_string();
     let filename = format!("converted.{}", actual_extension);
 
-    Ok((
-        [
-            (header::CONTENT_TYPE, content_type),
+    response_headers.insert(header::CONTENT_TYPE, header_value(content_type)?);
+    response_headers.insert(
+        header::CONTENT_DISPOSITION,
+        header_value(format!("attachment; filename=\"{}\"", filename))?,
+    );
+
+    let storage = state.queue.read().await.storage().clone();
+    let (status, stream, length) = match range {
+        ByteRange::Partial { start, end } => {
+            let length = end - start + 1;
+            response_headers.insert(
+                header::CONTENT_RANGE,
+                header_value(format!("bytes {}-{}/{}", start, end, result.size))?,
+            );
             (
-                header::CONTENT_DISPOSITION,
-                format!("attachment; filename=\"{}\"", filename),
-            ),
-        ],
-        data,
-    ))
+                StatusCode::PARTIAL_CONTENT,
+                storage.stream_range(&result.key, start, length).await?,
+                length,
+            )
+        }
+        _ => (
+            StatusCode::OK,
+            storage.stream(&result.key).await?,
+            result.size,
+        ),
+    };
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+
+    Ok((status, response_headers, Body::from_stream(stream)).into_response())
 }
 
 /// Riprova un job fallito
@@ -349,18 +593,18 @@ pub async fn download_job_result(
     responses(
         (status = 200, description = "Job rimesso in coda"),
         (status = 400, description = "Il job non è in stato failed"),
+        (status = 403, description = "Tipo o priorità non consentiti dal piano"),
         (status = 404, description = "Job non trovato"),
+        (status = 429, description = "Quota esaurita"),
     )
 )]
 pub async fn retry_job(
     State(state): State<JobsState>,
+    Extension(auth): Extension<AuthInfo>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>> {
     // Verifica che il job esista e sia in stato failed
-    let job = db_jobs::get_job(&state.db, &id)
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?
-        .ok_or_else(|| AppError::JobNotFound(id.clone()))?;
+    let job = authorize_job(&state.db, &auth, &id).await?;
 
     if job.status != "failed" {
         return Err(AppError::BadRequest(
@@ -378,6 +622,23 @@ pub async fn retry_job(
         )));
     }
 
+    // Stessi controlli di piano e quote della creazione: il piano della
+    // chiave può essere cambiato e le quote esaurite dal primo tentativo
+    check_conversion(&state.db, &auth, &job.conversion_type).await?;
+    check_priority(
+        &auth,
+        JobPriority::from_str(job.priority.as_deref().unwrap_or_default()),
+    )?;
+    let duration_ms = db_jobs::get_job_media_duration(&state.db, &id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    check_quota(
+        &state.db,
+        &auth,
+        incoming_conversion(job.file_size_bytes.unwrap_or(0), duration_ms),
+    )
+    .await?;
+
     // Reset del job per retry
     let success = db_jobs::reset_job_for_retry(&state.db, &id)
         .await
@@ -392,9 +653,13 @@ pub async fn retry_job(
     // Avvia elaborazione in background
     let job_id = Uuid::parse_str(&id).map_err(|_| AppError::JobNotFound(id.clone()))?;
     let queue_clone = state.queue.clone();
-    tokio::spawn(async move {
-        queue::process_job(queue_clone, job_id).await;
-    });
+    // Lo span del job resta figlio di quello della richiesta
+    tokio::spawn(
+        async move {
+            queue::process_job(queue_clone, job_id).await;
+        }
+        .in_current_span(),
+    );
 
     Ok(Json(serde_json::json!({
         "success": true,
@@ -419,13 +684,11 @@ pub async fn retry_job(
 )]
 pub async fn cancel_job(
     State(state): State<JobsState>,
+    Extension(auth): Extension<AuthInfo>,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>> {
     // Verifica che il job esista
-    let job = db_jobs::get_job(&state.db, &id)
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?
-        .ok_or_else(|| AppError::JobNotFound(id.clone()))?;
+    let job = authorize_job(&state.db, &auth, &id).await?;
 
     // Solo pending e processing possono essere cancellati
     if job.status != "pending" && job.status != "processing" {
@@ -0,0 +1,239 @@
+//! Export della cronologia delle conversioni in CSV o NDJSON
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::Utc;
+use futures::StreamExt;
+use serde::Deserialize;
+
+use crate::db::api_keys::ApiKeyRole;
+use crate::db::stats::{self, ConversionExportRow, HistoryFilters, UsageScope};
+use crate::error::{AppError, Result};
+use crate::models::AuthInfo;
+use crate::utils::csv::csv_line;
+
+use super::crud::validate_history_dates;
+use super::JobsState;
+
+/// Colonne del CSV, nell'ordine di `ConversionExportRow`
+const CSV_COLUMNS: [&str; 13] = [
+    "id",
+    "timestamp",
+    "api_key_id",
+    "is_guest",
+    "conversion_type",
+    "input_format",
+    "output_format",
+    "input_size_bytes",
+    "output_size_bytes",
+    "processing_time_ms",
+    "media_duration_ms",
+    "success",
+    "error",
+];
+
+/// Formato dell'export
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+/// Query per l'export della cronologia
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// Formato: csv (default) o ndjson
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(flatten)]
+    pub filters: HistoryFilters,
+}
+
+/// Query per l'export amministrativo
+#[derive(Debug, Deserialize)]
+pub struct AdminExportQuery {
+    /// Limita l'export a una API Key
+    #[serde(default)]
+    pub api_key_id: Option<String>,
+    #[serde(flatten)]
+    pub export: ExportQuery,
+}
+
+/// Esporta la cronologia delle conversioni
+///
+/// Legge `conversion_records` (le conversioni terminate, anche dopo la pulizia
+/// dei job) e la invia in streaming. I membri di un'organizzazione esportano
+/// le conversioni di tutto il team.
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/history/export",
+    tag = "Jobs",
+    params(
+        ("format" = Option<String>, Query, description = "Formato: csv (default), ndjson"),
+        ("date_filter" = Option<String>, Query, description = "Filtro data: today, week, month, all"),
+        ("from" = Option<String>, Query, description = "Inizio periodo (RFC 3339 o YYYY-MM-DD)"),
+        ("to" = Option<String>, Query, description = "Fine periodo inclusa (RFC 3339 o YYYY-MM-DD)"),
+        ("conversion_type" = Option<String>, Query, description = "Filtro tipo conversione"),
+        ("input_format" = Option<String>, Query, description = "Filtro formato input"),
+        ("output_format" = Option<String>, Query, description = "Filtro formato output"),
+        ("status" = Option<String>, Query, description = "Filtro stato: completed, failed, all"),
+    ),
+    responses(
+        (status = 200, description = "Conversioni in CSV o NDJSON", content_type = "text/csv"),
+        (status = 400, description = "Data non valida"),
+        (status = 401, description = "API Key richiesta"),
+    )
+)]
+pub async fn export_history(
+    State(state): State<JobsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response> {
+    let scope = auth.usage_scope().ok_or_else(|| {
+        AppError::Unauthorized("API Key richiesta per esportare la cronologia".to_string())
+    })?;
+    validate_history_dates(&query.filters)?;
+
+    let rows = stats::stream_conversions(state.db, Some(scope), &query.filters, false);
+    Ok(export_response(rows, query.format, false))
+}
+
+/// Esporta le conversioni di tutte le API Key (solo admin)
+///
+/// Come l'export della cronologia, con l'IP del client e, se indicato, limitato
+/// a una singola API Key.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/conversions/export",
+    tag = "Admin",
+    params(
+        ("format" = Option<String>, Query, description = "Formato: csv (default), ndjson"),
+        ("api_key_id" = Option<String>, Query, description = "Limita l'export a una API Key"),
+        ("date_filter" = Option<String>, Query, description = "Filtro data: today, week, month, all"),
+        ("from" = Option<String>, Query, description = "Inizio periodo (RFC 3339 o YYYY-MM-DD)"),
+        ("to" = Option<String>, Query, description = "Fine periodo inclusa (RFC 3339 o YYYY-MM-DD)"),
+        ("conversion_type" = Option<String>, Query, description = "Filtro tipo conversione"),
+        ("input_format" = Option<String>, Query, description = "Filtro formato input"),
+        ("output_format" = Option<String>, Query, description = "Filtro formato output"),
+        ("status" = Option<String>, Query, description = "Filtro stato: completed, failed, all"),
+    ),
+    responses(
+        (status = 200, description = "Conversioni in CSV o NDJSON", content_type = "text/csv"),
+        (status = 400, description = "Data non valida"),
+        (status = 403, description = "Richiede privilegi admin"),
+    )
+)]
+pub async fn admin_export_conversions(
+    State(state): State<JobsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Query(query): Query<AdminExportQuery>,
+) -> Result<Response> {
+    if auth.role != ApiKeyRole::Admin || auth.is_guest {
+        return Err(AppError::Forbidden(
+            "Questa operazione richiede privilegi admin".to_string(),
+        ));
+    }
+    let ExportQuery { format, filters } = query.export;
+    validate_history_dates(&filters)?;
+
+    let scope = query
+        .api_key_id
+        .as_deref()
+        .filter(|id| !id.is_empty())
+        .map(UsageScope::ApiKey);
+    let rows = stats::stream_conversions(state.db, scope, &filters, true);
+    Ok(export_response(rows, format, true))
+}
+
+/// Risposta in streaming con le righe serializzate
+///
+/// Un errore del database a metà export interrompe la risposta, così il client
+/// non scambia un file troncato per completo.
+fn export_response(
+    rows: impl futures::Stream<Item = std::result::Result<ConversionExportRow, sqlx::Error>>
+        + Send
+        + 'static,
+    format: ExportFormat,
+    include_client_ip: bool,
+) -> Response {
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+
+    let lines = rows.map(move |row| {
+        let row = row.map_err(|e| {
+            tracing::error!("Export conversioni interrotto: {}", e);
+            std::io::Error::other(e)
+        })?;
+        Ok::<_, std::io::Error>(match format {
+            ExportFormat::Csv => csv_row(&row, include_client_ip),
+            ExportFormat::Ndjson => {
+                let mut line = serde_json::to_string(&row).map_err(std::io::Error::other)?;
+                line.push('\n');
+                line
+            }
+        })
+    });
+    let body = match format {
+        ExportFormat::Csv => {
+            let header = csv_header(include_client_ip);
+            Body::from_stream(futures::stream::once(async { Ok(header) }).chain(lines))
+        }
+        ExportFormat::Ndjson => Body::from_stream(lines),
+    };
+
+    let filename = format!(
+        "conversions-{}.{}",
+        Utc::now().format("%Y%m%d-%H%M%S"),
+        extension
+    );
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn csv_header(include_client_ip: bool) -> String {
+    let mut columns = CSV_COLUMNS.to_vec();
+    if include_client_ip {
+        columns.push("client_ip");
+    }
+    csv_line(&columns)
+}
+
+fn csv_row(row: &ConversionExportRow, include_client_ip: bool) -> String {
+    let mut fields = vec![
+        row.id.clone(),
+        row.timestamp.clone(),
+        row.api_key_id.clone().unwrap_or_default(),
+        row.is_guest.to_string(),
+        row.conversion_type.clone(),
+        row.input_format.clone(),
+        row.output_format.clone(),
+        row.input_size_bytes.to_string(),
+        row.output_size_bytes.to_string(),
+        row.processing_time_ms.to_string(),
+        row.media_duration_ms.to_string(),
+        row.success.to_string(),
+        row.error.clone().unwrap_or_default(),
+    ];
+    if include_client_ip {
+        fields.push(row.client_ip.clone().unwrap_or_default());
+    }
+    csv_line(&fields)
+}
@@ -0,0 +1,79 @@
+//! Signed download links for job results
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    Extension, Json,
+};
+
+use crate::db::download_links;
+use crate::error::{AppError, Result};
+use crate::models::{AuthInfo, CreateDownloadLinkRequest, DownloadLinkResponse};
+use crate::services::download_links::generate_token;
+use crate::utils::json_body::optional_json;
+
+use super::access::authorize_job;
+use super::JobsState;
+
+/// Validità massima di un link firmato (7 giorni)
+const MAX_LINK_TTL_SECS: u64 = 7 * 24 * 3600;
+
+/// Crea un link di download firmato per il risultato di un job
+///
+/// Il link può essere aperto da browser, email o tag `<img>` senza API Key.
+#[utoipa::path(
+    post,
+    path = "/api/v1/jobs/{id}/link",
+    tag = "Jobs",
+    params(
+        ("id" = String, Path, description = "ID del job")
+    ),
+    request_body = CreateDownloadLinkRequest,
+    responses(
+        (status = 200, description = "Link creato", body = DownloadLinkResponse),
+        (status = 400, description = "Body JSON non valido"),
+        (status = 404, description = "Job non trovato o di un altro utente"),
+    )
+)]
+pub async fn create_download_link(
+    State(state): State<JobsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<Json<DownloadLinkResponse>> {
+    // Senza body valgono i default; un body non valido non deve produrre
+    // in silenzio un link riutilizzabile
+    let request: CreateDownloadLinkRequest = optional_json(&body)?;
+
+    // Il link aggira l'autenticazione: solo il proprietario o un admin può crearlo
+    let job = authorize_job(&state.db, &auth, &id).await?;
+
+    let links = state.queue.read().await.links().clone();
+    let ttl = request
+        .expires_in_secs
+        .unwrap_or(links.default_ttl_secs)
+        .clamp(1, MAX_LINK_TTL_SECS);
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(ttl as i64);
+
+    let token = if request.single_use {
+        let token = generate_token();
+        download_links::create_link(
+            &state.db,
+            &token,
+            &job.id,
+            auth.api_key_id.as_deref(),
+            &expires_at.to_rfc3339(),
+        )
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        Some(token)
+    } else {
+        None
+    };
+
+    Ok(Json(DownloadLinkResponse {
+        url: links.sign_url(&job.id, expires_at.timestamp(), token.as_deref()),
+        expires_at: expires_at.to_rfc3339(),
+        single_use: request.single_use,
+    }))
+}
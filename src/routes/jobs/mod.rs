@@ -2,9 +2,12 @@
 //!
 //! This module provides HTTP endpoints for managing asynchronous conversion jobs.
 
+mod access;
 mod crud;
 #[cfg(feature = "google-auth")]
 mod drive;
+mod export;
+mod links;
 mod stream;
 
 use axum::{
@@ -12,13 +15,17 @@ use axum::{
     Router,
 };
 
+use crate::config::Config;
 use crate::db::DbPool;
 use crate::services::queue::{JobQueue, ProgressSender};
 
+pub(crate) use access::{guest_session_token, hash_guest_token};
 // Re-export public items (including utoipa path types)
 pub use crud::*;
 #[cfg(feature = "google-auth")]
 pub use drive::*;
+pub use export::*;
+pub use links::*;
 pub use stream::*;
 
 /// Shared state for job routes
@@ -27,24 +34,37 @@ pub struct JobsState {
     pub queue: JobQueue,
     pub progress_tx: ProgressSender,
     pub db: DbPool,
+    pub config: Config,
 }
 
 /// Create the router for job endpoints (with google-auth feature)
 #[cfg(feature = "google-auth")]
-pub fn router(job_queue: JobQueue, progress_tx: ProgressSender, db: DbPool) -> Router {
+pub fn router(
+    job_queue: JobQueue,
+    progress_tx: ProgressSender,
+    db: DbPool,
+    config: Config,
+) -> Router {
     let state = JobsState {
         queue: job_queue,
         progress_tx,
         db,
+        config,
     };
 
     Router::new()
         .route("/api/v1/jobs", get(list_jobs))
         .route("/api/v1/jobs", post(create_job))
         .route("/api/v1/jobs/history", get(get_history))
+        .route("/api/v1/jobs/history/export", get(export_history))
+        .route(
+            "/api/v1/admin/conversions/export",
+            get(admin_export_conversions),
+        )
         .route("/api/v1/jobs/:id", get(get_job_status))
         .route("/api/v1/jobs/:id", delete(delete_job))
         .route("/api/v1/jobs/:id/download", get(download_job_result))
+        .route("/api/v1/jobs/:id/link", post(create_download_link))
         .route("/api/v1/jobs/:id/progress", get(job_progress_stream))
         .route("/api/v1/jobs/:id/retry", post(retry_job))
         .route("/api/v1/jobs/:id/cancel", post(cancel_job))
@@ -55,20 +75,32 @@ pub fn router(job_queue: JobQueue, progress_tx: ProgressSender, db: DbPool) -> R
 
 /// Create the router for job endpoints (without google-auth feature)
 #[cfg(not(feature = "google-auth"))]
-pub fn router(job_queue: JobQueue, progress_tx: ProgressSender, db: DbPool) -> Router {
+pub fn router(
+    job_queue: JobQueue,
+    progress_tx: ProgressSender,
+    db: DbPool,
+    config: Config,
+) -> Router {
     let state = JobsState {
         queue: job_queue,
         progress_tx,
         db,
+        config,
     };
 
     Router::new()
         .route("/api/v1/jobs", get(list_jobs))
         .route("/api/v1/jobs", post(create_job))
         .route("/api/v1/jobs/history", get(get_history))
+        .route("/api/v1/jobs/history/export", get(export_history))
+        .route(
+            "/api/v1/admin/conversions/export",
+            get(admin_export_conversions),
+        )
         .route("/api/v1/jobs/:id", get(get_job_status))
         .route("/api/v1/jobs/:id", delete(delete_job))
         .route("/api/v1/jobs/:id/download", get(download_job_result))
+        .route("/api/v1/jobs/:id/link", post(create_download_link))
         .route("/api/v1/jobs/:id/progress", get(job_progress_stream))
         .route("/api/v1/jobs/:id/retry", post(retry_job))
         .route("/api/v1/jobs/:id/cancel", post(cancel_job))
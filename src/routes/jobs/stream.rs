@@ -3,6 +3,7 @@
 use axum::{
     extract::{Path, State},
     response::sse::{Event, KeepAlive, Sse},
+    Extension,
 };
 use std::convert::Infallible;
 use std::pin::Pin;
@@ -12,8 +13,9 @@ use tokio_stream::Stream;
 use uuid::Uuid;
 
 use crate::error::{AppError, Result};
-use crate::models::{JobStatus, ProgressUpdate};
+use crate::models::{AuthInfo, JobStatus, ProgressUpdate};
 
+use super::access::authorize_job;
 use super::JobsState;
 
 /// Stream personalizzato per progress di un job
@@ -90,9 +92,11 @@ impl Stream for JobProgressStream {
 )]
 pub async fn job_progress_stream(
     State(state): State<JobsState>,
+    Extension(auth): Extension<AuthInfo>,
     Path(id): Path<String>,
 ) -> Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>> {
     let job_id = Uuid::parse_str(&id).map_err(|_| AppError::JobNotFound(id.clone()))?;
+    authorize_job(&state.db, &auth, &id).await?;
 
     // Verifica che il job esista e ottieni stato iniziale
     let initial_update = {
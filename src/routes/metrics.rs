@@ -0,0 +1,83 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+
+use crate::db::{jobs as db_jobs, DbPool};
+use crate::error::{AppError, Result};
+use crate::services::download_links::constant_time_eq;
+use crate::services::metrics::{self, QueueSnapshot};
+use crate::services::queue::JobQueue;
+
+/// Content type del formato testo di Prometheus
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Clone)]
+pub struct MetricsState {
+    pub db: DbPool,
+    pub job_queue: JobQueue,
+    pub token: Option<String>,
+}
+
+/// Endpoint `/metrics`, montato fuori dal middleware di autenticazione
+pub fn router(db: DbPool, job_queue: JobQueue, token: Option<String>) -> Router {
+    let state = MetricsState {
+        db,
+        job_queue,
+        token,
+    };
+    Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state(state)
+}
+
+/// Metriche in formato Prometheus
+///
+/// Se `CONVERTY_METRICS_TOKEN` è impostato serve `Authorization: Bearer <token>`.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metriche in formato testo Prometheus", content_type = "text/plain"),
+        (status = 401, description = "Token mancante o non valido"),
+    ),
+    tag = "Sistema"
+)]
+pub async fn get_metrics(
+    State(state): State<MetricsState>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if let Some(expected) = &state.token {
+        if !has_metrics_token(&headers, expected) {
+            return Err(AppError::Unauthorized(
+                "Token metriche mancante o non valido".to_string(),
+            ));
+        }
+    }
+
+    let (permits_in_use, permits_total) = state.job_queue.read().await.permits();
+    let jobs_by_status = db_jobs::count_jobs_by_status(&state.db)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let body = metrics::render(&QueueSnapshot {
+        permits_in_use,
+        permits_total,
+        jobs_by_status,
+    });
+
+    Ok(([(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)], body).into_response())
+}
+
+/// Verifica `Authorization: Bearer <token>` con il token delle metriche
+pub fn has_metrics_token(headers: &HeaderMap, expected: &str) -> bool {
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+    constant_time_eq(provided.as_bytes(), expected.as_bytes())
+}
@@ -4,9 +4,13 @@ pub mod auth;
 pub mod convert;
 pub mod health;
 pub mod jobs;
+pub mod metrics;
+pub mod organizations;
+pub mod plans;
 #[cfg(feature = "google-auth")]
 pub mod settings;
 pub mod stats;
+pub mod uploads;
 
 use axum::Router;
 
@@ -26,16 +30,29 @@ pub fn create_router(
 ) -> Router {
     Router::new()
         .merge(health::router(config.max_file_size_mb))
-        .merge(convert::router(job_queue.clone(), db.clone()))
-        .merge(jobs::router(job_queue, progress_tx, db.clone()))
+        .merge(convert::router(
+            job_queue.clone(),
+            db.clone(),
+            config.clone(),
+        ))
+        .merge(jobs::router(
+            job_queue,
+            progress_tx,
+            db.clone(),
+            config.clone(),
+        ))
+        .merge(uploads::router(db.clone(), config.clone()))
         .merge(stats::router(db.clone()))
         .merge(admin::router(db.clone()))
+        .merge(organizations::router(db.clone()))
+        .merge(plans::router(db.clone()))
         .merge(settings::router(db.clone()))
         .merge(auth::router(
             db,
             google_client_id,
             google_client_secret,
             frontend_url,
+            config.oidc.clone(),
         ))
 }
 
@@ -51,8 +68,20 @@ pub fn create_router(
 ) -> Router {
     Router::new()
         .merge(health::router(config.max_file_size_mb))
-        .merge(convert::router(job_queue.clone(), db.clone()))
-        .merge(jobs::router(job_queue, progress_tx, db.clone()))
+        .merge(convert::router(
+            job_queue.clone(),
+            db.clone(),
+            config.clone(),
+        ))
+        .merge(jobs::router(
+            job_queue,
+            progress_tx,
+            db.clone(),
+            config.clone(),
+        ))
+        .merge(uploads::router(db.clone(), config.clone()))
         .merge(stats::router(db.clone()))
         .merge(admin::router(db.clone()))
+        .merge(organizations::router(db.clone()))
+        .merge(plans::router(db.clone()))
 }
@@ -0,0 +1,511 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post, put},
+    Extension, Json, Router,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::db::api_keys::{self, ApiKeyRole};
+use crate::db::organizations::{
+    self, AddMemberRequest, CreateOrganizationRequest, OrgRole, Organization, OrganizationMember,
+    UpdateMemberRequest, UpdateOrganizationRequest,
+};
+use crate::db::stats::UsageScope;
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::models::{AuthInfo, QuotaLimits, QuotaStatus};
+use crate::routes::admin::MessageResponse;
+use crate::services::{audit, quota};
+
+#[derive(Clone)]
+pub struct OrganizationsState {
+    pub db: DbPool,
+}
+
+pub fn router(db: DbPool) -> Router {
+    let state = OrganizationsState { db };
+    Router::new()
+        // Gestione admin
+        .route("/api/v1/admin/organizations", get(list_organizations))
+        .route("/api/v1/admin/organizations", post(create_organization))
+        .route("/api/v1/admin/organizations/:id", get(get_organization))
+        .route("/api/v1/admin/organizations/:id", put(update_organization))
+        .route(
+            "/api/v1/admin/organizations/:id",
+            delete(delete_organization),
+        )
+        .route("/api/v1/admin/organizations/:id/members", post(add_member))
+        .route(
+            "/api/v1/admin/organizations/:id/members/:key_id",
+            delete(remove_member),
+        )
+        // Organizzazione del chiamante
+        .route("/api/v1/organization", get(get_own_organization))
+        .route(
+            "/api/v1/organization/members/:key_id",
+            put(update_own_member),
+        )
+        .route(
+            "/api/v1/organization/members/:key_id",
+            delete(remove_own_member),
+        )
+        .with_state(state)
+}
+
+/// Organizzazione con membri e consumo delle quote condivise
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrganizationDetails {
+    #[serde(flatten)]
+    pub organization: Organization,
+    pub members: Vec<OrganizationMember>,
+    pub quota: QuotaStatus,
+}
+
+/// Lista tutte le organizzazioni
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/organizations",
+    responses(
+        (status = 200, description = "Lista organizzazioni", body = Vec<Organization>),
+        (status = 401, description = "Non autorizzato"),
+        (status = 403, description = "Solo admin"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn list_organizations(
+    State(state): State<OrganizationsState>,
+    Extension(role): Extension<ApiKeyRole>,
+) -> Result<Json<Vec<Organization>>> {
+    require_admin(&role)?;
+
+    let orgs = organizations::list_organizations(&state.db)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(Json(orgs))
+}
+
+/// Crea una nuova organizzazione
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/organizations",
+    request_body = CreateOrganizationRequest,
+    responses(
+        (status = 201, description = "Organizzazione creata", body = Organization),
+        (status = 400, description = "Nome mancante"),
+        (status = 403, description = "Solo admin"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn create_organization(
+    State(state): State<OrganizationsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Json(request): Json<CreateOrganizationRequest>,
+) -> Result<(StatusCode, Json<Organization>)> {
+    require_admin(&auth.role)?;
+
+    if request.name.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "Il nome dell'organizzazione è obbligatorio".to_string(),
+        ));
+    }
+
+    let org = organizations::create_organization(&state.db, &request)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    audit::record(
+        &state.db,
+        &auth,
+        "organization.create",
+        Some(&org.id),
+        None,
+        audit::snapshot(&org),
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(org)))
+}
+
+/// Ottieni dettagli organizzazione
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/organizations/{id}",
+    params(
+        ("id" = String, Path, description = "ID organizzazione")
+    ),
+    responses(
+        (status = 200, description = "Dettagli organizzazione", body = OrganizationDetails),
+        (status = 403, description = "Solo admin"),
+        (status = 404, description = "Organizzazione non trovata"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn get_organization(
+    State(state): State<OrganizationsState>,
+    Extension(role): Extension<ApiKeyRole>,
+    Path(id): Path<String>,
+) -> Result<Json<OrganizationDetails>> {
+    require_admin(&role)?;
+
+    Ok(Json(organization_details(&state.db, &id).await?))
+}
+
+/// Aggiorna nome e quote di un'organizzazione
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/organizations/{id}",
+    params(
+        ("id" = String, Path, description = "ID organizzazione")
+    ),
+    request_body = UpdateOrganizationRequest,
+    responses(
+        (status = 200, description = "Organizzazione aggiornata", body = MessageResponse),
+        (status = 403, description = "Solo admin"),
+        (status = 404, description = "Organizzazione non trovata"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn update_organization(
+    State(state): State<OrganizationsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+    Json(request): Json<UpdateOrganizationRequest>,
+) -> Result<Json<MessageResponse>> {
+    require_admin(&auth.role)?;
+
+    let before = find_organization(&state.db, &id).await?;
+    let updated = organizations::update_organization(&state.db, &id, &request)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    if updated {
+        let after = find_organization(&state.db, &id).await?;
+        let (old, new) = audit::diff(&before, &after);
+        audit::record(&state.db, &auth, "organization.update", Some(&id), old, new).await;
+        Ok(Json(MessageResponse {
+            message: "Organizzazione aggiornata".to_string(),
+        }))
+    } else {
+        Err(AppError::NotFound(
+            "Organizzazione non trovata o nessuna modifica".to_string(),
+        ))
+    }
+}
+
+/// Elimina un'organizzazione
+///
+/// Le API Key membri non vengono eliminate: tornano senza organizzazione.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/organizations/{id}",
+    params(
+        ("id" = String, Path, description = "ID organizzazione")
+    ),
+    responses(
+        (status = 200, description = "Organizzazione eliminata", body = MessageResponse),
+        (status = 403, description = "Solo admin"),
+        (status = 404, description = "Organizzazione non trovata"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn delete_organization(
+    State(state): State<OrganizationsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+) -> Result<Json<MessageResponse>> {
+    require_admin(&auth.role)?;
+
+    let before = find_organization(&state.db, &id).await?;
+    let deleted = organizations::delete_organization(&state.db, &id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    if deleted {
+        audit::record(
+            &state.db,
+            &auth,
+            "organization.delete",
+            Some(&id),
+            audit::snapshot(&before),
+            None,
+        )
+        .await;
+        Ok(Json(MessageResponse {
+            message: "Organizzazione eliminata".to_string(),
+        }))
+    } else {
+        Err(AppError::NotFound("Organizzazione non trovata".to_string()))
+    }
+}
+
+/// Aggiungi un'API Key all'organizzazione
+///
+/// Se la chiave appartiene già a un'altra organizzazione viene spostata.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/organizations/{id}/members",
+    params(
+        ("id" = String, Path, description = "ID organizzazione")
+    ),
+    request_body = AddMemberRequest,
+    responses(
+        (status = 200, description = "Membro aggiunto", body = MessageResponse),
+        (status = 403, description = "Solo admin"),
+        (status = 404, description = "Organizzazione o API Key non trovata"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn add_member(
+    State(state): State<OrganizationsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+    Json(request): Json<AddMemberRequest>,
+) -> Result<Json<MessageResponse>> {
+    require_admin(&auth.role)?;
+
+    find_organization(&state.db, &id).await?;
+    api_keys::find_by_id(&state.db, &request.api_key_id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("API Key non trovata".to_string()))?;
+
+    organizations::add_member(&state.db, &id, &request.api_key_id, request.role)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    audit::record(
+        &state.db,
+        &auth,
+        "organization.member_add",
+        Some(&id),
+        None,
+        Some(serde_json::json!({ "api_key_id": request.api_key_id, "role": request.role })),
+    )
+    .await;
+
+    Ok(Json(MessageResponse {
+        message: format!("API Key aggiunta come {}", request.role),
+    }))
+}
+
+/// Rimuovi un'API Key dall'organizzazione
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/organizations/{id}/members/{key_id}",
+    params(
+        ("id" = String, Path, description = "ID organizzazione"),
+        ("key_id" = String, Path, description = "ID API Key")
+    ),
+    responses(
+        (status = 200, description = "Membro rimosso", body = MessageResponse),
+        (status = 403, description = "Solo admin"),
+        (status = 404, description = "Membro non trovato"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn remove_member(
+    State(state): State<OrganizationsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path((id, key_id)): Path<(String, String)>,
+) -> Result<Json<MessageResponse>> {
+    require_admin(&auth.role)?;
+
+    remove(&state.db, &auth, &id, &key_id).await
+}
+
+/// Organizzazione dell'API Key corrente
+#[utoipa::path(
+    get,
+    path = "/api/v1/organization",
+    responses(
+        (status = 200, description = "Organizzazione, membri e quote condivise", body = OrganizationDetails),
+        (status = 401, description = "API Key richiesta"),
+        (status = 404, description = "L'API Key non appartiene a un'organizzazione"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn get_own_organization(
+    State(state): State<OrganizationsState>,
+    Extension(auth): Extension<AuthInfo>,
+) -> Result<Json<OrganizationDetails>> {
+    let (org_id, _) = membership(&auth)?;
+
+    Ok(Json(organization_details(&state.db, org_id).await?))
+}
+
+/// Cambia il ruolo di un membro (solo owner)
+#[utoipa::path(
+    put,
+    path = "/api/v1/organization/members/{key_id}",
+    params(
+        ("key_id" = String, Path, description = "ID API Key del membro")
+    ),
+    request_body = UpdateMemberRequest,
+    responses(
+        (status = 200, description = "Ruolo aggiornato", body = MessageResponse),
+        (status = 400, description = "Un owner non può cambiare il proprio ruolo"),
+        (status = 403, description = "Solo owner"),
+        (status = 404, description = "Membro non trovato"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn update_own_member(
+    State(state): State<OrganizationsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(key_id): Path<String>,
+    Json(request): Json<UpdateMemberRequest>,
+) -> Result<Json<MessageResponse>> {
+    let org_id = require_owner(&auth, &key_id)?;
+
+    let updated = organizations::update_member_role(&state.db, org_id, &key_id, request.role)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    if updated {
+        audit::record(
+            &state.db,
+            &auth,
+            "organization.member_update",
+            Some(org_id),
+            None,
+            Some(serde_json::json!({ "api_key_id": key_id, "role": request.role })),
+        )
+        .await;
+        Ok(Json(MessageResponse {
+            message: format!("Ruolo aggiornato a {}", request.role),
+        }))
+    } else {
+        Err(AppError::NotFound("Membro non trovato".to_string()))
+    }
+}
+
+/// Rimuovi un membro dall'organizzazione (solo owner)
+#[utoipa::path(
+    delete,
+    path = "/api/v1/organization/members/{key_id}",
+    params(
+        ("key_id" = String, Path, description = "ID API Key del membro")
+    ),
+    responses(
+        (status = 200, description = "Membro rimosso", body = MessageResponse),
+        (status = 400, description = "Un owner non può rimuovere se stesso"),
+        (status = 403, description = "Solo owner"),
+        (status = 404, description = "Membro non trovato"),
+    ),
+    security(("api_key" = [])),
+    tag = "Organizzazioni"
+)]
+pub async fn remove_own_member(
+    State(state): State<OrganizationsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(key_id): Path<String>,
+) -> Result<Json<MessageResponse>> {
+    let org_id = require_owner(&auth, &key_id)?;
+
+    remove(&state.db, &auth, org_id, &key_id).await
+}
+
+async fn find_organization(db: &DbPool, id: &str) -> Result<Organization> {
+    organizations::get_organization(db, id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Organizzazione non trovata".to_string()))
+}
+
+async fn organization_details(db: &DbPool, id: &str) -> Result<OrganizationDetails> {
+    let organization = find_organization(db, id).await?;
+    let members = organizations::list_members(db, id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let quota = quota::quota_status(
+        db,
+        UsageScope::Organization(id),
+        &QuotaLimits::from(&organization),
+    )
+    .await?;
+
+    Ok(OrganizationDetails {
+        organization,
+        members,
+        quota,
+    })
+}
+
+async fn remove(
+    db: &DbPool,
+    auth: &AuthInfo,
+    org_id: &str,
+    key_id: &str,
+) -> Result<Json<MessageResponse>> {
+    let removed = organizations::remove_member(db, org_id, key_id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    if removed {
+        audit::record(
+            db,
+            auth,
+            "organization.member_remove",
+            Some(org_id),
+            Some(serde_json::json!({ "api_key_id": key_id })),
+            None,
+        )
+        .await;
+        Ok(Json(MessageResponse {
+            message: "Membro rimosso".to_string(),
+        }))
+    } else {
+        Err(AppError::NotFound("Membro non trovato".to_string()))
+    }
+}
+
+fn membership(auth: &AuthInfo) -> Result<(&str, OrgRole)> {
+    if auth.api_key_id.is_none() {
+        return Err(AppError::Unauthorized("API Key richiesta".to_string()));
+    }
+    auth.organization
+        .as_ref()
+        .map(|org| (org.id.as_str(), org.role))
+        .ok_or_else(|| {
+            AppError::NotFound("L'API Key non appartiene a un'organizzazione".to_string())
+        })
+}
+
+/// Verifica che il chiamante sia owner e non stia modificando se stesso
+///
+/// Così un'organizzazione non può restare senza owner per errore.
+fn require_owner<'a>(auth: &'a AuthInfo, key_id: &str) -> Result<&'a str> {
+    let (org_id, role) = membership(auth)?;
+    if role != OrgRole::Owner {
+        return Err(AppError::Forbidden(
+            "Solo gli owner possono gestire i membri dell'organizzazione".to_string(),
+        ));
+    }
+    if auth.api_key_id.as_deref() == Some(key_id) {
+        return Err(AppError::BadRequest(
+            "Un owner non può modificare o rimuovere se stesso".to_string(),
+        ));
+    }
+    Ok(org_id)
+}
+
+fn require_admin(role: &ApiKeyRole) -> Result<()> {
+    if *role != ApiKeyRole::Admin {
+        return Err(AppError::Forbidden(
+            "Questa operazione richiede privilegi admin".to_string(),
+        ));
+    }
+    Ok(())
+}
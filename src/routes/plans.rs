@@ -0,0 +1,319 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post, put},
+    Extension, Json, Router,
+};
+
+use crate::db::api_keys::{self, ApiKeyRole};
+use crate::db::plans::{self, AssignPlanRequest, CreatePlanRequest, Plan, UpdatePlanRequest};
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::models::AuthInfo;
+use crate::routes::admin::MessageResponse;
+use crate::services::audit;
+
+#[derive(Clone)]
+pub struct PlansState {
+    pub db: DbPool,
+}
+
+pub fn router(db: DbPool) -> Router {
+    let state = PlansState { db };
+    Router::new()
+        .route("/api/v1/admin/plans", get(list_plans))
+        .route("/api/v1/admin/plans", post(create_plan))
+        .route("/api/v1/admin/plans/:id", get(get_plan))
+        .route("/api/v1/admin/plans/:id", put(update_plan))
+        .route("/api/v1/admin/plans/:id", delete(delete_plan))
+        .route("/api/v1/admin/plans/:id/assign", post(assign_plan))
+        .with_state(state)
+}
+
+/// Lista tutti i piani
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/plans",
+    responses(
+        (status = 200, description = "Lista piani", body = Vec<Plan>),
+        (status = 401, description = "Non autorizzato"),
+        (status = 403, description = "Solo admin"),
+    ),
+    security(("api_key" = [])),
+    tag = "Piani"
+)]
+pub async fn list_plans(
+    State(state): State<PlansState>,
+    Extension(role): Extension<ApiKeyRole>,
+) -> Result<Json<Vec<Plan>>> {
+    require_admin(&role)?;
+
+    let plans = plans::list_plans(&state.db)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(Json(plans))
+}
+
+/// Crea un nuovo piano
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/plans",
+    request_body = CreatePlanRequest,
+    responses(
+        (status = 201, description = "Piano creato", body = Plan),
+        (status = 400, description = "Nome mancante o già in uso"),
+        (status = 403, description = "Solo admin"),
+    ),
+    security(("api_key" = [])),
+    tag = "Piani"
+)]
+pub async fn create_plan(
+    State(state): State<PlansState>,
+    Extension(auth): Extension<AuthInfo>,
+    Json(request): Json<CreatePlanRequest>,
+) -> Result<(StatusCode, Json<Plan>)> {
+    require_admin(&auth.role)?;
+
+    if request.name.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "Il nome del piano è obbligatorio".to_string(),
+        ));
+    }
+
+    let plan = plans::create_plan(&state.db, &request)
+        .await
+        .map_err(plan_error)?;
+
+    audit::record(
+        &state.db,
+        &auth,
+        "plan.create",
+        Some(&plan.id),
+        None,
+        audit::snapshot(&plan),
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(plan)))
+}
+
+/// Ottieni un piano
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/plans/{id}",
+    params(
+        ("id" = String, Path, description = "ID piano")
+    ),
+    responses(
+        (status = 200, description = "Dettagli piano", body = Plan),
+        (status = 403, description = "Solo admin"),
+        (status = 404, description = "Piano non trovato"),
+    ),
+    security(("api_key" = [])),
+    tag = "Piani"
+)]
+pub async fn get_plan(
+    State(state): State<PlansState>,
+    Extension(role): Extension<ApiKeyRole>,
+    Path(id): Path<String>,
+) -> Result<Json<Plan>> {
+    require_admin(&role)?;
+
+    Ok(Json(find_plan(&state.db, &id).await?))
+}
+
+/// Aggiorna i limiti di un piano
+///
+/// Le modifiche valgono subito per tutte le API Key assegnate.
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/plans/{id}",
+    params(
+        ("id" = String, Path, description = "ID piano")
+    ),
+    request_body = UpdatePlanRequest,
+    responses(
+        (status = 200, description = "Piano aggiornato", body = MessageResponse),
+        (status = 400, description = "Nome già in uso"),
+        (status = 403, description = "Solo admin"),
+        (status = 404, description = "Piano non trovato"),
+    ),
+    security(("api_key" = [])),
+    tag = "Piani"
+)]
+pub async fn update_plan(
+    State(state): State<PlansState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+    Json(request): Json<UpdatePlanRequest>,
+) -> Result<Json<MessageResponse>> {
+    require_admin(&auth.role)?;
+
+    let before = find_plan(&state.db, &id).await?;
+    let updated = plans::update_plan(&state.db, &id, &request)
+        .await
+        .map_err(plan_error)?;
+
+    if updated {
+        let after = find_plan(&state.db, &id).await?;
+        let (old, new) = audit::diff(&before, &after);
+        audit::record(&state.db, &auth, "plan.update", Some(&id), old, new).await;
+        Ok(Json(MessageResponse {
+            message: "Piano aggiornato".to_string(),
+        }))
+    } else {
+        Err(AppError::NotFound(
+            "Piano non trovato o nessuna modifica".to_string(),
+        ))
+    }
+}
+
+/// Elimina un piano
+///
+/// Le API Key e gli utenti assegnati restano senza piano.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/plans/{id}",
+    params(
+        ("id" = String, Path, description = "ID piano")
+    ),
+    responses(
+        (status = 200, description = "Piano eliminato", body = MessageResponse),
+        (status = 403, description = "Solo admin"),
+        (status = 404, description = "Piano non trovato"),
+    ),
+    security(("api_key" = [])),
+    tag = "Piani"
+)]
+pub async fn delete_plan(
+    State(state): State<PlansState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+) -> Result<Json<MessageResponse>> {
+    require_admin(&auth.role)?;
+
+    let before = find_plan(&state.db, &id).await?;
+    let deleted = plans::delete_plan(&state.db, &id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    if deleted {
+        audit::record(
+            &state.db,
+            &auth,
+            "plan.delete",
+            Some(&id),
+            audit::snapshot(&before),
+            None,
+        )
+        .await;
+        Ok(Json(MessageResponse {
+            message: "Piano eliminato".to_string(),
+        }))
+    } else {
+        Err(AppError::NotFound("Piano non trovato".to_string()))
+    }
+}
+
+/// Assegna il piano a un'API Key o a un utente OAuth
+///
+/// Per un utente OAuth il piano vale per la sua API Key attuale e per quelle
+/// rigenerate in futuro.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/plans/{id}/assign",
+    params(
+        ("id" = String, Path, description = "ID piano")
+    ),
+    request_body = AssignPlanRequest,
+    responses(
+        (status = 200, description = "Piano assegnato", body = MessageResponse),
+        (status = 400, description = "Né api_key_id né oauth_user_id indicati"),
+        (status = 403, description = "Solo admin"),
+        (status = 404, description = "Piano, API Key o utente non trovato"),
+    ),
+    security(("api_key" = [])),
+    tag = "Piani"
+)]
+pub async fn assign_plan(
+    State(state): State<PlansState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+    Json(request): Json<AssignPlanRequest>,
+) -> Result<Json<MessageResponse>> {
+    require_admin(&auth.role)?;
+
+    let plan = find_plan(&state.db, &id).await?;
+
+    let (target, assigned) = match (&request.api_key_id, &request.oauth_user_id) {
+        (Some(key_id), None) => {
+            let key = api_keys::find_by_id(&state.db, key_id)
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?
+                .ok_or_else(|| AppError::NotFound("API Key non trovata".to_string()))?;
+            let assigned = plans::assign_to_key(&state.db, &id, key_id)
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            (serde_json::json!({ "api_key_id": key.id }), assigned)
+        }
+        (None, Some(user_id)) => {
+            let assigned = plans::assign_to_oauth_user(&state.db, &id, user_id)
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            if !assigned {
+                return Err(AppError::NotFound("Utente non trovato".to_string()));
+            }
+            (serde_json::json!({ "oauth_user_id": user_id }), assigned)
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "Indicare solo uno tra api_key_id e oauth_user_id".to_string(),
+            ))
+        }
+    };
+
+    if !assigned {
+        return Err(AppError::NotFound("API Key non trovata".to_string()));
+    }
+
+    audit::record(
+        &state.db,
+        &auth,
+        "plan.assign",
+        Some(&id),
+        None,
+        Some(target),
+    )
+    .await;
+
+    Ok(Json(MessageResponse {
+        message: format!("Piano '{}' assegnato", plan.name),
+    }))
+}
+
+async fn find_plan(db: &DbPool, id: &str) -> Result<Plan> {
+    plans::get_plan(db, id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound("Piano non trovato".to_string()))
+}
+
+/// Il nome del piano è univoco: il conflitto è un errore del client
+fn plan_error(e: sqlx::Error) -> AppError {
+    match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => {
+            AppError::BadRequest("Esiste già un piano con questo nome".to_string())
+        }
+        _ => AppError::Internal(e.to_string()),
+    }
+}
+
+fn require_admin(role: &ApiKeyRole) -> Result<()> {
+    if *role != ApiKeyRole::Admin {
+        return Err(AppError::Forbidden(
+            "Questa operazione richiede privilegi admin".to_string(),
+        ));
+    }
+    Ok(())
+}
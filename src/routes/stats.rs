@@ -6,10 +6,17 @@ use axum::{
 use chrono::Utc;
 
 use crate::db::api_keys::ApiKeyRole;
-use crate::db::stats as db_stats;
+use crate::db::stats::{self as db_stats, UsageScope};
+use crate::db::timeseries::{self, TimeseriesFilter};
 use crate::db::DbPool;
 use crate::error::{AppError, Result};
-use crate::models::{AuthInfo, StatsQuery, StatsResponse, StatsSummary};
+use crate::models::{
+    AuthInfo, StatsQuery, StatsResponse, StatsSummary, TimeseriesQuery, TimeseriesResponse,
+};
+use crate::utils::validation::parse_date_param;
+
+/// Numero massimo di intervalli per serie
+const MAX_TIMESERIES_BUCKETS: usize = 2000;
 
 #[derive(Clone)]
 pub struct StatsState {
@@ -21,10 +28,15 @@ pub fn router(db: DbPool) -> Router {
     Router::new()
         .route("/api/v1/stats", get(get_stats))
         .route("/api/v1/stats/summary", get(get_summary))
+        .route("/api/v1/stats/timeseries", get(get_timeseries))
         .with_state(state)
 }
 
 /// Ottieni statistiche complete
+///
+/// Le cause di errore sono quelle di tutte le conversioni per gli admin,
+/// altrimenti solo quelle della propria API Key o organizzazione. Ai guest
+/// non vengono restituiti percentili né cause di errore.
 #[utoipa::path(
     get,
     path = "/api/v1/stats",
@@ -53,9 +65,28 @@ pub async fn get_stats(
     }
 
     // Ottieni statistiche globali
-    let global = db_stats::get_global_stats(&state.db)
+    let mut global = db_stats::get_global_stats(&state.db)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    // Percentili e coppie di formati: calcolati solo qui, non nei riepiloghi
+    let (processing_time_ms, by_format_pair) = db_stats::get_performance_stats(&state.db)
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
+    global.processing_time_ms = processing_time_ms;
+    global.by_format_pair = by_format_pair;
+
+    // I messaggi di errore di tutti solo agli admin, gli altri vedono i propri
+    let errors_scope = if auth.role == ApiKeyRole::Admin {
+        Some(None)
+    } else {
+        auth.usage_scope().map(Some)
+    };
+    if let Some(scope) = errors_scope {
+        global.top_errors = db_stats::get_top_errors(&state.db, scope)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+    }
 
     // Se l'utente è autenticato, ottieni le sue statistiche
     let api_key_stats = if let Some(ref key_id) = auth.api_key_id {
@@ -66,13 +97,22 @@ pub async fn get_stats(
         None
     };
 
-    // Conversioni recenti (filtrate per l'utente se non admin)
+    // Statistiche condivise dell'organizzazione
+    let organization_stats = if let Some(ref org) = auth.organization {
+        db_stats::get_organization_stats(&state.db, &org.id)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+    } else {
+        None
+    };
+
+    // Conversioni recenti (filtrate per l'utente o la sua organizzazione se non admin)
     let recent_conversions = if auth.role == ApiKeyRole::Admin {
         db_stats::get_recent_conversions(&state.db, &query, None)
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?
-    } else if let Some(ref key_id) = auth.api_key_id {
-        db_stats::get_recent_conversions(&state.db, &query, Some(key_id))
+    } else if let Some(scope) = auth.usage_scope() {
+        db_stats::get_recent_conversions(&state.db, &query, Some(scope))
             .await
             .map_err(|e| AppError::Internal(e.to_string()))?
     } else {
@@ -82,6 +122,7 @@ pub async fn get_stats(
     Ok(Json(StatsResponse {
         global,
         api_key_stats,
+        organization_stats,
         recent_conversions,
         server_uptime_seconds: 0, // TODO: implementare uptime
         generated_at: Utc::now(),
@@ -89,6 +130,7 @@ pub async fn get_stats(
 }
 
 async fn get_guest_stats(db: &DbPool) -> Result<Json<StatsResponse>> {
+    // Senza percentili né cause di errore, riservati agli utenti con API Key
     let global = db_stats::get_global_stats(db)
         .await
         .map_err(|e| AppError::Internal(e.to_string()))?;
@@ -96,6 +138,7 @@ async fn get_guest_stats(db: &DbPool) -> Result<Json<StatsResponse>> {
     Ok(Json(StatsResponse {
         global,
         api_key_stats: None,
+        organization_stats: None,
         recent_conversions: Vec::new(),
         server_uptime_seconds: 0,
         generated_at: Utc::now(),
@@ -138,3 +181,100 @@ pub async fn get_summary(State(state): State<StatsState>) -> Result<Json<StatsSu
         uptime_seconds: 0, // TODO: implementare uptime
     }))
 }
+
+/// Statistiche nel tempo
+///
+/// Conversioni, byte e tempi aggregati per ora, giorno o settimana (UTC),
+/// opzionalmente suddivisi per tipo, coppia di formati o API Key. Gli
+/// intervalli senza conversioni hanno valori a zero. Gli admin vedono tutte
+/// le conversioni, gli altri solo quelle della propria API Key o
+/// organizzazione.
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/timeseries",
+    params(
+        ("from" = Option<String>, Query, description = "Inizio periodo, RFC 3339 o YYYY-MM-DD (default: 48 ore, 30 giorni o 12 settimane prima di `to`)"),
+        ("to" = Option<String>, Query, description = "Fine periodo inclusa, RFC 3339 o YYYY-MM-DD (default: adesso)"),
+        ("bucket" = Option<String>, Query, description = "Intervallo: hour, day, week (default: day)"),
+        ("group_by" = Option<String>, Query, description = "Suddivisione: type, format, api_key"),
+        ("api_key_id" = Option<String>, Query, description = "Solo le conversioni di questa API Key (solo admin)"),
+    ),
+    responses(
+        (status = 200, description = "Serie temporali", body = TimeseriesResponse),
+        (status = 400, description = "Periodo non valido o troppo lungo"),
+        (status = 403, description = "Richiede una API Key"),
+    ),
+    security(("api_key" = [])),
+    tag = "Statistiche"
+)]
+pub async fn get_timeseries(
+    State(state): State<StatsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Query(query): Query<TimeseriesQuery>,
+) -> Result<Json<TimeseriesResponse>> {
+    if auth.is_guest {
+        return Err(AppError::Forbidden(
+            "Le statistiche nel tempo richiedono una API Key".to_string(),
+        ));
+    }
+
+    let scope = if auth.role == ApiKeyRole::Admin {
+        query.api_key_id.as_deref().map(UsageScope::ApiKey)
+    } else if query.api_key_id.is_some() {
+        return Err(AppError::Forbidden(
+            "Il filtro api_key_id è riservato agli admin".to_string(),
+        ));
+    } else {
+        Some(auth.usage_scope().ok_or_else(|| {
+            AppError::Forbidden("Le statistiche nel tempo richiedono una API Key".to_string())
+        })?)
+    };
+
+    let bucket = query.bucket;
+    let to = match query.to.as_deref() {
+        Some(value) => parse_date_param(value, true)?,
+        None => Utc::now(),
+    };
+    let from = match query.from.as_deref() {
+        Some(value) => parse_date_param(value, false)?,
+        None => to - bucket.default_range(),
+    };
+    if from > to {
+        return Err(AppError::BadRequest(
+            "'from' deve precedere 'to'".to_string(),
+        ));
+    }
+
+    let from = bucket.truncate(from);
+    let until = bucket.next(bucket.truncate(to));
+    if timeseries::bucket_starts(bucket, from, until)
+        .nth(MAX_TIMESERIES_BUCKETS)
+        .is_some()
+    {
+        return Err(AppError::BadRequest(format!(
+            "Periodo troppo lungo: massimo {} intervalli, usa un bucket più ampio",
+            MAX_TIMESERIES_BUCKETS
+        )));
+    }
+
+    let series = timeseries::get_timeseries(
+        &state.db,
+        TimeseriesFilter {
+            from,
+            until,
+            bucket,
+            group_by: query.group_by,
+            scope,
+        },
+    )
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(Json(TimeseriesResponse {
+        from,
+        to: until,
+        bucket,
+        group_by: query.group_by,
+        series,
+    }))
+}
@@ -0,0 +1,621 @@
+//! Upload riprendibili compatibili con il protocollo tus 1.0.0
+//!
+//! Il client crea l'upload con `POST /api/v1/uploads` dichiarando la
+//! dimensione totale, poi invia il contenuto a blocchi con `PATCH`.
+//! Se la connessione cade, `HEAD` restituisce l'offset da cui riprendere.
+//! Un upload completato si usa al posto del file in `POST /api/v1/jobs`
+//! tramite il parametro `upload_id`.
+//!
+//! Estensioni supportate: `creation`, `termination`, `expiration`
+//! (elencate nella risposta a `OPTIONS /api/v1/uploads`).
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Router,
+};
+use base64::Engine;
+use futures::StreamExt;
+use tempfile::TempPath;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::db::api_keys::ApiKeyRole;
+use crate::db::uploads::{self, UploadRecord};
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::middleware::auth::GUEST_TOKEN;
+use crate::middleware::body_limit::UploadLimit;
+use crate::models::AuthInfo;
+use crate::routes::jobs::{guest_session_token, hash_guest_token};
+use crate::utils::validate_file_size;
+
+/// Versione del protocollo tus supportata
+pub const TUS_VERSION: &str = "1.0.0";
+
+/// Estensioni tus supportate
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+
+/// Validità di un upload non utilizzato (ore)
+const UPLOAD_TTL_HOURS: i64 = 24;
+
+pub const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+pub const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+pub const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+pub const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+pub const UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+
+#[derive(Clone)]
+pub struct UploadsState {
+    pub db: DbPool,
+    pub config: Config,
+    /// Upload con un PATCH in corso (un solo writer per upload)
+    active: Arc<Mutex<HashSet<String>>>,
+}
+
+pub fn router(db: DbPool, config: Config) -> Router {
+    let state = UploadsState {
+        db,
+        config,
+        active: Arc::new(Mutex::new(HashSet::new())),
+    };
+
+    Router::new()
+        .route("/api/v1/uploads", post(create_upload))
+        .route(
+            "/api/v1/uploads/:id",
+            axum::routing::head(get_upload_offset)
+                .patch(append_upload)
+                .delete(delete_upload),
+        )
+        .with_state(state)
+}
+
+/// Percorso su disco del contenuto di un upload
+pub fn upload_path(config: &Config, id: &str) -> PathBuf {
+    config.temp_dir.join("uploads").join(id)
+}
+
+/// Upload completato pronto per diventare l'input di un job
+#[derive(Debug)]
+pub struct CompletedUpload {
+    pub id: String,
+    pub path: PathBuf,
+    pub filename: Option<String>,
+}
+
+/// Recupera un upload completato appartenente all'utente.
+///
+/// Il record va rivendicato con [`claim_upload`] subito prima della creazione
+/// del job, così un errore nei controlli (es. quota) permette di riprovare.
+pub async fn completed_upload(
+    db: &DbPool,
+    config: &Config,
+    auth: &AuthInfo,
+    id: &str,
+) -> Result<CompletedUpload> {
+    let upload = find_upload(db, auth, id).await?;
+    if !upload.is_complete() {
+        return Err(AppError::BadRequest(format!(
+            "Upload non completato: {}/{} byte",
+            upload.upload_offset, upload.upload_length
+        )));
+    }
+
+    Ok(CompletedUpload {
+        path: upload_path(config, &upload.id),
+        id: upload.id,
+        filename: upload.filename,
+    })
+}
+
+/// Rivendica un upload completato per un job, eliminandone il record
+///
+/// L'eliminazione è atomica: con due richieste concorrenti sullo stesso
+/// upload solo una ottiene il file. Il file viene rimosso quando il percorso
+/// restituito esce dallo scope, se nel frattempo non è stato spostato nello
+/// storage.
+pub async fn claim_upload(db: &DbPool, upload: &CompletedUpload) -> Result<TempPath> {
+    let claimed = uploads::claim_completed_upload(db, &upload.id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    if !claimed {
+        return Err(AppError::NotFound(format!("upload {}", upload.id)));
+    }
+    Ok(TempPath::try_from_path(&upload.path)?)
+}
+
+/// Cerca un upload verificando che appartenga all'utente (o admin)
+///
+/// Come per i job, gli upload altrui risultano inesistenti.
+async fn find_upload(db: &DbPool, auth: &AuthInfo, id: &str) -> Result<UploadRecord> {
+    let upload = uploads::get_upload(db, id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound(format!("upload {}", id)))?;
+
+    if !can_access(&upload, auth) {
+        return Err(AppError::NotFound(format!("upload {}", id)));
+    }
+
+    Ok(upload)
+}
+
+/// Come per i job: le API Key accedono ai propri upload, i guest solo a
+/// quelli creati con lo stesso `X-Guest-Token`
+fn can_access(upload: &UploadRecord, auth: &AuthInfo) -> bool {
+    if auth.role == ApiKeyRole::Admin && !auth.is_guest {
+        return true;
+    }
+    match (&upload.api_key_id, &auth.api_key_id) {
+        (Some(owner), Some(caller)) => owner == caller,
+        (None, None) => match (&upload.guest_token_hash, auth.guest_token.as_deref()) {
+            (Some(hash), Some(token)) => *hash == hash_guest_token(token),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Header comuni a tutte le risposte tus
+fn tus_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers
+}
+
+/// Verifica l'header `Tus-Resumable`: le versioni non supportate ricevono 412
+fn check_tus_version(headers: &HeaderMap) -> Option<Response> {
+    match headers.get(TUS_RESUMABLE).and_then(|v| v.to_str().ok()) {
+        Some(TUS_VERSION) => None,
+        _ => {
+            let mut response_headers = tus_headers();
+            response_headers.insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+            Some((StatusCode::PRECONDITION_FAILED, response_headers).into_response())
+        }
+    }
+}
+
+fn parse_u64_header(headers: &HeaderMap, name: &HeaderName) -> Option<u64> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+fn expires_header(expires_at: &str) -> Option<HeaderValue> {
+    let expires = chrono::DateTime::parse_from_rfc3339(expires_at).ok()?;
+    httpdate::fmt_http_date(expires.into()).parse().ok()
+}
+
+/// Interpreta `Upload-Metadata`: coppie `chiave valore_base64` separate da virgole
+fn parse_metadata(value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let mut parts = pair.trim().splitn(2, ' ');
+            let key = parts.next().filter(|k| !k.is_empty())?;
+            let value = match parts.next() {
+                Some(encoded) => {
+                    let bytes = base64::engine::general_purpose::STANDARD
+                        .decode(encoded.trim())
+                        .ok()?;
+                    String::from_utf8(bytes).ok()?
+                }
+                None => String::new(),
+            };
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+/// Rimuove l'upload dall'insieme di quelli attivi al termine del PATCH
+struct ActiveGuard {
+    active: Arc<Mutex<HashSet<String>>>,
+    id: String,
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.active.lock() {
+            active.remove(&self.id);
+        }
+    }
+}
+
+/// Aggiunge le capacità del server tus alle risposte `OPTIONS` sugli upload.
+///
+/// `CorsLayer` risponde direttamente a tutte le richieste `OPTIONS`, quindi
+/// gli header tus vanno aggiunti da un layer esterno.
+pub async fn tus_options(max_size: u64, request: Request, next: Next) -> Response {
+    let is_tus =
+        request.method() == Method::OPTIONS && request.uri().path().starts_with("/api/v1/uploads");
+
+    let mut response = next.run(request).await;
+    if is_tus {
+        let headers = response.headers_mut();
+        headers.extend(tus_headers());
+        headers.insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+        headers.insert(TUS_EXTENSION, HeaderValue::from_static(TUS_EXTENSIONS));
+        headers.insert(TUS_MAX_SIZE, HeaderValue::from(max_size));
+    }
+    response
+}
+
+/// Crea un upload riprendibile
+///
+/// Richiede `Upload-Length`; `Upload-Metadata` può contenere `filename`
+/// (usato per dedurre il formato di input del job). Ai guest viene restituito
+/// in `X-Guest-Token` il token di sessione da inviare nelle richieste
+/// successive sull'upload.
+#[utoipa::path(
+    post,
+    path = "/api/v1/uploads",
+    tag = "Uploads",
+    params(
+        ("Tus-Resumable" = String, Header, description = "Versione del protocollo (1.0.0)"),
+        ("Upload-Length" = u64, Header, description = "Dimensione totale del file in byte"),
+        ("Upload-Metadata" = Option<String>, Header, description = "Metadati tus (es. filename in base64)"),
+    ),
+    responses(
+        (status = 201, description = "Upload creato, URL nell'header Location (per i guest token in X-Guest-Token)"),
+        (status = 400, description = "Upload-Length mancante o non valido"),
+        (status = 412, description = "Versione tus non supportata"),
+        (status = 413, description = "File troppo grande"),
+    )
+)]
+pub async fn create_upload(
+    State(state): State<UploadsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Extension(limit): Extension<UploadLimit>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if let Some(response) = check_tus_version(&headers) {
+        return Ok(response);
+    }
+
+    let length = parse_u64_header(&headers, &UPLOAD_LENGTH)
+        .ok_or_else(|| AppError::BadRequest("Upload-Length mancante o non valido".to_string()))?;
+    validate_file_size(length, limit.max_mb)?;
+
+    let metadata = headers
+        .get(UPLOAD_METADATA)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let filename = metadata.as_deref().and_then(|m| {
+        parse_metadata(m)
+            .into_iter()
+            .find(|(k, _)| k == "filename" || k == "name")
+            .map(|(_, v)| v)
+            .filter(|v| !v.is_empty())
+    });
+
+    let id = Uuid::new_v4().to_string();
+    let path = upload_path(&state.config, &id);
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::File::create(&path).await?;
+
+    // Gli upload dei guest sono legati al token di sessione
+    let guest_token = auth.is_guest.then(|| guest_session_token(&auth));
+
+    let now = chrono::Utc::now();
+    let upload = UploadRecord {
+        id: id.clone(),
+        api_key_id: auth.api_key_id.clone(),
+        upload_length: length as i64,
+        upload_offset: 0,
+        metadata,
+        filename,
+        created_at: now.to_rfc3339(),
+        expires_at: (now + chrono::Duration::hours(UPLOAD_TTL_HOURS)).to_rfc3339(),
+        guest_token_hash: guest_token.as_deref().map(hash_guest_token),
+    };
+    if let Err(e) = uploads::create_upload(&state.db, &upload).await {
+        tokio::fs::remove_file(&path).await.ok();
+        return Err(AppError::Internal(e.to_string()));
+    }
+
+    let mut response_headers = tus_headers();
+    response_headers.insert(
+        header::LOCATION,
+        HeaderValue::from_str(&format!("/api/v1/uploads/{}", id))
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+    );
+    if let Some(expires) = expires_header(&upload.expires_at) {
+        response_headers.insert(UPLOAD_EXPIRES, expires);
+    }
+    if let Some(token) = guest_token.and_then(|t| HeaderValue::from_str(&t).ok()) {
+        response_headers.insert(GUEST_TOKEN, token);
+    }
+
+    Ok((StatusCode::CREATED, response_headers).into_response())
+}
+
+/// Stato di un upload (offset da cui riprendere)
+#[utoipa::path(
+    head,
+    path = "/api/v1/uploads/{id}",
+    tag = "Uploads",
+    params(
+        ("id" = String, Path, description = "ID dell'upload"),
+        ("Tus-Resumable" = String, Header, description = "Versione del protocollo (1.0.0)"),
+    ),
+    responses(
+        (status = 200, description = "Offset e dimensione in Upload-Offset e Upload-Length"),
+        (status = 404, description = "Upload non trovato, scaduto o di un altro utente"),
+    )
+)]
+pub async fn get_upload_offset(
+    State(state): State<UploadsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if let Some(response) = check_tus_version(&headers) {
+        return Ok(response);
+    }
+
+    let upload = find_upload(&state.db, &auth, &id).await?;
+
+    let mut response_headers = tus_headers();
+    response_headers.insert(UPLOAD_OFFSET, HeaderValue::from(upload.upload_offset));
+    response_headers.insert(UPLOAD_LENGTH, HeaderValue::from(upload.upload_length));
+    if let Some(metadata) = upload
+        .metadata
+        .as_deref()
+        .and_then(|m| HeaderValue::from_str(m).ok())
+    {
+        response_headers.insert(UPLOAD_METADATA, metadata);
+    }
+    if let Some(expires) = expires_header(&upload.expires_at) {
+        response_headers.insert(UPLOAD_EXPIRES, expires);
+    }
+
+    Ok((StatusCode::OK, response_headers).into_response())
+}
+
+/// Invia un blocco di dati
+///
+/// `Upload-Offset` deve coincidere con l'offset attuale. Se la connessione
+/// si interrompe, i byte già ricevuti restano salvati.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/uploads/{id}",
+    tag = "Uploads",
+    request_body(content = Vec<u8>, content_type = "application/offset+octet-stream"),
+    params(
+        ("id" = String, Path, description = "ID dell'upload"),
+        ("Tus-Resumable" = String, Header, description = "Versione del protocollo (1.0.0)"),
+        ("Upload-Offset" = u64, Header, description = "Offset del blocco inviato"),
+    ),
+    responses(
+        (status = 204, description = "Blocco salvato, nuovo offset in Upload-Offset"),
+        (status = 404, description = "Upload non trovato, scaduto o di un altro utente"),
+        (status = 409, description = "Upload-Offset non corrisponde o upload già in scrittura"),
+        (status = 413, description = "Il blocco supera Upload-Length"),
+        (status = 415, description = "Content-Type non valido"),
+    )
+)]
+pub async fn append_upload(
+    State(state): State<UploadsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response> {
+    if let Some(response) = check_tus_version(&headers) {
+        return Ok(response);
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if content_type != Some("application/offset+octet-stream") {
+        return Ok((StatusCode::UNSUPPORTED_MEDIA_TYPE, tus_headers()).into_response());
+    }
+
+    let upload = find_upload(&state.db, &auth, &id).await?;
+    let offset = parse_u64_header(&headers, &UPLOAD_OFFSET)
+        .ok_or_else(|| AppError::BadRequest("Upload-Offset mancante o non valido".to_string()))?;
+    check_offset(&upload, offset)?;
+
+    // Un solo PATCH alla volta per upload
+    let _guard = {
+        let mut active = state
+            .active
+            .lock()
+            .map_err(|_| AppError::Internal("Lock upload non disponibile".to_string()))?;
+        if !active.insert(upload.id.clone()) {
+            return Err(AppError::Conflict("Upload già in scrittura".to_string()));
+        }
+        ActiveGuard {
+            active: state.active.clone(),
+            id: upload.id.clone(),
+        }
+    };
+    // Un PATCH concorrente può aver spostato l'offset prima che prendessimo il lock
+    let upload = find_upload(&state.db, &auth, &id).await?;
+    check_offset(&upload, offset)?;
+
+    let length = upload.upload_length as u64;
+    let path = upload_path(&state.config, &upload.id);
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .await?;
+    // Scarta eventuali byte oltre l'offset registrato (scrittura interrotta)
+    file.set_len(offset).await?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+    let mut written = offset;
+    let mut stream = body.into_data_stream();
+    let mut outcome = Ok(());
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                outcome = Err(AppError::BadRequest(format!("Upload interrotto: {}", e)));
+                break;
+            }
+        };
+        if written + chunk.len() as u64 > length {
+            outcome = Err(AppError::UploadLengthExceeded(length));
+            break;
+        }
+        if let Err(e) = file.write_all(&chunk).await {
+            outcome = Err(e.into());
+            break;
+        }
+        written += chunk.len() as u64;
+    }
+    file.flush().await?;
+    file.sync_data().await?;
+
+    // Salva i progressi anche se il blocco è incompleto, così il client riprende da qui
+    uploads::update_upload_offset(&state.db, &upload.id, written as i64)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    outcome?;
+
+    let mut response_headers = tus_headers();
+    response_headers.insert(UPLOAD_OFFSET, HeaderValue::from(written));
+    if let Some(expires) = expires_header(&upload.expires_at) {
+        response_headers.insert(UPLOAD_EXPIRES, expires);
+    }
+
+    Ok((StatusCode::NO_CONTENT, response_headers).into_response())
+}
+
+/// `Upload-Offset` deve coincidere con i byte già ricevuti
+fn check_offset(upload: &UploadRecord, offset: u64) -> Result<()> {
+    if offset != upload.upload_offset as u64 {
+        return Err(AppError::Conflict(format!(
+            "Upload-Offset {} diverso dall'offset attuale {}",
+            offset, upload.upload_offset
+        )));
+    }
+    Ok(())
+}
+
+/// Annulla un upload ed elimina i dati ricevuti
+#[utoipa::path(
+    delete,
+    path = "/api/v1/uploads/{id}",
+    tag = "Uploads",
+    params(
+        ("id" = String, Path, description = "ID dell'upload"),
+        ("Tus-Resumable" = String, Header, description = "Versione del protocollo (1.0.0)"),
+    ),
+    responses(
+        (status = 204, description = "Upload eliminato"),
+        (status = 404, description = "Upload non trovato, scaduto o di un altro utente"),
+    )
+)]
+pub async fn delete_upload(
+    State(state): State<UploadsState>,
+    Extension(auth): Extension<AuthInfo>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if let Some(response) = check_tus_version(&headers) {
+        return Ok(response);
+    }
+
+    let upload = find_upload(&state.db, &auth, &id).await?;
+    uploads::delete_upload(&state.db, &upload.id)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    tokio::fs::remove_file(upload_path(&state.config, &upload.id))
+        .await
+        .ok();
+
+    Ok((StatusCode::NO_CONTENT, tus_headers()).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        // "video.mp4" e "video/mp4" in base64
+        let parsed = parse_metadata("filename dmlkZW8ubXA0,filetype dmlkZW8vbXA0,is_confidential");
+        assert_eq!(
+            parsed,
+            vec![
+                ("filename".to_string(), "video.mp4".to_string()),
+                ("filetype".to_string(), "video/mp4".to_string()),
+                ("is_confidential".to_string(), String::new()),
+            ]
+        );
+        // Valori non base64 vengono scartati
+        assert!(parse_metadata("filename !!!").is_empty());
+    }
+
+    fn upload(api_key_id: Option<&str>, guest_token: Option<&str>) -> UploadRecord {
+        UploadRecord {
+            id: "u1".to_string(),
+            api_key_id: api_key_id.map(str::to_string),
+            upload_length: 10,
+            upload_offset: 0,
+            metadata: None,
+            filename: None,
+            created_at: String::new(),
+            expires_at: String::new(),
+            guest_token_hash: guest_token.map(hash_guest_token),
+        }
+    }
+
+    fn guest(token: Option<&str>) -> AuthInfo {
+        AuthInfo {
+            guest_token: token.map(str::to_string),
+            ..AuthInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_guest_uploads_are_bound_to_session_token() {
+        let first = upload(None, Some("token-a"));
+        assert!(can_access(&first, &guest(Some("token-a"))));
+        // Un altro guest (altro token o nessun token) non vede l'upload
+        assert!(!can_access(&first, &guest(Some("token-b"))));
+        assert!(!can_access(&first, &guest(None)));
+        // Upload senza token (creati prima della migrazione): nessun guest vi accede
+        assert!(!can_access(&upload(None, None), &guest(None)));
+
+        // Gli upload delle API Key restano inaccessibili ai guest
+        let keyed = upload(Some("key-1"), None);
+        assert!(!can_access(&keyed, &guest(Some("token-a"))));
+        let owner = AuthInfo {
+            api_key_id: Some("key-1".to_string()),
+            is_guest: false,
+            ..AuthInfo::default()
+        };
+        assert!(can_access(&keyed, &owner));
+    }
+
+    #[test]
+    fn test_check_tus_version() {
+        let mut headers = HeaderMap::new();
+        assert!(check_tus_version(&headers).is_some());
+        headers.insert(TUS_RESUMABLE, HeaderValue::from_static("0.2.2"));
+        assert!(check_tus_version(&headers).is_some());
+        headers.insert(TUS_RESUMABLE, HeaderValue::from_static("1.0.0"));
+        assert!(check_tus_version(&headers).is_none());
+    }
+}
@@ -0,0 +1,96 @@
+//! Registrazione delle operazioni nel registro di audit
+//!
+//! Per le modifiche vengono salvati solo i campi cambiati, prima e dopo.
+//! Un errore di scrittura del registro non fa fallire l'operazione: viene
+//! solo segnalato nei log.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::db::audit::{self, NewAuditEntry};
+use crate::db::DbPool;
+use crate::models::AuthInfo;
+
+/// Campi che cambiano a ogni scrittura e non sono interessanti nel diff
+const IGNORED_FIELDS: &[&str] = &["updated_at", "last_used_at"];
+
+/// Serializza un valore per il registro
+pub fn snapshot<T: Serialize>(value: &T) -> Option<Value> {
+    serde_json::to_value(value).ok()
+}
+
+/// Campi diversi tra due stati, come coppia (prima, dopo)
+///
+/// Se i valori non sono oggetti JSON vengono restituiti interi.
+pub fn diff<T: Serialize>(before: &T, after: &T) -> (Option<Value>, Option<Value>) {
+    let (Some(Value::Object(before)), Some(Value::Object(after))) =
+        (snapshot(before), snapshot(after))
+    else {
+        return (snapshot(before), snapshot(after));
+    };
+
+    let mut old = Map::new();
+    let mut new = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        if IGNORED_FIELDS.contains(&key.as_str()) || old.contains_key(key) {
+            continue;
+        }
+        let (a, b) = (before.get(key), after.get(key));
+        if a != b {
+            old.insert(key.clone(), a.cloned().unwrap_or(Value::Null));
+            new.insert(key.clone(), b.cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    (Some(Value::Object(old)), Some(Value::Object(new)))
+}
+
+/// Registra un'azione eseguita dall'utente autenticato
+pub async fn record(
+    db: &DbPool,
+    auth: &AuthInfo,
+    action: &str,
+    target: Option<&str>,
+    before: Option<Value>,
+    after: Option<Value>,
+) {
+    record_entry(
+        db,
+        NewAuditEntry {
+            actor_key_id: auth.api_key_id.as_deref(),
+            action,
+            target,
+            before,
+            after,
+            ip: auth.client_ip.as_deref(),
+        },
+    )
+    .await
+}
+
+/// Registra una voce con attore esplicito (es. login, quando la richiesta non
+/// è ancora autenticata)
+pub async fn record_entry(db: &DbPool, entry: NewAuditEntry<'_>) {
+    if let Err(e) = audit::insert_entry(db, &entry).await {
+        tracing::warn!(
+            "Impossibile registrare l'audit di '{}': {}",
+            entry.action,
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_keeps_only_changed_fields() {
+        let before = json!({"name": "a", "rate_limit": 100, "updated_at": "x"});
+        let after = json!({"name": "a", "rate_limit": 50, "updated_at": "y", "notes": "n"});
+        let (old, new) = diff(&before, &after);
+        assert_eq!(old, Some(json!({"rate_limit": 100, "notes": null})));
+        assert_eq!(new, Some(json!({"rate_limit": 50, "notes": "n"})));
+    }
+}
@@ -1,48 +1,46 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::config::formats;
 use crate::error::Result;
 use crate::handlers::{document, image, media, pdf, svg};
 use crate::models::ConversionType;
 
-pub fn convert(
-    data: &[u8],
-    input_format: &str,
+/// Converte `input_path` scrivendo il risultato in `output_dir`.
+///
+/// Ritorna il percorso del file prodotto: per i PDF multi-pagina è uno ZIP.
+#[tracing::instrument(
+    name = "convert",
+    skip(input_path, output_dir),
+    fields(conversion_type = %conversion_type),
+    err(Display)
+)]
+pub fn convert_file_in_dir(
+    input_path: &Path,
+    output_dir: &Path,
     output_format: &str,
     conversion_type: &ConversionType,
     quality: Option<u8>,
-) -> Result<Vec<u8>> {
-    // Gestione speciale per SVG
-    if formats::is_svg_input(input_format) {
-        return svg::convert_svg_to_raster(data, output_format, None, None, quality);
-    }
+) -> Result<PathBuf> {
+    // Gestione speciale per PDF multi-pagina
+    let is_pdf = matches!(conversion_type, ConversionType::Pdf)
+        || input_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(formats::is_pdf_input);
 
-    // Gestione speciale per PDF - converte tutte le pagine in ZIP se multi-pagina
-    if formats::is_pdf_input(input_format) {
-        let page_count = pdf::get_pdf_page_count(data).unwrap_or(1);
-        if page_count > 1 {
-            return pdf::convert_pdf_to_zip(data, output_format, None, "pages");
-        } else {
-            return pdf::convert_pdf_to_image(data, output_format, None, None);
-        }
+    if is_pdf {
+        return Ok(convert_pdf_file_smart(input_path, output_dir, output_format)?.0);
     }
 
-    match conversion_type {
-        ConversionType::Image => {
-            image::convert_image_with_quality(data, input_format, output_format, quality)
-        }
-        ConversionType::Document => document::convert_document(data, input_format, output_format),
-        ConversionType::Audio => media::convert_audio(data, input_format, output_format, quality),
-        ConversionType::Video => media::convert_video(data, input_format, output_format, quality),
-        ConversionType::Pdf => {
-            let page_count = pdf::get_pdf_page_count(data).unwrap_or(1);
-            if page_count > 1 {
-                pdf::convert_pdf_to_zip(data, output_format, None, "pages")
-            } else {
-                pdf::convert_pdf_to_image(data, output_format, None, None)
-            }
-        }
-    }
+    let output_path = output_dir.join(format!("output.{}", output_format));
+    convert_file(
+        input_path,
+        &output_path,
+        output_format,
+        conversion_type,
+        quality,
+    )?;
+    Ok(output_path)
 }
 
 /// Converte un file PDF in immagini. Restituisce true se il risultato è uno ZIP (multi-pagina).
@@ -50,9 +48,8 @@ pub fn convert_pdf_file_smart(
     input_path: &Path,
     output_dir: &Path,
     output_format: &str,
-) -> Result<(std::path::PathBuf, bool)> {
-    let data = std::fs::read(input_path)?;
-    let page_count = pdf::get_pdf_page_count(&data).unwrap_or(1);
+) -> Result<(PathBuf, bool)> {
+    let page_count = pdf::get_pdf_page_count(input_path).unwrap_or(1);
 
     let base_name = input_path
         .file_stem()
@@ -61,9 +58,8 @@ pub fn convert_pdf_file_smart(
 
     if page_count > 1 {
         // Multi-page: crea ZIP
-        let zip_data = pdf::convert_pdf_to_zip(&data, output_format, None, base_name)?;
         let output_path = output_dir.join("output.zip");
-        std::fs::write(&output_path, zip_data)?;
+        pdf::convert_pdf_file_to_zip(input_path, &output_path, output_format, None, base_name)?;
         Ok((output_path, true))
     } else {
         // Single page: crea singola immagine
@@ -92,8 +88,7 @@ pub fn convert_file(
 
     // Gestione speciale per PDF - converte tutte le pagine
     if formats::is_pdf_input(input_ext) {
-        let data = std::fs::read(input_path)?;
-        let page_count = pdf::get_pdf_page_count(&data).unwrap_or(1);
+        let page_count = pdf::get_pdf_page_count(input_path).unwrap_or(1);
 
         if page_count > 1 {
             // Multi-page: crea ZIP nella stessa directory con estensione .zip
@@ -101,9 +96,8 @@ pub fn convert_file(
                 .file_stem()
                 .and_then(|s| s.to_str())
                 .unwrap_or("output");
-            let zip_data = pdf::convert_pdf_to_zip(&data, output_format, None, base_name)?;
             let zip_path = output_path.with_extension("zip");
-            std::fs::write(&zip_path, zip_data)?;
+            pdf::convert_pdf_file_to_zip(input_path, &zip_path, output_format, None, base_name)?;
             // Crea anche un file marker col path originale per il download
             std::fs::write(
                 output_path.with_extension("zip.marker"),
@@ -129,18 +123,15 @@ pub fn convert_file(
             media::convert_video_file(input_path, output_path, output_format, quality)
         }
         ConversionType::Pdf => {
-            let data = std::fs::read(input_path)?;
-            let page_count = pdf::get_pdf_page_count(&data).unwrap_or(1);
+            let page_count = pdf::get_pdf_page_count(input_path).unwrap_or(1);
 
             if page_count > 1 {
                 let base_name = input_path
                     .file_stem()
                     .and_then(|s| s.to_str())
                     .unwrap_or("output");
-                let zip_data = pdf::convert_pdf_to_zip(&data, output_format, None, base_name)?;
                 let zip_path = output_path.with_extension("zip");
-                std::fs::write(&zip_path, zip_data)?;
-                Ok(())
+                pdf::convert_pdf_file_to_zip(input_path, &zip_path, output_format, None, base_name)
             } else {
                 pdf::convert_pdf_file(input_path, output_path, output_format, None, None)
             }
@@ -148,6 +139,21 @@ pub fn convert_file(
     }
 }
 
+/// Durata dell'input per le conversioni audio/video (0 per gli altri tipi)
+///
+/// ffprobe viene eseguito in un thread bloccante per non fermare il runtime.
+pub async fn media_duration_ms(conversion_type: &ConversionType, input_path: &Path) -> i64 {
+    match conversion_type {
+        ConversionType::Audio | ConversionType::Video => {
+            let input_path = input_path.to_path_buf();
+            tokio::task::spawn_blocking(move || media::probe_duration_ms(&input_path).unwrap_or(0))
+                .await
+                .unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
 pub fn detect_conversion_type(extension: &str) -> Option<ConversionType> {
     let ext = extension.to_lowercase();
 
@@ -0,0 +1,191 @@
+//! Link di download firmati (HMAC-SHA256) per i risultati dei job
+//!
+//! Un link ha la forma
+//! `{public_url}/api/v1/jobs/{id}/download?expires={unix}&sig={hex}[&token={nonce}]`.
+//! La firma copre id del job, scadenza e token, quindi un link non può essere
+//! riusato per un altro job né prolungato. Il `token` identifica i link
+//! monouso, il cui consumo è registrato nel database.
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::error::{AppError, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Parametri di firma presenti nella query string del download
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct SignedLinkParams {
+    /// Scadenza (unix timestamp, secondi)
+    pub expires: Option<i64>,
+    /// Firma HMAC in esadecimale
+    pub sig: Option<String>,
+    /// Token dei link monouso
+    pub token: Option<String>,
+}
+
+impl SignedLinkParams {
+    /// Indica se la richiesta usa un link firmato
+    pub fn is_signed(&self) -> bool {
+        self.sig.is_some()
+    }
+}
+
+/// Firma e verifica dei link di download
+#[derive(Clone)]
+pub struct LinkSigner {
+    secret: Vec<u8>,
+    public_url: String,
+    /// Validità di default dei link (secondi)
+    pub default_ttl_secs: u64,
+}
+
+impl std::fmt::Debug for LinkSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LinkSigner")
+            .field("public_url", &self.public_url)
+            .field("default_ttl_secs", &self.default_ttl_secs)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LinkSigner {
+    pub fn new(secret: &[u8], public_url: &str, default_ttl_secs: u64) -> Self {
+        Self {
+            secret: secret.to_vec(),
+            public_url: public_url.trim_end_matches('/').to_string(),
+            default_ttl_secs,
+        }
+    }
+
+    /// Crea il signer dalla configurazione.
+    ///
+    /// Senza `CONVERTY_SIGNING_SECRET` viene generato un segreto casuale:
+    /// i link emessi non restano validi dopo un riavvio.
+    pub fn from_config(config: &Config) -> Self {
+        let secret = match &config.signing_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                let mut secret = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut secret);
+                secret
+            }
+        };
+        Self::new(&secret, &config.public_url(), config.download_link_ttl_secs)
+    }
+
+    fn signature(&self, job_id: &str, expires: i64, token: Option<&str>) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.secret)
+            .expect("HMAC accetta chiavi di qualsiasi lunghezza");
+        mac.update(format!("{}:{}:{}", job_id, expires, token.unwrap_or("")).as_bytes());
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+
+    /// Genera l'URL firmato per scaricare il risultato di un job
+    pub fn sign_url(&self, job_id: &str, expires: i64, token: Option<&str>) -> String {
+        let mut url = format!(
+            "{}/api/v1/jobs/{}/download?expires={}&sig={}",
+            self.public_url,
+            job_id,
+            expires,
+            self.signature(job_id, expires, token)
+        );
+        if let Some(token) = token {
+            url.push_str("&token=");
+            url.push_str(token);
+        }
+        url
+    }
+
+    /// Verifica firma e scadenza di un link
+    pub fn verify(&self, job_id: &str, params: &SignedLinkParams) -> Result<()> {
+        let (Some(expires), Some(sig)) = (params.expires, params.sig.as_deref()) else {
+            return Err(AppError::Forbidden(
+                "Link di download non valido".to_string(),
+            ));
+        };
+
+        let expected = self.signature(job_id, expires, params.token.as_deref());
+        if !constant_time_eq(expected.as_bytes(), sig.to_lowercase().as_bytes()) {
+            return Err(AppError::Forbidden(
+                "Link di download non valido".to_string(),
+            ));
+        }
+
+        if expires < chrono::Utc::now().timestamp() {
+            return Err(AppError::Forbidden("Link di download scaduto".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Genera un token casuale per i link monouso
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params_from_url(url: &str) -> SignedLinkParams {
+        let query = url.split_once('?').unwrap().1;
+        let mut params = SignedLinkParams::default();
+        for pair in query.split('&') {
+            let (k, v) = pair.split_once('=').unwrap();
+            match k {
+                "expires" => params.expires = v.parse().ok(),
+                "sig" => params.sig = Some(v.to_string()),
+                "token" => params.token = Some(v.to_string()),
+                _ => {}
+            }
+        }
+        params
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = LinkSigner::new(b"secret", "https://api.example.com/", 3600);
+        let expires = chrono::Utc::now().timestamp() + 60;
+        let url = signer.sign_url("job-1", expires, None);
+
+        assert!(url.starts_with("https://api.example.com/api/v1/jobs/job-1/download?"));
+        let params = params_from_url(&url);
+        assert!(signer.verify("job-1", &params).is_ok());
+        // Il link è legato al job
+        assert!(signer.verify("job-2", &params).is_err());
+    }
+
+    #[test]
+    fn test_rejects_tampering_and_expiry() {
+        let signer = LinkSigner::new(b"secret", "http://localhost:4000", 3600);
+        let expires = chrono::Utc::now().timestamp() + 60;
+
+        let mut params = params_from_url(&signer.sign_url("job", expires, Some("abc")));
+        assert!(signer.verify("job", &params).is_ok());
+
+        params.token = Some("def".to_string());
+        assert!(signer.verify("job", &params).is_err());
+
+        let mut params = params_from_url(&signer.sign_url("job", expires, None));
+        params.expires = Some(expires + 3600);
+        assert!(signer.verify("job", &params).is_err());
+
+        let past = chrono::Utc::now().timestamp() - 1;
+        let params = params_from_url(&signer.sign_url("job", past, None));
+        assert!(signer.verify("job", &params).is_err());
+
+        let other = LinkSigner::new(b"other", "http://localhost:4000", 3600);
+        let params = params_from_url(&signer.sign_url("job", expires, None));
+        assert!(other.verify("job", &params).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Verifica unica dei limiti per conversioni sincrone, job e batch
+//!
+//! I guest sono limitati dalla configurazione guest; le API Key dai propri
+//! scope, dal piano assegnato e dalle quote. Rate limit e dimensione massima
+//! dell'upload del piano sono già applicati dal middleware di autenticazione
+//! (vedi `AuthInfo::apply_plan`).
+
+use crate::db::stats;
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::models::{AuthInfo, JobPriority};
+use crate::services::quota::{check_quota, incoming_conversion};
+use crate::services::scopes::check_conversion_scope;
+
+/// Verifica che l'utente possa avviare una conversione del tipo indicato
+pub async fn check_conversion(db: &DbPool, auth: &AuthInfo, conversion_type: &str) -> Result<()> {
+    if auth.is_guest {
+        return check_guest_limits(db, auth, conversion_type).await;
+    }
+
+    check_conversion_scope(auth, conversion_type)?;
+    if let Some(plan) = auth
+        .plan
+        .as_ref()
+        .filter(|p| !p.allows_type(conversion_type))
+    {
+        return Err(AppError::Forbidden(format!(
+            "Tipo conversione '{}' non incluso nel piano '{}'. Tipi permessi: {}",
+            conversion_type,
+            plan.name,
+            plan.allowed_types.join(", ")
+        )));
+    }
+    // Il file non è ancora stato ricevuto: conta solo la conversione
+    check_quota(db, auth, incoming_conversion(0, 0)).await
+}
+
+/// Check guest limits for a conversion type
+pub async fn check_guest_limits(db: &DbPool, auth: &AuthInfo, conversion_type: &str) -> Result<()> {
+    let config = stats::get_guest_config(db)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    if !config.enabled {
+        return Err(AppError::Forbidden(
+            "Modalità guest disabilitata. Richiedi una API Key.".to_string(),
+        ));
+    }
+
+    // Verifica tipo conversione permesso
+    if !config.allowed_types.iter().any(|t| t == conversion_type) {
+        return Err(AppError::Forbidden(format!(
+            "Tipo conversione '{}' non permesso per guest. Tipi permessi: {}",
+            conversion_type,
+            config.allowed_types.join(", ")
+        )));
+    }
+
+    // Verifica limite giornaliero
+    if let Some(bucket) = auth.guest_bucket() {
+        let daily_usage = stats::get_guest_daily_usage(db, &bucket)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        if daily_usage >= config.daily_limit {
+            return Err(AppError::DailyLimitExceeded(format!(
+                "Limite giornaliero di {} conversioni raggiunto per guest",
+                config.daily_limit
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Verifica che la priorità richiesta per un job rientri nel piano
+pub fn check_priority(auth: &AuthInfo, priority: JobPriority) -> Result<()> {
+    match &auth.plan {
+        Some(plan) if priority > plan.max_priority => Err(AppError::Forbidden(format!(
+            "Priorità '{}' non consentita dal piano '{}' (massima: {})",
+            priority, plan.name, plan.max_priority
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Ore di conservazione del risultato di un job
+///
+/// Senza richiesta esplicita vale il massimo del piano; una richiesta oltre
+/// il massimo viene ridotta.
+pub fn job_retention_hours(auth: &AuthInfo, requested: Option<i64>) -> Option<i64> {
+    let max = auth.plan.as_ref().and_then(|p| p.retention_hours);
+    match (requested, max) {
+        (Some(hours), Some(max)) => Some(hours.min(max)),
+        (requested, max) => requested.or(max),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::plans::Plan;
+    use chrono::Utc;
+
+    fn plan() -> Plan {
+        Plan {
+            id: "p1".to_string(),
+            name: "Starter".to_string(),
+            description: None,
+            rate_limit: Some(10),
+            daily_limit: Some(50),
+            max_file_size_mb: Some(5),
+            allowed_types: vec!["image".to_string()],
+            max_concurrent_jobs: Some(1),
+            retention_hours: Some(12),
+            max_priority: JobPriority::Normal,
+            is_default: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_plan_overrides_key_limits() {
+        let mut auth = AuthInfo {
+            is_guest: false,
+            rate_limit: 100,
+            ..Default::default()
+        };
+        auth.quota.monthly_conversions = Some(1000);
+        auth.apply_plan(plan());
+
+        assert_eq!(auth.rate_limit, 10);
+        assert_eq!(auth.max_file_size_mb, Some(5));
+        assert_eq!(auth.quota.daily_conversions, Some(50));
+        // I limiti non definiti dal piano restano quelli della chiave
+        assert_eq!(auth.quota.monthly_conversions, Some(1000));
+        assert!(auth.plan.as_ref().unwrap().allows_type("IMAGE"));
+        assert!(!auth.plan.as_ref().unwrap().allows_type("video"));
+    }
+
+    #[test]
+    fn test_priority_and_retention_ceilings() {
+        let mut auth = AuthInfo::default();
+        assert!(check_priority(&auth, JobPriority::High).is_ok());
+        assert_eq!(job_retention_hours(&auth, None), None);
+        assert_eq!(job_retention_hours(&auth, Some(48)), Some(48));
+
+        auth.apply_plan(plan());
+        assert!(check_priority(&auth, JobPriority::Normal).is_ok());
+        assert!(check_priority(&auth, JobPriority::High).is_err());
+        assert_eq!(job_retention_hours(&auth, None), Some(12));
+        assert_eq!(job_retention_hours(&auth, Some(6)), Some(6));
+        assert_eq!(job_retention_hours(&auth, Some(48)), Some(12));
+    }
+}
@@ -0,0 +1,473 @@
+//! Metriche dell'applicazione in formato testo Prometheus
+//!
+//! Il registro è globale e vive in memoria: contatori e istogrammi vengono
+//! aggiornati dove avviene l'evento (middleware HTTP, conversioni, job,
+//! webhook, upload su Drive, tool esterni) senza dover passare stato fino
+//! agli handler sincroni. I valori istantanei della coda (permessi in uso,
+//! job per stato) sono letti al momento dello scrape.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::formats;
+
+/// Bucket (secondi) per la latenza delle richieste HTTP
+const REQUEST_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Bucket (secondi) per conversioni e tool esterni, che durano di più
+const CONVERSION_BUCKETS: &[f64] = &[
+    0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+static METRICS: Metrics = Metrics {
+    http_requests: CounterVec::new(
+        "converty_http_requests_total",
+        "Richieste HTTP per route e status",
+        &["method", "route", "status"],
+    ),
+    http_duration: HistogramVec::new(
+        "converty_http_request_duration_seconds",
+        "Latenza delle richieste HTTP per route e status",
+        &["method", "route", "status"],
+        REQUEST_BUCKETS,
+    ),
+    conversions: CounterVec::new(
+        "converty_conversions_total",
+        "Conversioni per tipo, coppia di formati ed esito",
+        &["type", "input_format", "output_format", "result"],
+    ),
+    conversion_duration: HistogramVec::new(
+        "converty_conversion_duration_seconds",
+        "Durata delle conversioni per tipo e coppia di formati",
+        &["type", "input_format", "output_format"],
+        CONVERSION_BUCKETS,
+    ),
+    conversion_input_bytes: CounterVec::new(
+        "converty_conversion_input_bytes_total",
+        "Byte in ingresso alle conversioni riuscite",
+        &["type", "input_format", "output_format"],
+    ),
+    conversion_output_bytes: CounterVec::new(
+        "converty_conversion_output_bytes_total",
+        "Byte prodotti dalle conversioni riuscite",
+        &["type", "input_format", "output_format"],
+    ),
+    webhooks: CounterVec::new(
+        "converty_webhooks_total",
+        "Webhook inviati per esito",
+        &["result"],
+    ),
+    drive_uploads: CounterVec::new(
+        "converty_drive_uploads_total",
+        "Upload dei risultati su Google Drive per esito",
+        &["result"],
+    ),
+    tool_duration: HistogramVec::new(
+        "converty_external_tool_duration_seconds",
+        "Durata delle invocazioni di tool esterni (ffmpeg, pdftoppm, ...)",
+        &["tool", "result"],
+        CONVERSION_BUCKETS,
+    ),
+    queue_waiting: AtomicI64::new(0),
+};
+
+/// Stato della coda job letto al momento dello scrape
+#[derive(Debug, Default)]
+pub struct QueueSnapshot {
+    /// Permessi del semaforo di concorrenza in uso
+    pub permits_in_use: usize,
+    /// Permessi totali del semaforo
+    pub permits_total: usize,
+    /// Numero di job per stato (dal database)
+    pub jobs_by_status: Vec<(String, i64)>,
+}
+
+struct Metrics {
+    http_requests: CounterVec,
+    http_duration: HistogramVec,
+    conversions: CounterVec,
+    conversion_duration: HistogramVec,
+    conversion_input_bytes: CounterVec,
+    conversion_output_bytes: CounterVec,
+    webhooks: CounterVec,
+    drive_uploads: CounterVec,
+    tool_duration: HistogramVec,
+    /// Job in attesa di un permesso del semaforo
+    queue_waiting: AtomicI64,
+}
+
+/// Registra una richiesta HTTP completata
+///
+/// `route` è il pattern della route (`/api/v1/jobs/:id`), non il path
+/// effettivo, per non creare una serie per ogni ID.
+pub fn record_request(method: &str, route: &str, status: u16, elapsed: Duration) {
+    let status = status.to_string();
+    let labels = [method, route, status.as_str()];
+    METRICS.http_requests.inc(&labels);
+    METRICS.http_duration.observe(&labels, elapsed);
+}
+
+/// Tipi di conversione usati come label
+const CONVERSION_TYPES: &[&str] = &["image", "svg", "document", "audio", "video", "pdf"];
+
+/// Registra una conversione (sincrona, batch o job)
+///
+/// Tipo e formati arrivano dal client: i valori sconosciuti diventano `other`,
+/// così non possono far crescere senza limite il numero di serie.
+pub fn record_conversion(
+    conversion_type: &str,
+    input_format: &str,
+    output_format: &str,
+    success: bool,
+    elapsed: Duration,
+    input_bytes: u64,
+    output_bytes: u64,
+) {
+    let conversion_type = conversion_type_label(conversion_type);
+    let input_format = format_label(input_format);
+    let output_format = format_label(output_format);
+    let labels = [
+        conversion_type,
+        input_format.as_str(),
+        output_format.as_str(),
+    ];
+    let result = if success { "success" } else { "failure" };
+
+    METRICS.conversions.inc(&[
+        conversion_type,
+        input_format.as_str(),
+        output_format.as_str(),
+        result,
+    ]);
+    METRICS.conversion_duration.observe(&labels, elapsed);
+    if success {
+        METRICS.conversion_input_bytes.inc_by(&labels, input_bytes);
+        METRICS
+            .conversion_output_bytes
+            .inc_by(&labels, output_bytes);
+    }
+}
+
+fn conversion_type_label(conversion_type: &str) -> &'static str {
+    let conversion_type = conversion_type.to_lowercase();
+    CONVERSION_TYPES
+        .iter()
+        .find(|t| **t == conversion_type)
+        .copied()
+        .unwrap_or("other")
+}
+
+fn format_label(format: &str) -> String {
+    if formats::is_known(format) {
+        format.to_lowercase()
+    } else {
+        "other".to_string()
+    }
+}
+
+/// Registra l'esito dell'invio di un webhook
+pub fn record_webhook(success: bool) {
+    METRICS
+        .webhooks
+        .inc(&[if success { "success" } else { "failure" }]);
+}
+
+/// Registra l'esito di un upload su Google Drive
+pub fn record_drive_upload(success: bool) {
+    METRICS
+        .drive_uploads
+        .inc(&[if success { "success" } else { "failure" }]);
+}
+
+/// Esegue un tool esterno misurandone la durata
+///
+/// Equivale a `command.output()`; l'esito è `failure` se il processo non
+/// parte o termina con status diverso da zero. L'esecuzione ha uno span
+/// `run_tool` figlio della conversione in corso.
+pub fn run_tool(command: &mut Command) -> std::io::Result<Output> {
+    let tool = command.get_program().to_string_lossy().into_owned();
+    let span = tracing::info_span!("run_tool", tool = %tool, exit_code = tracing::field::Empty);
+    let _entered = span.enter();
+    let start = Instant::now();
+    let output = command.output();
+    let success = output.as_ref().is_ok_and(|o| o.status.success());
+    if let Ok(code) = output.as_ref().map(|o| o.status.code()) {
+        span.record("exit_code", code.unwrap_or(-1));
+    }
+    METRICS.tool_duration.observe(
+        &[tool.as_str(), if success { "success" } else { "failure" }],
+        start.elapsed(),
+    );
+    output
+}
+
+/// Segna un job in attesa di un permesso di esecuzione
+///
+/// Il job esce dalla coda quando la guard viene rilasciata.
+pub fn queue_wait() -> QueueWaitGuard {
+    METRICS.queue_waiting.fetch_add(1, Ordering::Relaxed);
+    QueueWaitGuard
+}
+
+/// Job attualmente in attesa di un permesso di esecuzione
+pub fn queue_depth() -> u64 {
+    METRICS.queue_waiting.load(Ordering::Relaxed).max(0) as u64
+}
+
+pub struct QueueWaitGuard;
+
+impl Drop for QueueWaitGuard {
+    fn drop(&mut self) {
+        METRICS.queue_waiting.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Esporta tutte le metriche nel formato testo di Prometheus
+pub fn render(queue: &QueueSnapshot) -> String {
+    let mut out = String::new();
+    METRICS.http_requests.encode(&mut out);
+    METRICS.http_duration.encode(&mut out);
+    METRICS.conversions.encode(&mut out);
+    METRICS.conversion_duration.encode(&mut out);
+    METRICS.conversion_input_bytes.encode(&mut out);
+    METRICS.conversion_output_bytes.encode(&mut out);
+    METRICS.webhooks.encode(&mut out);
+    METRICS.drive_uploads.encode(&mut out);
+    METRICS.tool_duration.encode(&mut out);
+
+    write_gauge_header(
+        &mut out,
+        "converty_queue_depth",
+        "Job in attesa di un permesso di esecuzione",
+    );
+    let _ = writeln!(out, "converty_queue_depth {}", queue_depth());
+
+    write_gauge_header(
+        &mut out,
+        "converty_queue_permits_in_use",
+        "Permessi del semaforo di concorrenza in uso",
+    );
+    let _ = writeln!(
+        out,
+        "converty_queue_permits_in_use {}",
+        queue.permits_in_use
+    );
+
+    write_gauge_header(
+        &mut out,
+        "converty_queue_permits_total",
+        "Permessi totali del semaforo di concorrenza",
+    );
+    let _ = writeln!(out, "converty_queue_permits_total {}", queue.permits_total);
+
+    write_gauge_header(&mut out, "converty_jobs", "Job per stato");
+    for (status, count) in &queue.jobs_by_status {
+        let _ = writeln!(
+            out,
+            "converty_jobs{{status=\"{}\"}} {}",
+            escape_label(status),
+            count
+        );
+    }
+
+    out
+}
+
+fn write_gauge_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
+
+/// Contatore con etichette
+struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl CounterVec {
+    const fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn inc(&self, label_values: &[&str]) {
+        self.inc_by(label_values, 1);
+    }
+
+    fn inc_by(&self, label_values: &[&str], value: u64) {
+        let key = label_values.iter().map(|v| v.to_string()).collect();
+        let mut values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        *values.entry(key).or_insert(0) += value;
+    }
+
+    fn encode(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} counter", self.name);
+        let values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, value) in values.iter() {
+            let _ = writeln!(
+                out,
+                "{}{{{}}} {}",
+                self.name,
+                format_labels(self.labels, key),
+                value
+            );
+        }
+    }
+}
+
+/// Istogramma con etichette
+struct HistogramVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    buckets: &'static [f64],
+    values: Mutex<BTreeMap<Vec<String>, HistogramData>>,
+}
+
+#[derive(Default)]
+struct HistogramData {
+    /// Conteggi per bucket (non cumulativi)
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl HistogramVec {
+    const fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+        buckets: &'static [f64],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            buckets,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn observe(&self, label_values: &[&str], elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let key = label_values.iter().map(|v| v.to_string()).collect();
+        let mut values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        let data = values.entry(key).or_insert_with(|| HistogramData {
+            buckets: vec![0; self.buckets.len()],
+            ..Default::default()
+        });
+        if let Some(i) = self.buckets.iter().position(|bound| seconds <= *bound) {
+            data.buckets[i] += 1;
+        }
+        data.sum += seconds;
+        data.count += 1;
+    }
+
+    fn encode(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} histogram", self.name);
+        let values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, data) in values.iter() {
+            let labels = format_labels(self.labels, key);
+            let mut cumulative = 0;
+            for (bound, count) in self.buckets.iter().zip(&data.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{},le=\"{}\"}} {}",
+                    self.name, labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"+Inf\"}} {}",
+                self.name, labels, data.count
+            );
+            let _ = writeln!(out, "{}_sum{{{}}} {}", self.name, labels, data.sum);
+            let _ = writeln!(out, "{}_count{{{}}} {}", self.name, labels, data.count);
+        }
+    }
+}
+
+fn format_labels(names: &[&str], values: &[String]) -> String {
+    names
+        .iter()
+        .zip(values)
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Escape dei valori delle etichette secondo il formato testo di Prometheus
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let histogram = HistogramVec::new("test_seconds", "test", &["op"], &[0.1, 1.0]);
+        histogram.observe(&["a"], Duration::from_millis(50));
+        histogram.observe(&["a"], Duration::from_millis(500));
+        histogram.observe(&["a"], Duration::from_secs(5));
+
+        let mut out = String::new();
+        histogram.encode(&mut out);
+        assert!(out.contains("# TYPE test_seconds histogram"));
+        assert!(out.contains("test_seconds_bucket{op=\"a\",le=\"0.1\"} 1\n"));
+        assert!(out.contains("test_seconds_bucket{op=\"a\",le=\"1\"} 2\n"));
+        assert!(out.contains("test_seconds_bucket{op=\"a\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("test_seconds_count{op=\"a\"} 3\n"));
+    }
+
+    #[test]
+    fn test_counter_labels_and_escaping() {
+        let counter = CounterVec::new("test_total", "test", &["route", "status"]);
+        counter.inc(&["/a", "200"]);
+        counter.inc_by(&["/a", "200"], 2);
+        counter.inc(&["say \"hi\"\n", "500"]);
+
+        let mut out = String::new();
+        counter.encode(&mut out);
+        assert!(out.contains("test_total{route=\"/a\",status=\"200\"} 3\n"));
+        assert!(out.contains("test_total{route=\"say \\\"hi\\\"\\n\",status=\"500\"} 1\n"));
+    }
+
+    #[test]
+    fn test_conversion_labels_are_bounded() {
+        assert_eq!(conversion_type_label("Image"), "image");
+        assert_eq!(conversion_type_label("x".repeat(64).as_str()), "other");
+        assert_eq!(format_label("PNG"), "png");
+        assert_eq!(format_label("mp4"), "mp4");
+        assert_eq!(format_label("png-9f8e7d"), "other");
+        assert_eq!(format_label(""), "other");
+    }
+
+    #[test]
+    fn test_queue_wait_guard() {
+        let before = METRICS.queue_waiting.load(Ordering::Relaxed);
+        let guard = queue_wait();
+        assert_eq!(METRICS.queue_waiting.load(Ordering::Relaxed), before + 1);
+        drop(guard);
+        assert!(render(&QueueSnapshot::default()).contains("# TYPE converty_queue_depth gauge"));
+    }
+}
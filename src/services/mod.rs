@@ -1,7 +1,19 @@
+pub mod audit;
 pub mod converter;
+pub mod download_links;
+pub mod entitlements;
 #[cfg(feature = "google-auth")]
 pub mod google_auth;
 #[cfg(feature = "google-auth")]
 pub mod google_drive;
+pub mod metrics;
+#[cfg(feature = "google-auth")]
+pub mod oidc;
+pub mod outbound;
 pub mod queue;
+pub mod quota;
+pub mod readiness;
+pub mod scopes;
 pub mod stats;
+pub mod storage;
+pub mod telemetry;
@@ -0,0 +1,583 @@
+//! Client HTTP in uscita con protezione SSRF
+//!
+//! Tutte le richieste verso URL forniti dagli utenti (`source_url`, `webhook_url`)
+//! passano da questo modulo: il DNS viene risolto prima della connessione,
+//! le destinazioni private, loopback e link-local vengono rifiutate e ogni
+//! hop di redirect viene ricontrollato, compresi i domini consentiti dagli
+//! scope dell'API Key. La connessione usa l'indirizzo già verificato, così un
+//! secondo lookup DNS non può puntare altrove.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+use ipnet::IpNet;
+use reqwest::{header, redirect, Client, Method, RequestBuilder, Response, StatusCode, Url};
+use tokio::io::AsyncWriteExt;
+
+use crate::db::outbound::{self, OutboundConfig};
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::models::KeyScopes;
+
+/// Numero massimo di redirect seguiti
+const MAX_REDIRECTS: usize = 5;
+
+/// Timeout per il download di file remoti
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Regola per una destinazione (hostname, wildcard o rete)
+#[derive(Debug, Clone, PartialEq)]
+enum HostRule {
+    /// Hostname esatto
+    Host(String),
+    /// Dominio e tutti i sottodomini (`*.example.com`)
+    Suffix(String),
+    /// Indirizzo o rete
+    Net(IpNet),
+}
+
+impl HostRule {
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_lowercase();
+        if value.is_empty() {
+            return None;
+        }
+        if let Ok(net) = value.parse::<IpNet>() {
+            return Some(HostRule::Net(net));
+        }
+        if let Ok(ip) = value.parse::<IpAddr>() {
+            return Some(HostRule::Net(IpNet::from(ip)));
+        }
+        if let Some(domain) = value.strip_prefix("*.") {
+            return is_valid_hostname(domain).then(|| HostRule::Suffix(domain.to_string()));
+        }
+        is_valid_hostname(&value).then_some(HostRule::Host(value))
+    }
+
+    fn matches_host(&self, host: &str) -> bool {
+        match self {
+            HostRule::Host(h) => h == host,
+            HostRule::Suffix(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+            HostRule::Net(_) => false,
+        }
+    }
+
+    fn matches_ip(&self, ip: IpAddr) -> bool {
+        match self {
+            HostRule::Net(net) => net.contains(&ip),
+            _ => false,
+        }
+    }
+}
+
+fn is_valid_hostname(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+/// Verifica che una voce di allow/deny list sia valida
+pub fn validate_rule(value: &str) -> bool {
+    HostRule::parse(value).is_some()
+}
+
+/// Policy per le richieste in uscita, costruita da [`OutboundConfig`]
+#[derive(Debug, Clone, Default)]
+pub struct OutboundPolicy {
+    allow: Vec<HostRule>,
+    deny: Vec<HostRule>,
+    /// Scope dell'API Key per cui si scarica un `source_url`
+    source_scopes: KeyScopes,
+}
+
+impl OutboundPolicy {
+    pub fn from_config(config: &OutboundConfig) -> Self {
+        Self {
+            allow: config
+                .allowed_hosts
+                .iter()
+                .filter_map(|s| HostRule::parse(s))
+                .collect(),
+            deny: config
+                .denied_hosts
+                .iter()
+                .filter_map(|s| HostRule::parse(s))
+                .collect(),
+            source_scopes: KeyScopes::default(),
+        }
+    }
+
+    /// Limita anche ai domini `allowed_source_domains` dell'API Key
+    pub fn with_source_scopes(mut self, scopes: &KeyScopes) -> Self {
+        self.source_scopes = scopes.clone();
+        self
+    }
+
+    /// Carica la policy corrente dal database
+    pub async fn load(db: &DbPool) -> Result<Self> {
+        let config = outbound::get_outbound_config(db)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        Ok(Self::from_config(&config))
+    }
+
+    /// Verifica che la coppia host/indirizzo risolto sia raggiungibile
+    fn check(&self, host: &str, ip: IpAddr) -> Result<()> {
+        let ip = canonical_ip(ip);
+
+        if self
+            .deny
+            .iter()
+            .any(|r| r.matches_host(host) || r.matches_ip(ip))
+        {
+            return Err(blocked(host, "destinazione nella deny list"));
+        }
+
+        let explicitly_allowed_ip = self.allow.iter().any(|r| r.matches_ip(ip));
+
+        if !self.allow.is_empty()
+            && !explicitly_allowed_ip
+            && !self.allow.iter().any(|r| r.matches_host(host))
+        {
+            return Err(blocked(host, "destinazione non presente nella allow list"));
+        }
+
+        if is_restricted_ip(ip) && !explicitly_allowed_ip {
+            return Err(blocked(host, "indirizzo privato o riservato"));
+        }
+
+        Ok(())
+    }
+
+    /// Verifica che l'host sia tra i domini consentiti dall'API Key
+    fn check_source_scope(&self, url: &Url) -> Result<()> {
+        let host = url.host_str().unwrap_or_default();
+        if self.source_scopes.allows_source_host(host) {
+            return Ok(());
+        }
+        Err(AppError::Forbidden(format!(
+            "Dominio '{}' non permesso per questa API Key",
+            host
+        )))
+    }
+
+    /// Risolve l'host dell'URL e verifica tutti gli indirizzi ottenuti
+    async fn resolve(&self, url: &Url) -> Result<SocketAddr> {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(AppError::BadRequest(format!(
+                "Schema URL non supportato: {}",
+                url.scheme()
+            )));
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| AppError::BadRequest("URL senza host".to_string()))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_lowercase();
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| AppError::BadRequest("Porta URL non valida".to_string()))?;
+
+        let addrs: Vec<SocketAddr> = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![SocketAddr::new(ip, port)],
+            Err(_) => tokio::net::lookup_host((host.as_str(), port))
+                .await
+                .map_err(|e| AppError::BadRequest(format!("Host non risolvibile {}: {}", host, e)))?
+                .collect(),
+        };
+
+        if addrs.is_empty() {
+            return Err(AppError::BadRequest(format!(
+                "Host non risolvibile: {}",
+                host
+            )));
+        }
+
+        // Tutti gli indirizzi devono essere consentiti, altrimenti un record
+        // DNS misto potrebbe far arrivare la connessione su una rete interna
+        for addr in &addrs {
+            self.check(&host, addr.ip())?;
+        }
+
+        Ok(addrs[0])
+    }
+
+    /// Esegue una richiesta seguendo i redirect manualmente e ricontrollando ogni hop
+    ///
+    /// `body` viene applicato alla richiesta finché il metodo resta invariato
+    /// (307/308); sugli altri redirect la richiesta prosegue come GET senza body.
+    async fn send<F>(
+        &self,
+        method: Method,
+        url: &str,
+        timeout: Duration,
+        body: F,
+    ) -> Result<(Response, Url)>
+    where
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let mut current =
+            Url::parse(url).map_err(|e| AppError::BadRequest(format!("URL non valido: {}", e)))?;
+        let mut method = method;
+        let mut with_body = true;
+
+        for _ in 0..=MAX_REDIRECTS {
+            self.check_source_scope(&current)?;
+            let addr = self.resolve(&current).await?;
+
+            // Niente proxy di sistema: risolverebbe di nuovo l'host, scavalcando
+            // l'indirizzo appena verificato
+            let mut builder = Client::builder()
+                .no_proxy()
+                .redirect(redirect::Policy::none())
+                .timeout(timeout);
+            if let Some(domain) = current.domain() {
+                builder = builder.resolve(domain, addr);
+            }
+            let client = builder
+                .build()
+                .map_err(|e| AppError::Internal(format!("Errore client HTTP: {}", e)))?;
+
+            let mut request = client.request(method.clone(), current.clone());
+            if with_body {
+                request = body(request);
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(|e| AppError::Internal(format!("Errore richiesta HTTP: {}", e)))?;
+
+            if !response.status().is_redirection() {
+                return Ok((response, current));
+            }
+
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| AppError::Internal("Redirect senza Location".to_string()))?;
+
+            current = current
+                .join(location)
+                .map_err(|e| AppError::Internal(format!("Redirect non valido: {}", e)))?;
+
+            if !matches!(
+                response.status(),
+                StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT
+            ) {
+                method = Method::GET;
+                with_body = false;
+            }
+        }
+
+        Err(AppError::Internal(format!(
+            "Troppi redirect (massimo {})",
+            MAX_REDIRECTS
+        )))
+    }
+}
+
+fn blocked(host: &str, reason: &str) -> AppError {
+    AppError::Forbidden(format!(
+        "Destinazione non consentita ({}): {}",
+        reason, host
+    ))
+}
+
+/// Normalizza gli indirizzi IPv4-mapped (`::ffff:a.b.c.d`) in IPv4
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(v6),
+        },
+        v4 => v4,
+    }
+}
+
+/// Indica se un indirizzo appartiene a una rete non instradabile pubblicamente
+fn is_restricted_ip(ip: IpAddr) -> bool {
+    match canonical_ip(ip) {
+        IpAddr::V4(v4) => is_restricted_ipv4(v4),
+        IpAddr::V6(v6) => is_restricted_ipv6(v6),
+    }
+}
+
+fn is_restricted_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8 "this network"
+        || a == 0
+        // 100.64.0.0/10 shared address space (CGNAT)
+        || (a == 100 && (b & 0xc0) == 64)
+        // 192.0.0.0/24 IETF protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // 198.18.0.0/15 benchmarking
+        || (a == 198 && (b & 0xfe) == 18)
+        // 240.0.0.0/4 riservati
+        || a >= 240
+}
+
+fn is_restricted_ipv6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 unique local
+        || (segments[0] & 0xfe00) == 0xfc00
+        // fe80::/10 link-local
+        || (segments[0] & 0xffc0) == 0xfe80
+        // 2001:db8::/32 documentazione
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        // Indirizzi che incorporano un IPv4: controlla anche quello
+        || embedded_ipv4(ip).is_some_and(|v4| v4.iter().any(|v4| is_restricted_ipv4(*v4)))
+}
+
+/// IPv4 incorporati negli indirizzi di transizione (NAT64, IPv4-compatible,
+/// 6to4, Teredo)
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Vec<Ipv4Addr>> {
+    let s = ip.segments();
+    let v4 = |hi: u16, lo: u16| Ipv4Addr::new((hi >> 8) as u8, hi as u8, (lo >> 8) as u8, lo as u8);
+    match s {
+        // 64:ff9b::/96 NAT64
+        [0x0064, 0xff9b, 0, 0, 0, 0, hi, lo] => Some(vec![v4(hi, lo)]),
+        // ::/96 IPv4-compatible (deprecato)
+        [0, 0, 0, 0, 0, 0, hi, lo] => Some(vec![v4(hi, lo)]),
+        // 2002::/16 6to4: IPv4 nei bit 16-47
+        [0x2002, hi, lo, ..] => Some(vec![v4(hi, lo)]),
+        // 2001::/32 Teredo: server nei bit 32-63, client (invertito) negli ultimi 32
+        [0x2001, 0, server_hi, server_lo, _, _, client_hi, client_lo] => {
+            Some(vec![v4(server_hi, server_lo), v4(!client_hi, !client_lo)])
+        }
+        _ => None,
+    }
+}
+
+/// Risultato di un download verso disco
+#[derive(Debug)]
+pub struct DownloadedFile {
+    pub size_bytes: u64,
+    pub content_type: Option<String>,
+    /// URL finale dopo eventuali redirect
+    pub final_url: Url,
+}
+
+/// Scarica un URL direttamente su disco, rispettando la dimensione massima
+pub async fn download_to_file(
+    policy: &OutboundPolicy,
+    url: &str,
+    dest: &Path,
+    max_file_size_mb: u64,
+) -> Result<DownloadedFile> {
+    let max_bytes = max_file_size_mb * 1024 * 1024;
+
+    let (mut response, final_url) = policy
+        .send(Method::GET, url, DOWNLOAD_TIMEOUT, |r| r)
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(AppError::Internal(format!(
+            "Errore HTTP {}: impossibile scaricare il file",
+            response.status()
+        )));
+    }
+
+    if response.content_length().is_some_and(|len| len > max_bytes) {
+        return Err(AppError::FileTooLarge(max_file_size_mb));
+    }
+
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    let mut file = tokio::fs::File::create(dest).await?;
+    let mut size_bytes: u64 = 0;
+
+    loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                drop(file);
+                let _ = tokio::fs::remove_file(dest).await;
+                return Err(AppError::Internal(format!(
+                    "Errore lettura response: {}",
+                    e
+                )));
+            }
+        };
+
+        size_bytes += chunk.len() as u64;
+        if size_bytes > max_bytes {
+            drop(file);
+            let _ = tokio::fs::remove_file(dest).await;
+            return Err(AppError::FileTooLarge(max_file_size_mb));
+        }
+
+        file.write_all(&chunk).await?;
+    }
+
+    file.flush().await?;
+
+    Ok(DownloadedFile {
+        size_bytes,
+        content_type,
+        final_url,
+    })
+}
+
+/// Invia un payload JSON via POST (usato per i webhook)
+pub async fn post_json(
+    policy: &OutboundPolicy,
+    url: &str,
+    payload: &serde_json::Value,
+    timeout: Duration,
+) -> Result<StatusCode> {
+    let (response, _) = policy
+        .send(Method::POST, url, timeout, |r| r.json(payload))
+        .await?;
+    Ok(response.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> OutboundPolicy {
+        OutboundPolicy::from_config(&OutboundConfig {
+            allowed_hosts: allow.iter().map(|s| s.to_string()).collect(),
+            denied_hosts: deny.iter().map(|s| s.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn test_restricted_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            // 6to4 verso 127.0.0.1 e 10.0.0.1
+            "2002:7f00:1::",
+            "2002:a00:1::1",
+            // IPv4-compatible verso 169.254.169.254 e 192.168.0.1
+            "::a9fe:a9fe",
+            "::c0a8:1",
+            // Teredo con client 127.0.0.1 (invertito) e con server 10.0.0.1
+            "2001:0:808:808::80ff:fffe",
+            "2001:0:a00:1::f7f7:f7f7",
+        ] {
+            assert!(is_restricted_ip(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in [
+            "93.184.216.34",
+            "8.8.8.8",
+            "2606:4700:4700::1111",
+            // 6to4, IPv4-compatible e Teredo verso indirizzi pubblici
+            "2002:808:808::1",
+            "::808:808",
+            "2001:0:808:808::f7f7:f7f7",
+        ] {
+            assert!(!is_restricted_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_default_policy_blocks_private() {
+        let p = OutboundPolicy::default();
+        assert!(p.check("internal", "10.0.0.5".parse().unwrap()).is_err());
+        assert!(p
+            .check("example.com", "93.184.216.34".parse().unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_allowlist_cidr_unlocks_private_network() {
+        let p = policy(&["10.0.0.0/8"], &[]);
+        assert!(p
+            .check("hooks.internal", "10.1.1.1".parse().unwrap())
+            .is_ok());
+        assert!(p
+            .check("example.com", "93.184.216.34".parse().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_allowlist_domain_suffix() {
+        let p = policy(&["*.example.com"], &[]);
+        assert!(p
+            .check("cdn.example.com", "93.184.216.34".parse().unwrap())
+            .is_ok());
+        assert!(p
+            .check("example.org", "93.184.216.34".parse().unwrap())
+            .is_err());
+        // Un dominio consentito non sblocca indirizzi privati
+        assert!(p
+            .check("cdn.example.com", "127.0.0.1".parse().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_denylist_wins() {
+        let p = policy(&["*.example.com"], &["bad.example.com", "93.184.0.0/16"]);
+        assert!(p
+            .check("bad.example.com", "1.1.1.1".parse().unwrap())
+            .is_err());
+        assert!(p
+            .check("ok.example.com", "93.184.216.34".parse().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_source_scopes_apply_to_every_hop() {
+        let scopes = KeyScopes {
+            allowed_source_domains: vec!["*.example.com".to_string()],
+            ..KeyScopes::default()
+        };
+        let p = OutboundPolicy::default().with_source_scopes(&scopes);
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert!(p
+            .check_source_scope(&url("https://cdn.example.com/a.png"))
+            .is_ok());
+        // Redirect verso un dominio non consentito
+        assert!(p
+            .check_source_scope(&url("https://evil.test/a.png"))
+            .is_err());
+        // Senza scope ogni dominio è consentito
+        assert!(OutboundPolicy::default()
+            .check_source_scope(&url("https://evil.test/a.png"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_validate_rule() {
+        assert!(validate_rule("example.com"));
+        assert!(validate_rule("*.example.com"));
+        assert!(validate_rule("10.0.0.0/8"));
+        assert!(validate_rule("::1"));
+        assert!(!validate_rule("http://example.com"));
+        assert!(!validate_rule(""));
+    }
+}
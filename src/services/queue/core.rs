@@ -1,6 +1,6 @@
 //! Core job queue structures and basic operations
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::{broadcast, RwLock, Semaphore};
 use uuid::Uuid;
@@ -9,6 +9,8 @@ use crate::db::jobs::JobRecord;
 use crate::db::{jobs as db_jobs, DbPool};
 use crate::error::{AppError, Result};
 use crate::models::{ConversionType, Job, JobStatus, ProgressUpdate};
+use crate::services::download_links::LinkSigner;
+use crate::services::storage::{self, SharedStorage};
 
 /// Capacità del broadcast channel per progress updates
 const PROGRESS_CHANNEL_CAPACITY: usize = 100;
@@ -22,16 +24,31 @@ pub type JobQueue = Arc<RwLock<JobQueueInner>>;
 /// Sender globale per progress updates
 pub type ProgressSender = broadcast::Sender<ProgressUpdate>;
 
-/// Create a new job queue with database pool
-pub fn create_job_queue(db: DbPool) -> (JobQueue, ProgressSender) {
+/// Create a new job queue with database pool and storage backend
+///
+/// `work_dir` è la directory locale usata durante la conversione.
+pub fn create_job_queue(
+    db: DbPool,
+    storage: SharedStorage,
+    work_dir: PathBuf,
+    links: LinkSigner,
+) -> (JobQueue, ProgressSender) {
     let (tx, _) = broadcast::channel(PROGRESS_CHANNEL_CAPACITY);
-    let queue = Arc::new(RwLock::new(JobQueueInner::new(tx.clone(), db)));
+    let queue = Arc::new(RwLock::new(JobQueueInner::new(
+        tx.clone(),
+        db,
+        storage,
+        work_dir,
+        links,
+    )));
     (queue, tx)
 }
 
 /// Inner job queue structure
 pub struct JobQueueInner {
-    pub(crate) temp_dir: PathBuf,
+    pub(crate) work_dir: PathBuf,
+    pub(crate) storage: SharedStorage,
+    pub(crate) links: LinkSigner,
     pub(crate) progress_tx: ProgressSender,
     pub(crate) db: DbPool,
     pub(crate) concurrency_semaphore: Arc<Semaphore>,
@@ -40,18 +57,26 @@ pub struct JobQueueInner {
 impl std::fmt::Debug for JobQueueInner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("JobQueueInner")
-            .field("temp_dir", &self.temp_dir)
+            .field("work_dir", &self.work_dir)
+            .field("storage", &self.storage)
             .finish()
     }
 }
 
 impl JobQueueInner {
-    pub fn new(progress_tx: ProgressSender, db: DbPool) -> Self {
-        let temp_dir = std::env::temp_dir().join("converty").join("jobs");
-        std::fs::create_dir_all(&temp_dir).ok();
+    pub fn new(
+        progress_tx: ProgressSender,
+        db: DbPool,
+        storage: SharedStorage,
+        work_dir: PathBuf,
+        links: LinkSigner,
+    ) -> Self {
+        std::fs::create_dir_all(&work_dir).ok();
 
         Self {
-            temp_dir,
+            work_dir,
+            storage,
+            links,
             progress_tx,
             db,
             concurrency_semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_JOBS)),
@@ -74,16 +99,40 @@ impl JobQueueInner {
         &self.db
     }
 
+    /// Ottieni riferimento allo storage dei file
+    pub fn storage(&self) -> &SharedStorage {
+        &self.storage
+    }
+
+    /// Ottieni il signer per i link di download
+    pub fn links(&self) -> &LinkSigner {
+        &self.links
+    }
+
+    /// Directory di lavoro locale di un job
+    pub fn job_work_dir(&self, id: &Uuid) -> PathBuf {
+        self.work_dir.join(id.to_string())
+    }
+
     /// Ottieni semaforo per concorrenza
     pub fn semaphore(&self) -> Arc<Semaphore> {
         self.concurrency_semaphore.clone()
     }
 
+    /// Permessi di esecuzione in uso e totali
+    pub fn permits(&self) -> (usize, usize) {
+        let available = self.concurrency_semaphore.available_permits();
+        (
+            MAX_CONCURRENT_JOBS.saturating_sub(available),
+            MAX_CONCURRENT_JOBS,
+        )
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn create_job(
         &self,
         conversion_type: ConversionType,
-        input_data: Vec<u8>,
+        input_file: &Path,
         input_format: String,
         output_format: String,
         quality: Option<u8>,
@@ -114,14 +163,11 @@ impl JobQueueInner {
         // I job vengono sempre accettati e messi in coda.
         // Il semaforo in process_job controlla la concorrenza effettiva.
 
-        // Salva input in file temporaneo
+        // Salva input nello storage
         let job_id = Uuid::new_v4();
-        let job_dir = self.temp_dir.join(job_id.to_string());
-        std::fs::create_dir_all(&job_dir)?;
-
-        let input_path = job_dir.join(format!("input.{}", input_format));
-        let file_size = input_data.len() as i64;
-        std::fs::write(&input_path, input_data)?;
+        let input_key = storage::input_key(&job_id.to_string(), &input_format);
+        // Il file temporaneo (upload, download o tus) viene spostato nello storage
+        let file_size = self.storage.put_file(&input_key, input_file).await? as i64;
 
         let now = chrono::Utc::now();
         let now_str = now.to_rfc3339();
@@ -141,7 +187,7 @@ impl JobQueueInner {
             status: "pending".to_string(),
             progress: 0,
             progress_message: None,
-            input_path: input_path.to_string_lossy().to_string(),
+            input_path: input_key,
             result_path: None,
             error: None,
             file_size_bytes: Some(file_size),
@@ -184,12 +230,16 @@ impl JobQueueInner {
             .map_err(|e| AppError::Internal(e.to_string()))?;
 
         if let Some(job) = record {
-            // Rimuovi file temporanei
-            let job_dir = self.temp_dir.join(id.to_string());
-            std::fs::remove_dir_all(job_dir).ok();
+            // Rimuovi file dallo storage e directory di lavoro
+            std::fs::remove_dir_all(self.job_work_dir(id)).ok();
 
+            if let Err(e) = self.storage.delete(&job.input_path).await {
+                tracing::warn!("Errore rimozione input job {}: {}", id, e);
+            }
             if let Some(result_path) = job.result_path {
-                std::fs::remove_file(result_path).ok();
+                if let Err(e) = self.storage.delete(&result_path).await {
+                    tracing::warn!("Errore rimozione risultato job {}: {}", id, e);
+                }
             }
 
             // Elimina dal database
@@ -244,8 +294,7 @@ impl JobQueueInner {
     }
 
     /// Marca job come completato e invia notifica
-    pub async fn mark_job_completed(&self, id: &Uuid, result_path: PathBuf) {
-        let result_path_str = result_path.to_string_lossy().to_string();
+    pub async fn mark_job_completed(&self, id: &Uuid, result_key: &str) {
         let _ = db_jobs::update_job_status(
             &self.db,
             &id.to_string(),
@@ -253,7 +302,7 @@ impl JobQueueInner {
             100,
             Some("Conversione completata!"),
             None,
-            Some(&result_path_str),
+            Some(result_key),
         )
         .await;
 
@@ -8,7 +8,7 @@ mod webhooks;
 
 // Re-export public items
 pub use core::{create_job_queue, job_from_record, JobQueue, JobQueueInner, ProgressSender};
-pub use processor::{download_from_url, get_job_result, process_job};
+pub use processor::{download_from_url, get_job_result, process_job, JobResult};
 pub use webhooks::send_webhook;
 
 #[cfg(feature = "google-auth")]
@@ -1,16 +1,27 @@
 //! Job processing logic
 
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+use tracing::Instrument;
 use uuid::Uuid;
 
-use crate::db::jobs as db_jobs;
+use crate::db::stats::{self as db_stats, ConversionRecordDb};
+use crate::db::{jobs as db_jobs, DbPool};
 use crate::error::{AppError, Result};
-use crate::models::{ConversionType, JobStatus};
+use crate::models::{ConversionType, JobStatus, KeyScopes};
 use crate::services::converter;
+use crate::services::metrics;
+use crate::services::outbound::{self, OutboundPolicy};
+use crate::services::storage::{self, Storage};
 
 use super::core::JobQueue;
 use super::webhooks::send_webhook;
 
 /// Process a job
+///
+/// Lo span `process_job` riporta l'ID della richiesta che ha creato il job.
+#[tracing::instrument(skip(queue), fields(request_id = tracing::field::Empty))]
 pub async fn process_job(queue: JobQueue, job_id: Uuid) {
     // Acquisisci permesso dal semaforo
     let semaphore = {
@@ -18,9 +29,12 @@ pub async fn process_job(queue: JobQueue, job_id: Uuid) {
         q.semaphore()
     };
 
-    let _permit = match semaphore.acquire().await {
-        Ok(p) => p,
-        Err(_) => return,
+    let _permit = {
+        let _waiting = metrics::queue_wait();
+        match semaphore.acquire().await {
+            Ok(p) => p,
+            Err(_) => return,
+        }
     };
 
     // Marca come in elaborazione
@@ -40,6 +54,11 @@ pub async fn process_job(queue: JobQueue, job_id: Uuid) {
                     .await
                     .ok()
                     .flatten();
+                if let Ok(Some(request_id)) =
+                    db_jobs::get_job_request_id(q.db(), &job_id.to_string()).await
+                {
+                    tracing::Span::current().record("request_id", request_id.as_str());
+                }
                 let api_key_id = record.as_ref().and_then(|r| r.api_key_id.clone());
                 let original_filename = record.as_ref().and_then(|r| r.original_filename.clone());
                 (job, api_key_id, original_filename)
@@ -48,74 +67,93 @@ pub async fn process_job(queue: JobQueue, job_id: Uuid) {
         }
     };
 
-    let input_path = job.input_path;
+    let input_key = job.input_path.to_string_lossy().to_string();
     let output_format = job.output_format.clone();
     let conversion_type = job.conversion_type;
     let quality = job.quality;
 
     // Progress: caricamento file
-    {
+    let (storage, work_dir) = {
         let q = queue.read().await;
         q.update_job_progress(&job_id, 10, Some("Caricamento file...".to_string()))
             .await;
-    }
-
-    // Esegui conversione
-    let temp_dir = std::env::temp_dir()
-        .join("converty")
-        .join("jobs")
-        .join(job_id.to_string());
-    // Assicura che la directory esista
-    std::fs::create_dir_all(&temp_dir).ok();
+        (q.storage().clone(), q.job_work_dir(&job_id))
+    };
 
-    // Progress: conversione in corso
+    // Directory di lavoro locale per la conversione
+    std::fs::create_dir_all(&work_dir).ok();
+
+    let start = std::time::Instant::now();
+    let result = convert_job(
+        &queue,
+        &job_id,
+        &*storage,
+        &input_key,
+        &work_dir,
+        &output_format,
+        &conversion_type,
+        quality,
+    )
+    .await;
+
+    std::fs::remove_dir_all(&work_dir).ok();
+
+    // Registra la conversione (statistiche e quote dell'API Key)
     {
         let q = queue.read().await;
-        q.update_job_progress(&job_id, 30, Some("Conversione in corso...".to_string()))
-            .await;
-    }
-
-    // Gestione speciale per PDF multi-pagina
-    let is_pdf = matches!(conversion_type, ConversionType::Pdf)
-        || input_path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.to_lowercase())
-            == Some("pdf".to_string());
-
-    let (result, actual_output_path) = if is_pdf {
-        match converter::convert_pdf_file_smart(&input_path, &temp_dir, &output_format) {
-            Ok((path, _is_zip)) => (Ok(()), path),
-            Err(e) => (Err(e), temp_dir.join(format!("output.{}", output_format))),
-        }
-    } else {
-        let output_path = temp_dir.join(format!("output.{}", output_format));
-        let res = converter::convert_file(
-            &input_path,
-            &output_path,
+        let (input_size, output_size, media_duration_ms) = match &result {
+            Ok(output) => (
+                output.input_size,
+                output.output_size,
+                output.media_duration_ms,
+            ),
+            Err(_) => (0, 0, 0),
+        };
+        let elapsed = start.elapsed();
+        metrics::record_conversion(
+            &conversion_type.to_string(),
+            &job.input_format,
             &output_format,
-            &conversion_type,
-            quality,
+            result.is_ok(),
+            elapsed,
+            input_size.max(0) as u64,
+            output_size.max(0) as u64,
         );
-        (res, output_path)
-    };
-
-    // Progress: salvataggio
-    {
-        let q = queue.read().await;
-        q.update_job_progress(&job_id, 80, Some("Salvataggio risultato...".to_string()))
-            .await;
+        let record = ConversionRecordDb {
+            id: Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now(),
+            api_key_id: api_key_id.clone(),
+            is_guest: api_key_id.is_none(),
+            conversion_type: conversion_type.to_string(),
+            input_format: job.input_format.clone(),
+            output_format: output_format.clone(),
+            input_size_bytes: input_size,
+            output_size_bytes: output_size,
+            processing_time_ms: elapsed.as_millis() as i64,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            client_ip: None,
+            media_duration_ms,
+        };
+        if let Err(e) = db_stats::insert_conversion(q.db(), &record).await {
+            tracing::error!("Errore salvataggio statistiche job {}: {}", job_id, e);
+        }
     }
 
     // Aggiorna stato job
     #[allow(unused_variables)]
-    let (final_status, error_msg, completed_output_path) = {
+    let (final_status, error_msg, completed_result_key) = {
         let q = queue.read().await;
         match result {
-            Ok(_) => {
-                q.mark_job_completed(&job_id, actual_output_path.clone())
-                    .await;
-                ("completed", None, Some(actual_output_path))
+            Ok(output) => {
+                if let Err(e) =
+                    db_jobs::set_job_result_checksum(q.db(), &job_id.to_string(), &output.checksum)
+                        .await
+                {
+                    tracing::warn!("Errore salvataggio checksum job {}: {}", job_id, e);
+                }
+                q.mark_job_completed(&job_id, &output.result_key).await;
+                ("completed", None, Some(output.result_key))
             }
             Err(e) => {
                 let err = e.to_string();
@@ -128,12 +166,14 @@ pub async fn process_job(queue: JobQueue, job_id: Uuid) {
     // Upload to Google Drive if enabled (only for completed jobs)
     #[cfg(feature = "google-auth")]
     if final_status == "completed" {
-        if let (Some(key_id), Some(result_path)) = (&api_key_id, &completed_output_path) {
+        if let (Some(key_id), Some(result_key)) = (&api_key_id, &completed_result_key) {
             let q = queue.read().await;
             let db = q.db().clone();
+            let storage = q.storage().clone();
+            let scratch_dir = q.work_dir.clone();
             let job_id_str = job_id.to_string();
             let key_id = key_id.clone();
-            let result_path = result_path.clone();
+            let result_key = result_key.clone();
             let original_filename = original_filename.clone();
             let output_format = output_format.clone();
             let conv_type_str = conversion_type.to_string();
@@ -143,20 +183,25 @@ pub async fn process_job(queue: JobQueue, job_id: Uuid) {
             let google_client_secret = std::env::var("GOOGLE_CLIENT_SECRET").unwrap_or_default();
 
             if !google_client_id.is_empty() && !google_client_secret.is_empty() {
-                tokio::spawn(async move {
-                    super::webhooks::upload_to_drive_if_enabled(
-                        &db,
-                        &job_id_str,
-                        &key_id,
-                        &result_path,
-                        original_filename.as_deref(),
-                        &output_format,
-                        &conv_type_str,
-                        &google_client_id,
-                        &google_client_secret,
-                    )
-                    .await;
-                });
+                tokio::spawn(
+                    async move {
+                        super::webhooks::upload_to_drive_if_enabled(
+                            &db,
+                            &*storage,
+                            &scratch_dir,
+                            &job_id_str,
+                            &key_id,
+                            &result_key,
+                            original_filename.as_deref(),
+                            &output_format,
+                            &conv_type_str,
+                            &google_client_id,
+                            &google_client_secret,
+                        )
+                        .await;
+                    }
+                    .in_current_span(),
+                );
             }
         }
     }
@@ -166,15 +211,144 @@ pub async fn process_job(queue: JobQueue, job_id: Uuid) {
         let q = queue.read().await;
         if let Ok(Some(webhook_url)) = db_jobs::get_job_webhook(q.db(), &job_id.to_string()).await {
             let error_clone = error_msg.clone();
-            tokio::spawn(async move {
-                send_webhook(&webhook_url, &job_id, final_status, error_clone.as_deref()).await;
+            let db = q.db().clone();
+            // Link firmato per scaricare il risultato senza API Key
+            let download_url = (final_status == "completed").then(|| {
+                let links = q.links();
+                let expires = chrono::Utc::now().timestamp() + links.default_ttl_secs as i64;
+                links.sign_url(&job_id.to_string(), expires, None)
             });
+            tokio::spawn(
+                async move {
+                    let policy = match OutboundPolicy::load(&db).await {
+                        Ok(p) => p,
+                        Err(e) => {
+                            tracing::error!("Errore caricamento policy outbound: {}", e);
+                            return;
+                        }
+                    };
+                    send_webhook(
+                        &policy,
+                        &webhook_url,
+                        &job_id,
+                        final_status,
+                        error_clone.as_deref(),
+                        download_url.as_deref(),
+                    )
+                    .await;
+                }
+                .in_current_span(),
+            );
         }
     }
 }
 
+/// Esito della conversione di un job
+struct JobOutput {
+    /// Chiave del risultato nello storage
+    result_key: String,
+    /// SHA-256 del risultato
+    checksum: String,
+    input_size: i64,
+    output_size: i64,
+    media_duration_ms: i64,
+}
+
+/// Scarica l'input, esegue la conversione nella directory di lavoro e
+/// salva il risultato nello storage.
+#[allow(clippy::too_many_arguments)]
+async fn convert_job(
+    queue: &JobQueue,
+    job_id: &Uuid,
+    storage: &dyn Storage,
+    input_key: &str,
+    work_dir: &Path,
+    output_format: &str,
+    conversion_type: &ConversionType,
+    quality: Option<u8>,
+) -> Result<JobOutput> {
+    let input = storage::fetch_to_local(storage, input_key, work_dir)
+        .instrument(tracing::info_span!("fetch_input"))
+        .await?;
+    let input_path = input.path();
+    let input_size = std::fs::metadata(input_path)?.len() as i64;
+    let media_duration_ms = converter::media_duration_ms(conversion_type, input_path).await;
+
+    // Progress: conversione in corso
+    {
+        let q = queue.read().await;
+        q.update_job_progress(job_id, 30, Some("Conversione in corso...".to_string()))
+            .await;
+    }
+
+    let output_path = converter::convert_file_in_dir(
+        input_path,
+        work_dir,
+        output_format,
+        conversion_type,
+        quality,
+    )?;
+
+    // Progress: salvataggio
+    {
+        let q = queue.read().await;
+        q.update_job_progress(job_id, 80, Some("Salvataggio risultato...".to_string()))
+            .await;
+    }
+
+    let filename = output_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("output")
+        .to_string();
+    let result_key = storage::result_key(&job_id.to_string(), &filename);
+
+    let (checksum, output_size) = async {
+        let checksum = {
+            let output_path = output_path.clone();
+            tokio::task::spawn_blocking(move || file_sha256(&output_path))
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))??
+        };
+        let output_size = std::fs::metadata(&output_path)?.len() as i64;
+        storage.put_file(&result_key, &output_path).await?;
+        Ok::<_, AppError>((checksum, output_size))
+    }
+    .instrument(tracing::info_span!("store_result"))
+    .await?;
+
+    Ok(JobOutput {
+        result_key,
+        checksum,
+        input_size,
+        output_size,
+        media_duration_ms,
+    })
+}
+
+/// Calcola lo SHA-256 di un file senza caricarlo in memoria
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Metadati del risultato di un job completato
+#[derive(Debug, Clone)]
+pub struct JobResult {
+    /// Chiave del risultato nello storage
+    pub key: String,
+    pub size: u64,
+    /// SHA-256 del risultato (assente per job completati prima del calcolo)
+    pub checksum: Option<String>,
+    pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
 /// Get the result of a completed job
-pub async fn get_job_result(queue: &JobQueue, job_id: &Uuid) -> Result<Vec<u8>> {
+///
+/// Ritorna solo i metadati: il contenuto va letto in streaming dallo storage.
+pub async fn get_job_result(queue: &JobQueue, job_id: &Uuid) -> Result<JobResult> {
     let q = queue.read().await;
 
     let job = q
@@ -186,53 +360,54 @@ pub async fn get_job_result(queue: &JobQueue, job_id: &Uuid) -> Result<Vec<u8>>
         return Err(AppError::JobNotCompleted);
     }
 
-    let result_path = job
+    let result_key = job
         .result_path
         .as_ref()
-        .ok_or_else(|| AppError::Internal("Percorso risultato mancante".to_string()))?;
-
-    let data = std::fs::read(result_path)?;
+        .ok_or_else(|| AppError::Internal("Percorso risultato mancante".to_string()))?
+        .to_string_lossy()
+        .to_string();
 
-    Ok(data)
-}
-
-/// Scarica un file da URL remoto
-pub async fn download_from_url(url: &str) -> Result<(Vec<u8>, String)> {
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(60))
-        .build()
-        .map_err(|e| AppError::Internal(format!("Errore client HTTP: {}", e)))?;
-
-    let response = client
-        .get(url)
-        .send()
+    let size = q.storage().size(&result_key).await?;
+    let checksum = db_jobs::get_job_result_checksum(q.db(), &job_id.to_string())
         .await
-        .map_err(|e| AppError::Internal(format!("Errore download URL: {}", e)))?;
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(JobResult {
+        key: result_key,
+        size,
+        checksum,
+        completed_at: job.completed_at,
+    })
+}
 
-    if !response.status().is_success() {
-        return Err(AppError::Internal(format!(
-            "Errore HTTP {}: impossibile scaricare il file",
-            response.status()
-        )));
-    }
+/// Scarica un file da URL remoto direttamente su disco
+///
+/// La destinazione viene verificata dalla policy outbound configurata dall'admin
+/// (niente reti private/loopback se non esplicitamente consentite) e, a ogni
+/// redirect, dai domini consentiti da `scopes`; il download viene interrotto
+/// appena supera `max_file_size_mb`.
+/// Ritorna l'estensione del file ricavata dall'URL o dal content-type.
+pub async fn download_from_url(
+    db: &DbPool,
+    url: &str,
+    dest: &Path,
+    max_file_size_mb: u64,
+    scopes: &KeyScopes,
+) -> Result<String> {
+    let policy = OutboundPolicy::load(db).await?.with_source_scopes(scopes);
+    let downloaded = outbound::download_to_file(&policy, url, dest, max_file_size_mb).await?;
 
     // Estrai estensione dall'URL o dal content-type
     let extension = extract_extension_from_url(url)
         .or_else(|| {
-            response
-                .headers()
-                .get("content-type")
-                .and_then(|v| v.to_str().ok())
+            downloaded
+                .content_type
+                .as_deref()
                 .and_then(extension_from_mime)
         })
         .unwrap_or_else(|| "bin".to_string());
 
-    let bytes = response
-        .bytes()
-        .await
-        .map_err(|e| AppError::Internal(format!("Errore lettura response: {}", e)))?;
-
-    Ok((bytes.to_vec(), extension))
+    Ok(extension)
 }
 
 /// Estrae l'estensione del file dall'URL
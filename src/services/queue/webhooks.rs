@@ -1,43 +1,51 @@
 //! Webhook and external service integration
 
+use std::time::Duration;
+
 use uuid::Uuid;
 
+use crate::services::metrics;
+use crate::services::outbound::{self, OutboundPolicy};
+
 #[cfg(feature = "google-auth")]
 use crate::db::DbPool;
+#[cfg(feature = "google-auth")]
+use crate::services::storage::{self, Storage};
 
-/// Invia notifica webhook
-pub async fn send_webhook(webhook_url: &str, job_id: &Uuid, status: &str, error: Option<&str>) {
-    let client = match reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .build()
-    {
-        Ok(c) => c,
-        Err(e) => {
-            tracing::error!("Errore creazione client webhook: {}", e);
-            return;
-        }
-    };
+/// Timeout per l'invio dei webhook
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Invia notifica webhook
+///
+/// La destinazione è soggetta alla stessa policy outbound dei `source_url`.
+/// Per i job completati il payload include un link di download firmato.
+pub async fn send_webhook(
+    policy: &OutboundPolicy,
+    webhook_url: &str,
+    job_id: &Uuid,
+    status: &str,
+    error: Option<&str>,
+    download_url: Option<&str>,
+) {
     let payload = serde_json::json!({
         "job_id": job_id.to_string(),
         "status": status,
         "error": error,
+        "download_url": download_url,
         "timestamp": chrono::Utc::now().to_rfc3339()
     });
 
-    match client.post(webhook_url).json(&payload).send().await {
-        Ok(response) => {
-            if response.status().is_success() {
+    match outbound::post_json(policy, webhook_url, &payload, WEBHOOK_TIMEOUT).await {
+        Ok(status) => {
+            metrics::record_webhook(status.is_success());
+            if status.is_success() {
                 tracing::info!("Webhook inviato con successo per job {}", job_id);
             } else {
-                tracing::warn!(
-                    "Webhook per job {} ha ritornato status {}",
-                    job_id,
-                    response.status()
-                );
+                tracing::warn!("Webhook per job {} ha ritornato status {}", job_id, status);
             }
         }
         Err(e) => {
+            metrics::record_webhook(false);
             tracing::error!("Errore invio webhook per job {}: {}", job_id, e);
         }
     }
@@ -48,9 +56,11 @@ pub async fn send_webhook(webhook_url: &str, job_id: &Uuid, status: &str, error:
 #[allow(clippy::too_many_arguments)]
 pub async fn upload_to_drive_if_enabled(
     db: &DbPool,
+    storage: &dyn Storage,
+    scratch_dir: &std::path::Path,
     job_id: &str,
     api_key_id: &str,
-    result_path: &std::path::Path,
+    result_key: &str,
     original_filename: Option<&str>,
     output_format: &str,
     conversion_type: &str,
@@ -98,6 +108,7 @@ pub async fn upload_to_drive_if_enabled(
         Ok(token) => token,
         Err(e) => {
             tracing::error!("Failed to get Drive token for user {}: {}", user_id, e);
+            metrics::record_drive_upload(false);
             return;
         }
     };
@@ -110,6 +121,7 @@ pub async fn upload_to_drive_if_enabled(
         Ok(id) => id,
         Err(e) => {
             tracing::error!("Failed to ensure Drive folder: {}", e);
+            metrics::record_drive_upload(false);
             return;
         }
     };
@@ -131,12 +143,28 @@ pub async fn upload_to_drive_if_enabled(
         )
     };
 
+    // Rendi disponibile il risultato su disco
+    let result_file = match storage::fetch_to_local(storage, result_key, scratch_dir).await {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("Failed to read result for Drive upload: {}", e);
+            metrics::record_drive_upload(false);
+            return;
+        }
+    };
+
     // Upload file
     match drive
-        .upload_file_from_path(&access_token, &folder_id, result_path, Some(&filename))
+        .upload_file_from_path(
+            &access_token,
+            &folder_id,
+            result_file.path(),
+            Some(&filename),
+        )
         .await
     {
         Ok(file) => {
+            metrics::record_drive_upload(true);
             tracing::info!("File uploaded to Drive: {} (id: {})", file.name, file.id);
             // Save drive_file_id to job record
             if let Err(e) = db_jobs::update_job_drive_file_id(db, job_id, &file.id).await {
@@ -145,6 +173,7 @@ pub async fn upload_to_drive_if_enabled(
         }
         Err(e) => {
             tracing::error!("Failed to upload to Drive: {}", e);
+            metrics::record_drive_upload(false);
         }
     }
 }
@@ -0,0 +1,305 @@
+//! Quote giornaliere e mensili delle API Key e delle organizzazioni
+//!
+//! Il consumo è calcolato dai record in `conversion_records` (solo conversioni
+//! riuscite); i periodi sono il giorno e il mese solari in UTC. Le quote di
+//! un'organizzazione sono condivise da tutte le chiavi membri.
+
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+
+use crate::db::stats::{self, KeyUsage, UsageScope};
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::models::{AuthInfo, QuotaCounter, QuotaLimits, QuotaStatus};
+
+/// Inizio del giorno UTC di `now`
+fn day_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&now.date_naive().and_hms_opt(0, 0, 0).unwrap())
+}
+
+/// Inizio del mese UTC di `now`
+fn month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    let date = NaiveDate::from_ymd_opt(now.year(), now.month(), 1).unwrap();
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+/// Inizio del mese successivo a `now`
+fn next_month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    let date = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+/// Calcola lo stato delle quote di un'API Key o di un'organizzazione
+pub async fn quota_status(
+    db: &DbPool,
+    scope: UsageScope<'_>,
+    limits: &QuotaLimits,
+) -> Result<QuotaStatus> {
+    let now = Utc::now();
+    let today = stats::get_usage(db, scope, day_start(now))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let month = stats::get_usage(db, scope, month_start(now))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let tomorrow = day_start(now) + Duration::days(1);
+    let next_month = next_month_start(now);
+
+    Ok(QuotaStatus {
+        daily_conversions: QuotaCounter {
+            used: today.conversions as u64,
+            limit: limits.daily_conversions,
+            reset_at: tomorrow,
+        },
+        monthly_conversions: QuotaCounter {
+            used: month.conversions as u64,
+            limit: limits.monthly_conversions,
+            reset_at: next_month,
+        },
+        monthly_volume_mb: QuotaCounter {
+            used: month.input_bytes as u64 / (1024 * 1024),
+            limit: limits.monthly_volume_mb,
+            reset_at: next_month,
+        },
+        monthly_media_minutes: QuotaCounter {
+            used: month.media_duration_ms as u64 / 60_000,
+            limit: limits.monthly_media_minutes,
+            reset_at: next_month,
+        },
+    })
+}
+
+/// Verifica che l'API Key (e la sua organizzazione) possa avviare `incoming`
+///
+/// Al consumo registrato si sommano i job ancora in coda o in elaborazione e
+/// la richiesta corrente: prima di ricevere il file basta una conversione
+/// senza dimensione, dopo vanno indicate anche dimensione e durata.
+/// Non fa nulla per i guest (limitati da `entitlements::check_guest_limits`) e per le
+/// chiavi senza quote configurate.
+pub async fn check_quota(db: &DbPool, auth: &AuthInfo, incoming: KeyUsage) -> Result<()> {
+    let Some(api_key_id) = auth.api_key_id.as_deref() else {
+        return Ok(());
+    };
+
+    if !auth.quota.is_unlimited() {
+        ensure_within(
+            db,
+            UsageScope::ApiKey(api_key_id),
+            &auth.quota,
+            incoming,
+            "",
+        )
+        .await?;
+    }
+
+    if let Some(org) = auth
+        .organization
+        .as_ref()
+        .filter(|o| !o.quota.is_unlimited())
+    {
+        ensure_within(
+            db,
+            UsageScope::Organization(&org.id),
+            &org.quota,
+            incoming,
+            " dell'organizzazione",
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Verifica le quote per un file già ricevuto
+///
+/// `media_duration_ms` è la durata già letta da `scopes::check_input_scope`.
+pub async fn check_input_quota(
+    db: &DbPool,
+    auth: &AuthInfo,
+    input_path: &Path,
+    media_duration_ms: i64,
+) -> Result<()> {
+    let input_bytes = std::fs::metadata(input_path)?.len() as i64;
+    check_quota(
+        db,
+        auth,
+        incoming_conversion(input_bytes, media_duration_ms),
+    )
+    .await
+}
+
+/// Una conversione di `input_bytes` byte e `media_duration_ms` ms
+pub fn incoming_conversion(input_bytes: i64, media_duration_ms: i64) -> KeyUsage {
+    KeyUsage {
+        conversions: 1,
+        input_bytes,
+        media_duration_ms,
+    }
+}
+
+/// Errore `QuotaExceeded` se consumo, job pendenti e `incoming` superano un limite
+async fn ensure_within(
+    db: &DbPool,
+    scope: UsageScope<'_>,
+    limits: &QuotaLimits,
+    incoming: KeyUsage,
+    owner: &str,
+) -> Result<()> {
+    let now = Utc::now();
+    let today = stats::get_usage(db, scope, day_start(now))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let month = stats::get_usage(db, scope, month_start(now))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let pending = stats::get_pending_usage(db, scope)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let extra = add(pending, incoming);
+    match first_exceeded(limits, add(today, extra), add(month, extra), now) {
+        Some((limit, name, reset_at)) => Err(AppError::QuotaExceeded {
+            message: format!("limite di {} {}{} raggiunto", limit, name, owner),
+            reset_at,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn add(a: KeyUsage, b: KeyUsage) -> KeyUsage {
+    KeyUsage {
+        conversions: a.conversions + b.conversions,
+        input_bytes: a.input_bytes + b.input_bytes,
+        media_duration_ms: a.media_duration_ms + b.media_duration_ms,
+    }
+}
+
+/// Primo limite superato: valore, nome del contatore e momento dell'azzeramento
+fn first_exceeded(
+    limits: &QuotaLimits,
+    today: KeyUsage,
+    month: KeyUsage,
+    now: DateTime<Utc>,
+) -> Option<(u64, &'static str, DateTime<Utc>)> {
+    let tomorrow = day_start(now) + Duration::days(1);
+    let next_month = next_month_start(now);
+
+    // (uso, limite, unità del limite, nome, azzeramento)
+    let checks = [
+        (
+            today.conversions,
+            limits.daily_conversions,
+            1,
+            "conversioni giornaliere",
+            tomorrow,
+        ),
+        (
+            month.conversions,
+            limits.monthly_conversions,
+            1,
+            "conversioni mensili",
+            next_month,
+        ),
+        (
+            month.input_bytes,
+            limits.monthly_volume_mb,
+            1024 * 1024,
+            "MB mensili",
+            next_month,
+        ),
+        (
+            month.media_duration_ms,
+            limits.monthly_media_minutes,
+            60_000,
+            "minuti audio/video mensili",
+            next_month,
+        ),
+    ];
+    checks
+        .into_iter()
+        .find_map(|(used, limit, unit, name, reset_at)| {
+            let limit = limit?;
+            (used.max(0) as u64 > limit.saturating_mul(unit)).then_some((limit, name, reset_at))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_boundaries() {
+        let now = Utc.with_ymd_and_hms(2024, 12, 15, 13, 45, 0).unwrap();
+        assert_eq!(
+            day_start(now),
+            Utc.with_ymd_and_hms(2024, 12, 15, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            month_start(now),
+            Utc.with_ymd_and_hms(2024, 12, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            next_month_start(now),
+            Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_counter_exceeded() {
+        let counter = QuotaCounter {
+            used: 10,
+            limit: Some(10),
+            reset_at: Utc::now(),
+        };
+        assert!(counter.is_exceeded());
+        let unlimited = QuotaCounter {
+            limit: None,
+            ..counter
+        };
+        assert!(!unlimited.is_exceeded());
+    }
+
+    #[test]
+    fn test_incoming_conversion_counts_against_limits() {
+        let now = Utc.with_ymd_and_hms(2024, 12, 15, 13, 45, 0).unwrap();
+        let limits = QuotaLimits {
+            daily_conversions: Some(10),
+            monthly_volume_mb: Some(100),
+            ..Default::default()
+        };
+        let mb = 1024 * 1024;
+
+        // 9 conversioni (registrate o in coda) + quella in arrivo: nel limite
+        let used = KeyUsage {
+            conversions: 9,
+            input_bytes: 90 * mb,
+            media_duration_ms: 0,
+        };
+        let incoming = incoming_conversion(10 * mb, 0);
+        assert!(first_exceeded(&limits, add(used, incoming), add(used, incoming), now).is_none());
+
+        // Il file in arrivo porterebbe il volume oltre i 100 MB
+        let incoming = incoming_conversion(10 * mb + 1, 0);
+        let (limit, name, reset_at) =
+            first_exceeded(&limits, add(used, incoming), add(used, incoming), now).unwrap();
+        assert_eq!((limit, name), (100, "MB mensili"));
+        assert_eq!(reset_at, next_month_start(now));
+
+        // Con 10 conversioni già conteggiate non se ne può avviare un'altra
+        let used = KeyUsage {
+            conversions: 10,
+            ..Default::default()
+        };
+        let incoming = incoming_conversion(0, 0);
+        let (_, name, _) =
+            first_exceeded(&limits, add(used, incoming), add(used, incoming), now).unwrap();
+        assert_eq!(name, "conversioni giornaliere");
+    }
+}
@@ -0,0 +1,431 @@
+//! Verifiche di readiness del nodo
+//!
+//! Ogni verifica produce un esito `ok`, `warn` o `fail`: basta un `fail`
+//! perché il nodo risulti `degraded` e l'orchestratore smetta di inviargli
+//! traffico. I tool esterni mancanti sono solo un `warn` (i relativi formati
+//! spariscono da `/api/v1/formats`), a meno che non siano elencati in
+//! `CONVERTY_REQUIRED_TOOLS`.
+//!
+//! Le probe arrivano di continuo: il risultato viene riusato per qualche
+//! secondo e i tool esterni (che richiedono l'avvio di processi) vengono
+//! interrogati di rado.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::models::{CheckStatus, ReadinessCheck};
+use crate::services::metrics;
+use crate::services::queue::JobQueue;
+
+/// Tempo massimo per la query di prova sul database
+const DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Encoder FFmpeg usati dalle conversioni audio/video
+const REQUIRED_ENCODERS: &[&str] = &[
+    "libx264",
+    "aac",
+    "libvpx-vp9",
+    "libopus",
+    "mpeg4",
+    "libmp3lame",
+];
+
+/// Job in attesa per permesso oltre i quali la coda è considerata satura
+const QUEUE_SATURATION_FACTOR: u64 = 4;
+
+/// Per quanto viene riusato il risultato completo delle verifiche
+const RESULT_TTL: Duration = Duration::from_secs(5);
+
+/// Per quanto vengono riusate versioni ed encoder dei tool esterni
+const TOOLS_TTL: Duration = Duration::from_secs(300);
+
+/// Verifiche con cache, condivise tra le richieste a `/health/ready`
+#[derive(Clone, Default)]
+pub struct ReadinessCache {
+    inner: Arc<Mutex<CacheState>>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    tools: Option<(Instant, Vec<ReadinessCheck>)>,
+    result: Option<(Instant, Vec<ReadinessCheck>)>,
+}
+
+impl ReadinessCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Esito delle verifiche, ricalcolato al più ogni [`RESULT_TTL`]
+    ///
+    /// Il lock resta preso durante il calcolo, così richieste concorrenti
+    /// attendono lo stesso risultato invece di ripetere le verifiche.
+    pub async fn checks(
+        &self,
+        db: &DbPool,
+        queue: &JobQueue,
+        config: &Config,
+    ) -> Vec<ReadinessCheck> {
+        let mut state = self.inner.lock().await;
+        if let Some((at, checks)) = &state.result {
+            if at.elapsed() < RESULT_TTL {
+                return checks.clone();
+            }
+        }
+
+        let tools = match &state.tools {
+            Some((at, tools)) if at.elapsed() < TOOLS_TTL => tools.clone(),
+            _ => {
+                let tools = probe_tools(config.required_tools.clone()).await;
+                state.tools = Some((Instant::now(), tools.clone()));
+                tools
+            }
+        };
+
+        let checks = run_checks(db, queue, config, tools).await;
+        state.result = Some((Instant::now(), checks.clone()));
+        checks
+    }
+}
+
+/// Versioni ed encoder dei tool esterni, fuori dai worker async
+async fn probe_tools(required_tools: Vec<String>) -> Vec<ReadinessCheck> {
+    tokio::task::spawn_blocking(move || check_tools(&required_tools))
+        .await
+        .unwrap_or_else(|e| vec![failed("tools", format!("Verifica interrotta: {}", e))])
+}
+
+/// Esegue tutte le verifiche, con l'esito già noto dei tool esterni
+async fn run_checks(
+    db: &DbPool,
+    queue: &JobQueue,
+    config: &Config,
+    tools: Vec<ReadinessCheck>,
+) -> Vec<ReadinessCheck> {
+    let mut checks = vec![check_database(db).await];
+
+    let temp_dir = config.temp_dir.clone();
+    let min_free_mb = config.min_free_disk_mb;
+    // Filesystem: fuori dai worker async
+    let temp_dir_check =
+        tokio::task::spawn_blocking(move || check_temp_dir(&temp_dir, min_free_mb)).await;
+    checks.push(
+        temp_dir_check
+            .unwrap_or_else(|e| failed("temp_dir", format!("Verifica interrotta: {}", e))),
+    );
+    checks.extend(tools);
+
+    let (in_use, total) = queue.read().await.permits();
+    checks.push(check_queue(in_use, total, metrics::queue_depth()));
+
+    #[cfg(feature = "google-auth")]
+    checks.push(check_drive(config));
+
+    checks
+}
+
+/// Solo nome ed esito delle verifiche, senza messaggi e dettagli
+///
+/// Versioni, percorsi e spazio libero restano riservati a chi presenta il
+/// token delle metriche.
+pub fn redact(checks: Vec<ReadinessCheck>) -> Vec<ReadinessCheck> {
+    checks
+        .into_iter()
+        .map(|check| ReadinessCheck {
+            message: None,
+            details: serde_json::Value::Null,
+            ..check
+        })
+        .collect()
+}
+
+/// Esito complessivo: il peggiore tra quelli delle verifiche
+pub fn overall_status(checks: &[ReadinessCheck]) -> CheckStatus {
+    checks
+        .iter()
+        .map(|c| c.status)
+        .max()
+        .unwrap_or(CheckStatus::Ok)
+}
+
+async fn check_database(db: &DbPool) -> ReadinessCheck {
+    match tokio::time::timeout(DB_TIMEOUT, sqlx::query("SELECT 1").execute(db)).await {
+        Ok(Ok(_)) => passed("database", serde_json::Value::Null),
+        Ok(Err(e)) => failed("database", format!("Query di prova fallita: {}", e)),
+        Err(_) => failed(
+            "database",
+            format!("Nessuna risposta entro {}s", DB_TIMEOUT.as_secs()),
+        ),
+    }
+}
+
+fn check_temp_dir(dir: &Path, min_free_mb: u64) -> ReadinessCheck {
+    if let Err(e) = probe_write(dir) {
+        return failed(
+            "temp_dir",
+            format!("Directory {} non scrivibile: {}", dir.display(), e),
+        );
+    }
+
+    let Some(free_mb) = free_space_mb(dir) else {
+        return passed("temp_dir", json!({ "path": dir }));
+    };
+    let details = json!({
+        "path": dir,
+        "free_mb": free_mb,
+        "min_free_mb": min_free_mb,
+    });
+    if free_mb < min_free_mb {
+        return ReadinessCheck {
+            message: Some(format!(
+                "Spazio libero insufficiente: {} MB (minimo {} MB)",
+                free_mb, min_free_mb
+            )),
+            ..failed_with("temp_dir", details)
+        };
+    }
+    passed("temp_dir", details)
+}
+
+/// Crea e rimuove un file di prova
+fn probe_write(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe: PathBuf = dir.join(format!(".readiness-{}", uuid::Uuid::new_v4()));
+    std::fs::write(&probe, b"ok")?;
+    std::fs::remove_file(&probe)
+}
+
+#[cfg(unix)]
+fn free_space_mb(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` è una stringa C valida e `stat` una struct inizializzata
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64 / (1024 * 1024))
+}
+
+#[cfg(not(unix))]
+fn free_space_mb(_dir: &Path) -> Option<u64> {
+    None
+}
+
+fn check_tools(required: &[String]) -> Vec<ReadinessCheck> {
+    let is_required = |tool: &str| required.iter().any(|t| t == tool);
+    let mut checks = Vec::new();
+
+    // ffmpeg: versione ed encoder usati dalle conversioni
+    let ffmpeg = match tool_version("ffmpeg", "-version") {
+        Some(version) => {
+            let encoders = Command::new("ffmpeg")
+                .args(["-hide_banner", "-encoders"])
+                .output()
+                .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+                .unwrap_or_default();
+            let missing = missing_encoders(&encoders);
+            let details = json!({ "version": version, "missing_encoders": missing });
+            if missing.is_empty() {
+                passed("ffmpeg", details)
+            } else {
+                ReadinessCheck {
+                    message: Some(format!("Encoder mancanti: {}", missing.join(", "))),
+                    ..tool_problem("ffmpeg", is_required("ffmpeg"), details)
+                }
+            }
+        }
+        None => tool_missing("ffmpeg", is_required("ffmpeg")),
+    };
+    checks.push(ffmpeg);
+
+    // poppler-utils stampa la versione su stderr
+    for tool in ["pdftoppm", "pdfinfo"] {
+        checks.push(match tool_version(tool, "-v") {
+            Some(version) => passed(tool, json!({ "version": version })),
+            None => tool_missing(tool, is_required(tool)),
+        });
+    }
+
+    checks
+}
+
+/// Versione di un tool, `None` se non è eseguibile
+fn tool_version(tool: &str, flag: &str) -> Option<String> {
+    let output = Command::new(tool).arg(flag).output().ok()?;
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(parse_version(&text).unwrap_or_else(|| "sconosciuta".to_string()))
+}
+
+/// Estrae la versione da output del tipo `ffmpeg version 6.1.1-3ubuntu5 Copyright ...`
+fn parse_version(output: &str) -> Option<String> {
+    let first = output.lines().find(|l| l.contains("version"))?;
+    let mut words = first.split_whitespace();
+    words.find(|w| *w == "version")?;
+    words.next().map(str::to_string)
+}
+
+/// Encoder richiesti assenti dall'output di `ffmpeg -encoders`
+fn missing_encoders(encoders_output: &str) -> Vec<&'static str> {
+    // Righe del tipo ` V....D libx264   libx264 H.264 / AVC ...`
+    let available: Vec<&str> = encoders_output
+        .lines()
+        .filter_map(|l| l.split_whitespace().nth(1))
+        .collect();
+    REQUIRED_ENCODERS
+        .iter()
+        .copied()
+        .filter(|e| !available.contains(e))
+        .collect()
+}
+
+fn check_queue(in_use: usize, total: usize, waiting: u64) -> ReadinessCheck {
+    let details = json!({
+        "permits_in_use": in_use,
+        "permits_total": total,
+        "waiting": waiting,
+    });
+    let limit = total as u64 * QUEUE_SATURATION_FACTOR;
+    if waiting >= limit {
+        ReadinessCheck {
+            message: Some(format!("Coda satura: {} job in attesa", waiting)),
+            ..failed_with("queue", details)
+        }
+    } else if in_use >= total {
+        ReadinessCheck {
+            status: CheckStatus::Warn,
+            message: Some("Tutti i permessi di esecuzione sono in uso".to_string()),
+            ..passed("queue", details)
+        }
+    } else {
+        passed("queue", details)
+    }
+}
+
+/// Credenziali Google usate per il caricamento dei risultati su Drive
+#[cfg(feature = "google-auth")]
+fn check_drive(config: &Config) -> ReadinessCheck {
+    let set = |v: &Option<String>| v.as_deref().is_some_and(|s| !s.is_empty());
+    match (
+        set(&config.google_client_id),
+        set(&config.google_client_secret),
+    ) {
+        (true, true) => passed("drive", json!({ "configured": true })),
+        (false, false) => ReadinessCheck {
+            message: Some(
+                "Credenziali Google non configurate: upload su Drive disattivato".to_string(),
+            ),
+            ..passed("drive", json!({ "configured": false }))
+        },
+        _ => ReadinessCheck {
+            message: Some(
+                "Configurazione Google incompleta: servono GOOGLE_CLIENT_ID e GOOGLE_CLIENT_SECRET"
+                    .to_string(),
+            ),
+            ..failed_with("drive", json!({ "configured": false }))
+        },
+    }
+}
+
+fn tool_missing(tool: &str, required: bool) -> ReadinessCheck {
+    ReadinessCheck {
+        message: Some(format!("{} non disponibile", tool)),
+        ..tool_problem(tool, required, serde_json::Value::Null)
+    }
+}
+
+/// Problema di un tool: blocca il nodo solo se il tool è richiesto
+fn tool_problem(tool: &str, required: bool, details: serde_json::Value) -> ReadinessCheck {
+    ReadinessCheck {
+        name: tool.to_string(),
+        status: if required {
+            CheckStatus::Fail
+        } else {
+            CheckStatus::Warn
+        },
+        message: None,
+        details,
+    }
+}
+
+fn passed(name: &str, details: serde_json::Value) -> ReadinessCheck {
+    ReadinessCheck {
+        name: name.to_string(),
+        status: CheckStatus::Ok,
+        message: None,
+        details,
+    }
+}
+
+fn failed(name: &str, message: String) -> ReadinessCheck {
+    ReadinessCheck {
+        message: Some(message),
+        ..failed_with(name, serde_json::Value::Null)
+    }
+}
+
+fn failed_with(name: &str, details: serde_json::Value) -> ReadinessCheck {
+    ReadinessCheck {
+        name: name.to_string(),
+        status: CheckStatus::Fail,
+        message: None,
+        details,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tool_output() {
+        assert_eq!(
+            parse_version("ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023\nbuilt with gcc"),
+            Some("6.1.1-3ubuntu5".to_string())
+        );
+        assert_eq!(
+            parse_version("pdftoppm version 24.02.0\nCopyright 2005-2024"),
+            Some("24.02.0".to_string())
+        );
+        assert_eq!(parse_version("no info"), None);
+
+        let encoders = "Encoders:\n ------\n V....D libx264  libx264 H.264\n A....D aac  AAC\n \
+                        A....D libopus  libopus Opus\n V....D mpeg4  MPEG-4 part 2\n";
+        assert_eq!(missing_encoders(encoders), vec!["libvpx-vp9", "libmp3lame"]);
+    }
+
+    #[test]
+    fn test_queue_saturation_and_overall_status() {
+        assert_eq!(check_queue(1, 4, 0).status, CheckStatus::Ok);
+        assert_eq!(check_queue(4, 4, 3).status, CheckStatus::Warn);
+        assert_eq!(check_queue(4, 4, 16).status, CheckStatus::Fail);
+
+        let checks = vec![
+            passed("database", serde_json::Value::Null),
+            tool_missing("ffmpeg", false),
+        ];
+        assert_eq!(overall_status(&checks), CheckStatus::Warn);
+        assert_eq!(
+            overall_status(&[tool_missing("ffmpeg", true)]),
+            CheckStatus::Fail
+        );
+        assert_eq!(overall_status(&[]), CheckStatus::Ok);
+
+        let redacted = redact(vec![tool_missing("ffmpeg", true)]);
+        assert_eq!(redacted[0].name, "ffmpeg");
+        assert_eq!(redacted[0].status, CheckStatus::Fail);
+        assert!(redacted[0].message.is_none() && redacted[0].details.is_null());
+    }
+}
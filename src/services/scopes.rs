@@ -0,0 +1,118 @@
+//! Verifica degli scope delle API Key nei handler
+//!
+//! Gruppi di route e chiavi in sola lettura sono controllati dal middleware
+//! di autenticazione; qui restano i controlli che dipendono dalla richiesta:
+//! tipo di conversione, dominio del `source_url`, risoluzione e durata dell'input.
+
+use std::path::Path;
+
+use reqwest::Url;
+
+use crate::error::{AppError, Result};
+use crate::handlers::media;
+use crate::models::{AuthInfo, ConversionType};
+use crate::services::converter;
+
+/// Verifica che il tipo di conversione sia consentito all'API Key
+pub fn check_conversion_scope(auth: &AuthInfo, conversion_type: &str) -> Result<()> {
+    if auth.scopes.allows_type(conversion_type) {
+        return Ok(());
+    }
+    Err(AppError::Forbidden(format!(
+        "Tipo conversione '{}' non permesso per questa API Key. Tipi permessi: {}",
+        conversion_type,
+        auth.scopes.allowed_types.join(", ")
+    )))
+}
+
+/// Verifica che il dominio del `source_url` sia consentito all'API Key
+pub fn check_source_url_scope(auth: &AuthInfo, source_url: &str) -> Result<()> {
+    if auth.scopes.allowed_source_domains.is_empty() {
+        return Ok(());
+    }
+    let url = Url::parse(source_url)
+        .map_err(|_| AppError::BadRequest(format!("URL non valido: {}", source_url)))?;
+    let host = url.host_str().unwrap_or_default();
+    if auth.scopes.allows_source_host(host) {
+        return Ok(());
+    }
+    Err(AppError::Forbidden(format!(
+        "Dominio '{}' non permesso per questa API Key",
+        host
+    )))
+}
+
+/// Verifica le dimensioni richieste per l'output di un'immagine
+pub fn check_output_resolution(
+    auth: &AuthInfo,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Result<()> {
+    let (width, height) = (width.unwrap_or(0), height.unwrap_or(0));
+    if auth.scopes.allows_resolution(width, height) {
+        return Ok(());
+    }
+    Err(resolution_error(auth, width, height))
+}
+
+/// Verifica risoluzione e durata del file di input
+///
+/// I probe (header dell'immagine, ffprobe) girano in un thread bloccante per
+/// non fermare il runtime. Restituisce la durata audio/video (0 per gli altri
+/// tipi), da riusare per le quote senza un secondo probe. I valori che non si
+/// riescono a leggere non bloccano la richiesta: la conversione fallirà
+/// comunque se il file non è valido.
+pub async fn check_input_scope(
+    auth: &AuthInfo,
+    conversion_type: &ConversionType,
+    input_path: &Path,
+) -> Result<i64> {
+    let scopes = &auth.scopes;
+
+    if scopes.max_resolution.is_some()
+        && matches!(
+            conversion_type,
+            ConversionType::Image | ConversionType::Video
+        )
+    {
+        let is_image = *conversion_type == ConversionType::Image;
+        let path = input_path.to_path_buf();
+        let resolution = tokio::task::spawn_blocking(move || {
+            if is_image {
+                image::image_dimensions(&path).ok()
+            } else {
+                media::probe_resolution(&path)
+            }
+        })
+        .await
+        .ok()
+        .flatten();
+        if let Some((width, height)) = resolution {
+            if !scopes.allows_resolution(width, height) {
+                return Err(resolution_error(auth, width, height));
+            }
+        }
+    }
+
+    let duration_ms = converter::media_duration_ms(conversion_type, input_path).await;
+    if let Some(max_secs) = scopes.max_duration_secs {
+        if !scopes.allows_duration_ms(duration_ms) {
+            return Err(AppError::Forbidden(format!(
+                "Durata {}s oltre il massimo di {}s consentito per questa API Key",
+                duration_ms / 1000,
+                max_secs
+            )));
+        }
+    }
+
+    Ok(duration_ms)
+}
+
+fn resolution_error(auth: &AuthInfo, width: u32, height: u32) -> AppError {
+    AppError::Forbidden(format!(
+        "Risoluzione {}x{} oltre il massimo di {}px consentito per questa API Key",
+        width,
+        height,
+        auth.scopes.max_resolution.unwrap_or_default()
+    ))
+}
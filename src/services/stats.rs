@@ -124,6 +124,16 @@ impl StatsServiceInner {
                 .iter()
                 .filter(|r| r.conversion_type == "video")
                 .count() as u64,
+            pdf: self
+                .records
+                .iter()
+                .filter(|r| r.conversion_type == "pdf")
+                .count() as u64,
+            svg: self
+                .records
+                .iter()
+                .filter(|r| r.input_format == "svg")
+                .count() as u64,
         };
 
         // Stats per formato
@@ -166,6 +176,7 @@ impl StatsServiceInner {
             by_format,
             last_24h,
             last_hour,
+            ..Default::default()
         }
     }
 
@@ -235,7 +246,7 @@ impl StatsServiceInner {
         }
 
         // Ordina per timestamp decrescente e prendi limit
-        records.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        records.sort_by_key(|r| std::cmp::Reverse(r.timestamp));
         records.truncate(query.limit);
 
         records.into_iter().map(ConversionSummary::from).collect()
@@ -246,6 +257,7 @@ impl StatsServiceInner {
         StatsResponse {
             global: self.get_global_stats(),
             api_key_stats: api_key.and_then(|k| self.get_api_key_stats(k)),
+            organization_stats: None,
             recent_conversions: self.get_recent_conversions(query),
             server_uptime_seconds: self.start_time.elapsed().as_secs(),
             generated_at: Utc::now(),
@@ -267,13 +279,13 @@ impl StatsServiceInner {
             .into_iter()
             .map(|(format, count)| FormatCount { format, count })
             .collect();
-        input_formats.sort_by(|a, b| b.count.cmp(&a.count));
+        input_formats.sort_by_key(|f| std::cmp::Reverse(f.count));
 
         let mut output_formats: Vec<_> = output_counts
             .into_iter()
             .map(|(format, count)| FormatCount { format, count })
             .collect();
-        output_formats.sort_by(|a, b| b.count.cmp(&a.count));
+        output_formats.sort_by_key(|f| std::cmp::Reverse(f.count));
 
         FormatStats {
             input_formats,
@@ -0,0 +1,207 @@
+//! Storage su filesystem locale
+
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use super::{ByteStream, Storage};
+use crate::error::{AppError, Result};
+
+/// Storage su disco locale, con le chiavi relative a `root`
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        std::fs::create_dir_all(&root).ok();
+        Self { root }
+    }
+
+    /// Risolve una chiave in un percorso sotto `root`.
+    ///
+    /// I job creati prima dell'introduzione dello storage hanno nel database
+    /// un percorso assoluto: `join` lo restituisce invariato.
+    fn resolve(&self, key: &str) -> Result<PathBuf> {
+        let path = Path::new(key);
+        if path
+            .components()
+            .any(|c| matches!(c, std::path::Component::ParentDir))
+        {
+            return Err(AppError::Internal(format!(
+                "Chiave storage non valida: {}",
+                key
+            )));
+        }
+        Ok(self.root.join(path))
+    }
+
+    async fn ensure_parent(path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        Ok(())
+    }
+}
+
+fn not_found(key: &str, e: std::io::Error) -> AppError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        AppError::NotFound(key.to_string())
+    } else {
+        AppError::IoError(e)
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Bytes) -> Result<()> {
+        let path = self.resolve(key)?;
+        Self::ensure_parent(&path).await?;
+        tokio::fs::write(&path, &data).await?;
+        Ok(())
+    }
+
+    async fn put_file(&self, key: &str, source: &Path) -> Result<u64> {
+        let path = self.resolve(key)?;
+        Self::ensure_parent(&path).await?;
+
+        if source != path {
+            // rename fallisce tra filesystem diversi: in quel caso copia
+            if tokio::fs::rename(source, &path).await.is_err() {
+                tokio::fs::copy(source, &path).await?;
+                tokio::fs::remove_file(source).await.ok();
+            }
+        }
+
+        Ok(tokio::fs::metadata(&path).await?.len())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let path = self.resolve(key)?;
+        tokio::fs::read(&path).await.map_err(|e| not_found(key, e))
+    }
+
+    async fn stream(&self, key: &str) -> Result<ByteStream> {
+        let path = self.resolve(key)?;
+        let file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        Ok(Box::pin(ReaderStream::new(file)))
+    }
+
+    async fn stream_range(&self, key: &str, offset: u64, len: u64) -> Result<ByteStream> {
+        let path = self.resolve(key)?;
+        let mut file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        Ok(Box::pin(ReaderStream::new(file.take(len))))
+    }
+
+    async fn size(&self, key: &str) -> Result<u64> {
+        let path = self.resolve(key)?;
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        Ok(metadata.len())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = self.resolve(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+
+        // Rimuovi la directory del job se è rimasta vuota
+        if let Some(parent) = path.parent() {
+            if parent != self.root {
+                tokio::fs::remove_dir(parent).await.ok();
+            }
+        }
+        Ok(())
+    }
+
+    fn presign(&self, _key: &str, _expires_in: Duration) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        self.resolve(key).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_put_get_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = LocalStorage::new(dir.path().to_path_buf());
+
+        storage
+            .put("jobs/abc/input.txt", Bytes::from_static(b"ciao"))
+            .await
+            .unwrap();
+        assert_eq!(storage.get("jobs/abc/input.txt").await.unwrap(), b"ciao");
+
+        let mut stream = storage.stream("jobs/abc/input.txt").await.unwrap();
+        let mut streamed = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            streamed.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(streamed, b"ciao");
+
+        let mut stream = storage
+            .stream_range("jobs/abc/input.txt", 1, 2)
+            .await
+            .unwrap();
+        let mut ranged = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            ranged.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(ranged, b"ia");
+        assert_eq!(storage.size("jobs/abc/input.txt").await.unwrap(), 4);
+
+        storage.delete("jobs/abc/input.txt").await.unwrap();
+        assert!(matches!(
+            storage.get("jobs/abc/input.txt").await,
+            Err(AppError::NotFound(_))
+        ));
+        assert!(!dir.path().join("jobs/abc").exists());
+        // Eliminare una chiave inesistente non è un errore
+        storage.delete("jobs/abc/input.txt").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_put_file_moves_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = LocalStorage::new(dir.path().join("store"));
+        let source = dir.path().join("output.png");
+        std::fs::write(&source, b"png").unwrap();
+
+        let size = storage
+            .put_file("jobs/x/output.png", &source)
+            .await
+            .unwrap();
+        assert_eq!(size, 3);
+        assert!(!source.exists());
+        assert_eq!(storage.get("jobs/x/output.png").await.unwrap(), b"png");
+    }
+
+    #[test]
+    fn test_rejects_parent_components() {
+        let storage = LocalStorage::new(std::env::temp_dir().join("converty-test"));
+        assert!(storage.resolve("../etc/passwd").is_err());
+        assert!(storage.resolve("jobs/../../x").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Storage per input e risultati dei job
+//!
+//! I file dei job sono identificati da chiavi relative (`jobs/<id>/input.png`)
+//! salvate nel database. Il backend concreto (filesystem locale o object
+//! storage S3-compatibile) viene scelto tramite [`Config`].
+
+mod local;
+mod s3;
+
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use tokio::io::AsyncWriteExt;
+
+use crate::config::{Config, StorageBackend};
+use crate::error::Result;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+/// Stream di byte letti dallo storage
+pub type ByteStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
+
+/// Storage condiviso tra queue, route e task di background
+pub type SharedStorage = Arc<dyn Storage>;
+
+/// Backend di storage per i file dei job
+#[async_trait]
+pub trait Storage: Send + Sync + std::fmt::Debug {
+    /// Salva dati in memoria sotto `key`
+    async fn put(&self, key: &str, data: Bytes) -> Result<()>;
+
+    /// Sposta un file locale nello storage sotto `key`.
+    /// Il file sorgente non è più disponibile dopo la chiamata.
+    async fn put_file(&self, key: &str, path: &Path) -> Result<u64>;
+
+    /// Legge l'intero contenuto di `key`
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Legge `key` come stream
+    async fn stream(&self, key: &str) -> Result<ByteStream>;
+
+    /// Legge `len` byte di `key` a partire da `offset`
+    async fn stream_range(&self, key: &str, offset: u64, len: u64) -> Result<ByteStream>;
+
+    /// Dimensione in byte di `key`
+    async fn size(&self, key: &str) -> Result<u64>;
+
+    /// Elimina `key` (nessun errore se non esiste)
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// URL temporaneo per scaricare `key` direttamente dallo storage.
+    /// `None` se il backend non lo supporta (es. filesystem locale).
+    fn presign(&self, key: &str, expires_in: Duration) -> Result<Option<String>>;
+
+    /// Percorso su disco di `key`, se il backend è locale
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Crea il backend di storage configurato
+pub fn from_config(config: &Config) -> Result<SharedStorage> {
+    match &config.storage {
+        StorageBackend::Local => Ok(Arc::new(LocalStorage::new(config.temp_dir.clone()))),
+        StorageBackend::S3(s3) => Ok(Arc::new(S3Storage::new(s3.clone())?)),
+    }
+}
+
+/// Chiave dell'input di un job
+pub fn input_key(job_id: &str, input_format: &str) -> String {
+    format!("jobs/{}/input.{}", job_id, input_format)
+}
+
+/// Chiave del risultato di un job (mantiene il nome file prodotto dal converter)
+pub fn result_key(job_id: &str, filename: &str) -> String {
+    format!("jobs/{}/{}", job_id, filename)
+}
+
+/// File dello storage disponibile su disco locale
+///
+/// Per il backend locale punta direttamente al file; per gli altri backend
+/// è una copia temporanea che viene rimossa al drop.
+#[derive(Debug)]
+pub struct LocalFile {
+    path: PathBuf,
+    _temp: Option<tempfile::TempPath>,
+}
+
+impl LocalFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Rende disponibile `key` su disco, scaricandolo in `scratch_dir` se necessario
+pub async fn fetch_to_local(
+    storage: &dyn Storage,
+    key: &str,
+    scratch_dir: &Path,
+) -> Result<LocalFile> {
+    if let Some(path) = storage.local_path(key) {
+        return Ok(LocalFile { path, _temp: None });
+    }
+
+    std::fs::create_dir_all(scratch_dir)?;
+    let suffix = Path::new(key)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| format!(".{}", e))
+        .unwrap_or_default();
+    let temp = tempfile::Builder::new()
+        .suffix(&suffix)
+        .tempfile_in(scratch_dir)?
+        .into_temp_path();
+
+    let mut stream = storage.stream(key).await?;
+    let mut file = tokio::fs::File::create(&temp).await?;
+    while let Some(chunk) = stream.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
+
+    Ok(LocalFile {
+        path: temp.to_path_buf(),
+        _temp: Some(temp),
+    })
+}
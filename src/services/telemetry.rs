@@ -0,0 +1,140 @@
+//! Logging e tracce distribuite
+//!
+//! I log vanno sempre su stdout. Con la feature `otel` e un endpoint OTLP
+//! configurato (`OTEL_EXPORTER_OTLP_ENDPOINT` o
+//! `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) gli span vengono esportati anche via
+//! OTLP/HTTP: richieste, autenticazione, job e tool esterni finiscono nella
+//! stessa trace, e un `traceparent` ricevuto collega la trace a quella del
+//! chiamante.
+
+use axum::http::HeaderMap;
+use tracing::Span;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Nome del servizio nelle tracce se `OTEL_SERVICE_NAME` non è impostato
+#[cfg(feature = "otel")]
+const DEFAULT_SERVICE_NAME: &str = "converty";
+
+/// Telemetria attiva; va chiusa con [`Telemetry::shutdown`] per inviare gli
+/// span ancora in coda
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+/// Inizializza il subscriber globale di `tracing`
+pub fn init() -> Telemetry {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "converty=info,tower_http=info".into());
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer());
+
+    #[cfg(feature = "otel")]
+    {
+        let (provider, error) = match otlp_provider() {
+            Ok(provider) => (provider, None),
+            Err(e) => (None, Some(e)),
+        };
+        let layer = provider.as_ref().map(|provider| {
+            use opentelemetry::trace::TracerProvider as _;
+            tracing_opentelemetry::layer().with_tracer(provider.tracer("converty"))
+        });
+        registry.with(layer).init();
+
+        match (&provider, error) {
+            (Some(_), _) => tracing::info!("Export tracce OTLP attivo"),
+            (None, Some(e)) => tracing::warn!("Export tracce OTLP non disponibile: {}", e),
+            (None, None) => {}
+        }
+        Telemetry { provider }
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.init();
+        Telemetry {}
+    }
+}
+
+impl Telemetry {
+    /// Invia gli span rimasti e chiude l'exporter
+    pub fn shutdown(self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!("Errore chiusura exporter OTLP: {}", e);
+            }
+        }
+    }
+}
+
+/// Collega lo span al contesto di trace W3C (`traceparent`) degli header
+pub fn set_parent_from_headers(span: &Span, headers: &HeaderMap) {
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        if !headers.contains_key("traceparent") {
+            return;
+        }
+        let cx = opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(headers))
+        });
+        let _ = span.set_parent(cx);
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, headers);
+}
+
+/// Provider OTLP, se è configurato un endpoint
+#[cfg(feature = "otel")]
+fn otlp_provider() -> Result<Option<opentelemetry_sdk::trace::SdkTracerProvider>, String> {
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+
+    let configured = [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ]
+    .iter()
+    .any(|var| std::env::var(var).is_ok_and(|v| !v.is_empty()));
+    if !configured {
+        return Ok(None);
+    }
+
+    // Endpoint, header e timeout vengono letti dalle variabili OTEL_EXPORTER_OTLP_*
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut resource = Resource::builder();
+    if std::env::var("OTEL_SERVICE_NAME").is_err() {
+        resource = resource.with_service_name(DEFAULT_SERVICE_NAME);
+    }
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    Ok(Some(provider))
+}
+
+/// Lettura degli header HTTP per il propagatore W3C
+#[cfg(feature = "otel")]
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+#[cfg(feature = "otel")]
+impl opentelemetry::propagation::Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
@@ -0,0 +1,282 @@
+//! Risoluzione dell'IP del client dietro reverse proxy
+//!
+//! Viene letto un solo header, quello impostato dal proxy fidato
+//! (`CONVERTY_CLIENT_IP_HEADER`), e solo se la connessione arriva da un proxy
+//! fidato: gli altri header potrebbero essere stati inviati dal client e
+//! inoltrati invariati. La catena viene letta da destra verso sinistra
+//! saltando i proxy fidati: il primo indirizzo non fidato è il client. Così un
+//! client non può falsificare il proprio IP aggiungendo voci in testa
+//! all'header.
+
+use std::net::{IpAddr, Ipv6Addr};
+
+use axum::http::{header, HeaderMap, HeaderName};
+use ipnet::IpNet;
+
+/// Header con cui il proxy fidato comunica l'IP del client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For` (nginx, HAProxy, la maggior parte dei load balancer)
+    #[default]
+    XForwardedFor,
+    /// `Forwarded` (RFC 7239)
+    Forwarded,
+    /// `X-Real-IP`
+    XRealIp,
+}
+
+impl ForwardedHeader {
+    /// Interpreta il nome dell'header, senza distinzione tra maiuscole e minuscole
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "x-forwarded-for" => Some(Self::XForwardedFor),
+            "forwarded" => Some(Self::Forwarded),
+            "x-real-ip" => Some(Self::XRealIp),
+            _ => None,
+        }
+    }
+}
+
+/// Interpreta un IP (`10.0.0.1`) o una rete CIDR (`10.0.0.0/8`)
+pub fn parse_ip_net(entry: &str) -> Option<IpNet> {
+    let entry = entry.trim();
+    entry
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| entry.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Interpreta una lista di proxy fidati separati da virgola (IP o CIDR)
+///
+/// Le voci non valide vengono ignorate con un warning.
+pub fn parse_trusted_proxies(value: &str) -> Vec<IpNet> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .filter_map(|entry| {
+            let parsed = parse_ip_net(entry);
+            if parsed.is_none() {
+                tracing::warn!("Proxy fidato non valido ignorato: '{}'", entry);
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// IP del client per una connessione da `peer`
+///
+/// Viene considerato solo `source`, anche se la richiesta contiene altri
+/// header di inoltro.
+pub fn resolve_client_ip(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    trusted: &[IpNet],
+    source: ForwardedHeader,
+) -> IpAddr {
+    let peer = canonical(peer);
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let chain = forwarded_chain(headers, source);
+    let mut client = peer;
+    for hop in chain.iter().rev() {
+        // Una voce illeggibile interrompe la catena: non ci si fida di ciò che precede
+        let Some(ip) = parse_hop(hop) else {
+            break;
+        };
+        client = ip;
+        if !is_trusted(&ip) {
+            break;
+        }
+    }
+    client
+}
+
+/// Chiave con cui contare l'uso guest di un IP
+///
+/// Gli IPv6 sono raggruppati per /64 (la rete assegnata a un singolo
+/// utente), così cambiare indirizzo nella stessa rete non azzera il limite.
+pub fn guest_bucket(ip: IpAddr) -> String {
+    match canonical(ip) {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            let network = Ipv6Addr::new(s[0], s[1], s[2], s[3], 0, 0, 0, 0);
+            format!("{}/64", network)
+        }
+    }
+}
+
+/// Indirizzi inoltrati in `source`, dal più vecchio (client) al più recente
+fn forwarded_chain(headers: &HeaderMap, source: ForwardedHeader) -> Vec<String> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|s| s.trim().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    match source {
+        ForwardedHeader::XForwardedFor => values(HeaderName::from_static("x-forwarded-for")),
+        ForwardedHeader::XRealIp => values(HeaderName::from_static("x-real-ip")),
+        // RFC 7239: `Forwarded: for=192.0.2.1;proto=https, for="[2001:db8::1]:443"`
+        ForwardedHeader::Forwarded => values(header::FORWARDED)
+            .into_iter()
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.trim().split_once('=')?;
+                    key.eq_ignore_ascii_case("for")
+                        .then(|| value.trim().trim_matches('"').to_string())
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Estrae l'IP da una voce (`1.2.3.4`, `1.2.3.4:80`, `[::1]:80`, `::1`)
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    if let Ok(ip) = hop.parse::<IpAddr>() {
+        return Some(canonical(ip));
+    }
+    let host = match hop.strip_prefix('[') {
+        Some(rest) => rest.split(']').next()?,
+        None => hop.rsplit_once(':')?.0,
+    };
+    host.parse::<IpAddr>().ok().map(canonical)
+}
+
+/// Converte gli IPv4 mappati in IPv6 (`::ffff:1.2.3.4`) in IPv4
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const XFF: ForwardedHeader = ForwardedHeader::XForwardedFor;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_headers() {
+        let trusted = parse_trusted_proxies("10.0.0.0/8");
+        let h = headers(&[("x-forwarded-for", "1.1.1.1")]);
+        let peer: IpAddr = "203.0.113.5".parse().unwrap();
+        assert_eq!(resolve_client_ip(peer, &h, &trusted, XFF), peer);
+    }
+
+    #[test]
+    fn test_trusted_chain_skips_proxies_and_spoofed_entries() {
+        let trusted = parse_trusted_proxies("10.0.0.0/8, 192.168.1.1");
+        let peer: IpAddr = "10.0.0.2".parse().unwrap();
+
+        // Il client ha aggiunto "6.6.6.6" in testa: viene ignorato
+        let h = headers(&[("x-forwarded-for", "6.6.6.6, 198.51.100.7, 192.168.1.1")]);
+        assert_eq!(
+            resolve_client_ip(peer, &h, &trusted, XFF),
+            "198.51.100.7".parse::<IpAddr>().unwrap()
+        );
+
+        let h = headers(&[(
+            "forwarded",
+            "for=\"[2001:db8::1]:4711\";proto=https, for=10.1.1.1",
+        )]);
+        assert_eq!(
+            resolve_client_ip(peer, &h, &trusted, ForwardedHeader::Forwarded),
+            "2001:db8::1".parse::<IpAddr>().unwrap()
+        );
+
+        let h = headers(&[("x-real-ip", "198.51.100.8")]);
+        assert_eq!(
+            resolve_client_ip(peer, &h, &trusted, ForwardedHeader::XRealIp),
+            "198.51.100.8".parse::<IpAddr>().unwrap()
+        );
+
+        let h = headers(&[("x-forwarded-for", "unknown")]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted, XFF), peer);
+    }
+
+    #[test]
+    fn test_spoofed_header_behind_xff_proxy_is_ignored() {
+        let trusted = parse_trusted_proxies("10.0.0.0/8");
+        let peer: IpAddr = "10.0.0.2".parse().unwrap();
+
+        // Il proxy imposta solo X-Forwarded-For e inoltra gli altri header così come sono
+        let h = headers(&[
+            ("forwarded", "for=6.6.6.6"),
+            ("x-real-ip", "7.7.7.7"),
+            ("x-forwarded-for", "198.51.100.7"),
+        ]);
+        assert_eq!(
+            resolve_client_ip(peer, &h, &trusted, XFF),
+            "198.51.100.7".parse::<IpAddr>().unwrap()
+        );
+
+        // Senza X-Forwarded-For l'header falsificato non viene usato
+        let h = headers(&[("forwarded", "for=6.6.6.6")]);
+        assert_eq!(resolve_client_ip(peer, &h, &trusted, XFF), peer);
+    }
+
+    #[test]
+    fn test_parse_forwarded_header() {
+        assert_eq!(
+            ForwardedHeader::parse("X-Forwarded-For"),
+            Some(ForwardedHeader::XForwardedFor)
+        );
+        assert_eq!(
+            ForwardedHeader::parse(" forwarded "),
+            Some(ForwardedHeader::Forwarded)
+        );
+        assert_eq!(
+            ForwardedHeader::parse("x-real-ip"),
+            Some(ForwardedHeader::XRealIp)
+        );
+        assert_eq!(ForwardedHeader::parse("cf-connecting-ip"), None);
+    }
+
+    #[test]
+    fn test_parse_ip_net() {
+        let net = parse_ip_net("203.0.113.0/24").unwrap();
+        assert!(net.contains(&"203.0.113.9".parse::<IpAddr>().unwrap()));
+        let single = parse_ip_net(" 2001:db8::1 ").unwrap();
+        assert!(single.contains(&"2001:db8::1".parse::<IpAddr>().unwrap()));
+        assert!(!single.contains(&"2001:db8::2".parse::<IpAddr>().unwrap()));
+        assert!(parse_ip_net("example.com").is_none());
+    }
+
+    #[test]
+    fn test_guest_bucket() {
+        assert_eq!(
+            guest_bucket("198.51.100.7".parse().unwrap()),
+            "198.51.100.7"
+        );
+        assert_eq!(
+            guest_bucket("::ffff:198.51.100.7".parse().unwrap()),
+            "198.51.100.7"
+        );
+        assert_eq!(
+            guest_bucket("2001:db8:1:2:aaaa:bbbb:cccc:dddd".parse().unwrap()),
+            "2001:db8:1:2::/64"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! Scrittura di righe CSV (RFC 4180)
+
+/// Unisce i campi in una riga CSV terminata da `\r\n`
+pub fn csv_line<S: AsRef<str>>(fields: &[S]) -> String {
+    let mut line = fields
+        .iter()
+        .map(|f| csv_field(f.as_ref()))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+/// Escape di un campo CSV
+///
+/// I campi con separatori, virgolette o a capo vanno tra virgolette. I testi
+/// che iniziano con `=`, `+`, `-` o `@` vengono prefissati con `'` perché i
+/// fogli di calcolo li interpreterebbero come formule.
+pub fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_escaping() {
+        assert_eq!(csv_field("png"), "png");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\"\nbye"), "\"say \"\"hi\"\"\nbye\"");
+        assert_eq!(csv_field("=SUM(A1)"), "'=SUM(A1)");
+        assert_eq!(csv_field("-1,5"), "\"'-1,5\"");
+        assert_eq!(csv_line(&["id", "", "x,y"]), "id,,\"x,y\"\r\n");
+    }
+}
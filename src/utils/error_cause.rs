@@ -0,0 +1,124 @@
+//! Normalizzazione dei messaggi di errore delle conversioni
+//!
+//! Lo stesso problema produce messaggi diversi per percorso del file, ID del
+//! job o dimensioni. Per raggrupparli si tengono categoria e ultima riga
+//! significativa (dove ffmpeg e poppler scrivono la causa) e si sostituiscono
+//! le parti variabili con segnaposto.
+
+/// Lunghezza massima di una causa normalizzata
+const MAX_CAUSE_LEN: usize = 160;
+
+/// Riduce un messaggio di errore alla sua causa
+pub fn normalize_error(message: &str) -> String {
+    let lines: Vec<&str> = message
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    // Messaggi multiriga (stderr dei tool): categoria + ultima riga
+    let text = match lines.as_slice() {
+        [] => return "errore sconosciuto".to_string(),
+        [single] => single.to_string(),
+        [first, .., last] => {
+            let category = first.split_once(": ").map_or(*first, |(c, _)| c);
+            format!("{}: {}", category, last)
+        }
+    };
+
+    let mut cause = text
+        .split_whitespace()
+        .map(normalize_token)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if cause.chars().count() > MAX_CAUSE_LEN {
+        cause = cause.chars().take(MAX_CAUSE_LEN).collect::<String>() + "…";
+    }
+    cause
+}
+
+fn normalize_token(token: &str) -> String {
+    // Virgolette, parentesi e punteggiatura (`file.mp4:`) restano fuori dal segnaposto
+    let trimmed = token.trim_end_matches([':', ',', ';', '.', ')', '\'', '"']);
+    let suffix = &token[trimmed.len()..];
+    let core = trimmed.trim_start_matches(['(', '\'', '"']);
+    let prefix = &trimmed[..trimmed.len() - core.len()];
+
+    let replaced = if core.contains('/') || core.contains('\\') {
+        "<path>".to_string()
+    } else if is_uuid(core) {
+        "<id>".to_string()
+    } else if core.len() >= 16 && core.chars().all(|c| c.is_ascii_hexdigit()) {
+        "<hex>".to_string()
+    } else if core.starts_with(|c: char| c.is_ascii_digit()) {
+        // Solo i token che iniziano con una cifra: `mp3` o `h264` restano intatti
+        replace_numbers(core)
+    } else {
+        return token.to_string();
+    };
+    format!("{}{}{}", prefix, replaced, suffix)
+}
+
+fn is_uuid(s: &str) -> bool {
+    s.len() == 36
+        && s.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Sostituisce ogni sequenza numerica (anche decimale) con `<n>`
+fn replace_numbers(s: &str) -> String {
+    let mut out = String::new();
+    let mut in_number = false;
+    for c in s.chars() {
+        if c.is_ascii_digit() || (in_number && c == '.') {
+            if !in_number {
+                out.push_str("<n>");
+                in_number = true;
+            }
+        } else {
+            in_number = false;
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variable_parts_are_replaced() {
+        assert_eq!(
+            normalize_error("File troppo grande: massimo 50 MB"),
+            "File troppo grande: massimo <n> MB"
+        );
+        assert_eq!(
+            normalize_error(
+                "Job non trovato: 3f2b8c1e-1d2a-4c3b-9a8e-123456789abc (1920x1080, 12.5s)"
+            ),
+            "Job non trovato: <id> (<n>x<n>, <n>s)"
+        );
+        assert_eq!(
+            normalize_error("Formato non supportato: mp3"),
+            "Formato non supportato: mp3"
+        );
+    }
+
+    #[test]
+    fn test_tool_output_keeps_category_and_last_line() {
+        let a = "FFmpeg fallito: ffmpeg version 6.1 Copyright\n  built with gcc\n\
+                 /tmp/converty/work/abc/input.mp3: Invalid data found when processing input\n";
+        let b = "FFmpeg fallito: ffmpeg version 7.0\n\
+                 /tmp/other/input.mp3: Invalid data found when processing input";
+        assert_eq!(
+            normalize_error(a),
+            "FFmpeg fallito: <path>: Invalid data found when processing input"
+        );
+        assert_eq!(normalize_error(a), normalize_error(b));
+        assert_eq!(normalize_error("  \n"), "errore sconosciuto");
+    }
+}
@@ -2,6 +2,7 @@ use std::path::Path;
 use std::process::Command;
 
 use crate::error::{AppError, Result};
+use crate::services::metrics;
 
 pub fn get_extension(filename: &str) -> Option<String> {
     Path::new(filename)
@@ -33,9 +34,7 @@ pub fn check_pdftoppm_available() -> bool {
 }
 
 pub fn run_ffmpeg(args: &[&str]) -> Result<()> {
-    let output = Command::new("ffmpeg")
-        .args(args)
-        .output()
+    let output = metrics::run_tool(Command::new("ffmpeg").args(args))
         .map_err(|e| AppError::FfmpegError(format!("Impossibile eseguire ffmpeg: {}", e)))?;
 
     if !output.status.success() {
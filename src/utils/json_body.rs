@@ -0,0 +1,57 @@
+//! Body JSON opzionali
+
+use serde::de::DeserializeOwned;
+
+use crate::error::{AppError, Result};
+
+/// Interpreta un body JSON facoltativo
+///
+/// Un body vuoto equivale ai valori di default; un JSON non valido è un
+/// errore, così un campo con il tipo sbagliato non viene ignorato in silenzio.
+pub fn optional_json<T: DeserializeOwned + Default>(body: &[u8]) -> Result<T> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(T::default());
+    }
+    serde_json::from_slice(body)
+        .map_err(|e| AppError::BadRequest(format!("JSON non valido: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    struct Request {
+        #[serde(default)]
+        single_use: bool,
+        #[serde(default)]
+        expires_in_secs: Option<u64>,
+    }
+
+    #[test]
+    fn test_empty_body_uses_defaults() {
+        assert_eq!(optional_json::<Request>(b"").unwrap(), Request::default());
+        assert_eq!(
+            optional_json::<Request>(b" \n").unwrap(),
+            Request::default()
+        );
+    }
+
+    #[test]
+    fn test_valid_body() {
+        let request: Request = optional_json(br#"{"single_use": true}"#).unwrap();
+        assert!(request.single_use);
+    }
+
+    #[test]
+    fn test_invalid_body_is_rejected() {
+        let result =
+            optional_json::<Request>(br#"{"single_use": true, "expires_in_secs": "3600"}"#);
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert!(matches!(
+            optional_json::<Request>(b"{"),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
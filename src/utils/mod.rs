@@ -1,6 +1,12 @@
+pub mod client_ip;
 pub mod content_type;
+pub mod csv;
 pub mod encoding;
+pub mod error_cause;
 pub mod file;
+pub mod json_body;
+pub mod multipart;
+pub mod range;
 pub mod validation;
 
 pub use content_type::get_content_type;
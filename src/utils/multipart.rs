@@ -0,0 +1,92 @@
+//! Salvataggio su disco dei campi multipart
+
+use std::path::Path;
+
+use axum::extract::multipart::{Field, MultipartError};
+use axum::http::StatusCode;
+use tempfile::TempPath;
+use tokio::io::AsyncWriteExt;
+
+use crate::error::{AppError, Result};
+use crate::utils::{get_extension, validate_file_size};
+
+/// File ricevuto in un campo multipart, salvato in un file temporaneo
+/// (rimosso al drop)
+#[derive(Debug)]
+pub struct UploadedFile {
+    /// Nome file indicato dal client (`file` se assente)
+    pub filename: String,
+    /// Estensione in minuscolo del nome file
+    pub extension: String,
+    pub size: u64,
+    path: TempPath,
+}
+
+impl UploadedFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Nome del file senza estensione
+    pub fn base_name(&self) -> &str {
+        self.filename
+            .rsplit_once('.')
+            .map(|(n, _)| n)
+            .unwrap_or(&self.filename)
+    }
+
+    pub fn into_temp_path(self) -> TempPath {
+        self.path
+    }
+}
+
+/// Scrive un campo multipart in `dir` a blocchi, senza caricarlo in memoria.
+///
+/// Il file temporaneo mantiene l'estensione del nome originale, usata dai
+/// converter per riconoscere il formato. Se il campo supera `max_mb`
+/// la scrittura si interrompe con [`AppError::FileTooLarge`].
+pub async fn save_field(mut field: Field<'_>, dir: &Path, max_mb: u64) -> Result<UploadedFile> {
+    let filename = field.file_name().unwrap_or("file").to_string();
+    let extension = get_extension(&filename).unwrap_or_default();
+
+    let suffix = if extension.is_empty() {
+        String::new()
+    } else {
+        format!(".{}", extension)
+    };
+
+    tokio::fs::create_dir_all(dir).await?;
+    let path = tempfile::Builder::new()
+        .suffix(&suffix)
+        .tempfile_in(dir)?
+        .into_temp_path();
+
+    let mut file = tokio::fs::File::create(&path).await?;
+    let mut size = 0u64;
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| multipart_error(e, max_mb))?
+    {
+        size += chunk.len() as u64;
+        validate_file_size(size, max_mb)?;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+
+    Ok(UploadedFile {
+        filename,
+        extension,
+        size,
+        path,
+    })
+}
+
+/// Converte un errore multipart, distinguendo il superamento del limite del body
+pub fn multipart_error(err: MultipartError, max_mb: u64) -> AppError {
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::FileTooLarge(max_mb)
+    } else {
+        AppError::Internal(err.to_string())
+    }
+}
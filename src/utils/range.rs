@@ -0,0 +1,241 @@
+//! Supporto per richieste HTTP `Range` e GET condizionali
+
+use std::time::SystemTime;
+
+use axum::http::{header, HeaderMap};
+
+/// Esito della valutazione dell'header `Range`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// Nessun range (o range ignorato): contenuto completo
+    Full,
+    /// Intervallo inclusivo `start..=end`
+    Partial { start: u64, end: u64 },
+    /// Range non soddisfacibile per la dimensione del file (416)
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Interpreta l'header `Range` per un file di `size` byte.
+    ///
+    /// Sono supportati `bytes=a-b`, `bytes=a-` e `bytes=-n`. Le richieste
+    /// multi-range e le unità diverse da `bytes` vengono ignorate
+    /// (risposta completa), come consentito da RFC 9110.
+    pub fn parse(value: &str, size: u64) -> Self {
+        let Some(spec) = value.trim().strip_prefix("bytes=") else {
+            return ByteRange::Full;
+        };
+        if spec.contains(',') {
+            return ByteRange::Full;
+        }
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return ByteRange::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+
+        if start.is_empty() {
+            // Suffisso: ultimi n byte
+            return match end.parse::<u64>() {
+                Ok(0) => ByteRange::Unsatisfiable,
+                Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+                Ok(n) => ByteRange::Partial {
+                    start: size.saturating_sub(n),
+                    end: size - 1,
+                },
+                Err(_) => ByteRange::Full,
+            };
+        }
+
+        let Ok(start) = start.parse::<u64>() else {
+            return ByteRange::Full;
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            match end.parse::<u64>() {
+                Ok(end) if end >= start => Some(end),
+                _ => return ByteRange::Full,
+            }
+        };
+
+        if start >= size {
+            return ByteRange::Unsatisfiable;
+        }
+
+        ByteRange::Partial {
+            start,
+            end: end.map_or(size - 1, |e| e.min(size - 1)),
+        }
+    }
+}
+
+/// Validatori di una risorsa per le richieste condizionali
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    /// ETag già quotato (es. `"abc"` o `W/"abc"`)
+    pub etag: Option<String>,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    fn etag_matches(&self, list: &str, weak: bool) -> bool {
+        let Some(etag) = self.etag.as_deref() else {
+            return false;
+        };
+        if !weak && etag.starts_with("W/") {
+            return false;
+        }
+        let own = etag.trim_start_matches("W/");
+        list.split(',').map(str::trim).any(|candidate| {
+            candidate == "*"
+                || (weak && candidate.trim_start_matches("W/") == own)
+                || (!weak && candidate == own)
+        })
+    }
+
+    /// Verifica se la richiesta può ricevere `304 Not Modified`
+    pub fn is_not_modified(&self, headers: &HeaderMap) -> bool {
+        // If-None-Match ha precedenza su If-Modified-Since
+        if let Some(value) = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|v| v.to_str().ok())
+        {
+            return self.etag_matches(value, true);
+        }
+
+        match (
+            self.last_modified,
+            headers
+                .get(header::IF_MODIFIED_SINCE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| httpdate::parse_http_date(v).ok()),
+        ) {
+            (Some(last_modified), Some(since)) => truncate_secs(last_modified) <= since,
+            _ => false,
+        }
+    }
+
+    /// Verifica la precondizione `If-Range`: se non è soddisfatta
+    /// l'header `Range` va ignorato e si risponde con il contenuto completo.
+    pub fn if_range_matches(&self, headers: &HeaderMap) -> bool {
+        let Some(value) = headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) else {
+            return true;
+        };
+        let value = value.trim();
+
+        if value.starts_with('"') || value.starts_with("W/") {
+            // If-Range richiede un confronto forte
+            return self.etag_matches(value, false);
+        }
+
+        match (self.last_modified, httpdate::parse_http_date(value).ok()) {
+            (Some(last_modified), Some(date)) => truncate_secs(last_modified) == date,
+            _ => false,
+        }
+    }
+}
+
+/// Le date HTTP hanno risoluzione al secondo
+fn truncate_secs(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            ByteRange::parse("bytes=0-99", 1000),
+            ByteRange::Partial { start: 0, end: 99 }
+        );
+        assert_eq!(
+            ByteRange::parse("bytes=500-", 1000),
+            ByteRange::Partial {
+                start: 500,
+                end: 999
+            }
+        );
+        assert_eq!(
+            ByteRange::parse("bytes=-100", 1000),
+            ByteRange::Partial {
+                start: 900,
+                end: 999
+            }
+        );
+        // Suffisso più lungo del file: tutto il file
+        assert_eq!(
+            ByteRange::parse("bytes=-5000", 1000),
+            ByteRange::Partial { start: 0, end: 999 }
+        );
+        // Fine oltre la dimensione: troncata
+        assert_eq!(
+            ByteRange::parse("bytes=900-5000", 1000),
+            ByteRange::Partial {
+                start: 900,
+                end: 999
+            }
+        );
+        assert_eq!(
+            ByteRange::parse("bytes=1000-", 1000),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(ByteRange::parse("bytes=-0", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(ByteRange::parse("bytes=0-", 0), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_parse_range_ignored() {
+        assert_eq!(ByteRange::parse("items=0-1", 1000), ByteRange::Full);
+        assert_eq!(ByteRange::parse("bytes=0-1,5-6", 1000), ByteRange::Full);
+        assert_eq!(ByteRange::parse("bytes=10-5", 1000), ByteRange::Full);
+        assert_eq!(ByteRange::parse("bytes=abc", 1000), ByteRange::Full);
+    }
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        map.insert(name, HeaderValue::from_str(value).unwrap());
+        map
+    }
+
+    fn validators() -> Validators {
+        Validators {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").ok(),
+        }
+    }
+
+    #[test]
+    fn test_not_modified() {
+        let v = validators();
+        assert!(v.is_not_modified(&headers(header::IF_NONE_MATCH, "\"abc\"")));
+        assert!(v.is_not_modified(&headers(header::IF_NONE_MATCH, "\"x\", W/\"abc\"")));
+        assert!(!v.is_not_modified(&headers(header::IF_NONE_MATCH, "\"x\"")));
+        assert!(v.is_not_modified(&headers(
+            header::IF_MODIFIED_SINCE,
+            "Wed, 21 Oct 2015 07:28:00 GMT"
+        )));
+        assert!(!v.is_not_modified(&headers(
+            header::IF_MODIFIED_SINCE,
+            "Tue, 20 Oct 2015 07:28:00 GMT"
+        )));
+        assert!(!v.is_not_modified(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_if_range() {
+        let v = validators();
+        assert!(v.if_range_matches(&HeaderMap::new()));
+        assert!(v.if_range_matches(&headers(header::IF_RANGE, "\"abc\"")));
+        assert!(!v.if_range_matches(&headers(header::IF_RANGE, "\"other\"")));
+        assert!(!v.if_range_matches(&headers(header::IF_RANGE, "W/\"abc\"")));
+        assert!(v.if_range_matches(&headers(header::IF_RANGE, "Wed, 21 Oct 2015 07:28:00 GMT")));
+        assert!(!v.if_range_matches(&headers(header::IF_RANGE, "Thu, 22 Oct 2015 07:28:00 GMT")));
+    }
+}
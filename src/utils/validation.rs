@@ -1,5 +1,7 @@
 //! Validation utilities for format and tool checking
 
+use chrono::{DateTime, NaiveDate, Utc};
+
 use crate::config::formats;
 use crate::error::{AppError, Result};
 use crate::utils::file::{check_ffmpeg_available, check_pdftoppm_available};
@@ -134,6 +136,31 @@ pub fn validate_conversion_formats(
     Ok(())
 }
 
+/// Interpreta un istante RFC 3339 o una data `YYYY-MM-DD`
+///
+/// Una data come fine del periodo include l'intera giornata.
+pub fn parse_date_param(value: &str, end_of_day: bool) -> Result<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| {
+            if end_of_day {
+                date.and_hms_nano_opt(23, 59, 59, 999_999_999)
+            } else {
+                date.and_hms_opt(0, 0, 0)
+            }
+        })
+        .map(|dt| dt.and_utc())
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Data non valida: '{}' (usa RFC 3339 o YYYY-MM-DD)",
+                value
+            ))
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;